@@ -0,0 +1,408 @@
+//! JWT authentication shared by the bamboo servers: keys from config or a JWKS file,
+//! `exp`/`nbf`/`aud`/`iss` checks, and failures mapped to 401/403 [`Status`]. Role and
+//! scope policies live in [`policy`].
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, bail, Context};
+use http::StatusCode;
+use jsonwebtoken::{
+    errors::{Error, ErrorKind},
+    jwk::{AlgorithmParameters, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use bamboo_status::{errors::Status, status::AnyResult};
+
+pub use jsonwebtoken;
+pub use policy::{path_matches, Authorization, Policy, Rule};
+
+pub mod policy;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Jwt {
+    /// Accepted signing algorithms, e.g. `[RS256, ES256]`.
+    #[serde(default = "default_algorithms")]
+    pub algorithms: Vec<Algorithm>,
+    /// Shared secret of the HS algorithms.
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// PEM public key of the RS and ES algorithms.
+    #[serde(default)]
+    pub public_key: Option<PathBuf>,
+    /// JWKS file, keys are picked by the `kid` of the token.
+    #[serde(default)]
+    pub jwks: Option<PathBuf>,
+    /// Accepted `iss` values, not checked when empty.
+    #[serde(default)]
+    pub issuer: Vec<String>,
+    /// Accepted `aud` values, not checked when empty.
+    #[serde(default)]
+    pub audience: Vec<String>,
+    /// Clock skew allowed when checking `exp` and `nbf`.
+    #[serde(default = "default_leeway", with = "humantime_serde")]
+    pub leeway: Duration,
+    /// Let requests without a token through, handlers that need claims reject them.
+    #[serde(default)]
+    pub allow_anonymous: bool,
+}
+
+fn default_algorithms() -> Vec<Algorithm> {
+    vec![Algorithm::HS256]
+}
+
+fn default_leeway() -> Duration {
+    Duration::from_secs(60)
+}
+
+impl Jwt {
+    /// Verify HS256 tokens signed with `secret`.
+    pub fn with_secret(secret: &str) -> Self {
+        Self {
+            algorithms: default_algorithms(),
+            secret: Some(secret.to_string()),
+            public_key: None,
+            jwks: None,
+            issuer: Vec::new(),
+            audience: Vec::new(),
+            leeway: default_leeway(),
+            allow_anonymous: false,
+        }
+    }
+}
+
+/// The authenticated caller, put in the request extensions by the auth layers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Principal {
+    /// The `sub` claim, empty when the token has none.
+    pub subject: String,
+    pub claims: serde_json::Value,
+}
+
+impl Principal {
+    /// Deserialize the claims into the type a handler expects.
+    #[allow(clippy::result_large_err)]
+    pub fn claims<T: DeserializeOwned>(&self) -> Result<T, Status> {
+        serde_json::from_value(self.claims.clone()).map_err(|err| unauthenticated("invalid token claims").with_cause(&err))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Family {
+    Hmac,
+    Rsa,
+    Ec,
+    Ed,
+}
+
+impl Family {
+    fn of(alg: Algorithm) -> Self {
+        match alg {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => Family::Hmac,
+            Algorithm::ES256 | Algorithm::ES384 => Family::Ec,
+            Algorithm::EdDSA => Family::Ed,
+            _ => Family::Rsa,
+        }
+    }
+}
+
+struct Key {
+    kid: Option<String>,
+    family: Family,
+    key: DecodingKey,
+}
+
+/// Verifies bearer tokens against the keys of a [`Jwt`] config.
+pub struct Verifier {
+    keys: Vec<Key>,
+    validation: Validation,
+    allow_anonymous: bool,
+}
+
+impl Verifier {
+    pub fn new(conf: &Jwt) -> AnyResult<Self> {
+        if conf.algorithms.is_empty() {
+            bail!("jwt: no algorithms configured");
+        }
+        let families: Vec<Family> = conf.algorithms.iter().map(|alg| Family::of(*alg)).collect();
+        let mut keys = Vec::new();
+        if let Some(secret) = &conf.secret {
+            keys.push(Key {
+                kid: None,
+                family: Family::Hmac,
+                key: DecodingKey::from_secret(secret.as_bytes()),
+            });
+        }
+        if let Some(path) = &conf.public_key {
+            let pem = std::fs::read(path).with_context(|| format!("jwt: read {}", path.display()))?;
+            for family in [Family::Rsa, Family::Ec, Family::Ed] {
+                if !families.contains(&family) {
+                    continue;
+                }
+                let key = match family {
+                    Family::Rsa => DecodingKey::from_rsa_pem(&pem),
+                    Family::Ec => DecodingKey::from_ec_pem(&pem),
+                    _ => DecodingKey::from_ed_pem(&pem),
+                };
+                // One file holds one key, it only parses for the algorithms of its type.
+                if let Ok(key) = key {
+                    keys.push(Key { kid: None, family, key });
+                }
+            }
+        }
+        if let Some(path) = &conf.jwks {
+            keys.extend(load_jwks(path)?);
+        }
+        keys.retain(|k| families.contains(&k.family));
+        if keys.is_empty() {
+            bail!("jwt: no key for algorithms {:?}", conf.algorithms);
+        }
+
+        let mut validation = Validation::new(conf.algorithms[0]);
+        validation.algorithms = conf.algorithms.clone();
+        validation.leeway = conf.leeway.as_secs();
+        validation.validate_nbf = true;
+        let mut required = vec!["exp"];
+        if !conf.issuer.is_empty() {
+            validation.set_issuer(&conf.issuer);
+            required.push("iss");
+        }
+        if !conf.audience.is_empty() {
+            validation.set_audience(&conf.audience);
+            required.push("aud");
+        } else {
+            validation.validate_aud = false;
+        }
+        validation.set_required_spec_claims(&required);
+        Ok(Self {
+            keys,
+            validation,
+            allow_anonymous: conf.allow_anonymous,
+        })
+    }
+
+    /// Verify `token` and deserialize its claims.
+    #[allow(clippy::result_large_err)]
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, Status> {
+        let header = jsonwebtoken::decode_header(token).map_err(|err| reject(&err))?;
+        if !self.validation.algorithms.contains(&header.alg) {
+            return Err(unauthenticated("token algorithm not accepted"));
+        }
+        let family = Family::of(header.alg);
+        let key = self
+            .keys
+            .iter()
+            .filter(|k| k.family == family)
+            .find(|k| header.kid.is_none() || k.kid.is_none() || k.kid == header.kid)
+            .ok_or_else(|| unauthenticated("unknown token key"))?;
+        // The key decides the family, keep the other algorithms out of the check.
+        let mut validation = self.validation.clone();
+        validation.algorithms = vec![header.alg];
+        jsonwebtoken::decode::<T>(token, &key.key, &validation)
+            .map(|data| data.claims)
+            .map_err(|err| reject(&err))
+    }
+
+    /// Authenticate a request from its `authorization` header or metadata.
+    ///
+    /// Requests without a bearer token are anonymous (`None`) when allowed, rejected otherwise.
+    #[allow(clippy::result_large_err)]
+    pub fn authenticate(&self, authorization: Option<&str>) -> Result<Option<Principal>, Status> {
+        let Some(token) = authorization.and_then(bearer) else {
+            return if self.allow_anonymous {
+                Ok(None)
+            } else {
+                Err(unauthenticated("missing bearer token"))
+            };
+        };
+        let claims: serde_json::Value = self.verify(token)?;
+        let subject = claims.get("sub").and_then(|s| s.as_str()).unwrap_or_default().to_string();
+        Ok(Some(Principal { subject, claims }))
+    }
+}
+
+/// The token of an `authorization: Bearer <token>` value.
+pub fn bearer(authorization: &str) -> Option<&str> {
+    let (scheme, token) = authorization.trim().split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+}
+
+fn load_jwks(path: &PathBuf) -> AnyResult<Vec<Key>> {
+    let data = std::fs::read(path).with_context(|| format!("jwt: read {}", path.display()))?;
+    let set: JwkSet = serde_json::from_slice(&data).with_context(|| format!("jwt: parse {}", path.display()))?;
+    set.keys
+        .iter()
+        .map(|jwk| {
+            let family = match &jwk.algorithm {
+                AlgorithmParameters::RSA(_) => Family::Rsa,
+                AlgorithmParameters::EllipticCurve(_) => Family::Ec,
+                AlgorithmParameters::OctetKey(_) => Family::Hmac,
+                AlgorithmParameters::OctetKeyPair(_) => Family::Ed,
+            };
+            let key = DecodingKey::from_jwk(jwk).map_err(|err| anyhow!("jwt: key {:?}: {}", jwk.common.key_id, err))?;
+            Ok(Key {
+                kid: jwk.common.key_id.clone(),
+                family,
+                key,
+            })
+        })
+        .collect()
+}
+
+/// A 401 [`Status`]: the caller is not authenticated.
+pub fn unauthenticated(message: &str) -> Status {
+    Status::new("Unauthenticated", message).with_code(StatusCode::UNAUTHORIZED)
+}
+
+/// A 403 [`Status`]: the caller is authenticated but not allowed.
+pub fn permission_denied(message: &str) -> Status {
+    Status::new("PermissionDenied", message).with_code(StatusCode::FORBIDDEN)
+}
+
+/// Valid tokens meant for someone else are forbidden, everything else is unauthenticated.
+fn reject(err: &Error) -> Status {
+    let status = match err.kind() {
+        ErrorKind::InvalidAudience => permission_denied("token audience not accepted"),
+        ErrorKind::InvalidIssuer => permission_denied("token issuer not accepted"),
+        ErrorKind::ExpiredSignature => unauthenticated("token expired"),
+        ErrorKind::ImmatureSignature => unauthenticated("token not valid yet"),
+        ErrorKind::InvalidSignature => unauthenticated("invalid token signature"),
+        ErrorKind::MissingRequiredClaim(claim) => unauthenticated(&format!("token has no {} claim", claim)),
+        _ => unauthenticated("invalid token"),
+    };
+    status.with_cause(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{encode, get_current_timestamp, EncodingKey, Header};
+    use serde_json::json;
+
+    use super::*;
+
+    fn sign(claims: serde_json::Value) -> String {
+        encode(&Header::default(), &claims, &EncodingKey::from_secret(b"secret")).unwrap()
+    }
+
+    fn exp() -> u64 {
+        get_current_timestamp() + 600
+    }
+
+    #[test]
+    fn valid_bearer() {
+        assert_eq!(bearer("Bearer abc"), Some("abc"));
+        assert_eq!(bearer("bearer  abc "), Some("abc"));
+        assert_eq!(bearer("Basic abc"), None);
+        assert_eq!(bearer("Bearer "), None);
+    }
+
+    #[test]
+    fn valid_hs256() {
+        let mut conf = Jwt::with_secret("secret");
+        conf.issuer = vec!["bamboo".to_string()];
+        conf.audience = vec!["api".to_string()];
+        let verifier = Verifier::new(&conf).unwrap();
+
+        let token = sign(json!({"sub": "alice", "iss": "bamboo", "aud": "api", "exp": exp()}));
+        let principal = verifier.authenticate(Some(&format!("Bearer {}", token))).unwrap().unwrap();
+        assert_eq!(principal.subject, "alice");
+
+        let expired = sign(json!({"sub": "alice", "iss": "bamboo", "aud": "api", "exp": 1}));
+        let s = verifier.verify::<serde_json::Value>(&expired).unwrap_err();
+        assert_eq!((s.code, s.message.as_str()), (401, "token expired"));
+
+        let early = json!({"iss": "bamboo", "aud": "api", "exp": exp(), "nbf": exp()});
+        assert_eq!(verifier.verify::<serde_json::Value>(&sign(early)).unwrap_err().code, 401);
+
+        let other_aud = sign(json!({"iss": "bamboo", "aud": "web", "exp": exp()}));
+        assert_eq!(verifier.verify::<serde_json::Value>(&other_aud).unwrap_err().code, 403);
+
+        let other_iss = sign(json!({"iss": "other", "aud": "api", "exp": exp()}));
+        assert_eq!(verifier.verify::<serde_json::Value>(&other_iss).unwrap_err().code, 403);
+
+        let forged = encode(&Header::default(), &json!({"exp": exp()}), &EncodingKey::from_secret(b"nope")).unwrap();
+        let s = verifier.verify::<serde_json::Value>(&forged).unwrap_err();
+        assert_eq!((s.code, s.message.as_str()), (401, "invalid token signature"));
+
+        let s = verifier.authenticate(None).unwrap_err();
+        assert_eq!((s.code, s.reason.as_str()), (401, "Unauthenticated"));
+    }
+
+    #[test]
+    fn valid_anonymous() {
+        let mut conf = Jwt::with_secret("secret");
+        conf.allow_anonymous = true;
+        let verifier = Verifier::new(&conf).unwrap();
+        assert_eq!(verifier.authenticate(None).unwrap(), None);
+        assert!(verifier.authenticate(Some("Bearer junk")).is_err());
+    }
+
+    #[test]
+    fn valid_es256_pem() {
+        let key_pair = rcgen::KeyPair::generate().unwrap();
+        let path = std::env::temp_dir().join(format!("bamboo-auth-es256-{}.pem", std::process::id()));
+        std::fs::write(&path, key_pair.public_key_pem()).unwrap();
+        let mut conf = Jwt::with_secret("secret");
+        conf.algorithms = vec![Algorithm::ES256];
+        conf.secret = None;
+        conf.public_key = Some(path.clone());
+        let verifier = Verifier::new(&conf);
+        std::fs::remove_file(&path).unwrap();
+        let verifier = verifier.unwrap();
+
+        let signing = EncodingKey::from_ec_pem(key_pair.serialize_pem().as_bytes()).unwrap();
+        let token = encode(&Header::new(Algorithm::ES256), &json!({"sub": "bob", "exp": exp()}), &signing).unwrap();
+        let principal = verifier.authenticate(Some(&format!("Bearer {}", token))).unwrap().unwrap();
+        assert_eq!(principal.subject, "bob");
+
+        // HS256 is not accepted even though it's signed with a valid secret.
+        assert_eq!(verifier.verify::<serde_json::Value>(&sign(json!({"exp": exp()}))).unwrap_err().code, 401);
+    }
+
+    #[test]
+    fn valid_jwks() {
+        // `c2VjcmV0` is base64url for `secret`.
+        let jwks = json!({"keys": [
+            {"kty": "oct", "kid": "old", "k": "b2xk"},
+            {"kty": "oct", "kid": "new", "k": "c2VjcmV0"},
+        ]});
+        let path = std::env::temp_dir().join(format!("bamboo-auth-jwks-{}.json", std::process::id()));
+        std::fs::write(&path, jwks.to_string()).unwrap();
+        let mut conf = Jwt::with_secret("secret");
+        conf.secret = None;
+        conf.jwks = Some(path.clone());
+        let verifier = Verifier::new(&conf);
+        std::fs::remove_file(&path).unwrap();
+        let verifier = verifier.unwrap();
+
+        let mut header = Header {
+            kid: Some("new".to_string()),
+            ..Default::default()
+        };
+        let token = encode(&header, &json!({"exp": exp()}), &EncodingKey::from_secret(b"secret")).unwrap();
+        assert!(verifier.verify::<serde_json::Value>(&token).is_ok());
+
+        header.kid = Some("gone".to_string());
+        let token = encode(&header, &json!({"exp": exp()}), &EncodingKey::from_secret(b"secret")).unwrap();
+        let s = verifier.verify::<serde_json::Value>(&token).unwrap_err();
+        assert_eq!(s.message, "unknown token key");
+    }
+
+    #[test]
+    fn valid_typed_claims() {
+        #[derive(Deserialize)]
+        struct Claims {
+            sub: String,
+            tenant: u32,
+        }
+        let principal = Principal {
+            subject: "alice".to_string(),
+            claims: json!({"sub": "alice", "tenant": 7}),
+        };
+        let claims: Claims = principal.claims().unwrap();
+        assert_eq!((claims.sub.as_str(), claims.tenant), ("alice", 7));
+        assert_eq!(principal.claims::<Vec<u8>>().unwrap_err().code, 401);
+    }
+}
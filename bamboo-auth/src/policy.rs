@@ -0,0 +1,214 @@
+//! Role and scope requirements per route or gRPC method, checked against the claims of the
+//! [`Principal`]. Every decision on a protected resource is audit-logged.
+
+use serde::{Deserialize, Serialize};
+
+use bamboo_log::audit::{self, Decision, Event};
+use bamboo_status::errors::Status;
+
+use crate::{permission_denied, unauthenticated, Principal};
+
+/// What a caller needs to access a resource.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct Policy {
+    /// The caller needs one of these roles.
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// The caller needs all of these scopes.
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+impl Policy {
+    /// Only require an authenticated caller.
+    pub fn authenticated() -> Self {
+        Self::default()
+    }
+
+    pub fn any_role(roles: &[&str]) -> Self {
+        Self {
+            roles: roles.iter().map(|r| r.to_string()).collect(),
+            scopes: Vec::new(),
+        }
+    }
+
+    pub fn all_scopes(scopes: &[&str]) -> Self {
+        Self {
+            roles: Vec::new(),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+/// A policy for one route or gRPC method.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Rule {
+    /// The axum route, e.g. `/v1/users/:id`, a path prefix like `/v1/users`, or a gRPC
+    /// method like `/pkg.Service/Method` or service like `/pkg.Service`.
+    pub path: String,
+    /// Only apply to this HTTP method, all methods when missing.
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(flatten)]
+    pub policy: Policy,
+}
+
+impl Rule {
+    fn matches(&self, method: &str, path: &str, route: Option<&str>) -> bool {
+        path_matches(&self.path, path, route) && self.method.as_ref().is_none_or(|m| m.eq_ignore_ascii_case(method))
+    }
+}
+
+/// Whether `pattern` is the matched `route` of a request to `path`, or a prefix of `path`
+/// ending at a segment boundary: `/v1/users` matches `/v1/users/7` but not `/v1/usersx`.
+pub fn path_matches(pattern: &str, path: &str, route: Option<&str>) -> bool {
+    let prefix = pattern.trim_end_matches('/');
+    route == Some(pattern) || path == prefix || path.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('/'))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Authorization {
+    /// Claim holding the roles, a dotted path like `realm_access.roles` reaches nested claims.
+    #[serde(default = "default_roles_claim")]
+    pub roles_claim: String,
+    /// Claim holding the scopes, an array or a space separated string.
+    #[serde(default = "default_scopes_claim")]
+    pub scopes_claim: String,
+    /// The first matching rule applies, resources without one are open to every caller.
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+fn default_roles_claim() -> String {
+    "roles".to_string()
+}
+
+fn default_scopes_claim() -> String {
+    "scope".to_string()
+}
+
+impl Default for Authorization {
+    fn default() -> Self {
+        Self {
+            roles_claim: default_roles_claim(),
+            scopes_claim: default_scopes_claim(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl Authorization {
+    /// Add a rule after the configured ones.
+    pub fn rule(mut self, path: &str, method: Option<&str>, policy: Policy) -> Self {
+        self.rules.push(Rule {
+            path: path.to_string(),
+            method: method.map(str::to_string),
+            policy,
+        });
+        self
+    }
+
+    /// The policy of the first rule matching the request, `route` being the matched route.
+    pub fn policy(&self, method: &str, path: &str, route: Option<&str>) -> Option<&Policy> {
+        self.rules.iter().find(|r| r.matches(method, path, route)).map(|r| &r.policy)
+    }
+
+    /// Check `principal` against the policy of the request and audit-log the decision.
+    ///
+    /// Anonymous callers of protected resources get a 401, callers lacking roles or scopes a 403.
+    #[allow(clippy::result_large_err)]
+    pub fn authorize(&self, principal: Option<&Principal>, method: &str, path: &str, route: Option<&str>) -> Result<(), Status> {
+        let Some(policy) = self.policy(method, path, route) else {
+            return Ok(());
+        };
+        let subject = principal.map(|p| p.subject.as_str()).unwrap_or_default();
+        let denied = match principal {
+            None => Err(unauthenticated("missing bearer token")),
+            Some(principal) => self.check(principal, policy),
+        };
+        let reason = denied.as_ref().err().map(|s| s.message.as_str()).unwrap_or("policy satisfied");
+        audit::record(&Event {
+            subject,
+            action: method,
+            resource: path,
+            decision: if denied.is_ok() { Decision::Allow } else { Decision::Deny },
+            reason,
+        });
+        denied
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn check(&self, principal: &Principal, policy: &Policy) -> Result<(), Status> {
+        if !policy.roles.is_empty() {
+            let roles = principal.claim_values(&self.roles_claim);
+            if !policy.roles.iter().any(|r| roles.contains(r)) {
+                return Err(permission_denied(&format!("requires one of roles {}", policy.roles.join(", "))));
+            }
+        }
+        let scopes = principal.claim_values(&self.scopes_claim);
+        if let Some(missing) = policy.scopes.iter().find(|s| !scopes.contains(s)) {
+            return Err(permission_denied(&format!("requires scope {}", missing)));
+        }
+        Ok(())
+    }
+}
+
+impl Principal {
+    /// The strings of a claim that is an array or a space separated string, e.g. `scope`.
+    pub fn claim_values(&self, claim: &str) -> Vec<String> {
+        let value = claim.split('.').try_fold(&self.claims, |value, key| value.get(key));
+        match value {
+            Some(serde_json::Value::String(s)) => s.split_whitespace().map(str::to_string).collect(),
+            Some(serde_json::Value::Array(values)) => values
+                .iter()
+                .filter_map(|v| v.as_str().map(str::to_string))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn principal(claims: serde_json::Value) -> Principal {
+        Principal {
+            subject: "alice".to_string(),
+            claims,
+        }
+    }
+
+    #[test]
+    fn valid_claim_values() {
+        let p = principal(json!({"scope": "read write", "realm_access": {"roles": ["admin", 7]}}));
+        assert_eq!(p.claim_values("scope"), vec!["read", "write"]);
+        assert_eq!(p.claim_values("realm_access.roles"), vec!["admin"]);
+        assert!(p.claim_values("roles").is_empty());
+    }
+
+    #[test]
+    fn valid_authorize() {
+        let conf = Authorization::default()
+            .rule("/v1/users/:id", Some("DELETE"), Policy::any_role(&["admin", "owner"]))
+            .rule("/v1/users", None, Policy::all_scopes(&["users:read"]))
+            .rule("/pkg.Billing/Refund", None, Policy::any_role(&["finance"]));
+        let admin = principal(json!({"roles": ["admin"], "scope": "users:read"}));
+        let reader = principal(json!({"scope": "users:read"}));
+
+        assert!(conf.authorize(Some(&admin), "DELETE", "/v1/users/7", Some("/v1/users/:id")).is_ok());
+        let s = conf.authorize(Some(&reader), "DELETE", "/v1/users/7", Some("/v1/users/:id")).unwrap_err();
+        assert_eq!((s.code, s.message.as_str()), (403, "requires one of roles admin, owner"));
+
+        assert!(conf.authorize(Some(&reader), "GET", "/v1/users/7", None).is_ok());
+        let s = conf.authorize(Some(&principal(json!({}))), "GET", "/v1/users", None).unwrap_err();
+        assert_eq!((s.code, s.message.as_str()), (403, "requires scope users:read"));
+        assert_eq!(conf.authorize(None, "GET", "/v1/users", None).unwrap_err().code, 401);
+
+        assert_eq!(conf.authorize(Some(&admin), "POST", "/pkg.Billing/Refund", None).unwrap_err().code, 403);
+        assert!(conf.authorize(None, "POST", "/pkg.Billing/Charge", None).is_ok());
+        assert!(conf.authorize(None, "GET", "/v1/userss", None).is_ok());
+    }
+}
@@ -1,18 +1,23 @@
 use std::any::Any;
-use std::sync::Arc;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
 
 use dashmap::DashMap;
+use tokio::sync::{watch, Notify};
 use tokio_graceful::Shutdown;
 
+use bamboo_registry::Registrar;
 use bamboo_status::status::{AnyResult, Result};
 
 use crate::plugin::{Plugin, PluginRef};
+use crate::registration::{Registered, Registration};
 
 pub type Registry<T> = DashMap<String, T>;
 
 pub struct App<C> {
     conf : Arc<C>,
     components: Registry<PluginRef>,
+    registration: Option<Arc<Registered>>,
 }
 
 impl<C> App<C>
@@ -21,34 +26,92 @@ impl<C> App<C>
         Self {
             conf,
             components: Registry::new(),
+            registration: None,
         }
     }
 
+    pub fn name(&self) -> &str {
+        "App"
+    }
+
     pub fn with(&self, p: PluginRef) ->Result<()> {
         self.components.insert(p.name().to_string(), p);
         Ok(())
     }
 
+    /// Register the endpoints of the plugins with `registrar` once they all listen, and
+    /// deregister them on shutdown before the plugins start draining.
+    pub fn with_registrar(mut self, registrar: Arc<dyn Registrar>, conf: Registration) -> Self {
+        self.registration = Some(Arc::new(Registered::new(registrar, conf)));
+        self
+    }
+
     pub async fn run(&self) -> AnyResult<()> {
-        let shutdown = Shutdown::default();
+        self.run_until(tokio_graceful::default_signal()).await
+    }
+
+    /// Run until `signal` resolves or a plugin fails, then shut down gracefully. Fails with the
+    /// error of the first plugin that failed, if any.
+    pub async fn run_until<F>(&self, signal: F) -> AnyResult<()>
+        where F: Future<Output=()> + Send + 'static,
+    {
+        let registration = self.registration.clone();
+        let failed = Arc::new(Notify::new());
+        let stop = failed.clone();
+        let shutdown = Shutdown::new(async move {
+            // Don't keep serving without the failed plugin.
+            tokio::select! {
+                _ = signal => {}
+                _ = stop.notified() => {}
+            }
+            // Leave the registry first, so clients stop picking this instance while it drains.
+            if let Some(registration) = registration {
+                registration.deregister().await;
+            }
+        });
 
-        let it = self.components.iter();
-        for kv in it {
-            let block_conf = Arc::clone(&self.conf);
+        let failure = Arc::new(Mutex::new(None));
+        let mut plugins = Vec::new();
+        for kv in self.components.iter() {
             let t = kv.value().clone();
-            let _ = shutdown.spawn_task_fn(|guard: tokio_graceful::ShutdownGuard| async move {
-                let _ = t.serve(guard).await;
+            let (plugin_failed, on_failure) = watch::channel(false);
+            let failure = failure.clone();
+            let failed = failed.clone();
+            plugins.push((t.clone(), on_failure));
+            shutdown.spawn_task_fn(|guard: tokio_graceful::ShutdownGuard| async move {
+                if let Err(e) = t.serve(guard).await {
+                    log::error!("{} failed: {:?}", t.name(), e);
+                    plugin_failed.send_replace(true);
+                    failure.lock().unwrap().get_or_insert(e);
+                    // Stored if the shutdown isn't waiting yet.
+                    failed.notify_one();
+                }
             });
         }
+        if let Some(registration) = self.registration.clone() {
+            shutdown.spawn_task_fn(move |guard| async move { registration.run(plugins, guard).await });
+        }
 
         shutdown.shutdown().await;
-        Ok(())
+        let failure = failure.lock().unwrap().take();
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use bamboo_registry::{Discovery, Memory, ServiceInstance};
+    use tokio::sync::Notify;
+
     use super::*;
+    use crate::plugin::{Listening, ShutdownGuard};
 
     struct Config {}
 
@@ -63,4 +126,125 @@ mod tests {
         let app = App::new(Arc::new(Config::new()));
         assert_eq!(app.name(), "App");
     }
+
+    struct Fake {
+        listening: Listening,
+        registry: Memory,
+        registered_at_drain: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl Plugin for Fake {
+        async fn serve(&self, guard: ShutdownGuard) -> AnyResult<()> {
+            self.listening.set("0.0.0.0:9000".parse().unwrap());
+            guard.cancelled().await;
+            let registered = !self.registry.get_service("greeter").await?.is_empty();
+            self.registered_at_drain.store(registered, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn listening(&self) -> Option<String> {
+            Some(format!("grpc://{}", self.listening.addr().await))
+        }
+    }
+
+    struct Idle;
+
+    #[async_trait]
+    impl Plugin for Idle {
+        async fn serve(&self, guard: ShutdownGuard) -> AnyResult<()> {
+            guard.cancelled().await;
+            Ok(())
+        }
+    }
+
+    /// Fails before listening.
+    struct Failing;
+
+    #[async_trait]
+    impl Plugin for Failing {
+        async fn serve(&self, _guard: ShutdownGuard) -> AnyResult<()> {
+            Err(std::io::Error::other("address in use").into())
+        }
+
+        async fn listening(&self) -> Option<String> {
+            std::future::pending().await
+        }
+    }
+
+    /// Notifies the heartbeats sent to the registry.
+    struct Counting(Memory, Notify);
+
+    #[async_trait]
+    impl Registrar for Counting {
+        async fn register(&self, instance: &ServiceInstance) -> AnyResult<()> {
+            self.0.register(instance).await
+        }
+
+        async fn deregister(&self, instance: &ServiceInstance) -> AnyResult<()> {
+            self.0.deregister(instance).await
+        }
+
+        async fn heartbeat(&self, _instance: &ServiceInstance) -> AnyResult<()> {
+            self.1.notify_one();
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn valid_registration() {
+        let registry = Memory::new();
+        let registrar = Arc::new(Counting(registry.clone(), Notify::new()));
+        let registered_at_drain = Arc::new(AtomicBool::new(true));
+        let conf = Registration {
+            name: "greeter".to_string(),
+            host: Some("10.0.0.7".to_string()),
+            heartbeat: Some(Duration::from_millis(10)),
+            ..Default::default()
+        };
+        let app = App::new(Arc::new(Config::new())).with_registrar(registrar.clone(), conf);
+        app.with(PluginRef::new(Fake {
+            listening: Listening::default(),
+            registry: registry.clone(),
+            registered_at_drain: registered_at_drain.clone(),
+        }))
+        .unwrap();
+        app.with(PluginRef::new(Idle)).unwrap();
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let run = tokio::spawn(async move { app.run_until(async move { let _ = rx.await; }).await });
+        let mut watcher = registry.watch("greeter").await.unwrap();
+        let instances = loop {
+            let instances = watcher.next().await.unwrap();
+            if !instances.is_empty() {
+                break instances;
+            }
+        };
+        assert_eq!(instances[0].id, "greeter-10.0.0.7:9000");
+        assert_eq!(instances[0].endpoints, vec!["grpc://10.0.0.7:9000"]);
+        tokio::time::timeout(Duration::from_secs(5), registrar.1.notified()).await.unwrap();
+
+        tx.send(()).unwrap();
+        run.await.unwrap().unwrap();
+        assert!(registry.get_service("greeter").await.unwrap().is_empty());
+        assert!(!registered_at_drain.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn invalid_plugin() {
+        let registry = Memory::new();
+        let conf = Registration {
+            name: "greeter".to_string(),
+            ..Default::default()
+        };
+        let app = App::new(Arc::new(Config::new())).with_registrar(Arc::new(registry.clone()), conf);
+        app.with(PluginRef::new(Failing)).unwrap();
+        app.with(PluginRef::new(Idle)).unwrap();
+
+        // The failure shuts the app down without a signal.
+        let run = app.run_until(std::future::pending());
+        let err = tokio::time::timeout(Duration::from_secs(5), run).await.unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "address in use");
+        assert!(registry.get_service("greeter").await.unwrap().is_empty());
+    }
 }
@@ -2,6 +2,7 @@
 pub mod app;
 pub mod builder;
 pub mod plugin;
+pub mod registration;
 mod component;
 pub mod sync;
 pub mod time;
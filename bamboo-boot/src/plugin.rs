@@ -1,7 +1,9 @@
 use std::any::Any;
+use std::net::SocketAddr;
 use std::ops::Deref;
 use std::sync::Arc;
 use async_trait::async_trait;
+use tokio::sync::watch;
 pub use tokio_graceful::ShutdownGuard;
 use bamboo_status::status::AnyResult;
 
@@ -11,6 +13,36 @@ pub trait Plugin: Any + Send + Sync {
         std::any::type_name::<Self>()
     }
     async fn serve(&self, guard: ShutdownGuard) -> AnyResult<()>;
+
+    /// The endpoint the plugin serves once it listens, like `grpc://0.0.0.0:9000`, for the
+    /// registrar of the [`App`](crate::app::App). `None` right away for plugins not serving.
+    async fn listening(&self) -> Option<String> {
+        None
+    }
+}
+
+/// The address a plugin listens on, set once it is bound.
+#[derive(Debug, Clone)]
+pub struct Listening(watch::Sender<Option<SocketAddr>>);
+
+impl Default for Listening {
+    fn default() -> Self {
+        Self(watch::channel(None).0)
+    }
+}
+
+impl Listening {
+    pub fn set(&self, addr: SocketAddr) {
+        self.0.send_replace(Some(addr));
+    }
+
+    /// Wait until the address is set.
+    pub async fn addr(&self) -> SocketAddr {
+        let mut rx = self.0.subscribe();
+        let addr = rx.wait_for(Option::is_some).await.map(|addr| *addr);
+        // The sender lives in `self`, the channel can't close.
+        addr.ok().flatten().expect("listening address")
+    }
 }
 
 #[derive(Clone)]
@@ -28,4 +60,4 @@ impl Deref for PluginRef {
     fn deref(&self) -> &Self::Target {
         &*self.0
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,216 @@
+//! Registration of the endpoints of an [`App`](crate::app::App) with a [`Registrar`].
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tokio_graceful::ShutdownGuard;
+
+use bamboo_registry::{Registrar, ServiceInstance};
+
+use crate::plugin::PluginRef;
+
+/// How long shutdown waits for the registrar to deregister the instance.
+const DEREGISTER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How the App registers itself, see [`App::with_registrar`](crate::app::App::with_registrar).
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Registration {
+    /// Service name the instance is registered under.
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    /// Instance id, `{name}-{host}:{port}` of the first endpoint in sorted order when missing.
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Host registered for endpoints listening on all interfaces, the address of the
+    /// default route when missing.
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    /// Send heartbeats at this interval, e.g. `10s` for a registry TTL of `30s`. Disabled
+    /// when missing.
+    #[serde(default, with = "humantime_serde")]
+    pub heartbeat: Option<Duration>,
+}
+
+impl Registration {
+    /// The instance serving `endpoints`, their unspecified hosts replaced.
+    fn instance(&self, endpoints: Vec<String>) -> ServiceInstance {
+        let host = if endpoints.iter().any(|e| unspecified(e).is_some()) {
+            self.host.clone().unwrap_or_else(|| {
+                local_ip().map(|ip| ip.to_string()).unwrap_or_else(|| {
+                    log::warn!("registration: no host configured nor found, registering 127.0.0.1");
+                    "127.0.0.1".to_string()
+                })
+            })
+        } else {
+            String::new()
+        };
+        // Sorted so the instance, and its default id, don't depend on the order plugins run in.
+        let mut endpoints: Vec<String> = endpoints.iter().map(|e| advertise(e, &host)).collect();
+        endpoints.sort();
+        let id = self.id.clone().unwrap_or_else(|| {
+            let addr = endpoints[0].split_once("://").map_or(endpoints[0].as_str(), |(_, addr)| addr);
+            format!("{}-{}", self.name, addr)
+        });
+        ServiceInstance {
+            id,
+            name: self.name.clone(),
+            version: self.version.clone(),
+            endpoints,
+            metadata: self.metadata.clone(),
+        }
+    }
+}
+
+/// The port of an endpoint listening on all interfaces, like `grpc://0.0.0.0:9000`.
+fn unspecified(endpoint: &str) -> Option<u16> {
+    let (_, addr) = endpoint.split_once("://")?;
+    let addr: SocketAddr = addr.parse().ok()?;
+    addr.ip().is_unspecified().then_some(addr.port())
+}
+
+/// `endpoint` with `host` in place of an unspecified address.
+fn advertise(endpoint: &str, host: &str) -> String {
+    let (Some(port), Some((scheme, _))) = (unspecified(endpoint), endpoint.split_once("://")) else {
+        return endpoint.to_string();
+    };
+    match host.parse::<IpAddr>() {
+        Ok(ip) => format!("{}://{}", scheme, SocketAddr::new(ip, port)),
+        Err(_) => format!("{}://{}:{}", scheme, host, port),
+    }
+}
+
+/// The address of the interface of the default route, found without sending anything.
+fn local_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("8.8.8.8:80").ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
+
+/// Resolves once `failed` is set, never if the plugin ended without failing.
+async fn failure(mut failed: watch::Receiver<bool>) {
+    let set = failed.wait_for(|failed| *failed).await.is_ok();
+    if !set {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// The instance of the App while it is registered.
+pub(crate) struct Registered {
+    registrar: Arc<dyn Registrar>,
+    conf: Registration,
+    instance: Mutex<Option<ServiceInstance>>,
+}
+
+impl Registered {
+    pub(crate) fn new(registrar: Arc<dyn Registrar>, conf: Registration) -> Self {
+        Self {
+            registrar,
+            conf,
+            instance: Mutex::new(None),
+        }
+    }
+
+    /// Register once all `plugins` listen, then send heartbeats until shutdown. Nothing is
+    /// registered if a plugin fails first, as told by its flag.
+    pub(crate) async fn run(&self, plugins: Vec<(PluginRef, watch::Receiver<bool>)>, guard: ShutdownGuard) {
+        let listening = join_all(plugins.into_iter().map(|(plugin, failed)| async move {
+            tokio::select! {
+                endpoint = plugin.listening() => Ok(endpoint),
+                _ = failure(failed) => Err(plugin.name().to_string()),
+            }
+        }));
+        let endpoints = tokio::select! {
+            _ = guard.cancelled() => return,
+            endpoints = listening => endpoints.into_iter().collect::<Result<Vec<_>, _>>(),
+        };
+        let endpoints: Vec<String> = match endpoints {
+            Ok(endpoints) => endpoints.into_iter().flatten().collect(),
+            Err(plugin) => {
+                log::error!("registration: {} failed before listening, {} not registered", plugin, self.conf.name);
+                return;
+            }
+        };
+        if endpoints.is_empty() {
+            log::warn!("registration: no plugin serves an endpoint, {} not registered", self.conf.name);
+            return;
+        }
+        let instance = self.conf.instance(endpoints);
+        // Stored first, so a shutdown right now still deregisters it.
+        *self.instance.lock().unwrap() = Some(instance.clone());
+        self.register(&instance).await;
+
+        let Some(interval) = self.conf.heartbeat else {
+            return;
+        };
+        loop {
+            tokio::select! {
+                _ = guard.cancelled() => return,
+                _ = tokio::time::sleep(interval) => {}
+            }
+            if let Err(e) = self.registrar.heartbeat(&instance).await {
+                log::warn!("registration: heartbeat of {} failed, registering again: {:?}", instance.id, e);
+                self.register(&instance).await;
+            }
+        }
+    }
+
+    async fn register(&self, instance: &ServiceInstance) {
+        match self.registrar.register(instance).await {
+            Ok(()) => log::info!("registration: registered {} at {:?}", instance.id, instance.endpoints),
+            Err(e) => log::error!("registration: registering {} failed: {:?}", instance.id, e),
+        }
+    }
+
+    /// Leave the registry, if registered, giving up after [`DEREGISTER_TIMEOUT`] so an
+    /// unreachable registry doesn't hold the shutdown back.
+    pub(crate) async fn deregister(&self) {
+        let Some(instance) = self.instance.lock().unwrap().take() else {
+            return;
+        };
+        match tokio::time::timeout(DEREGISTER_TIMEOUT, self.registrar.deregister(&instance)).await {
+            Ok(Ok(())) => log::info!("registration: deregistered {}", instance.id),
+            Ok(Err(e)) => log::error!("registration: deregistering {} failed: {:?}", instance.id, e),
+            Err(_) => log::error!("registration: deregistering {} timed out", instance.id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_instance() {
+        let conf = Registration {
+            name: "greeter".to_string(),
+            host: Some("10.0.0.7".to_string()),
+            ..Default::default()
+        };
+        let instance = conf.instance(vec![
+            "grpc://0.0.0.0:9000".to_string(),
+            "http://127.0.0.1:8000".to_string(),
+            "https://[::]:8443".to_string(),
+        ]);
+        assert_eq!(instance.id, "greeter-10.0.0.7:9000");
+        assert_eq!(instance.endpoints, vec!["grpc://10.0.0.7:9000", "http://127.0.0.1:8000", "https://10.0.0.7:8443"]);
+        assert_eq!(advertise("grpc://[::]:9000", "fd00::7"), "grpc://[fd00::7]:9000");
+        assert_eq!(advertise("grpc://0.0.0.0:9000", "greeter.local"), "grpc://greeter.local:9000");
+
+        let shuffled = conf.instance(vec![
+            "https://[::]:8443".to_string(),
+            "grpc://0.0.0.0:9000".to_string(),
+            "http://127.0.0.1:8000".to_string(),
+        ]);
+        assert_eq!((shuffled.id, shuffled.endpoints), (instance.id, instance.endpoints));
+    }
+}
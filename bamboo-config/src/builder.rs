@@ -35,7 +35,7 @@ use crate::{config::Config, path::Expression, source::Source, value::Value};
 /// # Examples
 ///
 /// ```rust
-/// # use config::*;
+/// # use bamboo_config::*;
 /// # use std::error::Error;
 /// # fn main() -> Result<(), Box<dyn Error>> {
 /// let mut builder = Config::builder()
@@ -62,7 +62,7 @@ use crate::{config::Config, path::Expression, source::Source, value::Value};
 /// Calls can be not chained as well
 /// ```rust
 /// # use std::error::Error;
-/// # use config::*;
+/// # use bamboo_config::*;
 /// # fn main() -> Result<(), Box<dyn Error>> {
 /// let mut builder = Config::builder();
 /// builder = builder.set_default("default", "1")?;
@@ -76,14 +76,14 @@ use crate::{config::Config, path::Expression, source::Source, value::Value};
 /// Calling [`Config::builder`](Config::builder) yields builder in the default state.
 /// If having an asynchronous state as the initial state is desired, _turbofish_ notation needs to be used.
 /// ```rust
-/// # use config::{*, builder::AsyncState};
+/// # use bamboo_config::{*, builder::AsyncState};
 /// let mut builder = ConfigBuilder::<AsyncState>::default();
 /// ```
 ///
 /// If for some reason acquiring builder in default state is required without calling [`Config::builder`](Config::builder)
 /// it can also be achieved.
 /// ```rust
-/// # use config::{*, builder::DefaultState};
+/// # use bamboo_config::{*, builder::DefaultState};
 /// let mut builder = ConfigBuilder::<DefaultState>::default();
 /// ```
 #[derive(Debug, Clone, Default)]
@@ -4,8 +4,9 @@ use crate::{
     config::Config,
     file::File,
     env::Environment,
-    nacos::Nacos,
 };
+#[cfg(feature = "nacos")]
+use crate::nacos::Nacos;
 
 
 #[derive(Debug, Parser)]
@@ -46,6 +47,7 @@ pub fn load_env<'de, T>(path: &str) -> Result<T>
     Ok(bootstrap)
 }
 
+#[cfg(feature = "nacos")]
 pub fn load_nacos<'de, T>(path: &str) -> Result<T>
     where
         T: serde::Deserialize<'de>,
@@ -0,0 +1,172 @@
+//! HTTP client of the Consul agent API, shared by the configuration source and the service
+//! registry.
+
+use std::{collections::HashMap, time::Duration};
+
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    error::Result,
+    remote::{foreign, http_client, send},
+};
+
+/// A service registered with the local agent.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct AgentService {
+    #[serde(rename = "ID")]
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub address: String,
+    #[serde(default)]
+    pub port: u16,
+    #[serde(default)]
+    pub meta: HashMap<String, String>,
+    /// Addresses by scheme, like `grpc`.
+    #[serde(default)]
+    pub tagged_addresses: HashMap<String, TaggedAddress>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub check: Option<AgentCheck>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct TaggedAddress {
+    pub address: String,
+    pub port: u16,
+}
+
+/// A TTL check, critical unless passed within `ttl`.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct AgentCheck {
+    #[serde(rename = "CheckID")]
+    pub check_id: String,
+    #[serde(rename = "TTL")]
+    pub ttl: String,
+    pub deregister_critical_service_after: String,
+}
+
+/// A service of the catalog, as listed by the health endpoint.
+#[derive(Debug, Default, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct CatalogService {
+    #[serde(rename = "ID")]
+    pub id: String,
+    #[serde(rename = "Service")]
+    pub name: String,
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub address: String,
+    #[serde(default)]
+    pub port: u16,
+    #[serde(default)]
+    pub meta: Option<HashMap<String, String>>,
+    #[serde(default)]
+    pub tagged_addresses: Option<HashMap<String, TaggedAddress>>,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct ServiceEntry {
+    pub service: CatalogService,
+}
+
+/// Client of one Consul agent, cheap to clone.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    address: Url,
+    token: Option<String>,
+}
+
+impl Client {
+    /// A client of the agent at `address`, like `http://127.0.0.1:8500`.
+    pub fn new(address: &str) -> Result<Self> {
+        Ok(Self {
+            http: http_client()?,
+            address: Url::parse(address).map_err(foreign)?,
+            token: None,
+        })
+    }
+
+    /// Send `token` as the ACL token of every request.
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    fn request(&self, method: Method, path: &str) -> Result<RequestBuilder> {
+        let url = self.address.join(path).map_err(foreign)?;
+        let req = self.http.request(method, url);
+        Ok(match &self.token {
+            Some(token) => req.header("X-Consul-Token", token),
+            None => req,
+        })
+    }
+
+    /// The raw value of `key`, `None` when missing.
+    pub async fn kv(&self, key: &str) -> Result<Option<String>> {
+        let req = self.request(Method::GET, &format!("/v1/kv/{}", key.trim_start_matches('/')))?;
+        let res = send("consul", req.query(&[("raw", "true")])).await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        res.text().await.map(Some).map_err(foreign)
+    }
+
+    /// Register `service`, replacing a service with the same id.
+    pub async fn register_service(&self, service: &AgentService) -> Result<()> {
+        let body = serde_json::to_vec(service).map_err(foreign)?;
+        let req = self.request(Method::PUT, "/v1/agent/service/register")?;
+        send("consul", req.header("content-type", "application/json").body(body)).await?;
+        Ok(())
+    }
+
+    pub async fn deregister_service(&self, id: &str) -> Result<()> {
+        send("consul", self.request(Method::PUT, &format!("/v1/agent/service/deregister/{}", id))?).await?;
+        Ok(())
+    }
+
+    /// Mark the TTL check `check_id` as passing.
+    pub async fn pass_check(&self, check_id: &str) -> Result<()> {
+        let res = send("consul", self.request(Method::PUT, &format!("/v1/agent/check/pass/{}", check_id))?).await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Err(foreign(format!("consul: unknown check {}", check_id)));
+        }
+        Ok(())
+    }
+
+    /// The passing instances of `name` and the index of the answer. With an `index` from a
+    /// previous answer, waits at most `wait` for them to change.
+    pub async fn health_service(&self, name: &str, index: u64, wait: Duration) -> Result<(Vec<ServiceEntry>, u64)> {
+        let mut req = self
+            .request(Method::GET, &format!("/v1/health/service/{}", name))?
+            .query(&[("passing", "true")]);
+        if index > 0 {
+            req = req
+                .query(&[("index", index.to_string()), ("wait", format!("{}ms", wait.as_millis()))])
+                // The agent answers after `wait` plus up to a sixteenth of it.
+                .timeout(wait + wait / 8 + Duration::from_secs(5));
+        }
+        let res = send("consul", req).await?;
+        let index = res
+            .headers()
+            .get("X-Consul-Index")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok((Vec::new(), index));
+        }
+        let body = res.bytes().await.map_err(foreign)?;
+        let entries = serde_json::from_slice(&body).map_err(foreign)?;
+        Ok((entries, index))
+    }
+}
@@ -1,3 +1,4 @@
+pub mod client;
 mod format;
 pub mod source;
 
@@ -108,3 +109,45 @@ impl<T, F> Source for Consul<T, F>
             .map_err(|cause| ConfigError::FileParse { uri, cause })
     }
 }
+
+#[cfg(feature = "async")]
+#[async_trait::async_trait]
+impl crate::AsyncSource for Consul<source::remote::Remote, ConsulFormat> {
+    async fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+        let fetched = self.source.fetch().await;
+        crate::remote::parse(self.source.uri(), fetched, self.required, self.format, &format::ALL_EXTENSIONS)
+    }
+}
+
+#[cfg(all(test, feature = "async", feature = "yaml"))]
+mod tests {
+    use axum::{extract::Path, http::StatusCode, routing::get, Router};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::AsyncSource;
+
+    async fn kv(Path(key): Path<String>) -> Result<&'static str, StatusCode> {
+        match key.as_str() {
+            "app/config.yaml" => Ok("http:\n  address: 0.0.0.0:8000\n"),
+            _ => Err(StatusCode::NOT_FOUND),
+        }
+    }
+
+    #[tokio::test]
+    async fn valid_remote() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/v1/kv/*key", get(kv));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let consul = Consul::with_name(&format!("http://{}/app/config.yaml", addr));
+        let map = AsyncSource::collect(&consul).await.unwrap();
+        let http = map["http"].clone().into_table().unwrap();
+        assert_eq!(http["address"].clone().into_string().unwrap(), "0.0.0.0:8000");
+
+        let missing = Consul::with_name(&format!("http://{}/app/missing.yaml", addr));
+        assert!(AsyncSource::collect(&missing).await.is_err());
+        assert!(AsyncSource::collect(&missing.required(false)).await.unwrap().is_empty());
+    }
+}
@@ -4,6 +4,7 @@ use std::path::PathBuf;
 use crate::{
     format::Format,
     consul::{
+        client::Client,
         ConsulStoredFormat,
         source::ConsulSource,
         source::ConsulSourceResult,
@@ -21,6 +22,23 @@ impl Remote {
     pub fn new(u: url::Url) -> Self {
         Self { u }
     }
+
+    /// The URL of the key, like `http://127.0.0.1:8500/app/config.yaml?token=...`.
+    pub fn uri(&self) -> String {
+        self.u[..url::Position::AfterPath].to_string()
+    }
+
+    /// The key in the KV store, the path of the URL.
+    pub fn key(&self) -> &str {
+        self.u.path().trim_start_matches('/')
+    }
+
+    /// The value of the key, `None` when missing.
+    pub async fn fetch(&self) -> crate::error::Result<Option<String>> {
+        let token = self.u.query_pairs().find(|(k, _)| k == "token").map(|(_, v)| v.into_owned());
+        let client = Client::new(&self.u[..url::Position::BeforePath])?.with_token(token);
+        client.kv(self.key()).await
+    }
 }
 
 impl<F> ConsulSource<F> for Remote
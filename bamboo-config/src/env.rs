@@ -59,13 +59,13 @@ pub struct Environment {
     /// ## Example
     ///
     /// ```rust
-    /// # use config::{Environment, Config};
+    /// # use bamboo_config::{Environment, Config};
     /// # use serde::Deserialize;
     /// # use std::collections::HashMap;
     /// # use std::convert::TryInto;
     /// #
     /// #[test]
-    /// fn test_config() -> Result<(), config::ConfigError> {
+    /// fn test_config() -> Result<(), bamboo_config::ConfigError> {
     ///   #[derive(Clone, Debug, Deserialize)]
     ///   struct MyConfig {
     ///     pub my_string: String,
@@ -190,13 +190,13 @@ impl Environment {
     /// ## Example
     ///
     /// ```rust
-    /// # use config::{Environment, Config};
+    /// # use bamboo_config::{Environment, Config};
     /// # use serde::Deserialize;
     /// # use std::collections::HashMap;
     /// # use std::convert::TryInto;
     /// #
     /// #[test]
-    /// fn test_config() -> Result<(), config::ConfigError> {
+    /// fn test_config() -> Result<(), bamboo_config::ConfigError> {
     ///   #[derive(Clone, Debug, Deserialize)]
     ///   struct MyConfig {
     ///     pub my_string: String,
@@ -32,8 +32,12 @@ mod path;
 mod ser;
 mod source;
 mod value;
-mod nacos;
-mod consul;
+#[cfg(feature = "nacos")]
+pub mod nacos;
+#[cfg(feature = "consul")]
+pub mod consul;
+#[cfg(any(feature = "consul", feature = "nacos"))]
+mod remote;
 pub mod clap;
 
 pub use crate::builder::ConfigBuilder;
@@ -0,0 +1,197 @@
+//! HTTP client of the Nacos open API, shared by the configuration source and the service
+//! registry.
+
+use std::collections::HashMap;
+
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    error::Result,
+    remote::{foreign, http_client, send},
+};
+
+pub const DEFAULT_GROUP: &str = "DEFAULT_GROUP";
+
+/// An instance of a service in the naming service.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Instance {
+    #[serde(default)]
+    pub instance_id: String,
+    pub ip: String,
+    pub port: u16,
+    #[serde(default)]
+    pub service_name: String,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    #[serde(default = "default_true")]
+    pub healthy: bool,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct InstanceList {
+    #[serde(default)]
+    hosts: Vec<Instance>,
+}
+
+/// Heartbeat of an ephemeral instance, as the `beat` parameter expects it.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Beat<'a> {
+    ip: &'a str,
+    port: u16,
+    service_name: &'a str,
+    weight: f64,
+    metadata: &'a HashMap<String, String>,
+}
+
+/// Client of one Nacos server for one namespace, cheap to clone.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    address: Url,
+    namespace: String,
+    access_token: Option<String>,
+}
+
+impl Client {
+    /// A client of the server at `address`, like `http://127.0.0.1:8848`, in the public
+    /// namespace.
+    pub fn new(address: &str) -> Result<Self> {
+        Ok(Self {
+            http: http_client()?,
+            address: Url::parse(address).map_err(foreign)?,
+            namespace: String::new(),
+            access_token: None,
+        })
+    }
+
+    pub fn with_namespace(mut self, namespace: &str) -> Self {
+        self.namespace = namespace.to_string();
+        self
+    }
+
+    /// Send `token` as the `accessToken` of every request.
+    pub fn with_access_token(mut self, token: Option<String>) -> Self {
+        self.access_token = token;
+        self
+    }
+
+    fn request(&self, method: Method, path: &str) -> Result<RequestBuilder> {
+        let url = self.address.join(path).map_err(foreign)?;
+        let req = self.http.request(method, url);
+        Ok(match &self.access_token {
+            Some(token) => req.query(&[("accessToken", token)]),
+            None => req,
+        })
+    }
+
+    /// The content of the configuration `data_id` in `group`, `None` when missing.
+    pub async fn config(&self, data_id: &str, group: &str) -> Result<Option<String>> {
+        let req = self.request(Method::GET, "/nacos/v1/cs/configs")?.query(&[
+            ("dataId", data_id),
+            ("group", group),
+            ("tenant", &self.namespace),
+        ]);
+        let res = send("nacos", req).await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        res.text().await.map(Some).map_err(foreign)
+    }
+
+    fn naming(&self, service: &str, group: &str) -> [(&'static str, String); 3] {
+        [
+            ("serviceName", service.to_string()),
+            ("groupName", group.to_string()),
+            ("namespaceId", self.namespace.clone()),
+        ]
+    }
+
+    /// Register the ephemeral `instance` of `service`, kept while it sends heartbeats.
+    pub async fn register_instance(&self, service: &str, group: &str, instance: &Instance) -> Result<()> {
+        let metadata = serde_json::to_string(&instance.metadata).map_err(foreign)?;
+        let req = self
+            .request(Method::POST, "/nacos/v1/ns/instance")?
+            .query(&self.naming(service, group))
+            .query(&[
+                ("instanceId", instance.instance_id.clone()),
+                ("ip", instance.ip.clone()),
+                ("port", instance.port.to_string()),
+                ("weight", instance.weight.to_string()),
+                ("enabled", instance.enabled.to_string()),
+                ("healthy", instance.healthy.to_string()),
+                ("ephemeral", "true".to_string()),
+                ("metadata", metadata),
+            ]);
+        send("nacos", req).await?;
+        Ok(())
+    }
+
+    pub async fn deregister_instance(&self, service: &str, group: &str, instance: &Instance) -> Result<()> {
+        let req = self
+            .request(Method::DELETE, "/nacos/v1/ns/instance")?
+            .query(&self.naming(service, group))
+            .query(&[
+                ("ip", instance.ip.clone()),
+                ("port", instance.port.to_string()),
+                ("ephemeral", "true".to_string()),
+            ]);
+        send("nacos", req).await?;
+        Ok(())
+    }
+
+    /// Send the heartbeat of `instance`, failing when the server no longer knows it.
+    pub async fn beat(&self, service: &str, group: &str, instance: &Instance) -> Result<()> {
+        let beat = Beat {
+            ip: &instance.ip,
+            port: instance.port,
+            service_name: service,
+            weight: instance.weight,
+            metadata: &instance.metadata,
+        };
+        let req = self
+            .request(Method::PUT, "/nacos/v1/ns/instance/beat")?
+            .query(&self.naming(service, group))
+            .query(&[
+                ("ip", instance.ip.clone()),
+                ("port", instance.port.to_string()),
+                ("ephemeral", "true".to_string()),
+                ("beat", serde_json::to_string(&beat).map_err(foreign)?),
+            ]);
+        let res = send("nacos", req).await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Err(foreign(format!("nacos: unknown instance {}:{}", instance.ip, instance.port)));
+        }
+        Ok(())
+    }
+
+    /// The healthy instances of `service`.
+    pub async fn instances(&self, service: &str, group: &str) -> Result<Vec<Instance>> {
+        let req = self
+            .request(Method::GET, "/nacos/v1/ns/instance/list")?
+            .query(&self.naming(service, group))
+            .query(&[("healthyOnly", "true")]);
+        let res = send("nacos", req).await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        let body = res.bytes().await.map_err(foreign)?;
+        let list: InstanceList = serde_json::from_slice(&body).map_err(foreign)?;
+        Ok(list.hosts)
+    }
+}
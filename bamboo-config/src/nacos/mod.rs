@@ -1,3 +1,4 @@
+pub mod client;
 mod format;
 pub mod source;
 
@@ -109,3 +110,45 @@ impl<T, F> Source for Nacos<T, F>
             .map_err(|cause| ConfigError::FileParse { uri, cause })
     }
 }
+
+#[cfg(feature = "async")]
+#[async_trait::async_trait]
+impl crate::AsyncSource for Nacos<source::remote::Remote, NacosFormat> {
+    async fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+        let fetched = self.source.fetch().await;
+        crate::remote::parse(self.source.uri(), fetched, self.required, self.format, &format::ALL_EXTENSIONS)
+    }
+}
+
+#[cfg(all(test, feature = "async", feature = "toml"))]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::{extract::Query, http::StatusCode, routing::get, Router};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::AsyncSource;
+
+    async fn configs(Query(params): Query<HashMap<String, String>>) -> Result<&'static str, StatusCode> {
+        match (params["group"].as_str(), params["dataId"].as_str(), params["tenant"].as_str()) {
+            ("app", "config.toml", "dev") => Ok("[http]\naddress = \"0.0.0.0:8000\"\n"),
+            _ => Err(StatusCode::NOT_FOUND),
+        }
+    }
+
+    #[tokio::test]
+    async fn valid_remote() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/nacos/v1/cs/configs", get(configs));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let nacos = Nacos::with_name(&format!("http://{}/app/config.toml?namespace=dev", addr));
+        let http = AsyncSource::collect(&nacos).await.unwrap()["http"].clone().into_table().unwrap();
+        assert_eq!(http["address"].clone().into_string().unwrap(), "0.0.0.0:8000");
+
+        let other = Nacos::with_name(&format!("http://{}/app/config.toml", addr));
+        assert!(AsyncSource::collect(&other).await.is_err());
+    }
+}
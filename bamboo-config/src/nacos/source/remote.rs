@@ -2,6 +2,7 @@ use std::error::Error;
 use std::path::PathBuf;
 
 use crate::nacos::{
+    client::{Client, DEFAULT_GROUP},
     Format, NacosSource, NacosStoredFormat, source::NacosSourceResult,
 };
 
@@ -16,6 +17,31 @@ impl Remote {
     pub fn new(u: url::Url) -> Self {
         Self { u }
     }
+
+    /// The URL of the configuration, like `http://127.0.0.1:8848/DEFAULT_GROUP/app.yaml`.
+    pub fn uri(&self) -> String {
+        self.u[..url::Position::AfterPath].to_string()
+    }
+
+    /// The group and data id of the configuration, from the path `/{group}/{data_id}` or
+    /// `/{data_id}` in the default group.
+    pub fn data_id(&self) -> (&str, &str) {
+        match self.u.path().trim_start_matches('/').split_once('/') {
+            Some((group, data_id)) => (group, data_id),
+            None => (DEFAULT_GROUP, self.u.path().trim_start_matches('/')),
+        }
+    }
+
+    /// The content of the configuration, `None` when missing. The `namespace` and
+    /// `accessToken` query parameters are passed on.
+    pub async fn fetch(&self) -> crate::error::Result<Option<String>> {
+        let query = |name: &str| self.u.query_pairs().find(|(k, _)| k == name).map(|(_, v)| v.into_owned());
+        let client = Client::new(&self.u[..url::Position::BeforePath])?
+            .with_namespace(&query("namespace").unwrap_or_default())
+            .with_access_token(query("accessToken"));
+        let (group, data_id) = self.data_id();
+        client.config(data_id, group).await
+    }
 }
 
 impl<F> NacosSource<F> for Remote
@@ -0,0 +1,75 @@
+//! Helpers shared by the Consul and Nacos HTTP clients and configuration sources.
+
+use std::{collections::HashMap, hash::Hash, time::Duration};
+
+use reqwest::{RequestBuilder, Response, StatusCode};
+
+use crate::{
+    error::{ConfigError, Result},
+    format::Format,
+    map::Map,
+    value::Value,
+};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whole requests time out after it, unless they set their own like Consul blocking queries.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub(crate) fn foreign<E: Into<Box<dyn std::error::Error + Send + Sync>>>(e: E) -> ConfigError {
+    ConfigError::Foreign(e.into())
+}
+
+/// The HTTP client of a server, failing instead of hanging when it doesn't answer.
+pub(crate) fn http_client() -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(TIMEOUT)
+        .build()
+        .map_err(foreign)
+}
+
+/// Send `req` to `server`, failing on error statuses other than 404 with the body it answered.
+pub(crate) async fn send(server: &str, req: RequestBuilder) -> Result<Response> {
+    let res = req.send().await.map_err(foreign)?;
+    let status = res.status();
+    if status.is_success() || status == StatusCode::NOT_FOUND {
+        return Ok(res);
+    }
+    let body = res.text().await.unwrap_or_default();
+    Err(foreign(format!("{}: {} {}", server, status, body.trim())))
+}
+
+/// Parse the `fetched` contents of `uri` with `format`, or the format of its extension in
+/// `extensions`. Missing or unreachable contents are empty unless `required`.
+pub(crate) fn parse<F>(
+    uri: String,
+    fetched: Result<Option<String>>,
+    required: bool,
+    format: Option<F>,
+    extensions: &HashMap<F, Vec<&'static str>>,
+) -> Result<Map<String, Value>>
+    where F: Format + Copy + Eq + Hash,
+{
+    let contents = match fetched {
+        Ok(Some(contents)) => contents,
+        Ok(None) if !required => return Ok(Map::new()),
+        Ok(None) => return Err(ConfigError::NotFound(uri)),
+        Err(_) if !required => return Ok(Map::new()),
+        Err(error) => return Err(error),
+    };
+    // Without a format, guess it from the extension like files.
+    let format = format.or_else(|| {
+        let extension = uri.rsplit_once('.').map(|(_, extension)| extension)?;
+        extensions
+            .iter()
+            .find(|(_, names)| names.contains(&extension))
+            .map(|(format, _)| *format)
+    });
+    let Some(format) = format else {
+        return Err(ConfigError::Message(format!("{} is not of a registered format", uri)));
+    };
+    format
+        .parse(Some(&uri), &contents)
+        .map_err(|cause| ConfigError::FileParse { uri: Some(uri), cause })
+}
@@ -0,0 +1,74 @@
+//! Audit records of security decisions, logged to the [`TARGET`] target so they can be
+//! routed apart from the application logs.
+
+use std::fmt::{Display, Formatter};
+
+/// Log target of audit records.
+pub const TARGET: &str = "audit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Allow,
+    Deny,
+}
+
+impl Display for Decision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Decision::Allow => f.write_str("allow"),
+            Decision::Deny => f.write_str("deny"),
+        }
+    }
+}
+
+/// Who did what to which resource, and whether it was allowed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event<'a> {
+    /// The authenticated caller, empty for anonymous callers.
+    pub subject: &'a str,
+    /// e.g. the HTTP method.
+    pub action: &'a str,
+    /// e.g. the request path or the gRPC method.
+    pub resource: &'a str,
+    pub decision: Decision,
+    pub reason: &'a str,
+}
+
+impl Display for Event<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "decision={} subject={:?} action={} resource={} reason={:?}",
+            self.decision, self.subject, self.action, self.resource, self.reason
+        )
+    }
+}
+
+/// Log `event`, denials at warn level and the rest at info.
+pub fn record(event: &Event) {
+    let level = match event.decision {
+        Decision::Allow => log::Level::Info,
+        Decision::Deny => log::Level::Warn,
+    };
+    log::log!(target: TARGET, level, "{}", event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_event_display() {
+        let event = Event {
+            subject: "alice",
+            action: "DELETE",
+            resource: "/v1/users/7",
+            decision: Decision::Deny,
+            reason: "missing role admin",
+        };
+        assert_eq!(
+            event.to_string(),
+            r#"decision=deny subject="alice" action=DELETE resource=/v1/users/7 reason="missing role admin""#
+        );
+    }
+}
@@ -2,6 +2,8 @@
 
 extern crate log;
 
+pub mod audit;
+
 pub fn add(left: usize, right: usize) -> usize {
     left + right
 }
@@ -0,0 +1,40 @@
+//! Process-wide registry of encoded file descriptor sets, served by gRPC reflection.
+//!
+//! Generated code includes its sets with `tonic_build::configure().file_descriptor_set_path`
+//! and registers them once before serving:
+//!
+//! ```ignore
+//! bamboo_prost::descriptor::register_file_descriptor_set(
+//!     tonic::include_file_descriptor_set!("greeter_descriptor"),
+//! );
+//! ```
+
+use std::sync::Mutex;
+
+static SETS: Mutex<Vec<&'static [u8]>> = Mutex::new(Vec::new());
+
+/// Register the encoded `FileDescriptorSet` `set`, registering it again has no effect.
+pub fn register_file_descriptor_set(set: &'static [u8]) {
+    let mut sets = SETS.lock().unwrap();
+    if !sets.contains(&set) {
+        sets.push(set);
+    }
+}
+
+/// The registered sets, in registration order.
+pub fn file_descriptor_sets() -> Vec<&'static [u8]> {
+    SETS.lock().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_register() {
+        static SET: &[u8] = b"";
+        register_file_descriptor_set(SET);
+        register_file_descriptor_set(SET);
+        assert_eq!(file_descriptor_sets().iter().filter(|s| **s == SET).count(), 1);
+    }
+}
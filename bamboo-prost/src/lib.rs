@@ -1,17 +1,5 @@
 pub use prost::{Message, Name};
 pub use prost_types::Any;
+pub use descriptor::{file_descriptor_sets, register_file_descriptor_set};
 
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+pub mod descriptor;
@@ -0,0 +1,312 @@
+//! Consul backend: instances are agent services with a TTL check, found through blocking
+//! queries of the health endpoint.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use bamboo_config::consul::client::{AgentCheck, AgentService, CatalogService, Client, TaggedAddress};
+use bamboo_status::status::AnyResult;
+
+use crate::{join_endpoint, split_endpoint, Discovery, Registrar, ServiceInstance, Watcher};
+
+/// Addresses Consul tags services with itself, not endpoints.
+const AGENT_ADDRESSES: [&str; 6] = ["lan", "lan_ipv4", "lan_ipv6", "wan", "wan_ipv4", "wan_ipv6"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Consul {
+    /// Address of the agent, like `http://127.0.0.1:8500`.
+    pub address: String,
+    /// ACL token.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Instances turn critical without a heartbeat within this time, send them more often.
+    #[serde(default = "default_ttl", with = "humantime_serde")]
+    pub ttl: Duration,
+    /// Critical instances are removed after this time.
+    #[serde(default = "default_deregister_after", with = "humantime_serde")]
+    pub deregister_after: Duration,
+    /// Longest wait of a watch for changes before asking again.
+    #[serde(default = "default_wait", with = "humantime_serde")]
+    pub wait: Duration,
+}
+
+fn default_ttl() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_deregister_after() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_wait() -> Duration {
+    Duration::from_secs(30)
+}
+
+impl Consul {
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            token: None,
+            ttl: default_ttl(),
+            deregister_after: default_deregister_after(),
+            wait: default_wait(),
+        }
+    }
+}
+
+/// Registers and finds instances through a Consul agent.
+#[derive(Debug, Clone)]
+pub struct ConsulRegistry {
+    client: Client,
+    conf: Consul,
+}
+
+impl ConsulRegistry {
+    pub fn new(conf: &Consul) -> AnyResult<Self> {
+        Ok(Self {
+            client: Client::new(&conf.address)?.with_token(conf.token.clone()),
+            conf: conf.clone(),
+        })
+    }
+
+    fn check_id(instance: &ServiceInstance) -> String {
+        format!("service:{}", instance.id)
+    }
+
+    /// The agent service of `instance`, its endpoints tagged by scheme.
+    fn service(&self, instance: &ServiceInstance) -> AgentService {
+        let mut service = AgentService {
+            id: instance.id.clone(),
+            name: instance.name.clone(),
+            tags: vec![format!("version={}", instance.version)],
+            meta: instance.metadata.clone(),
+            check: Some(AgentCheck {
+                check_id: Self::check_id(instance),
+                ttl: format!("{}s", self.conf.ttl.as_secs().max(1)),
+                deregister_critical_service_after: format!("{}s", self.conf.deregister_after.as_secs().max(1)),
+            }),
+            ..Default::default()
+        };
+        for (scheme, host, port) in instance.endpoints.iter().filter_map(|e| split_endpoint(e)) {
+            if service.address.is_empty() {
+                service.address = host.to_string();
+                service.port = port;
+            }
+            let address = TaggedAddress {
+                address: host.to_string(),
+                port,
+            };
+            service.tagged_addresses.insert(scheme.to_string(), address);
+        }
+        service
+    }
+}
+
+/// The instance of a catalog service registered by [`ConsulRegistry`], or by others.
+fn instance(service: CatalogService) -> ServiceInstance {
+    let mut endpoints: Vec<String> = service
+        .tagged_addresses
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(scheme, _)| !AGENT_ADDRESSES.contains(&scheme.as_str()))
+        .map(|(scheme, addr)| join_endpoint(&scheme, &addr.address, addr.port))
+        .collect();
+    endpoints.sort();
+    if endpoints.is_empty() && !service.address.is_empty() {
+        endpoints.push(join_endpoint("http", &service.address, service.port));
+    }
+    let tags = service.tags.unwrap_or_default();
+    let version = tags.iter().find_map(|tag| tag.strip_prefix("version=")).unwrap_or_default();
+    ServiceInstance {
+        id: service.id,
+        name: service.name,
+        version: version.to_string(),
+        endpoints,
+        metadata: service.meta.unwrap_or_default(),
+    }
+}
+
+#[async_trait]
+impl Registrar for ConsulRegistry {
+    async fn register(&self, instance: &ServiceInstance) -> AnyResult<()> {
+        self.client.register_service(&self.service(instance)).await?;
+        // Pass the check right away, instead of being critical until the first heartbeat.
+        self.client.pass_check(&Self::check_id(instance)).await?;
+        Ok(())
+    }
+
+    async fn deregister(&self, instance: &ServiceInstance) -> AnyResult<()> {
+        Ok(self.client.deregister_service(&instance.id).await?)
+    }
+
+    async fn heartbeat(&self, instance: &ServiceInstance) -> AnyResult<()> {
+        Ok(self.client.pass_check(&Self::check_id(instance)).await?)
+    }
+}
+
+#[async_trait]
+impl Discovery for ConsulRegistry {
+    async fn get_service(&self, name: &str) -> AnyResult<Vec<ServiceInstance>> {
+        let (entries, _) = self.client.health_service(name, 0, self.conf.wait).await?;
+        Ok(entries.into_iter().map(|entry| instance(entry.service)).collect())
+    }
+
+    /// Follow the passing instances with blocking queries, until the watcher is dropped.
+    async fn watch(&self, name: &str) -> AnyResult<Watcher> {
+        let (entries, mut index) = self.client.health_service(name, 0, self.conf.wait).await?;
+        let instances = entries.into_iter().map(|entry| instance(entry.service)).collect();
+        let (tx, rx) = watch::channel(instances);
+        let (client, wait, name) = (self.client.clone(), self.conf.wait, name.to_string());
+        tokio::spawn(async move {
+            loop {
+                let query = client.health_service(&name, index.max(1), wait);
+                let res = tokio::select! {
+                    _ = tx.closed() => return,
+                    res = query => res,
+                };
+                match res {
+                    Ok((entries, next)) => {
+                        // An index going backwards means the agent restarted, start over.
+                        index = if next < index { 0 } else { next };
+                        let instances: Vec<_> = entries.into_iter().map(|entry| instance(entry.service)).collect();
+                        tx.send_if_modified(|current| {
+                            let changed = *current != instances;
+                            *current = instances;
+                            changed
+                        });
+                    }
+                    Err(e) => {
+                        log::warn!("consul: watching {} failed: {:?}", name, e);
+                        tokio::select! {
+                            _ = tx.closed() => return,
+                            _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+                        }
+                    }
+                }
+            }
+        });
+        Ok(Watcher::new(rx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        extract::{Path, Query, State},
+        http::{HeaderMap, StatusCode},
+        routing::{get, put},
+        Json, Router,
+    };
+    use serde_json::{json, Value};
+    use tokio::{net::TcpListener, sync::Notify};
+
+    use super::*;
+
+    /// Services by id and the index of the last change.
+    #[derive(Default)]
+    struct Agent {
+        services: Mutex<(HashMap<String, Value>, u64)>,
+        changed: Notify,
+    }
+
+    impl Agent {
+        fn change(&self, f: impl FnOnce(&mut HashMap<String, Value>)) {
+            let mut services = self.services.lock().unwrap();
+            f(&mut services.0);
+            services.1 += 1;
+            self.changed.notify_waiters();
+        }
+    }
+
+    async fn register(State(agent): State<Arc<Agent>>, Json(service): Json<Value>) {
+        let id = service["ID"].as_str().unwrap().to_string();
+        agent.change(|services| {
+            services.insert(id, service);
+        });
+    }
+
+    async fn deregister(State(agent): State<Arc<Agent>>, Path(id): Path<String>) {
+        agent.change(|services| {
+            services.remove(&id);
+        });
+    }
+
+    async fn pass(State(agent): State<Arc<Agent>>, Path(check): Path<String>) -> StatusCode {
+        let services = agent.services.lock().unwrap();
+        let known = services.0.values().any(|s| s["Check"]["CheckID"] == check.as_str());
+        if known { StatusCode::OK } else { StatusCode::INTERNAL_SERVER_ERROR }
+    }
+
+    async fn health(
+        State(agent): State<Arc<Agent>>,
+        Path(name): Path<String>,
+        Query(query): Query<HashMap<String, String>>,
+    ) -> (HeaderMap, Json<Value>) {
+        let index: u64 = query.get("index").and_then(|i| i.parse().ok()).unwrap_or(0);
+        let changed = agent.changed.notified();
+        if index >= agent.services.lock().unwrap().1 {
+            let _ = tokio::time::timeout(Duration::from_secs(1), changed).await;
+        }
+        let services = agent.services.lock().unwrap();
+        let entries: Vec<Value> = services
+            .0
+            .values()
+            .filter(|s| s["Name"] == name.as_str())
+            .map(|s| {
+                let mut service = s.clone();
+                service["Service"] = s["Name"].clone();
+                json!({ "Service": service, "Checks": [] })
+            })
+            .collect();
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Consul-Index", services.1.to_string().parse().unwrap());
+        (headers, Json(Value::Array(entries)))
+    }
+
+    async fn fake_agent() -> SocketAddr {
+        let app = Router::new()
+            .route("/v1/agent/service/register", put(register))
+            .route("/v1/agent/service/deregister/:id", put(deregister))
+            .route("/v1/agent/check/pass/:check", put(pass))
+            .route("/v1/health/service/:name", get(health))
+            .with_state(Arc::new(Agent::default()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    #[tokio::test]
+    async fn valid_consul() {
+        let addr = fake_agent().await;
+        let registry = ConsulRegistry::new(&Consul::new(&format!("http://{}", addr))).unwrap();
+        let greeter = ServiceInstance {
+            id: "greeter-1".to_string(),
+            name: "greeter".to_string(),
+            version: "v1".to_string(),
+            endpoints: vec!["grpc://10.0.0.7:9000".to_string(), "http://10.0.0.7:8000".to_string()],
+            metadata: HashMap::from([("weight".to_string(), "10".to_string())]),
+        };
+        assert!(registry.heartbeat(&greeter).await.is_err());
+
+        let mut watcher = registry.watch("greeter").await.unwrap();
+        assert_eq!(watcher.next().await, Some(Vec::new()));
+        registry.register(&greeter).await.unwrap();
+        registry.heartbeat(&greeter).await.unwrap();
+        assert_eq!(watcher.next().await, Some(vec![greeter.clone()]));
+        assert_eq!(registry.get_service("greeter").await.unwrap(), vec![greeter.clone()]);
+
+        registry.deregister(&greeter).await.unwrap();
+        assert_eq!(watcher.next().await, Some(Vec::new()));
+        assert!(registry.heartbeat(&greeter).await.is_err());
+    }
+}
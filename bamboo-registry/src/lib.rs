@@ -1,7 +1,125 @@
 //! [bamboo-registry](https://go-bamboo.github.io/docs/plugins/bamboo-registry)
 
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use bamboo_status::status::AnyResult;
+
+#[cfg(feature = "consul")]
+pub use consul::{Consul, ConsulRegistry};
+pub use memory::Memory;
+#[cfg(feature = "nacos")]
+pub use nacos::{Nacos, NacosRegistry};
+
+#[cfg(feature = "consul")]
+pub mod consul;
+pub mod memory;
+#[cfg(feature = "nacos")]
+pub mod nacos;
+
+/// Metadata key of the relative weight of an instance, used by weighted load balancing.
+pub const WEIGHT: &str = "weight";
+
+/// One running instance of a service.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ServiceInstance {
+    /// Unique among the instances of the service.
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    /// Where the instance listens, like `http://10.0.0.7:8000` and `grpc://10.0.0.7:9000`.
+    #[serde(default)]
+    pub endpoints: Vec<String>,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+impl ServiceInstance {
+    /// The first endpoint with one of `schemes`, e.g. `["grpc", "grpcs"]`.
+    pub fn endpoint(&self, schemes: &[&str]) -> Option<&str> {
+        self.endpoints.iter().map(String::as_str).find(|endpoint| {
+            endpoint
+                .split_once("://")
+                .is_some_and(|(scheme, _)| schemes.iter().any(|s| s.eq_ignore_ascii_case(scheme)))
+        })
+    }
+
+    /// The [`WEIGHT`] metadata, `None` when missing or invalid.
+    pub fn weight(&self) -> Option<u32> {
+        self.metadata.get(WEIGHT).and_then(|w| w.parse().ok())
+    }
+}
+
+/// The scheme, host and port of an endpoint like `grpc://10.0.0.7:9000` or `http://[::1]:80`.
+#[cfg_attr(not(any(feature = "consul", feature = "nacos")), allow(dead_code))]
+pub(crate) fn split_endpoint(endpoint: &str) -> Option<(&str, &str, u16)> {
+    let (scheme, addr) = endpoint.split_once("://")?;
+    let (host, port) = addr.trim_end_matches('/').rsplit_once(':')?;
+    Some((scheme, host.trim_start_matches('[').trim_end_matches(']'), port.parse().ok()?))
+}
+
+/// An endpoint from its parts, bracketing IPv6 hosts.
+#[cfg_attr(not(any(feature = "consul", feature = "nacos")), allow(dead_code))]
+pub(crate) fn join_endpoint(scheme: &str, host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("{}://[{}]:{}", scheme, host, port)
+    } else {
+        format!("{}://{}:{}", scheme, host, port)
+    }
+}
+
+/// Updates of the instances of one service, see [`Discovery::watch`].
+#[derive(Debug)]
+pub struct Watcher {
+    rx: watch::Receiver<Vec<ServiceInstance>>,
+    started: bool,
+}
+
+impl Watcher {
+    /// Watch the instances a registry publishes on `rx`.
+    pub fn new(rx: watch::Receiver<Vec<ServiceInstance>>) -> Self {
+        Self { rx, started: false }
+    }
+
+    /// The current instances on the first call, then every time they change. `None` once
+    /// the registry stopped watching.
+    pub async fn next(&mut self) -> Option<Vec<ServiceInstance>> {
+        if self.started {
+            self.rx.changed().await.ok()?;
+        }
+        self.started = true;
+        Some(self.rx.borrow_and_update().clone())
+    }
+}
+
+/// Announces the instances of this process to a registry.
+#[async_trait]
+pub trait Registrar: Send + Sync {
+    /// Add `instance`, replacing an instance with the same id.
+    async fn register(&self, instance: &ServiceInstance) -> AnyResult<()>;
+
+    /// Remove `instance`, doing nothing when it isn't registered.
+    async fn deregister(&self, instance: &ServiceInstance) -> AnyResult<()>;
+
+    /// Tell the registry `instance` is still alive, for registries expiring instances that
+    /// stop sending heartbeats.
+    async fn heartbeat(&self, _instance: &ServiceInstance) -> AnyResult<()> {
+        Ok(())
+    }
+}
+
+/// Finds the instances of services.
+#[async_trait]
+pub trait Discovery: Send + Sync {
+    /// The instances of `name` registered now.
+    async fn get_service(&self, name: &str) -> AnyResult<Vec<ServiceInstance>>;
+
+    /// Follow the instances of `name` as they come and go.
+    async fn watch(&self, name: &str) -> AnyResult<Watcher>;
 }
 
 #[cfg(test)]
@@ -9,8 +127,38 @@ mod tests {
     use super::*;
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn valid_instance() {
+        let instance = ServiceInstance {
+            endpoints: vec!["http://10.0.0.7:8000".to_string(), "grpc://10.0.0.7:9000".to_string()],
+            metadata: HashMap::from([(WEIGHT.to_string(), "20".to_string())]),
+            ..Default::default()
+        };
+        assert_eq!(instance.endpoint(&["grpc", "grpcs"]), Some("grpc://10.0.0.7:9000"));
+        assert_eq!(instance.endpoint(&["https"]), None);
+        assert_eq!(instance.weight(), Some(20));
+    }
+
+    #[test]
+    fn valid_endpoint() {
+        assert_eq!(split_endpoint("grpc://10.0.0.7:9000"), Some(("grpc", "10.0.0.7", 9000)));
+        assert_eq!(split_endpoint("http://[::1]:80/"), Some(("http", "::1", 80)));
+        assert_eq!(split_endpoint("10.0.0.7:9000"), None);
+        assert_eq!(join_endpoint("http", "::1", 80), "http://[::1]:80");
+    }
+
+    #[tokio::test]
+    async fn valid_watcher() {
+        let (tx, rx) = watch::channel(Vec::new());
+        let mut watcher = Watcher::new(rx);
+        assert_eq!(watcher.next().await, Some(Vec::new()));
+
+        let a = ServiceInstance {
+            id: "a".to_string(),
+            ..Default::default()
+        };
+        tx.send(vec![a.clone()]).unwrap();
+        assert_eq!(watcher.next().await, Some(vec![a]));
+        drop(tx);
+        assert_eq!(watcher.next().await, None);
     }
 }
@@ -0,0 +1,110 @@
+//! A registry kept in memory, for tests and for services running in one process.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use tokio::sync::watch;
+
+use bamboo_status::status::AnyResult;
+
+use crate::{Discovery, Registrar, ServiceInstance, Watcher};
+
+/// Both the [`Registrar`] and the [`Discovery`] of the instances registered with it. Clones
+/// share the instances.
+#[derive(Debug, Default, Clone)]
+pub struct Memory {
+    services: Arc<Mutex<HashMap<String, watch::Sender<Vec<ServiceInstance>>>>>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The channel of the instances of `name`, created empty.
+    fn service(&self, name: &str) -> watch::Sender<Vec<ServiceInstance>> {
+        let mut services = self.services.lock().unwrap();
+        services
+            .entry(name.to_string())
+            .or_insert_with(|| watch::channel(Vec::new()).0)
+            .clone()
+    }
+}
+
+#[async_trait]
+impl Registrar for Memory {
+    async fn register(&self, instance: &ServiceInstance) -> AnyResult<()> {
+        if instance.id.is_empty() || instance.name.is_empty() {
+            anyhow::bail!("registry: instance without id or name: {:?}", instance);
+        }
+        self.service(&instance.name).send_modify(|instances| {
+            match instances.iter_mut().find(|i| i.id == instance.id) {
+                Some(registered) => *registered = instance.clone(),
+                None => instances.push(instance.clone()),
+            }
+        });
+        Ok(())
+    }
+
+    async fn deregister(&self, instance: &ServiceInstance) -> AnyResult<()> {
+        self.service(&instance.name).send_if_modified(|instances| {
+            let len = instances.len();
+            instances.retain(|i| i.id != instance.id);
+            instances.len() != len
+        });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Discovery for Memory {
+    async fn get_service(&self, name: &str) -> AnyResult<Vec<ServiceInstance>> {
+        Ok(self.service(name).borrow().clone())
+    }
+
+    async fn watch(&self, name: &str) -> AnyResult<Watcher> {
+        Ok(Watcher::new(self.service(name).subscribe()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(id: &str, version: &str) -> ServiceInstance {
+        ServiceInstance {
+            id: id.to_string(),
+            name: "greeter".to_string(),
+            version: version.to_string(),
+            endpoints: vec![format!("grpc://{}:9000", id)],
+            ..Default::default()
+        }
+    }
+
+    fn ids(instances: &[ServiceInstance]) -> Vec<&str> {
+        instances.iter().map(|i| i.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn valid_memory() {
+        let registry = Memory::new();
+        let mut watcher = registry.watch("greeter").await.unwrap();
+        assert_eq!(watcher.next().await, Some(Vec::new()));
+
+        registry.register(&instance("a", "v1")).await.unwrap();
+        registry.clone().register(&instance("b", "v1")).await.unwrap();
+        registry.register(&instance("a", "v2")).await.unwrap();
+        let instances = registry.get_service("greeter").await.unwrap();
+        assert_eq!(ids(&instances), vec!["a", "b"]);
+        assert_eq!(instances[0].version, "v2");
+        assert_eq!(ids(&watcher.next().await.unwrap()), vec!["a", "b"]);
+
+        registry.deregister(&instance("a", "v2")).await.unwrap();
+        assert_eq!(ids(&watcher.next().await.unwrap()), vec!["b"]);
+        assert!(registry.get_service("other").await.unwrap().is_empty());
+        assert!(registry.register(&ServiceInstance::default()).await.is_err());
+    }
+}
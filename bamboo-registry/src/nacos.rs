@@ -0,0 +1,278 @@
+//! Nacos backend: instances are ephemeral naming instances kept by heartbeats, followed by
+//! polling their list.
+//!
+//! Nacos instances have one address, so the first endpoint is registered as the address and
+//! all of them in the `endpoints` metadata.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use bamboo_config::nacos::client::{Client, Instance, DEFAULT_GROUP};
+use bamboo_status::status::AnyResult;
+
+use crate::{join_endpoint, split_endpoint, Discovery, Registrar, ServiceInstance, Watcher, WEIGHT};
+
+const ENDPOINTS: &str = "endpoints";
+const VERSION: &str = "version";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Nacos {
+    /// Address of the server, like `http://127.0.0.1:8848`.
+    pub address: String,
+    /// Namespace id, the public namespace when empty.
+    #[serde(default)]
+    pub namespace: String,
+    #[serde(default = "default_group")]
+    pub group: String,
+    #[serde(default)]
+    pub access_token: Option<String>,
+    /// Interval a watch lists the instances at.
+    #[serde(default = "default_poll", with = "humantime_serde")]
+    pub poll: Duration,
+}
+
+fn default_group() -> String {
+    DEFAULT_GROUP.to_string()
+}
+
+fn default_poll() -> Duration {
+    Duration::from_secs(5)
+}
+
+impl Nacos {
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            namespace: String::new(),
+            group: default_group(),
+            access_token: None,
+            poll: default_poll(),
+        }
+    }
+}
+
+/// Registers and finds instances through the naming service of a Nacos server.
+#[derive(Debug, Clone)]
+pub struct NacosRegistry {
+    client: Client,
+    conf: Nacos,
+}
+
+impl NacosRegistry {
+    pub fn new(conf: &Nacos) -> AnyResult<Self> {
+        let client = Client::new(&conf.address)?
+            .with_namespace(&conf.namespace)
+            .with_access_token(conf.access_token.clone());
+        Ok(Self {
+            client,
+            conf: conf.clone(),
+        })
+    }
+
+    /// The naming instance of `instance`, at its first endpoint.
+    fn instance(instance: &ServiceInstance) -> AnyResult<Instance> {
+        let (_, ip, port) = instance
+            .endpoints
+            .first()
+            .and_then(|e| split_endpoint(e))
+            .ok_or_else(|| anyhow::anyhow!("nacos: instance {} has no endpoint", instance.id))?;
+        let mut metadata = instance.metadata.clone();
+        metadata.insert(ENDPOINTS.to_string(), instance.endpoints.join(","));
+        metadata.insert(VERSION.to_string(), instance.version.clone());
+        Ok(Instance {
+            instance_id: instance.id.clone(),
+            ip: ip.to_string(),
+            port,
+            service_name: instance.name.clone(),
+            weight: instance.weight().unwrap_or(1) as f64,
+            healthy: true,
+            enabled: true,
+            metadata,
+        })
+    }
+
+    async fn list(client: &Client, name: &str, group: &str) -> AnyResult<Vec<ServiceInstance>> {
+        let instances = client.instances(name, group).await?;
+        Ok(instances
+            .into_iter()
+            .filter(|i| i.enabled)
+            .map(|i| service_instance(name, i))
+            .collect())
+    }
+}
+
+/// The instance of a naming instance registered by [`NacosRegistry`], or by others.
+fn service_instance(name: &str, instance: Instance) -> ServiceInstance {
+    let mut metadata = instance.metadata;
+    let endpoints = match metadata.remove(ENDPOINTS) {
+        Some(endpoints) => endpoints.split(',').map(str::to_string).collect(),
+        None => vec![join_endpoint("http", &instance.ip, instance.port)],
+    };
+    if !metadata.contains_key(WEIGHT) {
+        metadata.insert(WEIGHT.to_string(), (instance.weight.round() as u32).to_string());
+    }
+    let id = match instance.instance_id.is_empty() {
+        true => format!("{}#{}", instance.ip, instance.port),
+        false => instance.instance_id,
+    };
+    ServiceInstance {
+        id,
+        name: name.to_string(),
+        version: metadata.remove(VERSION).unwrap_or_default(),
+        endpoints,
+        metadata,
+    }
+}
+
+#[async_trait]
+impl Registrar for NacosRegistry {
+    async fn register(&self, instance: &ServiceInstance) -> AnyResult<()> {
+        let nacos = Self::instance(instance)?;
+        Ok(self.client.register_instance(&instance.name, &self.conf.group, &nacos).await?)
+    }
+
+    async fn deregister(&self, instance: &ServiceInstance) -> AnyResult<()> {
+        let nacos = Self::instance(instance)?;
+        Ok(self.client.deregister_instance(&instance.name, &self.conf.group, &nacos).await?)
+    }
+
+    async fn heartbeat(&self, instance: &ServiceInstance) -> AnyResult<()> {
+        let nacos = Self::instance(instance)?;
+        Ok(self.client.beat(&instance.name, &self.conf.group, &nacos).await?)
+    }
+}
+
+#[async_trait]
+impl Discovery for NacosRegistry {
+    async fn get_service(&self, name: &str) -> AnyResult<Vec<ServiceInstance>> {
+        Self::list(&self.client, name, &self.conf.group).await
+    }
+
+    /// Follow the healthy instances by listing them every `poll`, until the watcher is dropped.
+    async fn watch(&self, name: &str) -> AnyResult<Watcher> {
+        let (tx, rx) = watch::channel(self.get_service(name).await?);
+        let (client, conf, name) = (self.client.clone(), self.conf.clone(), name.to_string());
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tx.closed() => return,
+                    _ = tokio::time::sleep(conf.poll) => {}
+                }
+                match Self::list(&client, &name, &conf.group).await {
+                    Ok(instances) => {
+                        tx.send_if_modified(|current| {
+                            let changed = *current != instances;
+                            *current = instances;
+                            changed
+                        });
+                    }
+                    Err(e) => log::warn!("nacos: watching {} failed: {:?}", name, e),
+                }
+            }
+        });
+        Ok(Watcher::new(rx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        extract::{Query, State},
+        http::StatusCode,
+        routing::{post, put},
+        Json, Router,
+    };
+    use serde_json::{json, Value};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    type Params = Query<HashMap<String, String>>;
+
+    /// Instances by `service@@ip:port`.
+    type Naming = Arc<Mutex<HashMap<String, Value>>>;
+
+    fn key(params: &HashMap<String, String>) -> String {
+        format!("{}@@{}@@{}:{}", params["groupName"], params["serviceName"], params["ip"], params["port"])
+    }
+
+    async fn register(State(naming): State<Naming>, Query(params): Params) {
+        let metadata: Value = serde_json::from_str(&params["metadata"]).unwrap();
+        let instance = json!({
+            "instanceId": params["instanceId"],
+            "ip": params["ip"],
+            "port": params["port"].parse::<u16>().unwrap(),
+            "weight": params["weight"].parse::<f64>().unwrap(),
+            "healthy": true,
+            "enabled": true,
+            "metadata": metadata,
+        });
+        naming.lock().unwrap().insert(key(&params), instance);
+    }
+
+    async fn deregister(State(naming): State<Naming>, Query(params): Params) {
+        naming.lock().unwrap().remove(&key(&params));
+    }
+
+    async fn beat(State(naming): State<Naming>, Query(params): Params) -> StatusCode {
+        match naming.lock().unwrap().contains_key(&key(&params)) {
+            true => StatusCode::OK,
+            false => StatusCode::NOT_FOUND,
+        }
+    }
+
+    async fn list(State(naming): State<Naming>, Query(params): Params) -> Json<Value> {
+        let prefix = format!("{}@@{}@@", params["groupName"], params["serviceName"]);
+        let naming = naming.lock().unwrap();
+        let hosts: Vec<Value> = naming.iter().filter(|(k, _)| k.starts_with(&prefix)).map(|(_, v)| v.clone()).collect();
+        Json(json!({ "name": params["serviceName"], "hosts": hosts }))
+    }
+
+    async fn fake_server() -> SocketAddr {
+        let app = Router::new()
+            .route("/nacos/v1/ns/instance", post(register).delete(deregister))
+            .route("/nacos/v1/ns/instance/beat", put(beat))
+            .route("/nacos/v1/ns/instance/list", axum::routing::get(list))
+            .with_state(Naming::default());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    #[tokio::test]
+    async fn valid_nacos() {
+        let addr = fake_server().await;
+        let mut conf = Nacos::new(&format!("http://{}", addr));
+        conf.poll = Duration::from_millis(10);
+        let registry = NacosRegistry::new(&conf).unwrap();
+        let greeter = ServiceInstance {
+            id: "greeter-1".to_string(),
+            name: "greeter".to_string(),
+            version: "v1".to_string(),
+            endpoints: vec!["grpc://10.0.0.7:9000".to_string(), "http://10.0.0.7:8000".to_string()],
+            metadata: HashMap::from([(WEIGHT.to_string(), "10".to_string())]),
+        };
+        assert!(registry.heartbeat(&greeter).await.is_err());
+
+        let mut watcher = registry.watch("greeter").await.unwrap();
+        assert_eq!(watcher.next().await, Some(Vec::new()));
+        registry.register(&greeter).await.unwrap();
+        registry.heartbeat(&greeter).await.unwrap();
+        assert_eq!(watcher.next().await, Some(vec![greeter.clone()]));
+        assert_eq!(registry.get_service("greeter").await.unwrap(), vec![greeter.clone()]);
+
+        registry.deregister(&greeter).await.unwrap();
+        assert_eq!(watcher.next().await, Some(Vec::new()));
+        assert!(registry.heartbeat(&greeter).await.is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use futures_util::FutureExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio_graceful::{ShutdownGuard, WeakShutdownGuard};
+
+use bamboo_status::errors::Status;
+
+/// Admin endpoints, mounted under `prefix`:
+///
+/// - `GET {prefix}/health`: `200 {"status":"UP"}`, `503 {"status":"DOWN"}` once shutting down.
+/// - `GET {prefix}/metrics`: request counters in the Prometheus text format.
+/// - `GET {prefix}/config`: the configuration with secrets redacted, only when the server was
+///   given one through [`Server::with_config_dump`](crate::Server::with_config_dump).
+///
+/// They go through the same rate limiting, authentication and authorization as the other routes.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Admin {
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+}
+
+fn default_prefix() -> String {
+    "/admin".to_string()
+}
+
+impl Default for Admin {
+    fn default() -> Self {
+        Self {
+            prefix: default_prefix(),
+        }
+    }
+}
+
+/// Request counters of the application routes, admin requests aren't counted.
+#[derive(Debug)]
+pub struct Metrics {
+    started: Instant,
+    requests: AtomicU64,
+    in_flight: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            requests: AtomicU64::new(0),
+            in_flight: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Metrics {
+    /// Middleware counting requests, install it with
+    /// `axum::middleware::from_fn_with_state(metrics, Metrics::track)`.
+    pub async fn track(State(metrics): State<Arc<Metrics>>, req: Request, next: Next) -> Response {
+        metrics.requests.fetch_add(1, Ordering::Relaxed);
+        let in_flight = InFlight::new(&metrics);
+        let res = next.run(req).await;
+        drop(in_flight);
+        // Handler errors are rendered with 200, the `Status` extension tells them apart.
+        if res.status().is_server_error() || res.extensions().get::<Status>().is_some() {
+            metrics.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        res
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let gauges = [
+            ("http_requests_total", "counter", self.requests.load(Ordering::Relaxed) as f64),
+            ("http_requests_in_flight", "gauge", self.in_flight.load(Ordering::Relaxed) as f64),
+            ("http_errors_total", "counter", self.errors.load(Ordering::Relaxed) as f64),
+            ("process_uptime_seconds", "gauge", self.started.elapsed().as_secs_f64()),
+        ];
+        for (name, kind, value) in gauges {
+            let _ = writeln!(out, "# TYPE {} {}\n{} {}", name, kind, name, value);
+        }
+        out
+    }
+}
+
+/// Counts a request in flight until dropped, so requests dropped on a timeout, a client going
+/// away or the shutdown are no longer counted either.
+struct InFlight<'a>(&'a Metrics);
+
+impl<'a> InFlight<'a> {
+    fn new(metrics: &'a Metrics) -> Self {
+        metrics.in_flight.fetch_add(1, Ordering::Relaxed);
+        Self(metrics)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone)]
+struct AdminState {
+    /// A weak guard, the admin router must not hold up the shutdown.
+    guard: WeakShutdownGuard,
+    metrics: Arc<Metrics>,
+    config: Option<Arc<Value>>,
+}
+
+/// Build the admin router, to be nested under [`Admin::prefix`].
+pub(crate) fn router(guard: &ShutdownGuard, metrics: Arc<Metrics>, config: Option<Arc<Value>>) -> Router {
+    let mut r = Router::new()
+        .route("/health", get(health))
+        .route("/metrics", get(metrics_text));
+    if config.is_some() {
+        r = r.route("/config", get(config_dump));
+    }
+    r.with_state(AdminState {
+        guard: guard.clone_weak(),
+        metrics,
+        config,
+    })
+}
+
+async fn health(State(state): State<AdminState>) -> Response {
+    // Resolves at once when the shutdown has started.
+    if state.guard.cancelled().now_or_never().is_some() {
+        (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"status": "DOWN"}))).into_response()
+    } else {
+        Json(json!({"status": "UP"})).into_response()
+    }
+}
+
+async fn metrics_text(State(state): State<AdminState>) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+        .into_response()
+}
+
+async fn config_dump(State(state): State<AdminState>) -> Json<Value> {
+    Json(state.config.as_deref().cloned().unwrap_or_default())
+}
+
+/// Replace values whose key looks like a credential with `"******"`, whatever their type.
+pub(crate) fn redact(value: &mut Value) {
+    const SECRETS: [&str; 5] = ["password", "secret", "token", "credential", "key"];
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map.iter_mut() {
+                let k = k.to_ascii_lowercase();
+                if SECRETS.iter().any(|s| k.contains(s)) {
+                    *v = Value::String("******".to_string());
+                } else {
+                    redact(v);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::body::Body;
+    use tokio_graceful::Shutdown;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn get_body(app: Router, uri: &str) -> (StatusCode, String) {
+        let req = Request::get(uri).body(Body::empty()).unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        let status = res.status();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn valid_redact() {
+        let mut v = json!({
+            "http": {"address": "0.0.0.0:80"},
+            "db": {"password": "p", "api_key": "k", "servers": [{"token": "t", "host": "h"}]},
+        });
+        redact(&mut v);
+        assert_eq!(v["http"]["address"], "0.0.0.0:80");
+        assert_eq!(v["db"]["password"], "******");
+        assert_eq!(v["db"]["api_key"], "******");
+        assert_eq!(v["db"]["servers"][0]["token"], "******");
+        assert_eq!(v["db"]["servers"][0]["host"], "h");
+
+        let mut v = json!({
+            "tokens": ["a", "b"],
+            "credentials": {"user": "u", "pass": "p"},
+            "nested": {"secret": {"inner": [1, 2]}},
+            "servers": [{"host": "h"}],
+        });
+        redact(&mut v);
+        assert_eq!(v["tokens"], "******");
+        assert_eq!(v["credentials"], "******");
+        assert_eq!(v["nested"]["secret"], "******");
+        assert_eq!(v["servers"][0]["host"], "h");
+    }
+
+    #[tokio::test]
+    async fn valid_admin() {
+        let shutdown = Shutdown::new(std::future::pending());
+        let metrics = Arc::new(Metrics::default());
+        let app = Router::new()
+            .route("/", get(|| async { Status::new("Oops", "") }))
+            .layer(axum::middleware::from_fn_with_state(metrics.clone(), Metrics::track))
+            .nest("/admin", router(&shutdown.guard(), metrics, None));
+
+        let _ = get_body(app.clone(), "/").await;
+        let (status, body) = get_body(app.clone(), "/admin/health").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"status":"UP"}"#);
+        let (_, body) = get_body(app.clone(), "/admin/metrics").await;
+        assert!(body.contains("http_requests_total 1\n"));
+        assert!(body.contains("http_errors_total 1\n"));
+        let (status, _) = get_body(app, "/admin/config").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn valid_metrics_dropped() {
+        let metrics = Arc::new(Metrics::default());
+        let app = Router::new()
+            .route("/", get(std::future::pending::<()>))
+            .layer(axum::middleware::from_fn_with_state(metrics.clone(), Metrics::track));
+
+        let req = Request::get("/").body(Body::empty()).unwrap();
+        let res = tokio::time::timeout(Duration::from_millis(10), app.oneshot(req)).await;
+        assert!(res.is_err());
+        assert!(metrics.render().contains("http_requests_in_flight 0\n"));
+    }
+
+    #[tokio::test]
+    async fn valid_health_draining() {
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let shutdown = Shutdown::new(async move {
+            let _ = rx.await;
+        });
+        let app = router(&shutdown.guard(), Arc::new(Metrics::default()), Some(Arc::new(json!({"a": 1}))));
+        let (_, body) = get_body(app.clone(), "/config").await;
+        assert_eq!(body, r#"{"a":1}"#);
+        let (status, _) = get_body(app.clone(), "/health").await;
+        assert_eq!(status, StatusCode::OK);
+        tx.send(()).unwrap();
+        shutdown.guard_weak().cancelled().await;
+        let (status, _) = get_body(app, "/health").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
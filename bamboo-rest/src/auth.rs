@@ -0,0 +1,178 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequestParts, MatchedPath, Request, State},
+    http::{header, request::Parts},
+    middleware::Next,
+    response::Response,
+};
+use serde::de::DeserializeOwned;
+
+use bamboo_auth::{unauthenticated, Authorization, Principal, Verifier};
+use crate::{ratelimit::Subject, rejection::reject};
+
+/// Claims of the bearer token, deserialized into `T`.
+///
+/// Requests without a valid token are rejected with a 401 [`Status`](bamboo_status::errors::Status); use
+/// `Option<Claims<T>>` for routes that also serve anonymous callers.
+#[derive(Debug, Clone)]
+pub struct Claims<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Claims<T>
+    where
+        T: DeserializeOwned,
+        S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let principal = parts
+            .extensions
+            .get::<Principal>()
+            .ok_or_else(|| reject(unauthenticated("missing bearer token")))?;
+        principal.claims().map(Claims).map_err(reject)
+    }
+}
+
+/// Middleware verifying the bearer token of every request.
+///
+/// The caller is put in the request extensions as a [`Principal`] and a rate limiting
+/// [`Subject`]. Install it with `axum::middleware::from_fn_with_state(verifier, authenticate)`,
+/// or through [`Http::auth`](crate::Http::auth).
+pub async fn authenticate(State(verifier): State<Arc<Verifier>>, mut req: Request, next: Next) -> Response {
+    let authorization = req.headers().get(header::AUTHORIZATION).and_then(|v| v.to_str().ok());
+    match verifier.authenticate(authorization) {
+        Ok(Some(principal)) => {
+            req.extensions_mut().insert(Subject(principal.subject.clone()));
+            req.extensions_mut().insert(principal);
+            next.run(req).await
+        }
+        Ok(None) => next.run(req).await,
+        Err(status) => reject(status),
+    }
+}
+
+/// Middleware checking the caller against the [`Authorization`] rule of the route.
+///
+/// Runs after [`authenticate`], install it with
+/// `axum::middleware::from_fn_with_state(authorization, authorize)` or through
+/// [`Http::authorization`](crate::Http::authorization).
+pub async fn authorize(State(conf): State<Arc<Authorization>>, req: Request, next: Next) -> Response {
+    let route = req.extensions().get::<MatchedPath>().map(MatchedPath::as_str);
+    let principal = req.extensions().get::<Principal>();
+    match conf.authorize(principal, req.method().as_str(), req.uri().path(), route) {
+        Ok(()) => next.run(req).await,
+        Err(status) => reject(status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::StatusCode, routing::get, Router};
+    use bamboo_auth::{
+        jsonwebtoken::{encode, get_current_timestamp, EncodingKey, Header},
+        Jwt, Policy,
+    };
+    use serde::Deserialize;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct User {
+        sub: String,
+    }
+
+    fn app(conf: Jwt) -> Router {
+        let verifier = Arc::new(Verifier::new(&conf).unwrap());
+        Router::new()
+            .route("/me", get(|Claims(user): Claims<User>| async move { user.sub }))
+            .route(
+                "/hello",
+                get(|user: Option<Claims<User>>| async move { user.map(|Claims(u)| u.sub).unwrap_or_default() }),
+            )
+            .layer(axum::middleware::from_fn_with_state(verifier, authenticate))
+    }
+
+    async fn call(app: &Router, uri: &str, token: Option<&str>) -> (StatusCode, String) {
+        let mut req = Request::get(uri);
+        if let Some(token) = token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let res = app.clone().oneshot(req.body(Body::empty()).unwrap()).await.unwrap();
+        let status = res.status();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn token(claims: serde_json::Value) -> String {
+        encode(&Header::default(), &claims, &EncodingKey::from_secret(b"secret")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn valid_authenticate() {
+        let mut conf = Jwt::with_secret("secret");
+        conf.audience = vec!["api".to_string()];
+        let app = app(conf);
+        let exp = get_current_timestamp() + 600;
+
+        let alice = token(json!({"sub": "alice", "aud": "api", "exp": exp}));
+        assert_eq!(call(&app, "/me", Some(&alice)).await, (StatusCode::OK, "alice".to_string()));
+
+        let (status, body) = call(&app, "/me", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(body.contains("Unauthenticated"));
+
+        let web = token(json!({"sub": "alice", "aud": "web", "exp": exp}));
+        let (status, body) = call(&app, "/me", Some(&web)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(body.contains("PermissionDenied"));
+    }
+
+    #[tokio::test]
+    async fn valid_anonymous_claims() {
+        let mut conf = Jwt::with_secret("secret");
+        conf.allow_anonymous = true;
+        let app = app(conf);
+
+        assert_eq!(call(&app, "/hello", None).await, (StatusCode::OK, String::new()));
+        assert_eq!(call(&app, "/me", None).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(call(&app, "/hello", Some("junk")).await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn valid_authorize() {
+        let mut conf = Jwt::with_secret("secret");
+        conf.allow_anonymous = true;
+        let verifier = Arc::new(Verifier::new(&conf).unwrap());
+        let policies = Arc::new(
+            Authorization::default()
+                .rule("/users/:id", Some("DELETE"), Policy::any_role(&["admin"]))
+                .rule("/reports", None, Policy::authenticated()),
+        );
+        let app = Router::new()
+            .route("/users/:id", get(|| async { "user" }).delete(|| async { "deleted" }))
+            .route("/reports", get(|| async { "report" }))
+            .layer(axum::middleware::from_fn_with_state(policies, authorize))
+            .layer(axum::middleware::from_fn_with_state(verifier, authenticate));
+        let exp = get_current_timestamp() + 600;
+        let admin = token(json!({"sub": "root", "roles": ["admin"], "exp": exp}));
+        let alice = token(json!({"sub": "alice", "exp": exp}));
+        let delete = |token: String| {
+            let req = Request::delete("/users/7")
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap();
+            app.clone().oneshot(req)
+        };
+
+        assert_eq!(delete(admin).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(delete(alice.clone()).await.unwrap().status(), StatusCode::FORBIDDEN);
+        assert_eq!(call(&app, "/users/7", None).await.0, StatusCode::OK);
+        assert_eq!(call(&app, "/reports", None).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(call(&app, "/reports", Some(&alice)).await.0, StatusCode::OK);
+    }
+}
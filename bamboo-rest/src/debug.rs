@@ -0,0 +1,41 @@
+use axum::{http::header, response::Response};
+
+use bamboo_status::errors::Status;
+
+/// Re-render [`Status`] error responses with their cause chain and backtrace.
+///
+/// Installed by [`Server`](crate::Server) when `Http.debug` is set, with
+/// `axum::middleware::map_response(expose_internals)`.
+pub async fn expose_internals(res: Response) -> Response {
+    let Some(status) = res.extensions().get::<Status>().cloned() else {
+        return res;
+    };
+    let (parts, _) = res.into_parts();
+    let (_, body) = status.into_debug_response().into_parts();
+    let mut res = Response::from_parts(parts, body);
+    res.headers_mut().remove(header::CONTENT_LENGTH);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, extract::Request, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn valid_expose_internals() {
+        let app = Router::new()
+            .route("/", get(|| async {
+                Status::from(anyhow::anyhow!("disk full").context("save user"))
+            }))
+            .layer(axum::middleware::map_response(expose_internals));
+
+        let res = app.oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["message"], "save user");
+        assert_eq!(body["causes"][1], "disk full");
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+pub use bamboo_status::i18n::{Catalog, parse_accept_language};
+use bamboo_status::errors::Status;
+
+/// Middleware that re-renders [`Status`] error responses in the language asked for by
+/// the `Accept-Language` request header.
+///
+/// Install it with `axum::middleware::from_fn_with_state(catalog, localize)`, or through
+/// [`Server::with_catalog`](crate::Server::with_catalog).
+pub async fn localize(State(catalog): State<Arc<Catalog>>, req: Request, next: Next) -> Response {
+    let langs = req
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|v| v.to_str().ok())
+        .map(parse_accept_language)
+        .unwrap_or_default();
+
+    let mut res = next.run(req).await;
+    let Some(status) = res.extensions_mut().remove::<Status>() else {
+        return res;
+    };
+    let (mut parts, _) = res.into_parts();
+    let (localized, body) = catalog.localize(status, &langs).into_response().into_parts();
+    // Hand the localized status on to outer layers.
+    parts.extensions.extend(localized.extensions);
+    let mut res = Response::from_parts(parts, body);
+    res.headers_mut().remove(header::CONTENT_LENGTH);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn valid_localize() {
+        let mut catalog = Catalog::new("en-US");
+        catalog.insert("zh-CN", "UserNotFound", "用户 {id} 不存在");
+        let app = Router::new()
+            .route("/", get(|| async {
+                let mut s = Status::new("UserNotFound", "user not found");
+                s.metadata.insert("id".to_string(), "7".to_string());
+                s
+            }))
+            .layer(axum::middleware::from_fn_with_state(Arc::new(catalog), localize));
+
+        let req = Request::get("/")
+            .header(header::ACCEPT_LANGUAGE, "zh-CN,en;q=0.5")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["message"], "用户 7 不存在");
+    }
+}
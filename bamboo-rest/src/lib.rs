@@ -1,181 +1,449 @@
-use std::{net::SocketAddr, sync::Arc, time::Duration};
-use std::convert::Infallible;
+use std::{convert::Infallible, net::SocketAddr, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
 use axum::{
+    extract::Request,
     http::StatusCode,
-    response::{
-        IntoResponse,
-        Response,
-    },
+    response::IntoResponse,
+    routing::Route,
     Router,
-    http::Request,
-    serve::IncomingStream,
-    body::Body,
 };
 pub use axum::extract::{State, Path, FromRequest};
 use serde::{Deserialize, Serialize};
-use tokio::{
-    net::TcpListener,
-    time::sleep,
-};
+use tokio::net::TcpListener;
 use tokio_graceful::ShutdownGuard;
-use tower_http::{
-    ServiceBuilderExt,
-    timeout::TimeoutLayer,
-    trace::TraceLayer,
-};
+use tower_layer::Layer;
 use tower_service::Service;
 
-use bamboo_boot::plugin::Plugin;
+use bamboo_boot::plugin::{Listening, Plugin};
 use bamboo_status::status::AnyResult;
+use bamboo_status::i18n::Catalog;
+use bamboo_status::errors::Status;
 
 pub use axum::Json;
 pub use axum::routing::{get, post};
 pub use axum::debug_handler;
 
+pub use middleware::{Compression, Cors, Encoding, Level, RequestIdKind};
+pub use admin::Admin;
+pub use routes::Group;
+pub use bamboo_tls::{ClientAuth, PeerIdentity, Tls};
+pub use bamboo_status::spring::Success;
+pub use openapi::Docs;
+pub use ratelimit::{Quota, RateLimit, RateLimitKey, RouteQuota, Subject};
+pub use auth::Claims;
+pub use rejection::Rejection;
+pub use streaming::{Streaming, Streams};
+pub use upload::{Upload, Uploads};
+pub use bamboo_auth::{Authorization, Jwt, Policy, Principal};
+
 pub mod validate;
+pub mod i18n;
+pub mod debug;
+pub mod routes;
+pub mod admin;
+pub mod openapi;
+pub mod ratelimit;
+pub mod auth;
+pub mod streaming;
+pub mod upload;
+pub mod rejection;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+mod middleware;
+mod tls;
 
-#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Http {
     pub address: String,
+    /// Include error cause chains and backtraces in responses. Never enable in production.
+    #[serde(default)]
+    pub debug: bool,
+    /// Per-request timeout, e.g. `10s`. Graceful shutdown waits at most this long for requests.
+    #[serde(default = "default_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+    /// Maximum request body size in bytes, `0` keeps axum's default of 2MB.
+    #[serde(default)]
+    pub body_limit: usize,
+    /// Compress responses and decompress requests, disabled when missing. The former `true`
+    /// and `false` still mean the default [`Compression`] and none.
+    #[serde(default, deserialize_with = "middleware::deserialize_compression")]
+    pub compression: Option<Compression>,
+    /// Answer CORS requests, disabled when missing.
+    #[serde(default)]
+    pub cors: Option<Cors>,
+    /// How `x-request-id` is generated when the client didn't send one.
+    #[serde(default)]
+    pub request_id: RequestIdKind,
+    /// Log the number of in-flight requests at this interval, e.g. `10s`.
+    #[serde(default, with = "humantime_serde")]
+    pub in_flight_interval: Option<Duration>,
+    /// Serve the admin endpoints, disabled when missing.
+    #[serde(default)]
+    pub admin: Option<Admin>,
+    /// Serve HTTPS, plain HTTP when missing.
+    #[serde(default)]
+    pub tls: Option<Tls>,
+    /// Serve the OpenAPI document of the documented routes, disabled when missing.
+    #[serde(default)]
+    pub docs: Option<Docs>,
+    /// Throttle clients, disabled when missing.
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    /// Verify JWT bearer tokens, disabled when missing.
+    #[serde(default)]
+    pub auth: Option<Jwt>,
+    /// Role and scope rules per route, checked against the claims of `auth` which is required.
+    #[serde(default)]
+    pub authorization: Option<Authorization>,
+    /// Heartbeats and connection limits of SSE and WebSocket streams.
+    #[serde(default)]
+    pub streaming: Streaming,
+    /// Size limits and accepted file types of multipart uploads.
+    #[serde(default)]
+    pub upload: Upload,
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+impl Default for Http {
+    fn default() -> Self {
+        Self {
+            address: String::new(),
+            debug: false,
+            timeout: default_timeout(),
+            body_limit: 0,
+            compression: None,
+            cors: None,
+            request_id: RequestIdKind::default(),
+            in_flight_interval: None,
+            admin: None,
+            tls: None,
+            docs: None,
+            rate_limit: None,
+            auth: None,
+            authorization: None,
+            streaming: Streaming::default(),
+            upload: Upload::default(),
+        }
+    }
 }
 
+/// Result of documented handlers, rendered in the [`Success`] or [`Status`] envelope.
+pub type ApiResult<T> = Result<Success<T>, Status>;
+
 pub trait Config {
     fn http(&self) -> &Http;
 }
 
-pub struct Server<C, S> {
+pub struct Server<C, S = ()> {
     conf: Arc<C>,
     r: Router<S>,
+    catalog: Option<Arc<Catalog>>,
+    config_dump: Option<Arc<serde_json::Value>>,
+    openapi: openapi::OpenApi,
+    rate_limit_backend: Option<Arc<dyn ratelimit::Backend>>,
+    listening: Listening,
 }
 
 impl<C, S> Server<C, S>
+    where S: Clone + Send + Sync + 'static,
 {
     pub fn new(conf: Arc<C>, r: Router<S>) -> Self {
         Self {
             conf,
             r,
+            catalog: None,
+            config_dump: None,
+            openapi: openapi::OpenApi::default(),
+            rate_limit_backend: None,
+            listening: Listening::default(),
+        }
+    }
+
+    /// Keep rate limiting state in `backend` instead of process memory.
+    pub fn with_rate_limit_backend(mut self, backend: Arc<dyn ratelimit::Backend>) -> Self {
+        self.rate_limit_backend = Some(backend);
+        self
+    }
+
+    /// Mount a route group at its `/{version}/{prefix}` path.
+    pub fn group(mut self, group: Group<S>) -> Self {
+        self.r = group.mount(self.r, &mut self.openapi);
+        self
+    }
+
+    /// Serve the configuration at `{admin.prefix}/config`, with secrets redacted.
+    pub fn with_config_dump(mut self) -> AnyResult<Self>
+        where C: Serialize,
+    {
+        let mut value = serde_json::to_value(self.conf.as_ref())?;
+        admin::redact(&mut value);
+        self.config_dump = Some(Arc::new(value));
+        Ok(self)
+    }
+
+    /// Provide the state of the routes, the server is a [`Plugin`] once it needs none.
+    pub fn with_state(self, state: S) -> Server<C> {
+        Server {
+            conf: self.conf,
+            r: self.r.with_state(state),
+            catalog: self.catalog,
+            config_dump: self.config_dump,
+            openapi: self.openapi,
+            rate_limit_backend: self.rate_limit_backend,
+            listening: self.listening,
         }
     }
 
-    async fn update_in_flight_requests_metric(&self, count: usize) {
-        // ...
-        // log::info!(" {} requests per 10s", count)
+    /// Localize error responses with `catalog` based on `Accept-Language`.
+    pub fn with_catalog(mut self, catalog: Arc<Catalog>) -> Self {
+        self.catalog = Some(catalog);
+        self
     }
 
-    // fn app(&self) -> Router {
-    //     // state
-    //
-    //     let (in_flight_requests_layer, counter) = InFlightRequestsLayer::pair();
-    //
-    //     // Spawn a task that will receive the number of in-flight requests every 10 seconds.
-    //     // tokio::spawn(
-    //     //     counter.run_emitter(Duration::from_secs(10), |count| async move {
-    //     //         self.update_in_flight_requests_metric(count).await;
-    //     //     }),
-    //     // );
-    //
-    //     // Build our database for holding the key/value pairs
-    //     let sensitive_headers: Arc<[_]> = vec![header::AUTHORIZATION, header::COOKIE].into();
-    //
-    //     // Build our middleware stack
-    //     let middleware = ServiceBuilder::new()
-    //         // Mark the `Authorization` and `Cookie` headers as sensitive so it doesn't show in logs
-    //         .sensitive_request_headers(sensitive_headers.clone())
-    //         .set_x_request_id(MyMakeRequestId::default())
-    //         // Set a timeout
-    //         // .layer(TimeoutLayer::new(Duration::from_secs(100)))
-    //         // .layer(ValidateRequestHeaderLayer::custom(AcceptBody::new()))
-    //         .layer(
-    //             TraceLayer::new_for_http()
-    //                 .make_span_with(DefaultMakeSpan::new().include_headers(true))
-    //                 .on_request(DefaultOnRequest::default())
-    //                 .on_body_chunk(DefaultOnBodyChunk::default())
-    //                 .on_failure(DefaultOnFailure::default())
-    //                 .on_response(
-    //                     DefaultOnResponse::new()
-    //                         .include_headers(true)
-    //                         .latency_unit(LatencyUnit::Micros),
-    //                 ),
-    //         )
-    //         .layer(in_flight_requests_layer)
-    //         // Box the response body so it implements `Default` which is required by axum
-    //         // .map_response_body(axum::body::boxed)
-    //         // Compress responses
-    //         .compression()
-    //         .propagate_x_request_id()
-    //         // Set a `Content-Type` if there isn't one already.
-    //         .sensitive_response_headers(sensitive_headers);
-    //
-    //     // Build route service
-    //     Router::new()
-    //         .layer(middleware)
-    //         .route_service("/", self.r.clone())
-    // }
+    /// Add a tower layer around the routes, inside the configured middleware stack.
+    ///
+    /// Layers run in reverse order of registration, like [`Router::layer`].
+    pub fn layer<L>(mut self, layer: L) -> Self
+        where
+            L: Layer<Route> + Clone + Send + 'static,
+            L::Service: Service<Request> + Clone + Send + 'static,
+            <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+            <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+            <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.r = self.r.layer(layer);
+        self
+    }
+}
+
+impl<C> Server<C>
+    where C: Config + Send + Sync + 'static,
+{
+    /// The routes wrapped in the configured middleware, exactly as they are served.
+    pub fn app(&self, guard: &ShutdownGuard) -> AnyResult<Router> {
+        let mut app = self
+            .r
+            .clone()
+            .layer(axum::Extension(Streams::new(&self.conf.http().streaming, guard)))
+            .layer(axum::Extension(Arc::new(self.conf.http().upload.clone())));
+        // Layers only wrap the routes added before them: the admin routes aren't counted by the
+        // metrics, but go through the rate limiting and auth layers below.
+        if let Some(conf) = &self.conf.http().admin {
+            let metrics = Arc::new(admin::Metrics::default());
+            let admin = admin::router(guard, metrics.clone(), self.config_dump.clone());
+            app = app
+                .layer(axum::middleware::from_fn_with_state(metrics, admin::Metrics::track))
+                .nest(&routes::join(&[&conf.prefix]), admin);
+        }
+        if let Some(conf) = &self.conf.http().rate_limit {
+            let backend = self
+                .rate_limit_backend
+                .clone()
+                .unwrap_or_else(|| Arc::new(ratelimit::InMemory::default()));
+            let limiter = Arc::new(ratelimit::RateLimiter::new(conf.clone(), backend));
+            app = app.layer(axum::middleware::from_fn_with_state(limiter, ratelimit::rate_limit));
+        }
+        if let Some(conf) = &self.conf.http().authorization {
+            // Without auth every caller is anonymous and protected routes would all be 401.
+            if self.conf.http().auth.is_none() {
+                anyhow::bail!("authorization needs auth to be configured");
+            }
+            app = app.layer(axum::middleware::from_fn_with_state(Arc::new(conf.clone()), auth::authorize));
+        }
+        // Authenticate before authorizing and rate limiting, so clients can be limited by subject.
+        if let Some(conf) = &self.conf.http().auth {
+            let verifier = Arc::new(bamboo_auth::Verifier::new(conf)?);
+            app = app.layer(axum::middleware::from_fn_with_state(verifier, auth::authenticate));
+        }
+        // The OpenAPI document is public.
+        if let Some(docs) = &self.conf.http().docs {
+            // Requests to every route can be turned away by the middleware configured above.
+            let mut openapi = self.openapi.clone();
+            if self.conf.http().rate_limit.is_some() {
+                openapi.rejects(StatusCode::TOO_MANY_REQUESTS);
+            }
+            if self.conf.http().auth.is_some() {
+                openapi.rejects(StatusCode::UNAUTHORIZED);
+            }
+            if self.conf.http().authorization.is_some() {
+                openapi.rejects(StatusCode::FORBIDDEN);
+            }
+            app = app.merge(openapi::router(docs, openapi.document(docs)));
+        }
+        if let Some(catalog) = &self.catalog {
+            app = app.layer(axum::middleware::from_fn_with_state(catalog.clone(), i18n::localize));
+        }
+        if self.conf.http().debug {
+            app = app.layer(axum::middleware::map_response(debug::expose_internals));
+        }
+        middleware::stack(app, self.conf.http(), guard)
+    }
 }
 
 #[async_trait]
-impl<C, S> Plugin for Server<C, S>
+impl<C> Plugin for Server<C>
     where C: Config + Send + Sync + 'static,
-          S: Service<Request<Body>, Response=Response, Error=Infallible>
-          + Clone
-          + Send
-          + Sync + 'static,
-          Router<S>: for<'a> Service<IncomingStream<'a>>,
 {
     async fn serve(&self, guard: ShutdownGuard) -> AnyResult<()> {
-        let r = Router::new()
-            .route("/status", get(status))
-            .route("/json", get(json));
-        // Create a regular axum app.
-        let app = Router::new()
-            .route("/slow", get(|| sleep(Duration::from_secs(5))))
-            .route("/forever", get(std::future::pending::<()>))
-            .merge(r)
-            .merge(self.r.clone())
-            .layer((
-                TraceLayer::new_for_http(),
-                // Graceful shutdown will wait for outstanding requests to complete. Add a timeout so
-                // requests don't hang forever.
-                TimeoutLayer::new(Duration::from_secs(10)),
-            ));
+        let app = self.app(&guard)?;
 
         // Create a `TcpListener` using tokio.
         let addr = self.conf.http().address.parse::<SocketAddr>()?;
-        let listener = TcpListener::bind(&addr).await.unwrap();
-        log::info!("Http Listening on {}", addr);
-
-        // Run the server with graceful shutdown
-        axum::serve(listener, app)
-            .with_graceful_shutdown(async move {
-                guard.cancelled().await
-            })
-            .await?;
+        let listener = TcpListener::bind(&addr).await?;
+        self.listening.set(listener.local_addr()?);
+
+        if let Some(conf) = &self.conf.http().tls {
+            let acceptor = bamboo_tls::Acceptor::new(conf, &tls::ALPN)?;
+            log::info!("Https Listening on {}", addr);
+            tls::serve(listener, app, acceptor, guard).await?;
+        } else {
+            log::info!("Http Listening on {}", addr);
+            // Run the server with graceful shutdown
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+                .with_graceful_shutdown(async move {
+                    guard.cancelled().await
+                })
+                .await?;
+        }
         log::info!("Http stopping");
         Ok(())
     }
-}
-
 
-// `StatusCode` gives an empty response with that status code
-async fn status() -> StatusCode {
-    StatusCode::NOT_FOUND
-}
-
-async fn json() -> Json<Vec<String>> {
-    Json(vec!["foo".to_owned(), "bar".to_owned()])
+    async fn listening(&self) -> Option<String> {
+        let scheme = if self.conf.http().tls.is_some() { "https" } else { "http" };
+        Some(format!("{}://{}", scheme, self.listening.addr().await))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    #[test]
-    fn it_works() {
-        assert_eq!(4, 4);
+    #[tokio::test]
+    async fn valid_serve() {
+        use std::sync::Arc;
+
+        use axum::{http::StatusCode, Router};
+        use bamboo_status::errors::Status;
+
+        use crate::{get, testing::TestServer, ApiResult, Config, Http, Jwt, Server, Success};
+
+        struct Conf(Http);
+
+        impl Config for Conf {
+            fn http(&self) -> &Http {
+                &self.0
+            }
+        }
+
+        async fn hello() -> ApiResult<String> {
+            Ok(Success("hello".to_string()))
+        }
+
+        async fn missing() -> ApiResult<String> {
+            Err(Status::new("NotFound", "no such user"))
+        }
+
+        let r = Router::new().route("/hello", get(hello)).route("/missing", get(missing));
+        let server = Server::new(Arc::new(Conf(Http::default())), r.clone());
+        let test = TestServer::new(&server).unwrap();
+        let res = test.get("/hello").await;
+        assert!(res.headers.contains_key("x-request-id"));
+        assert_eq!(res.assert_success::<String>(), "hello");
+        assert_eq!(test.get("/missing").await.assert_status("NotFound").message, "no such user");
+        test.shutdown().await;
+
+        let conf = Http {
+            auth: Some(Jwt::with_secret("secret")),
+            ..Default::default()
+        };
+        let test = TestServer::new(&Server::new(Arc::new(Conf(conf)), r)).unwrap();
+        let res = test.get("/hello").await;
+        assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+        res.assert_status("Unauthenticated");
+        test.shutdown().await;
     }
 
     #[tokio::test]
-    async fn valid_serve() {
-        assert_eq!(4, 4);
+    async fn valid_admin_auth() {
+        use std::sync::Arc;
+
+        use axum::{http::StatusCode, Router};
+        use serde::Serialize;
+
+        use crate::{testing::TestServer, Admin, Config, Http, Jwt, Server};
+
+        #[derive(Serialize)]
+        struct Conf(Http);
+
+        impl Config for Conf {
+            fn http(&self) -> &Http {
+                &self.0
+            }
+        }
+
+        let conf = Http {
+            auth: Some(Jwt::with_secret("secret")),
+            admin: Some(Admin::default()),
+            ..Default::default()
+        };
+        let server = Server::new(Arc::new(Conf(conf)), Router::new()).with_config_dump().unwrap();
+        let test = TestServer::new(&server).unwrap();
+        for uri in ["/admin/config", "/admin/metrics", "/admin/health"] {
+            assert_eq!(test.get(uri).await.status, StatusCode::UNAUTHORIZED, "{}", uri);
+        }
+        test.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn invalid_authorization_without_auth() {
+        use std::sync::Arc;
+
+        use axum::Router;
+        use tokio_graceful::Shutdown;
+
+        use crate::{Authorization, Config, Http, Server};
+
+        struct Conf(Http);
+
+        impl Config for Conf {
+            fn http(&self) -> &Http {
+                &self.0
+            }
+        }
+
+        let conf = Http {
+            authorization: Some(Authorization::default()),
+            ..Default::default()
+        };
+        let server = Server::new(Arc::new(Conf(conf)), Router::new());
+        let shutdown = Shutdown::default();
+        let err = server.app(&shutdown.guard()).unwrap_err();
+        assert_eq!(err.to_string(), "authorization needs auth to be configured");
+    }
+
+    #[tokio::test]
+    async fn valid_state() {
+        use std::sync::Arc;
+
+        use axum::{extract::State, Router};
+
+        use crate::{get, testing::TestServer, Config, Http, Server};
+
+        struct Conf(Http);
+
+        impl Config for Conf {
+            fn http(&self) -> &Http {
+                &self.0
+            }
+        }
+
+        let r = Router::new().route("/name", get(|State(name): State<&'static str>| async move { name }));
+        let server = Server::new(Arc::new(Conf(Http::default())), r).with_state("bamboo");
+        let test = TestServer::new(&server).unwrap();
+        assert_eq!(test.get("/name").await.text(), "bamboo");
+        test.shutdown().await;
     }
 }
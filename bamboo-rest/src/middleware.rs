@@ -0,0 +1,437 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    body::HttpBody,
+    extract::DefaultBodyLimit,
+    http::{header, HeaderName, HeaderValue, Method, Response},
+    Router,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use tokio_graceful::ShutdownGuard;
+use tower_http::{
+    compression::{
+        predicate::{NotForContentType, Predicate, SizeAbove},
+        CompressionLayer, CompressionLevel,
+    },
+    cors::{AllowOrigin, Any, CorsLayer},
+    decompression::RequestDecompressionLayer,
+    metrics::InFlightRequestsLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    sensitive_headers::{SetSensitiveRequestHeadersLayer, SetSensitiveResponseHeadersLayer},
+    timeout::TimeoutLayer,
+    trace::TraceLayer,
+};
+
+use bamboo_tower_http::{
+    log::{
+        on_failure::DefaultOnFailure,
+        on_request::DefaultOnRequest,
+        on_response::DefaultOnResponse,
+    },
+    request_id::MyMakeRequestId,
+};
+
+use bamboo_status::status::AnyResult;
+
+use crate::Http;
+
+/// How `x-request-id` is generated for requests that don't carry one.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestIdKind {
+    #[default]
+    Uuid,
+    /// A process-local counter.
+    Counter,
+    /// Leave requests without an id.
+    None,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Cors {
+    /// Allowed origins, `*` allows any.
+    #[serde(default)]
+    pub allow_origins: Vec<String>,
+    /// Allowed methods, `*` allows any.
+    #[serde(default)]
+    pub allow_methods: Vec<String>,
+    /// Allowed request headers, `*` allows any.
+    #[serde(default)]
+    pub allow_headers: Vec<String>,
+    #[serde(default)]
+    pub allow_credentials: bool,
+    #[serde(default, with = "humantime_serde")]
+    pub max_age: Option<Duration>,
+}
+
+impl Cors {
+    fn layer(&self) -> AnyResult<CorsLayer> {
+        if self.allow_credentials {
+            // tower-http panics on wildcards with credentials, browsers reject them anyway.
+            let wildcards = [("origins", &self.allow_origins), ("methods", &self.allow_methods), ("headers", &self.allow_headers)];
+            if let Some((what, _)) = wildcards.iter().find(|(_, values)| values.iter().any(|v| v == "*")) {
+                anyhow::bail!("cors: allow_{} can't be `*` with allow_credentials, list them instead", what);
+            }
+        }
+        let mut layer = CorsLayer::new();
+        if self.allow_origins.iter().any(|o| o == "*") {
+            layer = layer.allow_origin(Any);
+        } else {
+            let origins: Vec<HeaderValue> = parse_all(&self.allow_origins, "origin");
+            layer = layer.allow_origin(AllowOrigin::list(origins));
+        }
+        if self.allow_methods.iter().any(|m| m == "*") {
+            layer = layer.allow_methods(Any);
+        } else {
+            layer = layer.allow_methods(parse_all::<Method>(&self.allow_methods, "method"));
+        }
+        if self.allow_headers.iter().any(|h| h == "*") {
+            layer = layer.allow_headers(Any);
+        } else {
+            layer = layer.allow_headers(parse_all::<HeaderName>(&self.allow_headers, "header"));
+        }
+        if let Some(max_age) = self.max_age {
+            layer = layer.max_age(max_age);
+        }
+        Ok(layer.allow_credentials(self.allow_credentials))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Gzip,
+    Br,
+    Zstd,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Fastest,
+    #[default]
+    Default,
+    Best,
+    /// An algorithm specific level, clamped to its maximum.
+    Precise(i32),
+}
+
+impl From<Level> for CompressionLevel {
+    fn from(value: Level) -> Self {
+        match value {
+            Level::Fastest => CompressionLevel::Fastest,
+            Level::Default => CompressionLevel::Default,
+            Level::Best => CompressionLevel::Best,
+            Level::Precise(level) => CompressionLevel::Precise(level),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Compression {
+    /// Encodings offered to clients and accepted in requests.
+    #[serde(default = "default_encodings")]
+    pub encodings: Vec<Encoding>,
+    /// Responses smaller than this many bytes are sent as is.
+    #[serde(default = "default_min_size")]
+    pub min_size: u16,
+    /// Only compress these content types, like `application/json` or `text/*`. Everything but
+    /// images is compressed when empty; gRPC and SSE responses never are.
+    #[serde(default)]
+    pub content_types: Vec<String>,
+    #[serde(default)]
+    pub level: Level,
+    /// Decompress request bodies, those in other encodings are rejected with a 415.
+    #[serde(default = "default_decompress_requests")]
+    pub decompress_requests: bool,
+}
+
+fn default_encodings() -> Vec<Encoding> {
+    vec![Encoding::Gzip, Encoding::Br, Encoding::Zstd]
+}
+
+fn default_min_size() -> u16 {
+    1024
+}
+
+fn default_decompress_requests() -> bool {
+    true
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            encodings: default_encodings(),
+            min_size: default_min_size(),
+            content_types: Vec::new(),
+            level: Level::default(),
+            decompress_requests: default_decompress_requests(),
+        }
+    }
+}
+
+/// Read `compression` as a [`Compression`] table or as the boolean it used to be.
+pub(crate) fn deserialize_compression<'de, D>(deserializer: D) -> Result<Option<Compression>, D::Error>
+    where D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Enabled(bool),
+        Conf(Compression),
+    }
+    Ok(match Option::<Repr>::deserialize(deserializer)? {
+        Some(Repr::Enabled(true)) => Some(Compression::default()),
+        Some(Repr::Enabled(false)) | None => None,
+        Some(Repr::Conf(conf)) => Some(conf),
+    })
+}
+
+impl Compression {
+    fn layer(&self) -> CompressionLayer<Compressible> {
+        let enabled = |encoding| self.encodings.contains(&encoding);
+        let predicate = Compressible {
+            min_size: SizeAbove::new(self.min_size),
+            content_types: self.content_types.clone().into(),
+        };
+        CompressionLayer::new()
+            .gzip(enabled(Encoding::Gzip))
+            .br(enabled(Encoding::Br))
+            .zstd(enabled(Encoding::Zstd))
+            .deflate(false)
+            .quality(self.level.into())
+            .compress_when(predicate)
+    }
+
+    fn decompression_layer(&self) -> RequestDecompressionLayer {
+        let enabled = |encoding| self.encodings.contains(&encoding);
+        RequestDecompressionLayer::new()
+            .gzip(enabled(Encoding::Gzip))
+            .br(enabled(Encoding::Br))
+            .zstd(enabled(Encoding::Zstd))
+            .deflate(false)
+    }
+}
+
+/// Compress responses of at least `min_size` bytes with one of `content_types`.
+#[derive(Debug, Clone)]
+struct Compressible {
+    min_size: SizeAbove,
+    content_types: Arc<[String]>,
+}
+
+impl Predicate for Compressible {
+    fn should_compress<B>(&self, response: &Response<B>) -> bool
+        where B: HttpBody,
+    {
+        let streamed = NotForContentType::GRPC.and(NotForContentType::SSE);
+        if !self.min_size.and(streamed).should_compress(response) {
+            return false;
+        }
+        if self.content_types.is_empty() {
+            return NotForContentType::IMAGES.should_compress(response);
+        }
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        self.content_types.iter().any(|pattern| content_type_matches(pattern, content_type))
+    }
+}
+
+/// Whether `content_type`, parameters ignored, matches `pattern` like `image/png` or `image/*`.
+pub(crate) fn content_type_matches(pattern: &str, content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    match pattern.strip_suffix("/*") {
+        Some(kind) => essence.split('/').next().is_some_and(|k| k.eq_ignore_ascii_case(kind)),
+        None => pattern.eq_ignore_ascii_case(essence),
+    }
+}
+
+/// Parse config values, skipping (and logging) the invalid ones.
+fn parse_all<T: std::str::FromStr>(values: &[String], what: &str) -> Vec<T> {
+    values
+        .iter()
+        .filter_map(|v| match v.parse() {
+            Ok(v) => Some(v),
+            Err(_) => {
+                log::warn!("cors: ignoring invalid {} {:?}", what, v);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Wrap `app` in the middleware stack configured by `conf`.
+///
+/// From the outside in: sensitive request headers, request id, trace, sensitive response
+/// headers, request id propagation, in-flight metrics, CORS, timeout, request decompression,
+/// response compression and the body limit.
+pub(crate) fn stack(mut app: Router, conf: &Http, guard: &ShutdownGuard) -> AnyResult<Router> {
+    if conf.body_limit > 0 {
+        app = app.layer(DefaultBodyLimit::max(conf.body_limit));
+    }
+    if let Some(compression) = &conf.compression {
+        app = app.layer(compression.layer());
+        if compression.decompress_requests {
+            app = app.layer(compression.decompression_layer());
+        }
+    }
+    // Graceful shutdown will wait for outstanding requests to complete, the timeout keeps
+    // requests from hanging forever.
+    app = app.layer(TimeoutLayer::new(conf.timeout));
+    if let Some(cors) = &conf.cors {
+        app = app.layer(cors.layer()?);
+    }
+    if let Some(interval) = conf.in_flight_interval {
+        let (layer, counter) = InFlightRequestsLayer::pair();
+        app = app.layer(layer);
+        let guard = guard.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = counter.run_emitter(interval, |count| async move {
+                    log::info!("Http {} requests in flight", count);
+                }) => {}
+                _ = guard.cancelled() => {}
+            }
+        });
+    }
+
+    let sensitive_headers: Arc<[_]> = vec![header::AUTHORIZATION, header::COOKIE, header::SET_COOKIE].into();
+    app = app
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetSensitiveResponseHeadersLayer::from_shared(sensitive_headers.clone()))
+        .layer(
+            TraceLayer::new_for_http()
+                .on_request(DefaultOnRequest::default())
+                .on_response(DefaultOnResponse::default())
+                .on_failure(DefaultOnFailure::default()),
+        );
+    app = match conf.request_id {
+        RequestIdKind::Uuid => app.layer(SetRequestIdLayer::x_request_id(MakeRequestUuid)),
+        RequestIdKind::Counter => app.layer(SetRequestIdLayer::x_request_id(MyMakeRequestId::default())),
+        RequestIdKind::None => app,
+    };
+    Ok(app.layer(SetSensitiveRequestHeadersLayer::from_shared(sensitive_headers)))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, extract::Request, routing::get};
+    use tokio_graceful::Shutdown;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn http() -> Http {
+        Http {
+            body_limit: 4,
+            cors: Some(Cors {
+                allow_origins: vec!["https://example.com".to_string()],
+                allow_methods: vec!["GET".to_string()],
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn valid_compression_bool() {
+        let http = |compression: &str| {
+            let json = format!(r#"{{"address": "0.0.0.0:8080", "compression": {}}}"#, compression);
+            serde_json::from_str::<Http>(&json).unwrap().compression
+        };
+        assert_eq!(http("true").unwrap().encodings, default_encodings());
+        assert!(http("false").is_none());
+        assert!(http("null").is_none());
+        let conf = http(r#"{"encodings": ["gzip"], "min_size": 10}"#).unwrap();
+        assert_eq!((conf.encodings, conf.min_size), (vec![Encoding::Gzip], 10));
+        assert!(serde_json::from_str::<Http>(r#"{"address": ""}"#).unwrap().compression.is_none());
+    }
+
+    #[tokio::test]
+    async fn valid_stack() {
+        let shutdown = Shutdown::default();
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }).post(|body: String| async move { body }));
+        let app = stack(app, &http(), &shutdown.guard()).unwrap();
+
+        let req = Request::get("/")
+            .header(header::ORIGIN, "https://example.com")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert!(res.headers().contains_key("x-request-id"));
+        assert_eq!(res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://example.com");
+
+        let req = Request::get("/")
+            .header("x-request-id", "abc")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.headers()["x-request-id"], "abc");
+
+        let req = Request::post("/").body(Body::from("too long")).unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), 413);
+    }
+
+    #[tokio::test]
+    async fn invalid_cors_credentials() {
+        let shutdown = Shutdown::default();
+        let mut conf = http();
+        let cors = conf.cors.as_mut().unwrap();
+        cors.allow_origins = vec!["*".to_string()];
+        cors.allow_credentials = true;
+        let err = stack(Router::new(), &conf, &shutdown.guard()).unwrap_err();
+        assert!(err.to_string().contains("allow_origins"));
+    }
+
+    #[tokio::test]
+    async fn valid_compression() {
+        let shutdown = Shutdown::default();
+        let conf = Http {
+            compression: Some(Compression {
+                encodings: vec![Encoding::Gzip],
+                min_size: 16,
+                content_types: vec!["text/*".to_string()],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let text = "bamboo ".repeat(64);
+        let app = Router::new()
+            .route("/text", get(move || async move { text }))
+            .route("/short", get(|| async { "short" }))
+            .route("/json", get(|| async { axum::Json("bamboo ".repeat(64)) }))
+            .route("/echo", axum::routing::post(|body: String| async move { body }));
+        let app = stack(app, &conf, &shutdown.guard()).unwrap();
+        let get = |uri: &str, encoding: &str| {
+            let req = Request::get(uri)
+                .header(header::ACCEPT_ENCODING, encoding)
+                .body(Body::empty())
+                .unwrap();
+            app.clone().oneshot(req)
+        };
+
+        let res = get("/text", "br, gzip").await.unwrap();
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+        let gzipped = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert!(gzipped.len() < 64);
+        assert!(!get("/text", "br").await.unwrap().headers().contains_key(header::CONTENT_ENCODING));
+        assert!(!get("/short", "gzip").await.unwrap().headers().contains_key(header::CONTENT_ENCODING));
+        assert!(!get("/json", "gzip").await.unwrap().headers().contains_key(header::CONTENT_ENCODING));
+
+        let post = |encoding: &str| {
+            Request::post("/echo")
+                .header(header::CONTENT_ENCODING, encoding)
+                .body(Body::from(gzipped.clone()))
+                .unwrap()
+        };
+        let res = app.clone().oneshot(post("gzip")).await.unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "bamboo ".repeat(64));
+        assert_eq!(app.oneshot(post("zstd")).await.unwrap().status(), 415);
+    }
+}
@@ -0,0 +1,767 @@
+//! OpenAPI 3 documents generated from the handlers registered with [`Group::api`].
+//!
+//! Only those handlers are documented. Routes added with [`Group::route`](crate::Group::route)
+//! or [`Group::merge`](crate::Group::merge), the router given to
+//! [`Server::new`](crate::Server::new) and the admin endpoints don't show up in the document, as
+//! axum gives no way to look into their handlers.
+//!
+//! Request and response schemas come from the handler signature: `ValidatedJson<T>`,
+//! `ValidatedQuery<T>`, `ValidatedPath<T>`, ... describe the request and
+//! [`ApiResult<T>`](crate::ApiResult) the response. Types derive [`JsonSchema`], which also
+//! turns `#[validate(...)]` rules into schema keywords (`minLength`, `maximum`, `format`, ...).
+//!
+//! ```
+//! use bamboo_rest::{openapi::{self, JsonSchema}, validate::ValidatedJson, ApiResult, Group, Success};
+//! use serde::{Deserialize, Serialize};
+//! use validator::Validate;
+//!
+//! #[derive(Deserialize, Validate, JsonSchema)]
+//! struct CreateUser {
+//!     #[validate(length(min = 1, max = 32))]
+//!     name: String,
+//! }
+//!
+//! #[derive(Serialize, JsonSchema)]
+//! struct User {
+//!     id: u64,
+//! }
+//!
+//! async fn create(ValidatedJson(req): ValidatedJson<CreateUser>) -> ApiResult<User> {
+//!     Ok(Success(User { id: req.name.len() as u64 }))
+//! }
+//!
+//! let users = Group::<()>::new("/users")
+//!     .version("v1")
+//!     .api("/", openapi::post(create).summary("Create a user"));
+//! ```
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    future::Future,
+    sync::Arc,
+};
+
+use axum::{
+    extract::{ws::WebSocketUpgrade, Path, Query, Request, State},
+    handler::Handler,
+    http::{HeaderMap, Method, StatusCode},
+    response::{Html, Response},
+    routing::{self, MethodRouter},
+    Extension, Json, Router,
+};
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::{Schema, SchemaObject},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use bamboo_status::{errors::Status, spring::Success};
+
+pub use schemars::{self, JsonSchema};
+
+use crate::auth::Claims;
+use crate::streaming::Streams;
+use crate::upload::Uploads;
+use crate::validate::{
+    ValidatedForm, ValidatedHeaders, ValidatedJson, ValidatedMultipart, ValidatedPath, ValidatedQuery,
+};
+
+/// Serve the OpenAPI document, and optionally a Swagger UI for it.
+///
+/// The document lists the routes added with [`Group::api`](crate::Group::api) only.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Docs {
+    #[serde(default = "default_title")]
+    pub title: String,
+    #[serde(default = "default_version")]
+    pub version: String,
+    /// Where the JSON document is served.
+    #[serde(default = "default_path")]
+    pub path: String,
+    /// Where the Swagger UI is served, disabled when missing.
+    #[serde(default)]
+    pub swagger_ui: Option<String>,
+    /// Base URL of the `swagger-ui-dist` assets, point it to a local copy when offline.
+    #[serde(default = "default_swagger_ui_assets")]
+    pub swagger_ui_assets: String,
+}
+
+fn default_title() -> String {
+    "API".to_string()
+}
+
+fn default_version() -> String {
+    "1.0.0".to_string()
+}
+
+fn default_path() -> String {
+    "/openapi.json".to_string()
+}
+
+fn default_swagger_ui_assets() -> String {
+    "https://unpkg.com/swagger-ui-dist@5".to_string()
+}
+
+impl Default for Docs {
+    fn default() -> Self {
+        Self {
+            title: default_title(),
+            version: default_version(),
+            path: default_path(),
+            swagger_ui: None,
+            swagger_ui_assets: default_swagger_ui_assets(),
+        }
+    }
+}
+
+/// One operation of the document.
+#[derive(Debug, Clone, Default)]
+pub struct Operation {
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    parameters: Vec<Value>,
+    request_body: Option<Value>,
+    /// Content type and schema of the successful response.
+    response: Option<(String, Option<Value>)>,
+    /// Whether the handler can fail with a [`Status`].
+    errors: bool,
+    /// HTTP statuses of the [`Rejection`](crate::Rejection)s of the request.
+    rejections: BTreeSet<u16>,
+    definitions: BTreeMap<String, Value>,
+}
+
+impl Operation {
+    /// Document a request parameter for each property of `T`.
+    pub fn parameters<T: JsonSchema>(&mut self, gen: &mut SchemaGenerator, location: &str) {
+        let schema = gen.subschema_for::<T>();
+        let schema = resolve(gen, schema);
+        let Some(object) = schema.object else {
+            return;
+        };
+        for (name, schema) in object.properties {
+            self.parameters.push(json!({
+                "name": name,
+                "in": location,
+                // Path parameters are always required.
+                "required": location == "path" || object.required.contains(&name),
+                "schema": schema,
+            }));
+        }
+    }
+
+    /// Document a request body of `T`.
+    pub fn request_body<T: JsonSchema>(&mut self, gen: &mut SchemaGenerator, content_type: &str) {
+        self.request_body = Some(json!({
+            "required": true,
+            "content": { content_type: { "schema": gen.subschema_for::<T>() } },
+        }));
+    }
+
+    /// Document the successful response, `schema` is `None` for bodies without one.
+    pub fn response(&mut self, content_type: &str, schema: Option<Value>) {
+        self.response = Some((content_type.to_string(), schema));
+    }
+
+    /// Document that the handler answers with the [`Status`] error envelope.
+    pub fn errors(&mut self) {
+        self.errors = true;
+    }
+
+    /// Document that the request can be rejected with the [`Status`] error envelope and the
+    /// HTTP status `code`.
+    pub fn rejects(&mut self, code: StatusCode) {
+        self.rejections.insert(code.as_u16());
+    }
+
+    fn to_value(&self) -> Value {
+        let error = json!({ "$ref": "#/components/schemas/Status" });
+        // Errors returned by the handler are rendered with HTTP 200, so they share the response.
+        let content = match (&self.response, self.errors) {
+            (Some((content_type, Some(schema))), true) if content_type == "application/json" => {
+                json!({ content_type: { "schema": { "oneOf": [schema, error] } } })
+            }
+            (Some((content_type, Some(schema))), _) => json!({ content_type: { "schema": schema } }),
+            (Some((content_type, None)), _) => json!({ content_type: {} }),
+            (None, true) => json!({ "application/json": { "schema": error } }),
+            (None, false) => json!({}),
+        };
+        let mut responses = json!({ "200": { "description": "OK", "content": content } });
+        for code in &self.rejections {
+            let description = StatusCode::from_u16(*code).ok().and_then(|c| c.canonical_reason()).unwrap_or("Rejected");
+            responses[code.to_string()] = json!({
+                "description": description,
+                "content": { "application/json": { "schema": error } },
+            });
+        }
+        let mut op = json!({ "responses": responses });
+        if let Some(summary) = &self.summary {
+            op["summary"] = json!(summary);
+        }
+        if let Some(description) = &self.description {
+            op["description"] = json!(description);
+        }
+        if !self.tags.is_empty() {
+            op["tags"] = json!(self.tags);
+        }
+        if !self.parameters.is_empty() {
+            op["parameters"] = json!(self.parameters);
+        }
+        if let Some(body) = &self.request_body {
+            op["requestBody"] = body.clone();
+        }
+        op
+    }
+}
+
+/// Follow a `$ref` to its definition.
+fn resolve(gen: &SchemaGenerator, schema: Schema) -> SchemaObject {
+    let object = schema.into_object();
+    let name = object.reference.as_deref().and_then(|r| r.rsplit('/').next());
+    match name.and_then(|name| gen.definitions().get(name)) {
+        Some(definition) => definition.clone().into_object(),
+        None => object,
+    }
+}
+
+fn generator() -> SchemaGenerator {
+    SchemaSettings::openapi3().into_generator()
+}
+
+/// A handler argument that shows up in the document.
+///
+/// Extractors that aren't part of the API contract (state, extensions, ...) keep the
+/// default, which documents nothing.
+pub trait OperationInput {
+    fn describe(_op: &mut Operation, _gen: &mut SchemaGenerator) {}
+}
+
+/// A handler return type that shows up in the document.
+pub trait OperationOutput {
+    fn describe(_op: &mut Operation, _gen: &mut SchemaGenerator) {}
+}
+
+/// Statuses of the rejections of the `Validated*` body extractors: invalid, too large, or of
+/// another content type.
+const BODY_REJECTIONS: &[StatusCode] =
+    &[StatusCode::BAD_REQUEST, StatusCode::PAYLOAD_TOO_LARGE, StatusCode::UNSUPPORTED_MEDIA_TYPE];
+
+macro_rules! impl_body_input {
+    ($content_type:literal, $rejections:expr, $($ty:ident),*) => {
+        $(
+            impl<T: JsonSchema> OperationInput for $ty<T> {
+                fn describe(op: &mut Operation, gen: &mut SchemaGenerator) {
+                    op.request_body::<T>(gen, $content_type);
+                    for code in $rejections {
+                        op.rejects(*code);
+                    }
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_parameters_input {
+    ($location:literal, $rejections:expr, $($ty:ident),*) => {
+        $(
+            impl<T: JsonSchema> OperationInput for $ty<T> {
+                fn describe(op: &mut Operation, gen: &mut SchemaGenerator) {
+                    op.parameters::<T>(gen, $location);
+                    for code in $rejections {
+                        op.rejects(*code);
+                    }
+                }
+            }
+        )*
+    };
+}
+
+// Axum's own extractors reject with plain text, not with the envelope.
+impl_body_input!("application/json", BODY_REJECTIONS, ValidatedJson);
+impl_body_input!("application/json", &[] as &[StatusCode], Json);
+impl_body_input!("application/x-www-form-urlencoded", BODY_REJECTIONS, ValidatedForm);
+impl_body_input!("multipart/form-data", BODY_REJECTIONS, ValidatedMultipart);
+impl_parameters_input!("query", &[StatusCode::BAD_REQUEST], ValidatedQuery);
+impl_parameters_input!("query", &[] as &[StatusCode], Query);
+impl_parameters_input!("path", &[StatusCode::BAD_REQUEST], ValidatedPath);
+impl_parameters_input!("path", &[] as &[StatusCode], Path);
+impl_parameters_input!("header", &[StatusCode::BAD_REQUEST], ValidatedHeaders);
+
+impl<T> OperationInput for State<T> {}
+impl<T> OperationInput for Extension<T> {}
+impl<T: OperationInput> OperationInput for Option<T> {}
+impl OperationInput for HeaderMap {}
+impl OperationInput for Request {}
+impl OperationInput for String {}
+impl OperationInput for bytes::Bytes {}
+impl OperationInput for Streams {}
+impl OperationInput for WebSocketUpgrade {}
+
+impl OperationInput for Uploads {
+    fn describe(op: &mut Operation, _gen: &mut SchemaGenerator) {
+        for code in BODY_REJECTIONS {
+            op.rejects(*code);
+        }
+    }
+}
+
+impl<T> OperationInput for Claims<T> {
+    fn describe(op: &mut Operation, _gen: &mut SchemaGenerator) {
+        op.rejects(StatusCode::UNAUTHORIZED);
+    }
+}
+
+impl<T: JsonSchema> OperationOutput for Success<T> {
+    fn describe(op: &mut Operation, gen: &mut SchemaGenerator) {
+        let schema = json!({
+            "type": "object",
+            "required": ["success", "code", "message", "data"],
+            "properties": {
+                "success": { "type": "boolean", "enum": [true] },
+                "code": { "type": "string", "enum": ["OK"] },
+                "message": { "type": "string" },
+                "data": gen.subschema_for::<T>(),
+            },
+        });
+        op.response("application/json", Some(schema));
+    }
+}
+
+impl<T: JsonSchema> OperationOutput for Json<T> {
+    fn describe(op: &mut Operation, gen: &mut SchemaGenerator) {
+        op.response("application/json", Some(json!(gen.subschema_for::<T>())));
+    }
+}
+
+impl<T: OperationOutput, E: OperationOutput> OperationOutput for Result<T, E> {
+    fn describe(op: &mut Operation, gen: &mut SchemaGenerator) {
+        T::describe(op, gen);
+        E::describe(op, gen);
+    }
+}
+
+impl OperationOutput for Status {
+    fn describe(op: &mut Operation, _gen: &mut SchemaGenerator) {
+        op.errors();
+    }
+}
+
+impl OperationOutput for String {
+    fn describe(op: &mut Operation, _gen: &mut SchemaGenerator) {
+        op.response("text/plain", Some(json!({ "type": "string" })));
+    }
+}
+
+impl OperationOutput for &'static str {
+    fn describe(op: &mut Operation, _gen: &mut SchemaGenerator) {
+        op.response("text/plain", Some(json!({ "type": "string" })));
+    }
+}
+
+impl OperationOutput for () {}
+impl OperationOutput for Response {}
+
+/// Handlers whose arguments and return type describe an [`Operation`].
+pub trait DocHandler<Args> {
+    fn operation() -> Operation;
+}
+
+macro_rules! impl_doc_handler {
+    ($($ty:ident),*) => {
+        #[allow(non_snake_case, unused_mut)]
+        impl<F, Fut, R, $($ty,)*> DocHandler<($($ty,)*)> for F
+            where
+                F: FnOnce($($ty,)*) -> Fut,
+                Fut: Future<Output = R>,
+                R: OperationOutput,
+                $($ty: OperationInput,)*
+        {
+            fn operation() -> Operation {
+                let mut gen = generator();
+                let mut op = Operation::default();
+                $($ty::describe(&mut op, &mut gen);)*
+                R::describe(&mut op, &mut gen);
+                op.definitions = gen
+                    .take_definitions()
+                    .into_iter()
+                    .map(|(name, schema)| (name, json!(schema)))
+                    .collect();
+                op
+            }
+        }
+    };
+}
+
+impl_doc_handler!();
+impl_doc_handler!(T1);
+impl_doc_handler!(T1, T2);
+impl_doc_handler!(T1, T2, T3);
+impl_doc_handler!(T1, T2, T3, T4);
+impl_doc_handler!(T1, T2, T3, T4, T5);
+impl_doc_handler!(T1, T2, T3, T4, T5, T6);
+impl_doc_handler!(T1, T2, T3, T4, T5, T6, T7);
+impl_doc_handler!(T1, T2, T3, T4, T5, T6, T7, T8);
+
+/// A [`MethodRouter`] that also documents its handlers, see [`get`], [`post`], ...
+pub struct ApiRouter<S = ()> {
+    pub(crate) router: MethodRouter<S>,
+    pub(crate) operations: Vec<(Method, Operation)>,
+}
+
+macro_rules! api_method {
+    ($($name:ident => $method:ident),*) => {
+        $(
+            #[doc = concat!("Route `", stringify!($method), "` requests to a documented handler.")]
+            pub fn $name<H, T, D, S>(handler: H) -> ApiRouter<S>
+                where
+                    H: Handler<T, S> + DocHandler<D>,
+                    T: 'static,
+                    S: Clone + Send + Sync + 'static,
+            {
+                ApiRouter {
+                    router: routing::$name(handler),
+                    operations: vec![(Method::$method, H::operation())],
+                }
+            }
+        )*
+
+        impl<S> ApiRouter<S>
+            where S: Clone + Send + Sync + 'static,
+        {
+            $(
+                #[doc = concat!("Also route `", stringify!($method), "` requests to a documented handler.")]
+                pub fn $name<H, T, D>(mut self, handler: H) -> Self
+                    where
+                        H: Handler<T, S> + DocHandler<D>,
+                        T: 'static,
+                {
+                    self.router = self.router.$name(handler);
+                    self.operations.push((Method::$method, H::operation()));
+                    self
+                }
+            )*
+        }
+    };
+}
+
+api_method!(get => GET, post => POST, put => PUT, patch => PATCH, delete => DELETE);
+
+impl<S> ApiRouter<S> {
+    fn last(&mut self) -> Option<&mut Operation> {
+        self.operations.last_mut().map(|(_, op)| op)
+    }
+
+    /// Summary of the handler added last.
+    pub fn summary(mut self, summary: &str) -> Self {
+        if let Some(op) = self.last() {
+            op.summary = Some(summary.to_string());
+        }
+        self
+    }
+
+    /// Description of the handler added last.
+    pub fn description(mut self, description: &str) -> Self {
+        if let Some(op) = self.last() {
+            op.description = Some(description.to_string());
+        }
+        self
+    }
+
+    /// Tag the handler added last.
+    pub fn tag(mut self, tag: &str) -> Self {
+        if let Some(op) = self.last() {
+            op.tags.push(tag.to_string());
+        }
+        self
+    }
+}
+
+/// The documented operations, keyed by OpenAPI path and method.
+#[derive(Debug, Clone, Default)]
+pub struct OpenApi {
+    paths: BTreeMap<String, BTreeMap<String, Operation>>,
+}
+
+impl OpenApi {
+    /// Add an operation at an axum `path`, `/:id` and `/*rest` become `/{id}` and `/{rest}`.
+    pub fn add(&mut self, path: &str, method: &Method, op: Operation) {
+        let path: Vec<String> = path
+            .split('/')
+            .map(|s| match s.strip_prefix(':').or_else(|| s.strip_prefix('*')) {
+                Some(name) => format!("{{{}}}", name),
+                None => s.to_string(),
+            })
+            .collect();
+        self.paths
+            .entry(path.join("/"))
+            .or_default()
+            .insert(method.as_str().to_ascii_lowercase(), op);
+    }
+
+    /// Document that every operation can be rejected with `code`, by a middleware in front of
+    /// all the routes.
+    pub fn rejects(&mut self, code: StatusCode) {
+        for op in self.paths.values_mut().flat_map(BTreeMap::values_mut) {
+            op.rejects(code);
+        }
+    }
+
+    pub fn document(&self, docs: &Docs) -> Value {
+        let mut schemas = error_schemas();
+        let mut paths = serde_json::Map::new();
+        for (path, ops) in &self.paths {
+            let mut item = serde_json::Map::new();
+            for (method, op) in ops {
+                for (name, schema) in &op.definitions {
+                    schemas.entry(name.clone()).or_insert_with(|| schema.clone());
+                }
+                item.insert(method.clone(), op.to_value());
+            }
+            paths.insert(path.clone(), Value::Object(item));
+        }
+        json!({
+            "openapi": "3.0.3",
+            "info": { "title": docs.title, "version": docs.version },
+            "paths": paths,
+            "components": { "schemas": schemas },
+        })
+    }
+}
+
+/// Schemas of the [`Status`] error envelope.
+fn error_schemas() -> serde_json::Map<String, Value> {
+    let schemas = json!({
+        "Status": {
+            "description": "Error envelope. Errors of the handlers come with HTTP 200, rejected requests with the HTTP status of their code.",
+            "type": "object",
+            "required": ["success", "code", "message", "data"],
+            "properties": {
+                "success": { "type": "boolean", "enum": [false] },
+                "code": { "type": "string", "description": "Machine readable reason, e.g. `UserNotFound`." },
+                "message": { "type": "string", "description": "Human readable, possibly localized, message." },
+                "data": {
+                    "description": "Field violations of invalid requests, an empty string otherwise.",
+                    "oneOf": [
+                        { "$ref": "#/components/schemas/BadRequest" },
+                        { "type": "string" },
+                    ],
+                },
+            },
+        },
+        "BadRequest": {
+            "type": "object",
+            "properties": {
+                "field_violations": {
+                    "type": "array",
+                    "items": { "$ref": "#/components/schemas/FieldViolation" },
+                },
+            },
+        },
+        "FieldViolation": {
+            "type": "object",
+            "properties": {
+                "field": { "type": "string", "description": "Path to the field, e.g. `items[0].name`." },
+                "description": { "type": "string" },
+                "reason": { "type": "string", "description": "The violated rule, e.g. `length`." },
+                "localized_message": { "type": "string" },
+                "params": { "type": "object", "additionalProperties": { "type": "string" } },
+            },
+        },
+    });
+    match schemas {
+        Value::Object(map) => map,
+        _ => unreachable!(),
+    }
+}
+
+/// Routes serving `document` and the Swagger UI.
+pub(crate) fn router(docs: &Docs, document: Value) -> Router {
+    let document = Arc::new(document);
+    let path = crate::routes::join(&[&docs.path]);
+    let mut r = Router::new().route(
+        &path,
+        routing::get(move || async move { Json(document.as_ref().clone()) }),
+    );
+    if let Some(ui) = &docs.swagger_ui {
+        let html = swagger_ui(docs, &path);
+        r = r.route(
+            &crate::routes::join(&[ui]),
+            routing::get(move || async move { Html(html.clone()) }),
+        );
+    }
+    r
+}
+
+fn swagger_ui(docs: &Docs, path: &str) -> String {
+    let title = docs.title.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    let assets = docs.swagger_ui_assets.trim_end_matches('/');
+    let assets = assets.replace('"', "%22");
+    format!(
+        r##"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<link rel="stylesheet" href="{assets}/swagger-ui.css">
+</head>
+<body>
+<div id="swagger-ui"></div>
+<script src="{assets}/swagger-ui-bundle.js"></script>
+<script>window.ui = SwaggerUIBundle({{ url: {url}, dom_id: "#swagger-ui" }});</script>
+</body>
+</html>
+"##,
+        title = title,
+        assets = assets,
+        url = json!(path),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use tower::ServiceExt;
+    use validator::Validate;
+
+    use super::*;
+    use crate::{routes::Group, ApiResult};
+
+    #[derive(Deserialize, Validate, JsonSchema)]
+    struct CreateUser {
+        #[validate(length(min = 1, max = 32))]
+        name: String,
+        #[validate(email)]
+        email: String,
+        #[validate(range(min = 18, max = 150))]
+        age: Option<u8>,
+    }
+
+    #[derive(Deserialize, Validate, JsonSchema)]
+    struct UserPath {
+        id: u64,
+    }
+
+    #[derive(Serialize, JsonSchema)]
+    struct User {
+        id: u64,
+        name: String,
+    }
+
+    async fn create(ValidatedJson(req): ValidatedJson<CreateUser>) -> ApiResult<User> {
+        Ok(Success(User { id: 1, name: req.name }))
+    }
+
+    async fn find(ValidatedPath(path): ValidatedPath<UserPath>) -> ApiResult<User> {
+        Ok(Success(User { id: path.id, name: String::new() }))
+    }
+
+    async fn ping() -> &'static str {
+        "pong"
+    }
+
+    fn document() -> Value {
+        let mut openapi = OpenApi::default();
+        let users = Group::<()>::new("/users")
+            .version("v1")
+            .tag("users")
+            .api("/", post(create).summary("Create a user"))
+            .api("/:id", get(find));
+        let root = Group::<()>::new("").api("/ping", get(ping).tag("health"));
+        let _ = root.mount(users.mount(Router::new(), &mut openapi), &mut openapi);
+        openapi.document(&Docs::default())
+    }
+
+    #[test]
+    fn valid_document() {
+        let doc = document();
+        assert_eq!(doc["openapi"], "3.0.3");
+
+        let create = &doc["paths"]["/v1/users"]["post"];
+        assert_eq!(create["summary"], "Create a user");
+        assert_eq!(create["tags"], json!(["users"]));
+        assert_eq!(
+            create["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/CreateUser"
+        );
+        let schema = &create["responses"]["200"]["content"]["application/json"]["schema"];
+        assert_eq!(schema["oneOf"][0]["properties"]["data"]["$ref"], "#/components/schemas/User");
+        assert_eq!(schema["oneOf"][1]["$ref"], "#/components/schemas/Status");
+        for code in ["400", "413", "415"] {
+            let schema = &create["responses"][code]["content"]["application/json"]["schema"];
+            assert_eq!(schema["$ref"], "#/components/schemas/Status", "{}", code);
+        }
+        assert_eq!(create["responses"]["413"]["description"], "Payload Too Large");
+
+        let find = &doc["paths"]["/v1/users/{id}"]["get"];
+        assert_eq!(find["parameters"][0]["name"], "id");
+        assert_eq!(find["parameters"][0]["in"], "path");
+        assert_eq!(find["parameters"][0]["required"], true);
+        assert!(find["responses"]["400"].is_object());
+        assert!(find["responses"]["413"].is_null());
+
+        let ping = &doc["paths"]["/ping"]["get"];
+        assert_eq!(ping["tags"], json!(["health"]));
+        assert_eq!(ping["responses"]["200"]["content"]["text/plain"]["schema"]["type"], "string");
+        assert_eq!(ping["responses"].as_object().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn valid_rejections() {
+        #[derive(Deserialize)]
+        struct Token {}
+
+        async fn me(Claims(_): Claims<Token>) -> &'static str {
+            "me"
+        }
+
+        let mut openapi = OpenApi::default();
+        let _ = Group::<()>::new("").api("/me", get(me)).api("/ping", get(ping)).mount(Router::new(), &mut openapi);
+        openapi.rejects(StatusCode::TOO_MANY_REQUESTS);
+        let doc = openapi.document(&Docs::default());
+
+        let me = &doc["paths"]["/me"]["get"]["responses"];
+        assert_eq!(me["401"]["content"]["application/json"]["schema"]["$ref"], "#/components/schemas/Status");
+        assert!(me["200"]["content"]["text/plain"].is_object());
+        assert_eq!(me["429"]["description"], "Too Many Requests");
+        assert!(doc["paths"]["/ping"]["get"]["responses"]["429"].is_object());
+        assert!(doc["paths"]["/ping"]["get"]["responses"]["401"].is_null());
+    }
+
+    #[test]
+    fn valid_constraints() {
+        let doc = document();
+        let schemas = &doc["components"]["schemas"];
+        let user = &schemas["CreateUser"]["properties"];
+        assert_eq!(user["name"]["minLength"], 1);
+        assert_eq!(user["name"]["maxLength"], 32);
+        assert_eq!(user["email"]["format"], "email");
+        assert_eq!(user["age"]["minimum"], 18.0);
+        assert_eq!(user["age"]["maximum"], 150.0);
+        assert_eq!(schemas["CreateUser"]["required"], json!(["email", "name"]));
+        assert_eq!(schemas["Status"]["properties"]["data"]["oneOf"][0]["$ref"], "#/components/schemas/BadRequest");
+        assert!(schemas["FieldViolation"]["properties"]["field"].is_object());
+    }
+
+    #[tokio::test]
+    async fn valid_router() {
+        let docs = Docs {
+            swagger_ui: Some("/swagger-ui".to_string()),
+            ..Default::default()
+        };
+        let app = router(&docs, document());
+
+        let req = Request::get("/openapi.json").body(Body::empty()).unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert!(body["paths"]["/v1/users"].is_object());
+
+        let req = Request::get("/swagger-ui").body(Body::empty()).unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(r#"url: "/openapi.json""#));
+        assert!(body.contains("https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"));
+    }
+}
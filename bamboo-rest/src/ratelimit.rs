@@ -0,0 +1,464 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use tokio::time::Instant;
+
+use bamboo_status::errors::Status;
+use bamboo_auth::path_matches;
+use bamboo_tls::PeerIdentity;
+
+use crate::rejection::reject;
+
+/// What identifies a client.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitKey {
+    /// The peer address, or the client address in `forwarded_header` behind `trusted_proxies`.
+    #[default]
+    Ip,
+    /// The value of `header`, e.g. an API key, or the address of clients without it. Clients
+    /// pick the value, and get a fresh quota with every new one, so only use it behind a proxy
+    /// or gateway that sets or verifies the header.
+    Header,
+    /// The authenticated [`Subject`], or the mTLS client certificate.
+    Subject,
+}
+
+/// `rate` requests per `per`, allowing bursts of up to `burst` requests.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Quota {
+    /// At least 1.
+    #[serde(deserialize_with = "deserialize_rate")]
+    pub rate: u32,
+    #[serde(with = "humantime_serde")]
+    pub per: Duration,
+    /// Defaults to `rate`.
+    #[serde(default)]
+    pub burst: Option<u32>,
+}
+
+/// Read a `rate`, refusing 0 which would block every request.
+fn deserialize_rate<'de, D>(deserializer: D) -> Result<u32, D::Error>
+    where D: Deserializer<'de>,
+{
+    match u32::deserialize(deserializer)? {
+        0 => Err(serde::de::Error::custom("rate must be at least 1")),
+        rate => Ok(rate),
+    }
+}
+
+impl Quota {
+    /// # Panics
+    ///
+    /// When `rate` is 0.
+    pub fn new(rate: u32, per: Duration) -> Self {
+        assert!(rate > 0, "rate must be at least 1");
+        Self { rate, per, burst: None }
+    }
+
+    /// Time between two requests at the sustained rate.
+    fn interval(&self) -> Duration {
+        self.per / self.rate.max(1)
+    }
+
+    /// How far ahead of the sustained rate a client may get.
+    fn tolerance(&self) -> Duration {
+        self.interval() * self.burst.unwrap_or(self.rate).max(1).saturating_sub(1)
+    }
+}
+
+/// A quota for one route.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RouteQuota {
+    /// The axum route, e.g. `/v1/users/:id`, or a path prefix like `/v1/users`.
+    pub path: String,
+    /// Only limit this method, all methods when missing.
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(flatten)]
+    pub quota: Quota,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RateLimit {
+    #[serde(default)]
+    pub key: RateLimitKey,
+    /// Header holding the client key when `key` is `header`.
+    #[serde(default = "default_header")]
+    pub header: String,
+    /// Header set by proxies with the client address, e.g. `x-forwarded-for`. Only read on
+    /// requests from `trusted_proxies`, clients could send anything otherwise.
+    #[serde(default)]
+    pub forwarded_header: Option<String>,
+    /// Addresses or CIDR ranges of the proxies in front of the server, e.g. `10.0.0.0/8`.
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    /// Quota of routes without one of their own, unlimited when missing.
+    #[serde(default)]
+    pub default: Option<Quota>,
+    #[serde(default)]
+    pub routes: Vec<RouteQuota>,
+}
+
+fn default_header() -> String {
+    "x-api-key".to_string()
+}
+
+/// The authenticated principal of a request, put in the extensions by auth middleware.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subject(pub String);
+
+/// Where the rate limiter keeps its state.
+#[async_trait]
+pub trait Backend: Send + Sync {
+    /// Take one request from the quota of `key`, or return how long to wait before retrying.
+    async fn acquire(&self, key: &str, quota: &Quota) -> Result<(), Duration>;
+}
+
+/// GCRA state in process memory, fine for a single instance and for tests.
+#[derive(Debug, Default)]
+pub struct InMemory {
+    state: Mutex<Tats>,
+}
+
+#[derive(Debug, Default)]
+struct Tats {
+    /// Theoretical arrival time of the next request, per key.
+    tats: HashMap<String, Instant>,
+    /// When clients back to a full quota were last forgotten.
+    swept: Option<Instant>,
+}
+
+/// Forget clients that are back to a full quota once the map grows past this size, at most
+/// once per `SWEEP_INTERVAL` so many live clients don't cost every request a full scan.
+const SWEEP_THRESHOLD: usize = 10_000;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+#[async_trait]
+impl Backend for InMemory {
+    async fn acquire(&self, key: &str, quota: &Quota) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let Tats { tats, swept } = &mut *state;
+        let tat = tats.get(key).copied().unwrap_or(now).max(now);
+        let ahead = tat - now;
+        let tolerance = quota.tolerance();
+        if ahead > tolerance {
+            return Err(ahead - tolerance);
+        }
+        if tats.len() >= SWEEP_THRESHOLD && swept.is_none_or(|swept| now - swept >= SWEEP_INTERVAL) {
+            tats.retain(|_, tat| *tat > now);
+            *swept = Some(now);
+        }
+        tats.insert(key.to_string(), tat + quota.interval());
+        Ok(())
+    }
+}
+
+pub struct RateLimiter {
+    conf: RateLimit,
+    backend: Arc<dyn Backend>,
+    /// `trusted_proxies` as networks and prefix lengths.
+    proxies: Vec<(IpAddr, u32)>,
+}
+
+impl RateLimiter {
+    pub fn new(conf: RateLimit, backend: Arc<dyn Backend>) -> Self {
+        let proxies = conf
+            .trusted_proxies
+            .iter()
+            .filter_map(|p| {
+                let net = parse_net(p);
+                if net.is_none() {
+                    log::warn!("rate limit: ignoring invalid trusted proxy {:?}", p);
+                }
+                net
+            })
+            .collect();
+        Self { conf, backend, proxies }
+    }
+
+    fn trusted(&self, ip: IpAddr) -> bool {
+        self.proxies.iter().any(|(net, len)| contains(*net, *len, ip))
+    }
+
+    /// The rightmost address of `forwarded` that isn't a trusted proxy, entries on its left
+    /// may be made up by the client.
+    fn forwarded(&self, forwarded: &str) -> Option<IpAddr> {
+        let mut last = None;
+        for entry in forwarded.rsplit(',').map(str::trim) {
+            match entry.parse::<IpAddr>() {
+                Ok(ip) if self.trusted(ip) => last = Some(ip),
+                Ok(ip) => return Some(ip),
+                Err(_) => break,
+            }
+        }
+        last
+    }
+
+    /// The quota that applies to `req` and the bucket it is counted in.
+    fn quota(&self, req: &Request) -> Option<(String, &Quota)> {
+        let matched = req.extensions().get::<MatchedPath>().map(MatchedPath::as_str);
+        let path = req.uri().path();
+        let route = self.conf.routes.iter().enumerate().find(|(_, r)| {
+            path_matches(&r.path, path, matched)
+                && r.method.as_ref().is_none_or(|m| m.eq_ignore_ascii_case(req.method().as_str()))
+        });
+        match route {
+            Some((i, r)) => Some((format!("route{}", i), &r.quota)),
+            None => self.conf.default.as_ref().map(|q| ("default".to_string(), q)),
+        }
+    }
+
+    fn client(&self, req: &Request) -> String {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        let key = match self.conf.key {
+            RateLimitKey::Ip => None,
+            RateLimitKey::Header => header(&self.conf.header).map(|v| format!("key:{}", v)),
+            RateLimitKey::Subject => req
+                .extensions()
+                .get::<Subject>()
+                .map(|s| s.0.clone())
+                .or_else(|| req.extensions().get::<PeerIdentity>().map(|p| p.common_name.clone()))
+                .map(|s| format!("sub:{}", s)),
+        };
+        // Anonymous clients are limited by address.
+        key.unwrap_or_else(|| {
+            let peer = req.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip());
+            let ip = match (peer, self.conf.forwarded_header.as_deref()) {
+                (Some(peer), Some(name)) if self.trusted(peer) => {
+                    header(name).and_then(|v| self.forwarded(&v)).or(Some(peer))
+                }
+                _ => peer,
+            };
+            format!("ip:{}", ip.map(|ip| ip.to_string()).unwrap_or_default())
+        })
+    }
+}
+
+/// Parse `10.0.0.0/8`, or a single address as a network of its own.
+fn parse_net(net: &str) -> Option<(IpAddr, u32)> {
+    let (addr, len) = match net.split_once('/') {
+        Some((addr, len)) => (addr.parse::<IpAddr>().ok()?, Some(len.parse::<u32>().ok()?)),
+        None => (net.parse::<IpAddr>().ok()?, None),
+    };
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let len = len.unwrap_or(max);
+    (len <= max).then_some((addr, len))
+}
+
+fn contains(net: IpAddr, len: u32, ip: IpAddr) -> bool {
+    match (net, ip.to_canonical()) {
+        (IpAddr::V4(net), IpAddr::V4(ip)) => {
+            let mask = u32::MAX.checked_shl(32 - len).unwrap_or(0);
+            u32::from(net) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(net), IpAddr::V6(ip)) => {
+            let mask = u128::MAX.checked_shl(128 - len).unwrap_or(0);
+            u128::from(net) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Middleware rejecting requests over their quota with a 429 [`Status`] and `Retry-After`.
+///
+/// Install it with `axum::middleware::from_fn_with_state(limiter, rate_limit)`, or through
+/// [`Http::rate_limit`](crate::Http::rate_limit).
+pub async fn rate_limit(State(limiter): State<Arc<RateLimiter>>, req: Request, next: Next) -> Response {
+    let Some((bucket, quota)) = limiter.quota(&req) else {
+        return next.run(req).await;
+    };
+    let key = format!("{}:{}", bucket, limiter.client(&req));
+    match limiter.backend.acquire(&key, quota).await {
+        Ok(()) => next.run(req).await,
+        Err(wait) => too_many_requests(wait),
+    }
+}
+
+fn too_many_requests(wait: Duration) -> Response {
+    // Round up, retrying a bit early would be rejected again.
+    let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    let mut status = Status::new("TooManyRequests", "rate limit exceeded").with_code(StatusCode::TOO_MANY_REQUESTS);
+    status.metadata.insert("retry_after".to_string(), secs.to_string());
+    let mut res = reject(status);
+    res.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(secs));
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn app(conf: RateLimit) -> Router {
+        let limiter = Arc::new(RateLimiter::new(conf, Arc::new(InMemory::default())));
+        Router::new()
+            .route("/users/:id", get(|| async { "user" }).post(|| async { "created" }))
+            .route("/ping", get(|| async { "pong" }))
+            .layer(axum::middleware::from_fn_with_state(limiter, rate_limit))
+    }
+
+    async fn call(app: &Router, method: &str, uri: &str, key: Option<&str>) -> Response {
+        let mut req = Request::builder().method(method).uri(uri);
+        if let Some(key) = key {
+            req = req.header("x-api-key", key);
+        }
+        let mut req = req.body(Body::empty()).unwrap();
+        req.extensions_mut().insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 1234))));
+        app.clone().oneshot(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn valid_gcra() {
+        tokio::time::pause();
+        let backend = InMemory::default();
+        let quota = Quota { rate: 2, per: Duration::from_secs(1), burst: Some(2) };
+        assert!(backend.acquire("a", &quota).await.is_ok());
+        assert!(backend.acquire("a", &quota).await.is_ok());
+        assert_eq!(backend.acquire("a", &quota).await, Err(Duration::from_millis(500)));
+        assert!(backend.acquire("b", &quota).await.is_ok());
+        tokio::time::advance(Duration::from_millis(500)).await;
+        assert!(backend.acquire("a", &quota).await.is_ok());
+        assert!(backend.acquire("a", &quota).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn valid_sweep() {
+        tokio::time::pause();
+        let backend = InMemory::default();
+        let quota = Quota::new(1, Duration::from_secs(1));
+        for i in 0..SWEEP_THRESHOLD {
+            backend.acquire(&i.to_string(), &quota).await.unwrap();
+        }
+        tokio::time::advance(Duration::from_secs(1)).await;
+        // Swept once, then not again before the interval.
+        backend.acquire("a", &quota).await.unwrap();
+        assert_eq!(backend.state.lock().unwrap().tats.len(), 1);
+        for i in 0..SWEEP_THRESHOLD {
+            backend.acquire(&i.to_string(), &quota).await.unwrap();
+        }
+        tokio::time::advance(Duration::from_secs(1)).await;
+        backend.acquire("b", &quota).await.unwrap();
+        assert_eq!(backend.state.lock().unwrap().tats.len(), SWEEP_THRESHOLD + 2);
+        tokio::time::advance(SWEEP_INTERVAL).await;
+        backend.acquire("c", &quota).await.unwrap();
+        assert_eq!(backend.state.lock().unwrap().tats.len(), 1);
+    }
+
+    #[test]
+    fn invalid_quota_rate() {
+        let err = serde_json::from_str::<Quota>(r#"{"rate": 0, "per": "1s"}"#).unwrap_err();
+        assert!(err.to_string().starts_with("rate must be at least 1"), "{}", err);
+        assert!(serde_json::from_str::<Quota>(r#"{"rate": 1, "per": "1s"}"#).is_ok());
+    }
+
+    #[tokio::test]
+    async fn valid_route_quota() {
+        tokio::time::pause();
+        let app = app(RateLimit {
+            key: RateLimitKey::Header,
+            header: default_header(),
+            routes: vec![RouteQuota {
+                path: "/users/:id".to_string(),
+                method: Some("POST".to_string()),
+                quota: Quota::new(1, Duration::from_secs(60)),
+            }],
+            ..Default::default()
+        });
+
+        assert_eq!(call(&app, "POST", "/users/1", Some("a")).await.status(), StatusCode::OK);
+        let res = call(&app, "POST", "/users/2", Some("a")).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "60");
+        let status = res.extensions().get::<Status>().unwrap();
+        assert_eq!(status.reason, "TooManyRequests");
+        assert_eq!(status.code, 429);
+
+        // Other clients, methods and routes have their own quotas.
+        assert_eq!(call(&app, "POST", "/users/1", Some("b")).await.status(), StatusCode::OK);
+        assert_eq!(call(&app, "GET", "/users/1", Some("a")).await.status(), StatusCode::OK);
+        assert_eq!(call(&app, "GET", "/ping", Some("a")).await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn valid_default_quota_by_ip() {
+        tokio::time::pause();
+        let app = app(RateLimit {
+            default: Some(Quota::new(1, Duration::from_secs(1))),
+            routes: vec![RouteQuota {
+                path: "/users".to_string(),
+                method: None,
+                quota: Quota::new(2, Duration::from_secs(1)),
+            }],
+            ..Default::default()
+        });
+
+        // The API key is ignored when limiting by address.
+        assert_eq!(call(&app, "GET", "/ping", Some("a")).await.status(), StatusCode::OK);
+        assert_eq!(call(&app, "GET", "/ping", Some("b")).await.status(), StatusCode::TOO_MANY_REQUESTS);
+        // `/users` is a prefix of `/users/:id`.
+        assert_eq!(call(&app, "GET", "/users/1", None).await.status(), StatusCode::OK);
+        assert_eq!(call(&app, "GET", "/users/1", None).await.status(), StatusCode::OK);
+        assert_eq!(call(&app, "GET", "/users/1", None).await.status(), StatusCode::TOO_MANY_REQUESTS);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(call(&app, "GET", "/ping", None).await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn valid_subject() {
+        let limiter = RateLimiter::new(
+            RateLimit { key: RateLimitKey::Subject, ..Default::default() },
+            Arc::new(InMemory::default()),
+        );
+        let mut req = Request::get("/").body(Body::empty()).unwrap();
+        assert_eq!(limiter.client(&req), "ip:");
+        req.extensions_mut().insert(Subject("alice".to_string()));
+        assert_eq!(limiter.client(&req), "sub:alice");
+    }
+
+    #[test]
+    fn valid_forwarded() {
+        let limiter = RateLimiter::new(
+            RateLimit {
+                forwarded_header: Some("x-forwarded-for".to_string()),
+                trusted_proxies: vec!["10.0.0.0/8".to_string(), "192.168.1.1".to_string()],
+                ..Default::default()
+            },
+            Arc::new(InMemory::default()),
+        );
+        let req = |peer: [u8; 4], forwarded: &str| {
+            let mut req = Request::get("/").header("x-forwarded-for", forwarded).body(Body::empty()).unwrap();
+            req.extensions_mut().insert(ConnectInfo(SocketAddr::from((peer, 1234))));
+            req
+        };
+        assert_eq!(limiter.client(&req([10, 0, 0, 1], "1.2.3.4, 10.0.0.2")), "ip:1.2.3.4");
+        assert_eq!(limiter.client(&req([192, 168, 1, 1], "1.2.3.4")), "ip:1.2.3.4");
+        // Addresses prepended by the client are skipped.
+        assert_eq!(limiter.client(&req([10, 0, 0, 1], "6.6.6.6, 1.2.3.4")), "ip:1.2.3.4");
+        assert_eq!(limiter.client(&req([10, 0, 0, 1], "10.0.0.3")), "ip:10.0.0.3");
+        // Untrusted peers can't pick their address.
+        assert_eq!(limiter.client(&req([8, 8, 8, 8], "1.2.3.4")), "ip:8.8.8.8");
+        assert_eq!(limiter.client(&req([192, 168, 1, 2], "1.2.3.4")), "ip:192.168.1.2");
+    }
+}
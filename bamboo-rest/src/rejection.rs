@@ -0,0 +1,41 @@
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use bamboo_status::errors::Status;
+
+/// A request rejected by an extractor or a middleware.
+///
+/// Unlike a [`Status`] returned by a handler, which always renders HTTP 200, it renders the
+/// same envelope with the HTTP status of `Status.code`, so clients and proxies see the failure
+/// too. Handlers reading the body themselves, like [`Uploads`](crate::Uploads), return it as
+/// their error type.
+#[derive(Debug, Clone)]
+pub struct Rejection(pub Status);
+
+impl From<Status> for Rejection {
+    fn from(value: Status) -> Self {
+        Self(value)
+    }
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        reject(self.0)
+    }
+}
+
+/// Render `status` with the HTTP status of its code, see [`Rejection`].
+pub(crate) fn reject(status: Status) -> Response {
+    let code = u16::try_from(status.code)
+        .ok()
+        .and_then(|c| StatusCode::from_u16(c).ok())
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let mut res = status.into_response();
+    *res.status_mut() = code;
+    if code == StatusCode::UNAUTHORIZED {
+        res.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    }
+    res
+}
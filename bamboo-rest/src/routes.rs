@@ -0,0 +1,168 @@
+use std::convert::Infallible;
+
+use axum::{
+    extract::Request,
+    http::Method,
+    response::IntoResponse,
+    routing::{MethodRouter, Route},
+    Router,
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::openapi::{ApiRouter, OpenApi, Operation};
+
+/// A group of routes mounted under `/{version}/{prefix}`, with middleware of its own.
+///
+/// ```
+/// use bamboo_rest::{get, routes::Group};
+///
+/// let users = Group::<()>::new("/users")
+///     .version("v1")
+///     .route("/:id", get(|| async { "user" }));
+/// assert_eq!(users.path(), "/v1/users");
+/// ```
+pub struct Group<S = ()> {
+    prefix: String,
+    version: Option<String>,
+    tag: Option<String>,
+    r: Router<S>,
+    operations: Vec<(String, Method, Operation)>,
+}
+
+impl<S> Group<S>
+    where S: Clone + Send + Sync + 'static,
+{
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            version: None,
+            tag: None,
+            r: Router::new(),
+            operations: Vec::new(),
+        }
+    }
+
+    /// Mount the group under a version segment, e.g. `v1`.
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = Some(version.to_string());
+        self
+    }
+
+    /// Tag the documented operations of the group that have no tag of their own.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_string());
+        self
+    }
+
+    /// Add a route that is left out of the OpenAPI document, see [`Group::api`].
+    pub fn route(mut self, path: &str, method_router: MethodRouter<S>) -> Self {
+        self.r = self.r.route(path, method_router);
+        self
+    }
+
+    /// Add a route that shows up in the OpenAPI document.
+    pub fn api(mut self, path: &str, api: ApiRouter<S>) -> Self {
+        self.r = self.r.route(path, api.router);
+        for (method, op) in api.operations {
+            self.operations.push((path.to_string(), method, op));
+        }
+        self
+    }
+
+    /// Add all routes of `r` to the group, they are left out of the OpenAPI document.
+    pub fn merge(mut self, r: Router<S>) -> Self {
+        self.r = self.r.merge(r);
+        self
+    }
+
+    /// Add a tower layer that only wraps the routes of this group.
+    ///
+    /// Only routes added before the call are wrapped, like [`Router::layer`].
+    pub fn layer<L>(mut self, layer: L) -> Self
+        where
+            L: Layer<Route> + Clone + Send + 'static,
+            L::Service: Service<Request> + Clone + Send + 'static,
+            <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+            <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+            <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.r = self.r.layer(layer);
+        self
+    }
+
+    /// The path the group is mounted at, `/` when it has neither version nor prefix.
+    pub fn path(&self) -> String {
+        join(&[self.version.as_deref().unwrap_or(""), &self.prefix])
+    }
+
+    pub(crate) fn mount(self, r: Router<S>, openapi: &mut OpenApi) -> Router<S> {
+        let path = self.path();
+        for (route, method, mut op) in self.operations {
+            if op.tags.is_empty() {
+                op.tags.extend(self.tag.clone());
+            }
+            openapi.add(&join(&[&path, &route]), &method, op);
+        }
+        if path == "/" {
+            // axum doesn't nest at the root.
+            r.merge(self.r)
+        } else {
+            r.nest(&path, self.r)
+        }
+    }
+}
+
+/// Join path segments into `/a/b`, ignoring empty segments and extra slashes.
+pub(crate) fn join(segments: &[&str]) -> String {
+    let path: Vec<&str> = segments
+        .iter()
+        .flat_map(|s| s.split('/'))
+        .filter(|s| !s.is_empty())
+        .collect();
+    format!("/{}", path.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::StatusCode, routing::get};
+    use tower::ServiceExt;
+    use tower_http::set_header::SetResponseHeaderLayer;
+
+    use super::*;
+
+    async fn get_status(app: Router, uri: &str) -> (StatusCode, bool) {
+        let req = Request::get(uri).body(Body::empty()).unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        (res.status(), res.headers().contains_key("x-group"))
+    }
+
+    #[test]
+    fn valid_join() {
+        assert_eq!(join(&["", ""]), "/");
+        assert_eq!(join(&["v1", "/users/"]), "/v1/users");
+        assert_eq!(join(&["/admin//", "health"]), "/admin/health");
+    }
+
+    #[tokio::test]
+    async fn valid_group() {
+        let v1 = Group::new("/users")
+            .version("v1")
+            .route("/:id", get(|| async { "v1" }))
+            .layer(SetResponseHeaderLayer::overriding(
+                axum::http::HeaderName::from_static("x-group"),
+                axum::http::HeaderValue::from_static("v1"),
+            ));
+        let v2 = Group::new("users")
+            .version("v2")
+            .route("/:id", get(|| async { "v2" }));
+        let root = Group::new("").route("/ping", get(|| async { "pong" }));
+        let openapi = &mut OpenApi::default();
+        let app = root.mount(v2.mount(v1.mount(Router::new(), openapi), openapi), openapi);
+
+        assert_eq!(get_status(app.clone(), "/v1/users/1").await, (StatusCode::OK, true));
+        assert_eq!(get_status(app.clone(), "/v2/users/1").await, (StatusCode::OK, false));
+        assert_eq!(get_status(app.clone(), "/ping").await, (StatusCode::OK, false));
+        assert_eq!(get_status(app, "/users/1").await.0, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,462 @@
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        FromRequestParts,
+    },
+    http::{request::Parts, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use futures_util::{
+    stream::{self, SplitSink, SplitStream},
+    SinkExt, Stream, StreamExt,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{watch, Mutex, OwnedSemaphorePermit, Semaphore},
+    task::JoinHandle,
+    time::{Instant, Interval, MissedTickBehavior},
+};
+use tokio_graceful::{ShutdownGuard, WeakShutdownGuard};
+
+use bamboo_status::errors::Status;
+
+use crate::Rejection;
+
+/// Name of the last event of SSE streams closed by a server shutdown.
+pub const SHUTDOWN_EVENT: &str = "shutdown";
+
+/// Heartbeats and connection limits of the SSE and WebSocket streams opened with [`Streams`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Streaming {
+    /// SSE keep-alive comments and WebSocket pings are sent at this interval.
+    #[serde(default = "default_heartbeat", with = "humantime_serde")]
+    pub heartbeat: Duration,
+    /// Maximum number of open SSE and WebSocket connections, unlimited when `0`.
+    #[serde(default)]
+    pub max_connections: usize,
+}
+
+fn default_heartbeat() -> Duration {
+    Duration::from_secs(15)
+}
+
+impl Default for Streaming {
+    fn default() -> Self {
+        Self {
+            heartbeat: default_heartbeat(),
+            max_connections: 0,
+        }
+    }
+}
+
+type BoxFuture = Pin<Box<dyn Future<Output=()> + Send>>;
+
+/// Opens SSE and WebSocket streams that end with the server, extract it in handlers.
+///
+/// Open streams hold up graceful shutdown until they have said goodbye: SSE streams send a
+/// [`SHUTDOWN_EVENT`] and WebSockets a close frame.
+///
+/// ```
+/// use axum::response::{sse::Event, Response};
+/// use bamboo_rest::streaming::Streams;
+/// use futures_util::{stream, StreamExt};
+///
+/// async fn events(streams: Streams) -> Response {
+///     let events = stream::iter([Ok::<_, std::convert::Infallible>(Event::default().data("hello"))]);
+///     streams.sse(events.chain(stream::pending()))
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Streams {
+    heartbeat: Duration,
+    permits: Option<Arc<Semaphore>>,
+    guard: WeakShutdownGuard,
+}
+
+/// An open stream, keeps shutdown waiting and a connection slot taken until dropped.
+struct Connection {
+    guard: ShutdownGuard,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Connection {
+    fn cancelled(&self) -> BoxFuture {
+        let guard = self.guard.clone_weak();
+        Box::pin(async move { guard.cancelled().await })
+    }
+}
+
+impl Streams {
+    /// The server installs one for every request, build one yourself for a plain `Router`.
+    pub fn new(conf: &Streaming, guard: &ShutdownGuard) -> Self {
+        Self {
+            heartbeat: conf.heartbeat,
+            permits: (conf.max_connections > 0).then(|| Arc::new(Semaphore::new(conf.max_connections))),
+            guard: guard.clone_weak(),
+        }
+    }
+
+    /// Take a connection slot, `None` when all are taken.
+    fn connect(&self) -> Option<Connection> {
+        let permit = match &self.permits {
+            Some(permits) => Some(permits.clone().try_acquire_owned().ok()?),
+            None => None,
+        };
+        Some(Connection {
+            guard: self.guard.clone().upgrade(),
+            _permit: permit,
+        })
+    }
+
+    /// Answer with an SSE stream of `events`, with heartbeats, ended by shutdown.
+    pub fn sse<S, E>(&self, events: S) -> Response
+        where
+            S: Stream<Item=Result<Event, E>> + Send + 'static,
+            E: Into<axum::BoxError> + 'static,
+    {
+        let Some(conn) = self.connect() else {
+            return too_many_connections();
+        };
+        let cancelled = conn.cancelled();
+        let state = Some((Box::pin(events), cancelled, conn));
+        let events = stream::unfold(state, |state| async move {
+            let (mut events, mut cancelled, conn) = state?;
+            tokio::select! {
+                event = events.next() => event.map(|event| (event, Some((events, cancelled, conn)))),
+                _ = &mut cancelled => {
+                    let event = Event::default().event(SHUTDOWN_EVENT).data("server shutting down");
+                    Some((Ok(event), None))
+                }
+            }
+        });
+        Sse::new(events)
+            .keep_alive(KeepAlive::new().interval(self.heartbeat))
+            .into_response()
+    }
+
+    /// Upgrade to a WebSocket served by `handler`, with heartbeats, closed by shutdown.
+    ///
+    /// Heartbeats and the shutdown close frame are sent by a background task, so handlers that
+    /// only send are closed too: their next [`Socket::send`] fails.
+    pub fn websocket<F, Fut>(&self, ws: WebSocketUpgrade, handler: F) -> Response
+        where
+            F: FnOnce(Socket) -> Fut + Send + 'static,
+            Fut: Future<Output=()> + Send + 'static,
+    {
+        let Some(conn) = self.connect() else {
+            return too_many_connections();
+        };
+        let heartbeat = self.heartbeat;
+        ws.on_upgrade(move |socket| async move {
+            let mut ticker = tokio::time::interval_at(Instant::now() + heartbeat, heartbeat);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let (sink, stream) = socket.split();
+            let shared = Arc::new(Shared {
+                sink: Mutex::new(sink),
+                closed: watch::Sender::new(false),
+            });
+            let driver = tokio::spawn(drive(shared.clone(), ticker, conn.cancelled()));
+            let socket = Socket {
+                stream,
+                shared,
+                driver,
+                _conn: conn,
+            };
+            handler(socket).await
+        })
+    }
+}
+
+fn too_many_connections() -> Response {
+    let status = Status::new("TooManyConnections", "too many streaming connections")
+        .with_code(StatusCode::SERVICE_UNAVAILABLE);
+    Rejection(status).into_response()
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Streams
+    where S: Send + Sync,
+{
+    type Rejection = Rejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Streams>().cloned().ok_or_else(|| {
+            let status = Status::new("StreamsMissing", "streams are not installed on this router")
+                .with_code(StatusCode::INTERNAL_SERVER_ERROR);
+            Rejection(status)
+        })
+    }
+}
+
+/// A WebSocket that pings the client and closes itself on shutdown.
+pub struct Socket {
+    stream: SplitStream<WebSocket>,
+    shared: Arc<Shared>,
+    /// Sends the heartbeats and the shutdown close frame.
+    driver: JoinHandle<()>,
+    _conn: Connection,
+}
+
+/// The half of a [`Socket`] shared with its driver.
+struct Shared {
+    sink: Mutex<SplitSink<WebSocket, Message>>,
+    closed: watch::Sender<bool>,
+}
+
+impl Shared {
+    async fn close(&self, code: u16, reason: &'static str) {
+        let mut sink = self.sink.lock().await;
+        if self.closed.send_replace(true) {
+            return;
+        }
+        let frame = CloseFrame {
+            code,
+            reason: reason.into(),
+        };
+        if let Err(err) = sink.send(Message::Close(Some(frame))).await {
+            log::debug!("WebSocket close failed: {}", err);
+        }
+    }
+}
+
+/// Resolves once either side closed the connection.
+async fn closed(mut closed: watch::Receiver<bool>) {
+    let _ = closed.wait_for(|closed| *closed).await;
+}
+
+async fn drive(shared: Arc<Shared>, mut heartbeat: Interval, mut cancelled: BoxFuture) {
+    let mut closed = Box::pin(closed(shared.closed.subscribe()));
+    loop {
+        tokio::select! {
+            _ = heartbeat.tick() => {
+                let mut sink = shared.sink.lock().await;
+                if *shared.closed.borrow() {
+                    return;
+                }
+                if sink.send(Message::Ping(Vec::new())).await.is_err() {
+                    shared.closed.send_replace(true);
+                    return;
+                }
+            }
+            _ = &mut cancelled => {
+                shared.close(close_code::AWAY, "server shutting down").await;
+                return;
+            }
+            _ = &mut closed => return,
+        }
+    }
+}
+
+impl Socket {
+    /// The next message of the client, `None` once either side closed the connection.
+    pub async fn recv(&mut self) -> Option<Result<Message, axum::Error>> {
+        tokio::select! {
+            biased;
+            _ = closed(self.shared.closed.subscribe()) => None,
+            msg = self.stream.next() => {
+                if msg.is_none() {
+                    self.shared.closed.send_replace(true);
+                }
+                msg
+            }
+        }
+    }
+
+    /// Send `msg`, fails once either side closed the connection.
+    pub async fn send(&mut self, msg: Message) -> Result<(), axum::Error> {
+        let mut sink = self.shared.sink.lock().await;
+        if *self.shared.closed.borrow() {
+            return Err(axum::Error::new("WebSocket closed"));
+        }
+        sink.send(msg).await
+    }
+
+    /// Send a close frame, further [`recv`](Self::recv) calls return `None`.
+    pub async fn close(&mut self, code: u16, reason: &'static str) {
+        self.shared.close(code, reason).await
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        self.driver.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, net::SocketAddr};
+
+    use axum::{body::Body, extract::Request, routing::get, Extension, Router};
+    use futures_util::SinkExt;
+    use tokio::net::TcpListener;
+    use tokio_graceful::Shutdown;
+    use tokio_tungstenite::tungstenite::{self, protocol::frame::coding::CloseCode};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    type WsClient = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+    fn shutdown() -> (Shutdown, tokio::sync::oneshot::Sender<()>) {
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let shutdown = Shutdown::new(async move {
+            let _ = rx.await;
+        });
+        (shutdown, tx)
+    }
+
+    async fn events(streams: Streams) -> Response {
+        let hello = stream::iter([Ok::<_, Infallible>(Event::default().data("hello"))]);
+        streams.sse(hello.chain(stream::pending()))
+    }
+
+    #[tokio::test]
+    async fn valid_sse_shutdown() {
+        let (shutdown, tx) = shutdown();
+        let conf = Streaming {
+            max_connections: 1,
+            ..Default::default()
+        };
+        let app = Router::new()
+            .route("/events", get(events))
+            .layer(Extension(Streams::new(&conf, &shutdown.guard())));
+        let call = || app.clone().oneshot(Request::get("/events").body(Body::empty()).unwrap());
+
+        let res = call().await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let mut body = res.into_body().into_data_stream();
+        assert_eq!(body.next().await.unwrap().unwrap(), "data: hello\n\n");
+        let res = call().await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.extensions().get::<Status>().unwrap().reason, "TooManyConnections");
+
+        tx.send(()).unwrap();
+        let last = body.next().await.unwrap().unwrap();
+        assert_eq!(last, "event: shutdown\ndata: server shutting down\n\n");
+        assert!(body.next().await.is_none());
+        drop(body);
+        shutdown.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn invalid_streams_missing() {
+        let app = Router::new().route("/events", get(events));
+        let res = app.oneshot(Request::get("/events").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(res.extensions().get::<Status>().unwrap().reason, "StreamsMissing");
+    }
+
+    async fn echo(streams: Streams, ws: WebSocketUpgrade) -> Response {
+        streams.websocket(ws, |mut socket| async move {
+            while let Some(Ok(msg)) = socket.recv().await {
+                if let Message::Text(text) = msg {
+                    let _ = socket.send(Message::Text(text)).await;
+                }
+            }
+        })
+    }
+
+    async fn serve(shutdown: &Shutdown, app: Router<()>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        shutdown.spawn_task_fn(move |guard| async move {
+            axum::serve(listener, app)
+                .with_graceful_shutdown(async move { guard.cancelled().await })
+                .await
+                .unwrap()
+        });
+        addr
+    }
+
+    async fn next_close(client: &mut WsClient) -> CloseCode {
+        loop {
+            match client.next().await.unwrap().unwrap() {
+                tungstenite::Message::Close(frame) => break frame.unwrap().code,
+                _ => continue,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn valid_websocket_shutdown() {
+        let (shutdown, tx) = shutdown();
+        let conf = Streaming {
+            heartbeat: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let app = Router::new()
+            .route("/ws", get(echo))
+            .layer(Extension(Streams::new(&conf, &shutdown.guard())));
+        let addr = serve(&shutdown, app).await;
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+        client.send(tungstenite::Message::Text("hi".into())).await.unwrap();
+        let mut pinged = false;
+        loop {
+            match client.next().await.unwrap().unwrap() {
+                tungstenite::Message::Text(text) => {
+                    assert_eq!(text, "hi");
+                    break;
+                }
+                tungstenite::Message::Ping(_) => pinged = true,
+                msg => panic!("unexpected {:?}", msg),
+            }
+        }
+        while !pinged {
+            pinged = matches!(client.next().await.unwrap().unwrap(), tungstenite::Message::Ping(_));
+        }
+
+        tx.send(()).unwrap();
+        assert_eq!(next_close(&mut client).await, CloseCode::Away);
+        drop(client);
+        tokio::time::timeout(Duration::from_secs(5), shutdown.shutdown()).await.unwrap();
+    }
+
+    async fn ticks(streams: Streams, ws: WebSocketUpgrade) -> Response {
+        streams.websocket(ws, |mut socket| async move {
+            let mut ticker = tokio::time::interval(Duration::from_millis(10));
+            while socket.send(Message::Text("tick".into())).await.is_ok() {
+                ticker.tick().await;
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn valid_websocket_send_only_shutdown() {
+        let (shutdown, tx) = shutdown();
+        let conf = Streaming {
+            heartbeat: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let app = Router::new()
+            .route("/ws", get(ticks))
+            .layer(Extension(Streams::new(&conf, &shutdown.guard())));
+        let addr = serve(&shutdown, app).await;
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+        // The handler never calls `recv`, pings and the close frame must still go out.
+        let pinged = async {
+            loop {
+                match client.next().await.unwrap().unwrap() {
+                    tungstenite::Message::Text(text) => assert_eq!(text, "tick"),
+                    tungstenite::Message::Ping(_) => break,
+                    msg => panic!("unexpected {:?}", msg),
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), pinged).await.unwrap();
+
+        tx.send(()).unwrap();
+        let close = tokio::time::timeout(Duration::from_secs(5), next_close(&mut client)).await.unwrap();
+        assert_eq!(close, CloseCode::Away);
+        drop(client);
+        tokio::time::timeout(Duration::from_secs(5), shutdown.shutdown()).await.unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+//! In-process servers for tests, enabled by the `test-util` feature.
+
+use std::{fmt::Debug, net::SocketAddr, time::Duration};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Request},
+    http::{header, HeaderMap, StatusCode},
+    Router,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::oneshot;
+use tokio_graceful::Shutdown;
+use tower::ServiceExt;
+
+use bamboo_status::{errors::Status, status::AnyResult};
+
+use crate::{Config, Server};
+
+/// Runs the router and middleware stack of a [`Server`] without binding a port.
+///
+/// Needs a tokio runtime, e.g. `#[tokio::test]`.
+///
+/// ```
+/// use std::sync::Arc;
+/// use bamboo_rest::{get, testing::TestServer, Config, Http, Server};
+/// use axum::Router;
+///
+/// struct Conf(Http);
+///
+/// impl Config for Conf {
+///     fn http(&self) -> &Http {
+///         &self.0
+///     }
+/// }
+///
+/// # tokio_test();
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn tokio_test() {
+/// let r = Router::new().route("/ping", get(|| async { "pong" }));
+/// let server = Server::new(Arc::new(Conf(Http::default())), r);
+/// let test = TestServer::new(&server).unwrap();
+/// assert_eq!(test.get("/ping").await.text(), "pong");
+/// test.shutdown().await;
+/// # }
+/// ```
+pub struct TestServer {
+    app: Router,
+    shutdown: Shutdown,
+    trigger: oneshot::Sender<()>,
+}
+
+impl TestServer {
+    pub fn new<C>(server: &Server<C>) -> AnyResult<Self>
+        where C: Config + Send + Sync + 'static,
+    {
+        let (trigger, rx) = oneshot::channel::<()>();
+        let shutdown = Shutdown::new(async move {
+            let _ = rx.await;
+        });
+        let app = server.app(&shutdown.guard())?;
+        Ok(Self { app, shutdown, trigger })
+    }
+
+    /// Send `req` through the server, it comes from `127.0.0.1` unless it has `ConnectInfo`.
+    pub async fn call(&self, mut req: Request) -> TestResponse {
+        if req.extensions().get::<ConnectInfo<SocketAddr>>().is_none() {
+            req.extensions_mut().insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0))));
+        }
+        let res = self.app.clone().oneshot(req).await.unwrap();
+        let (parts, body) = res.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        TestResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+            error: parts.extensions.get::<Status>().cloned(),
+        }
+    }
+
+    pub async fn get(&self, uri: &str) -> TestResponse {
+        self.call(Request::get(uri).body(Body::empty()).unwrap()).await
+    }
+
+    pub async fn post_json<T: Serialize>(&self, uri: &str, body: &T) -> TestResponse {
+        let req = Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(body).unwrap()))
+            .unwrap();
+        self.call(req).await
+    }
+
+    /// Trigger a graceful shutdown and wait for the server's background tasks.
+    pub async fn shutdown(self) {
+        let _ = self.trigger.send(());
+        if let Err(err) = self.shutdown.shutdown_with_limit(Duration::from_secs(5)).await {
+            panic!("test server did not shut down: {}", err);
+        }
+    }
+}
+
+/// A buffered response, with assertions for the response envelope.
+#[derive(Debug)]
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    /// The [`Status`] the response was rendered from, if any.
+    error: Option<Status>,
+}
+
+impl TestResponse {
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(&self.body).unwrap_or_else(|err| panic!("{}: {}", err, self.text()))
+    }
+
+    /// Assert a `{"success": true, ...}` envelope and return its `data`.
+    pub fn assert_success<T: DeserializeOwned>(&self) -> T {
+        let envelope: serde_json::Value = self.json();
+        assert_eq!(envelope["success"], true, "expected a success envelope, got {}", self.text());
+        serde_json::from_value(envelope["data"].clone()).unwrap_or_else(|err| panic!("{}: {}", err, self.text()))
+    }
+
+    /// Assert a [`Status`] envelope with `reason` and return the status.
+    pub fn assert_status(&self, reason: &str) -> Status {
+        let envelope: serde_json::Value = self.json();
+        assert_eq!(envelope["success"], false, "expected a status envelope, got {}", self.text());
+        assert_eq!(envelope["code"], reason, "unexpected status {}", self.text());
+        self.error.clone().unwrap_or_else(|| {
+            Status::new(reason, envelope["message"].as_str().unwrap_or_default())
+        })
+    }
+}
+
+/// Assert that `res` failed with a [`Status`] carrying `reason`.
+pub fn assert_status<T: Debug>(res: Result<T, Status>, reason: &str) -> Status {
+    let status = res.expect_err("expected a status");
+    assert_eq!(status.reason, reason, "unexpected status {}", status);
+    status
+}
@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    Router,
+};
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+    service::TowerToHyperService,
+};
+use tokio::net::TcpListener;
+use tokio_graceful::ShutdownGuard;
+use tower::ServiceExt;
+
+use bamboo_status::status::AnyResult;
+use bamboo_tls::{Acceptor, PeerIdentity};
+
+/// ALPN protocols announced by the REST server.
+pub(crate) const ALPN: [&[u8]; 2] = [b"h2", b"http/1.1"];
+
+/// Serve `app` over TLS until shutdown, then wait for open connections to finish.
+///
+/// Like `axum::serve` with connect info, requests carry `ConnectInfo<SocketAddr>`. The client
+/// certificate of mTLS connections is put in the request extensions as [`PeerIdentity`],
+/// handlers read it with `Option<Extension<PeerIdentity>>`.
+pub(crate) async fn serve(listener: TcpListener, app: Router, acceptor: Acceptor, guard: ShutdownGuard) -> AnyResult<()> {
+    acceptor.watch(&guard);
+    loop {
+        let (tcp, remote) = tokio::select! {
+            res = listener.accept() => match res {
+                Ok(conn) => conn,
+                Err(err) => {
+                    // Mostly running out of file descriptors, back off like axum::serve does.
+                    log::error!("Https accept error: {}", err);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+            _ = guard.cancelled() => break,
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        guard.spawn_task_fn(move |guard| async move {
+            let stream = match acceptor.accept(tcp).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    log::debug!("Https handshake with {} failed: {:#}", remote, err);
+                    return;
+                }
+            };
+            let peer = PeerIdentity::from_connection(stream.get_ref().1);
+            let svc = app.map_request(move |mut req: Request<Incoming>| {
+                req.extensions_mut().insert(ConnectInfo(remote));
+                if let Some(peer) = &peer {
+                    req.extensions_mut().insert(peer.clone());
+                }
+                req
+            });
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let conn = builder.serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(svc));
+            tokio::pin!(conn);
+            tokio::select! {
+                res = conn.as_mut() => {
+                    if let Err(err) = res {
+                        log::debug!("Https connection with {} failed: {}", remote, err);
+                    }
+                }
+                _ = guard.cancelled() => {
+                    conn.as_mut().graceful_shutdown();
+                    let _ = conn.await;
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, routing::get, Extension};
+    use bamboo_tls::{testing::Pki, ClientAuth};
+    use tokio_graceful::Shutdown;
+    use tokio_rustls::{rustls::pki_types::ServerName, TlsConnector};
+
+    use super::*;
+
+    async fn whoami(peer: Option<Extension<PeerIdentity>>) -> String {
+        peer.map(|Extension(p)| p.common_name).unwrap_or_default()
+    }
+
+    async fn call(addr: std::net::SocketAddr, pki: &Pki, client: Option<&str>) -> AnyResult<String> {
+        let tcp = tokio::net::TcpStream::connect(addr).await?;
+        let connector = TlsConnector::from(Arc::new(pki.client_config(client, &[b"http/1.1"])));
+        let stream = connector.connect(ServerName::try_from("localhost")?, tcp).await?;
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+        tokio::spawn(conn);
+        let req = Request::get("/").header("host", "localhost").body(Body::empty())?;
+        let res = sender.send_request(req).await?;
+        let body = axum::body::to_bytes(Body::new(res.into_body()), usize::MAX).await?;
+        Ok(String::from_utf8(body.to_vec())?)
+    }
+
+    #[tokio::test]
+    async fn valid_mtls_serve() {
+        let pki = Pki::new("rest");
+        pki.issue("client", "alice");
+        let acceptor = Acceptor::new(&pki.tls(ClientAuth::Optional), &ALPN).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let shutdown = Shutdown::new(async move {
+            let _ = rx.await;
+        });
+        let app = Router::new().route("/", get(whoami));
+        shutdown.spawn_task_fn(move |guard| serve(listener, app, acceptor, guard));
+
+        assert_eq!(call(addr, &pki, Some("client")).await.unwrap(), "alice");
+        assert_eq!(call(addr, &pki, None).await.unwrap(), "");
+
+        tx.send(()).unwrap();
+        shutdown.shutdown().await;
+        assert!(call(addr, &pki, None).await.is_err());
+    }
+}
@@ -0,0 +1,367 @@
+//! Multipart uploads streamed to disk or any [`AsyncWrite`], within the configured limits.
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http::{header, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use bamboo_status::errors::Status;
+
+use crate::{middleware::content_type_matches, Rejection};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Upload {
+    /// Maximum size of one part in bytes.
+    #[serde(default = "default_max_file_size")]
+    pub max_file_size: u64,
+    /// Maximum size of the whole multipart body in bytes.
+    #[serde(default = "default_max_total_size")]
+    pub max_total_size: u64,
+    /// Accepted content types of files, like `image/png` or `image/*`, any when empty.
+    #[serde(default)]
+    pub content_types: Vec<String>,
+    /// Where [`Part::save`] writes files, the system temp dir when missing.
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+}
+
+fn default_max_file_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_max_total_size() -> u64 {
+    50 * 1024 * 1024
+}
+
+impl Default for Upload {
+    fn default() -> Self {
+        Self {
+            max_file_size: default_max_file_size(),
+            max_total_size: default_max_total_size(),
+            content_types: Vec::new(),
+            dir: None,
+        }
+    }
+}
+
+impl Upload {
+    fn accepts(&self, content_type: Option<&str>) -> bool {
+        if self.content_types.is_empty() {
+            return true;
+        }
+        let content_type = content_type.unwrap_or_default();
+        self.content_types.iter().any(|pattern| content_type_matches(pattern, content_type))
+    }
+}
+
+/// A `multipart/form-data` body read part by part, without buffering it.
+///
+/// Limits and accepted content types come from [`Http::upload`](crate::Http::upload). Bodies
+/// announcing more than `max_total_size` are rejected with a 413 before they are read, others
+/// fail with a 413 [`Rejection`] once a limit is crossed and with a 415 for files of other
+/// types. Return it as is so clients get the HTTP status too.
+///
+/// ```
+/// use bamboo_rest::{upload::Uploads, Rejection, Success};
+///
+/// async fn avatar(mut uploads: Uploads) -> Result<Success<u64>, Rejection> {
+///     let mut size = 0;
+///     while let Some(part) = uploads.next_part().await? {
+///         if part.file_name().is_some() {
+///             size += part.save().await?.size;
+///         }
+///     }
+///     Ok(Success(size))
+/// }
+/// ```
+pub struct Uploads {
+    inner: multer::Multipart<'static>,
+    conf: Arc<Upload>,
+}
+
+impl Uploads {
+    /// The next part, `None` after the last one.
+    #[allow(clippy::result_large_err)]
+    pub async fn next_part(&mut self) -> Result<Option<Part>, Rejection> {
+        let Some(field) = self.inner.next_field().await.map_err(rejection)? else {
+            return Ok(None);
+        };
+        let part = Part {
+            field,
+            conf: self.conf.clone(),
+        };
+        if part.file_name().is_some() && !self.conf.accepts(part.content_type()) {
+            let content_type = part.content_type().unwrap_or("no content type");
+            return Err(unsupported_media_type(&format!("file type {} is not accepted", content_type)).into());
+        }
+        Ok(Some(part))
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for Uploads
+    where S: Send + Sync,
+{
+    type Rejection = Rejection;
+
+    async fn from_request(req: Request, _state: &S) -> Result<Self, Self::Rejection> {
+        let conf = req.extensions().get::<Arc<Upload>>().cloned().unwrap_or_default();
+        let boundary = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| multer::parse_boundary(v).ok())
+            .ok_or_else(|| unsupported_media_type("expected a multipart/form-data body"))?;
+        let length = req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        if length.is_some_and(|len| len > conf.max_total_size) {
+            return Err(payload_too_large(conf.max_total_size).into());
+        }
+        let limits = multer::SizeLimit::new()
+            .whole_stream(conf.max_total_size)
+            .per_field(conf.max_file_size);
+        let constraints = multer::Constraints::new().size_limit(limits);
+        let inner = multer::Multipart::with_constraints(req.into_body().into_data_stream(), boundary, constraints);
+        Ok(Self { inner, conf })
+    }
+}
+
+/// One part of an [`Uploads`] body, a file when it has a file name.
+pub struct Part {
+    field: multer::Field<'static>,
+    conf: Arc<Upload>,
+}
+
+/// A part written to disk by [`Part::save`], the caller owns the file.
+#[derive(Debug, Clone)]
+pub struct SavedFile {
+    pub name: Option<String>,
+    pub file_name: Option<String>,
+    pub content_type: Option<String>,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+static SAVED: AtomicU64 = AtomicU64::new(0);
+
+impl Part {
+    /// The form field name.
+    pub fn name(&self) -> Option<&str> {
+        self.field.name()
+    }
+
+    pub fn file_name(&self) -> Option<&str> {
+        self.field.file_name()
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.field.content_type().map(|m| m.essence_str())
+    }
+
+    /// The next chunk of the part, `None` at its end.
+    #[allow(clippy::result_large_err)]
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, Rejection> {
+        self.field.chunk().await.map_err(rejection)
+    }
+
+    /// Read a text field.
+    #[allow(clippy::result_large_err)]
+    pub async fn text(self) -> Result<String, Rejection> {
+        self.field.text().await.map_err(rejection)
+    }
+
+    /// Stream the part into `sink` and return its size.
+    #[allow(clippy::result_large_err)]
+    pub async fn copy_to<W>(mut self, sink: &mut W) -> Result<u64, Rejection>
+        where W: AsyncWrite + Unpin + ?Sized,
+    {
+        let mut size = 0;
+        while let Some(chunk) = self.chunk().await? {
+            sink.write_all(&chunk).await.map_err(|err| io_failed(&err))?;
+            size += chunk.len() as u64;
+        }
+        sink.flush().await.map_err(|err| io_failed(&err))?;
+        Ok(size)
+    }
+
+    /// Stream the part to a new file in [`Upload::dir`], which is removed if the upload fails.
+    #[allow(clippy::result_large_err)]
+    pub async fn save(self) -> Result<SavedFile, Rejection> {
+        let dir = self.conf.dir.clone().unwrap_or_else(std::env::temp_dir);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        let n = SAVED.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("upload-{}-{}-{}", std::process::id(), nanos, n));
+        let mut saved = SavedFile {
+            name: self.name().map(str::to_string),
+            file_name: self.file_name().map(str::to_string),
+            content_type: self.content_type().map(str::to_string),
+            path,
+            size: 0,
+        };
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&saved.path)
+            .await
+            .map_err(|err| io_failed(&err))?;
+        match self.copy_to(&mut file).await {
+            Ok(size) => {
+                saved.size = size;
+                Ok(saved)
+            }
+            Err(rejection) => {
+                drop(file);
+                if let Err(err) = tokio::fs::remove_file(&saved.path).await {
+                    log::warn!("failed to remove partial upload {}: {}", saved.path.display(), err);
+                }
+                Err(rejection)
+            }
+        }
+    }
+}
+
+fn rejection(err: multer::Error) -> Rejection {
+    let status = match err {
+        multer::Error::StreamSizeExceeded { limit } => payload_too_large(limit),
+        multer::Error::FieldSizeExceeded { limit, .. } => payload_too_large(limit),
+        err => Status::new("MultipartRejection", &err.to_string())
+            .with_code(StatusCode::BAD_REQUEST)
+            .with_cause(&err),
+    };
+    Rejection(status)
+}
+
+fn payload_too_large(limit: u64) -> Status {
+    Status::new("PayloadTooLarge", &format!("upload exceeds the limit of {} bytes", limit))
+        .with_code(StatusCode::PAYLOAD_TOO_LARGE)
+}
+
+fn unsupported_media_type(message: &str) -> Status {
+    Status::new("UnsupportedMediaType", message).with_code(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+}
+
+fn io_failed(err: &std::io::Error) -> Rejection {
+    Rejection(Status::new("UploadFailed", "failed to store the upload").with_cause(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, routing::post, Extension, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::Success;
+
+    async fn store(mut uploads: Uploads) -> Result<Success<Vec<(String, u64)>>, Rejection> {
+        let mut saved = Vec::new();
+        while let Some(part) = uploads.next_part().await? {
+            if part.file_name().is_none() {
+                let name = part.name().unwrap_or_default().to_string();
+                saved.push((name, part.text().await?.len() as u64));
+                continue;
+            }
+            let file = part.save().await?;
+            assert_eq!(tokio::fs::metadata(&file.path).await.unwrap().len(), file.size);
+            tokio::fs::remove_file(&file.path).await.unwrap();
+            saved.push((file.file_name.unwrap(), file.size));
+        }
+        Ok(Success(saved))
+    }
+
+    fn app(dir: &std::path::Path) -> Router {
+        let conf = Upload {
+            max_file_size: 8,
+            max_total_size: 1024,
+            content_types: vec!["text/plain".to_string(), "image/*".to_string()],
+            dir: Some(dir.to_path_buf()),
+        };
+        Router::new().route("/", post(store)).layer(Extension(Arc::new(conf)))
+    }
+
+    fn body(content_type: &str, content: &str) -> String {
+        format!(
+            "--X\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nhi\r\n\
+             --X\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a\"\r\n\
+             Content-Type: {}\r\n\r\n{}\r\n--X--\r\n",
+            content_type, content
+        )
+    }
+
+    async fn call(app: &Router, body: String) -> (StatusCode, serde_json::Value) {
+        let req = Request::post("/")
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=X")
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(Body::from(body))
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        let status = res.status();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn valid_upload() {
+        let dir = std::env::temp_dir().join(format!("bamboo-upload-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let app = app(&dir);
+
+        let (status, res) = call(&app, body("text/plain", "abc")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(res["data"], serde_json::json!([["title", 2], ["a", 3]]));
+        assert_eq!(call(&app, body("image/png", "abc")).await.1["success"], true);
+
+        let (status, res) = call(&app, body("application/pdf", "abc")).await;
+        assert_eq!((status, res["code"].as_str()), (StatusCode::UNSUPPORTED_MEDIA_TYPE, Some("UnsupportedMediaType")));
+        let (status, res) = call(&app, body("text/plain", "too large for one file")).await;
+        assert_eq!((status, res["code"].as_str()), (StatusCode::PAYLOAD_TOO_LARGE, Some("PayloadTooLarge")));
+        let broken = body("text/plain", "abc").replace("--X--", "--Y");
+        let (status, res) = call(&app, broken).await;
+        assert_eq!((status, res["code"].as_str()), (StatusCode::BAD_REQUEST, Some("MultipartRejection")));
+
+        let (status, res) = call(&app, body("text/plain", &"x".repeat(2048))).await;
+        assert_eq!((status, res["code"].as_str()), (StatusCode::PAYLOAD_TOO_LARGE, Some("PayloadTooLarge")));
+        let req = Request::post("/").body(Body::from("abc")).unwrap();
+        assert_eq!(app.clone().oneshot(req).await.unwrap().status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let mut left = tokio::fs::read_dir(&dir).await.unwrap();
+        assert!(left.next_entry().await.unwrap().is_none(), "partial uploads are removed");
+        tokio::fs::remove_dir(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn valid_copy_to() {
+        let app = Router::new().route(
+            "/",
+            post(|mut uploads: Uploads| async move {
+                let mut sink = Vec::new();
+                while let Some(part) = uploads.next_part().await.unwrap() {
+                    part.copy_to(&mut sink).await.unwrap();
+                }
+                String::from_utf8(sink).unwrap()
+            }),
+        );
+        let req = Request::post("/")
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=X")
+            .body(Body::from(body("text/plain", "abc")))
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "hiabc");
+    }
+}
@@ -1,29 +1,33 @@
 use async_trait::async_trait;
-use axum::extract::{FromRequest, Json, Request};
-use axum::extract::rejection::JsonRejection;
+use axum::extract::{Form, FromRequest, FromRequestParts, Json, Multipart, Path, Query, Request};
+use axum::extract::multipart::MultipartRejection;
+use axum::extract::rejection::{FormRejection, JsonRejection, PathRejection, QueryRejection};
+use axum::http::{request::Parts, StatusCode};
 use serde::de::DeserializeOwned;
 use validator::Validate;
 
 use bamboo_status::errors::Status;
 
-// #[derive(Debug, Clone, Copy, Default)]
-// pub struct ValidatedForm<T>(pub T);
-//
-// #[async_trait]
-// impl<T, S> FromRequest<S> for ValidatedForm<T>
-//     where
-//         T: DeserializeOwned + Validate,
-//         S: Send + Sync,
-//         Form<T>: FromRequest<S, Rejection = FormRejection>,
-// {
-//     type Rejection = Status;
-//
-//     async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
-//         let Form(value) = Form::<T>::from_request(req, state).await?;
-//         value.validate()?;
-//         Ok(ValidatedForm(value))
-//     }
-// }
+use crate::Rejection;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidatedForm<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedForm<T>
+    where
+        T: DeserializeOwned + Validate,
+        S: Send + Sync,
+        Form<T>: FromRequest<S, Rejection=FormRejection>,
+{
+    type Rejection = Rejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Form(value) = Form::<T>::from_request(req, state).await.map_err(Status::from)?;
+        value.validate().map_err(Status::from)?;
+        Ok(ValidatedForm(value))
+    }
+}
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct ValidatedJson<T>(pub T);
@@ -35,11 +39,261 @@ impl<T, S> FromRequest<S> for ValidatedJson<T>
         S: Send + Sync,
         Json<T>: FromRequest<S, Rejection=JsonRejection>,
 {
-    type Rejection = Status;
+    type Rejection = Rejection;
 
     async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
-        let Json(value) = Json::<T>::from_request(req, state).await?;
-        value.validate()?;
+        let Json(value) = Json::<T>::from_request(req, state).await.map_err(Status::from)?;
+        value.validate().map_err(Status::from)?;
         Ok(ValidatedJson(value))
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidatedQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ValidatedQuery<T>
+    where
+        T: DeserializeOwned + Validate,
+        S: Send + Sync,
+        Query<T>: FromRequestParts<S, Rejection=QueryRejection>,
+{
+    type Rejection = Rejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::<T>::from_request_parts(parts, state).await.map_err(Status::from)?;
+        value.validate().map_err(Status::from)?;
+        Ok(ValidatedQuery(value))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidatedPath<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ValidatedPath<T>
+    where
+        T: DeserializeOwned + Validate + Send,
+        S: Send + Sync,
+        Path<T>: FromRequestParts<S, Rejection=PathRejection>,
+{
+    type Rejection = Rejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(value) = Path::<T>::from_request_parts(parts, state).await.map_err(Status::from)?;
+        value.validate().map_err(Status::from)?;
+        Ok(ValidatedPath(value))
+    }
+}
+
+/// Validated request headers.
+///
+/// Header names are lowercase, so fields usually need `#[serde(rename = "x-tenant-id")]`.
+/// Values are parsed like form fields, so numbers and booleans work as usual. Bytes that are not
+/// UTF-8 are replaced with `U+FFFD`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidatedHeaders<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ValidatedHeaders<T>
+    where
+        T: DeserializeOwned + Validate,
+        S: Send + Sync,
+{
+    type Rejection = Rejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // Values are decoded lossily, so unrelated headers with opaque bytes don't reject the request.
+        let pairs: Vec<_> = parts
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), String::from_utf8_lossy(value.as_bytes())))
+            .collect();
+        let value: T = from_pairs(&pairs, "HeadersRejection")?;
+        value.validate().map_err(Status::from)?;
+        Ok(ValidatedHeaders(value))
+    }
+}
+
+/// Validated text fields of a `multipart/form-data` body.
+///
+/// File parts (those with a filename) are skipped, text fields are parsed like form fields.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidatedMultipart<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedMultipart<T>
+    where
+        T: DeserializeOwned + Validate,
+        S: Send + Sync,
+        Multipart: FromRequest<S, Rejection=MultipartRejection>,
+{
+    type Rejection = Rejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let mut multipart = Multipart::from_request(req, state).await.map_err(Status::from)?;
+        let mut pairs = Vec::new();
+        while let Some(field) = multipart.next_field().await.map_err(Status::from)? {
+            if field.file_name().is_some() {
+                continue;
+            }
+            let Some(name) = field.name().map(str::to_string) else {
+                continue;
+            };
+            pairs.push((name, field.text().await.map_err(Status::from)?));
+        }
+        let value: T = from_pairs(&pairs, "MultipartRejection")?;
+        value.validate().map_err(Status::from)?;
+        Ok(ValidatedMultipart(value))
+    }
+}
+
+/// Deserialize name/value pairs the same way `Form` does.
+#[allow(clippy::result_large_err)]
+fn from_pairs<K, V, T>(pairs: &[(K, V)], reason: &str) -> Result<T, Status>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+        T: DeserializeOwned,
+{
+    let pairs: Vec<(&str, &str)> = pairs.iter().map(|(k, v)| (k.as_ref(), v.as_ref())).collect();
+    let rejection = |err: &(dyn std::error::Error + 'static)| {
+        Status::new(reason, &format!("Failed to deserialize: {}", err))
+            .with_code(StatusCode::BAD_REQUEST)
+            .with_cause(err)
+    };
+    let encoded = serde_urlencoded::to_string(pairs).map_err(|err| rejection(&err))?;
+    serde_urlencoded::from_str(&encoded).map_err(|err| rejection(&err))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::header, routing::{get, post}, Router};
+    use serde::Deserialize;
+    use tower::ServiceExt;
+    use validator::Validate;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, Validate)]
+    struct SignUp {
+        #[validate(email)]
+        email: String,
+        #[validate(length(min = 8))]
+        password: String,
+    }
+
+    #[tokio::test]
+    async fn valid_json_field_violations() {
+        let app = Router::new().route("/", post(|ValidatedJson(_): ValidatedJson<SignUp>| async {}));
+        let req = Request::post("/")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"email":"nope","password":"short"}"#))
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let status = res.extensions().get::<Status>().cloned().unwrap();
+        assert_eq!(status.code, 400);
+
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let violations = &body["data"]["field_violations"];
+        assert_eq!(violations[0]["field"], "email");
+        assert_eq!(violations[1]["field"], "password");
+        assert_eq!(violations[1]["reason"], "length");
+        assert_eq!(violations[1]["params"]["min"], "8");
+    }
+
+    #[derive(Debug, Deserialize, Validate)]
+    struct Page {
+        #[validate(range(min = 1, max = 100))]
+        size: u32,
+    }
+
+    #[derive(Debug, Deserialize, Validate)]
+    struct Tenant {
+        #[serde(rename = "x-tenant-id")]
+        #[validate(length(min = 3))]
+        tenant_id: String,
+    }
+
+    async fn status_of(app: Router, req: Request) -> Status {
+        let res = app.oneshot(req).await.unwrap();
+        let status = res.extensions().get::<Status>().cloned().unwrap_or_else(|| Status::new("OK", "").with_code(res.status()));
+        // Rejections render the HTTP status of their code.
+        assert_eq!(res.status().as_u16() as i32, status.code);
+        status
+    }
+
+    fn get_req(uri: &str) -> Request {
+        Request::get(uri).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn valid_query() {
+        let app = Router::new().route("/", get(|ValidatedQuery(_): ValidatedQuery<Page>| async {}));
+        assert_eq!(status_of(app.clone(), get_req("/?size=10")).await.code, 200);
+        let s = status_of(app.clone(), get_req("/?size=1000")).await;
+        assert_eq!(s.code, 400);
+        assert_eq!(s.bad_request.unwrap().field_violations[0].field, "size");
+        let s = status_of(app, get_req("/?size=abc")).await;
+        assert_eq!((s.code, s.reason.as_str()), (400, "QueryRejection"));
+    }
+
+    #[tokio::test]
+    async fn valid_path() {
+        let app = Router::new().route("/:size", get(|ValidatedPath(_): ValidatedPath<Page>| async {}));
+        assert_eq!(status_of(app.clone(), get_req("/10")).await.code, 200);
+        assert_eq!(status_of(app.clone(), get_req("/0")).await.code, 400);
+        let s = status_of(app, get_req("/abc")).await;
+        assert_eq!((s.code, s.reason.as_str()), (400, "PathRejection"));
+    }
+
+    #[tokio::test]
+    async fn valid_form() {
+        let app = Router::new().route("/", post(|ValidatedForm(_): ValidatedForm<Page>| async {}));
+        let req = Request::post("/")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from("size=0"))
+            .unwrap();
+        assert_eq!(status_of(app, req).await.code, 400);
+    }
+
+    #[tokio::test]
+    async fn valid_headers() {
+        let app = Router::new().route("/", get(|ValidatedHeaders(_): ValidatedHeaders<Tenant>| async {}));
+        let req = |v: &str| Request::get("/").header("x-tenant-id", v).body(Body::empty()).unwrap();
+        assert_eq!(status_of(app.clone(), req("acme")).await.code, 200);
+        let s = status_of(app.clone(), req("a")).await;
+        assert_eq!(s.bad_request.unwrap().field_violations[0].field, "x-tenant-id");
+        let s = status_of(app.clone(), get_req("/")).await;
+        assert_eq!((s.code, s.reason.as_str()), (400, "HeadersRejection"));
+        // Undeclared headers with non-ASCII values are not rejected.
+        let req = Request::get("/")
+            .header("x-tenant-id", "acme")
+            .header("x-note", header::HeaderValue::from_bytes("café".as_bytes()).unwrap())
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(status_of(app, req).await.code, 200);
+    }
+
+    #[tokio::test]
+    async fn valid_multipart() {
+        let app = Router::new().route("/", post(|ValidatedMultipart(_): ValidatedMultipart<Page>| async {}));
+        let req = |size: &str| {
+            let body = format!(
+                "--X\r\nContent-Disposition: form-data; name=\"size\"\r\n\r\n{}\r\n\
+                 --X\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\r\nabc\r\n--X--\r\n",
+                size
+            );
+            Request::post("/")
+                .header(header::CONTENT_TYPE, "multipart/form-data; boundary=X")
+                .body(Body::from(body))
+                .unwrap()
+        };
+        assert_eq!(status_of(app.clone(), req("5")).await.code, 200);
+        assert_eq!(status_of(app.clone(), req("500")).await.code, 400);
+        let s = status_of(app, Request::post("/").body(Body::empty()).unwrap()).await;
+        assert_eq!((s.code, s.reason.as_str()), (400, "MultipartRejection"));
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use http::{header, HeaderValue, Request, Response};
+use serde::de::DeserializeOwned;
+use tonic::service::Interceptor;
+use tower_layer::Layer;
+use tower_service::Service;
+
+pub use bamboo_auth::{Authorization, Jwt, Policy, Principal, Verifier};
+use bamboo_auth::unauthenticated;
+
+type BoxFuture<'a, T> = Pin<Box<dyn std::future::Future<Output=T> + Send + 'a>>;
+
+/// Health checks stay reachable for load balancers without a token.
+pub(crate) const HEALTH_PREFIX: &str = "/grpc.health.v1.Health/";
+
+/// Claims of the bearer token the call was authenticated with, deserialized into `T`.
+#[allow(clippy::result_large_err)]
+pub fn claims<T: DeserializeOwned, M>(req: &tonic::Request<M>) -> Result<T, tonic::Status> {
+    let principal = req
+        .extensions()
+        .get::<Principal>()
+        .ok_or_else(|| unauthenticated("missing bearer token"))?;
+    Ok(principal.claims()?)
+}
+
+/// Verifies the `authorization` metadata of every call, for `FooServer::with_interceptor`.
+///
+/// The caller is put in the request extensions as a [`Principal`], read it with [`claims`].
+#[derive(Clone)]
+pub struct JwtInterceptor {
+    verifier: Arc<Verifier>,
+}
+
+impl JwtInterceptor {
+    pub fn new(verifier: Arc<Verifier>) -> Self {
+        Self { verifier }
+    }
+}
+
+impl Interceptor for JwtInterceptor {
+    fn call(&mut self, mut req: tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status> {
+        let authorization = req.metadata().get("authorization").and_then(|v| v.to_str().ok());
+        if let Some(principal) = self.verifier.authenticate(authorization)? {
+            req.extensions_mut().insert(principal);
+        }
+        Ok(req)
+    }
+}
+
+/// The server wide version of [`JwtInterceptor`], installed by [`Grpc::auth`](crate::Grpc::auth).
+///
+/// Unlike an interceptor it sees the method path, so health checks are let through and
+/// [`Authorization`] rules can be checked per `/pkg.Service/Method`.
+#[derive(Clone)]
+pub struct AuthLayer {
+    verifier: Arc<Verifier>,
+    authorization: Option<Arc<Authorization>>,
+}
+
+impl AuthLayer {
+    pub fn new(verifier: Arc<Verifier>) -> Self {
+        Self {
+            verifier,
+            authorization: None,
+        }
+    }
+
+    /// Check the role and scope rules of the called method after authenticating.
+    pub fn with_authorization(mut self, authorization: Arc<Authorization>) -> Self {
+        self.authorization = Some(authorization);
+        self
+    }
+}
+
+impl<S> Layer<S> for AuthLayer {
+    type Service = Auth<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Auth {
+            inner,
+            verifier: self.verifier.clone(),
+            authorization: self.authorization.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Auth<S> {
+    inner: S,
+    verifier: Arc<Verifier>,
+    authorization: Option<Arc<Authorization>>,
+}
+
+impl<S> Auth<S> {
+    #[allow(clippy::result_large_err)]
+    fn check<B>(&self, req: &mut Request<B>) -> Result<(), bamboo_status::errors::Status> {
+        let principal = authenticate(
+            &self.verifier,
+            self.authorization.as_deref(),
+            req.headers().get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()),
+            req.method().as_str(),
+            req.uri().path(),
+        )?;
+        if let Some(principal) = principal {
+            req.extensions_mut().insert(principal);
+        }
+        Ok(())
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Auth<S>
+    where
+        S: Service<Request<ReqBody>, Response=Response<ResBody>> + Clone + Send + 'static,
+        S::Future: Send + 'static,
+        ReqBody: Send + 'static,
+        ResBody: Default + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        if !req.uri().path().starts_with(HEALTH_PREFIX) {
+            if let Err(status) = self.check(&mut req) {
+                let res = reject(status.into());
+                return Box::pin(async move { Ok(res) });
+            }
+        }
+
+        // See bamboo-tower's `MyMiddleware` for why the ready service is swapped out.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move { inner.call(req).await })
+    }
+}
+
+/// Authenticate the `authorization` header of a call, then check the rules of the called
+/// method if any.
+#[allow(clippy::result_large_err)]
+pub(crate) fn authenticate(
+    verifier: &Verifier,
+    authorization: Option<&Authorization>,
+    header: Option<&str>,
+    method: &str,
+    path: &str,
+) -> Result<Option<Principal>, bamboo_status::errors::Status> {
+    let principal = verifier.authenticate(header)?;
+    if let Some(conf) = authorization {
+        conf.authorize(principal.as_ref(), method, path, None)?;
+    }
+    Ok(principal)
+}
+
+/// A trailers-only error response, like tonic sends for interceptor errors.
+fn reject<B: Default>(status: tonic::Status) -> Response<B> {
+    let mut res = Response::new(B::default());
+    res.headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+    if let Err(err) = status.add_header(res.headers_mut()) {
+        log::warn!("auth grpc status: {}", err);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use bamboo_auth::jsonwebtoken::{encode, get_current_timestamp, EncodingKey, Header};
+    use bamboo_status::errors::Status;
+    use serde::Deserialize;
+    use serde_json::json;
+    use tonic::Code;
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct User {
+        sub: String,
+    }
+
+    fn verifier() -> Arc<Verifier> {
+        Arc::new(Verifier::new(&Jwt::with_secret("secret")).unwrap())
+    }
+
+    fn bearer(sub: &str) -> String {
+        let claims = json!({"sub": sub, "exp": get_current_timestamp() + 600});
+        let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(b"secret")).unwrap();
+        format!("Bearer {}", token)
+    }
+
+    #[test]
+    fn valid_interceptor() {
+        let mut interceptor = JwtInterceptor::new(verifier());
+        let mut req = tonic::Request::new(());
+        req.metadata_mut().insert("authorization", bearer("alice").parse().unwrap());
+        let req = interceptor.call(req).unwrap();
+        assert_eq!(claims::<User, _>(&req).unwrap().sub, "alice");
+
+        let ts = interceptor.call(tonic::Request::new(())).unwrap_err();
+        assert_eq!(ts.code(), Code::Unauthenticated);
+        let s: Status = ts.into();
+        assert_eq!(s.reason, "Unauthenticated");
+    }
+
+    #[tokio::test]
+    async fn valid_auth_layer() {
+        let svc = AuthLayer::new(verifier()).layer(service_fn(|req: Request<()>| async move {
+            let user = req.extensions().get::<Principal>().map(|p| p.subject.clone()).unwrap_or_default();
+            let mut res = Response::new(());
+            res.headers_mut().insert("x-user", user.parse().unwrap());
+            Ok::<_, std::convert::Infallible>(res)
+        }));
+        let call = |path: &str, authorization: Option<String>| {
+            let mut req = Request::post(path);
+            if let Some(authorization) = authorization {
+                req = req.header(header::AUTHORIZATION, authorization);
+            }
+            svc.clone().oneshot(req.body(()).unwrap())
+        };
+
+        let res = call("/pkg.Users/Get", Some(bearer("alice"))).await.unwrap();
+        assert_eq!(res.headers()["x-user"], "alice");
+
+        let res = call("/pkg.Users/Get", None).await.unwrap();
+        let ts = tonic::Status::from_header_map(res.headers()).unwrap();
+        assert_eq!(ts.code(), Code::Unauthenticated);
+
+        let res = call("/grpc.health.v1.Health/Check", None).await.unwrap();
+        assert!(tonic::Status::from_header_map(res.headers()).is_none());
+    }
+
+    #[tokio::test]
+    async fn valid_authorization() {
+        let policies = Authorization::default()
+            .rule("/pkg.Billing/Refund", None, Policy::any_role(&["finance"]))
+            .rule("/pkg.Billing", None, Policy::authenticated());
+        let layer = AuthLayer::new(verifier()).with_authorization(Arc::new(policies));
+        let svc = layer.layer(service_fn(|_req: Request<()>| async {
+            Ok::<_, std::convert::Infallible>(Response::new(()))
+        }));
+        let code = |path: &str| {
+            let req = Request::post(path)
+                .header(header::AUTHORIZATION, bearer("alice"))
+                .body(())
+                .unwrap();
+            let svc = svc.clone();
+            async move {
+                let res = svc.oneshot(req).await.unwrap();
+                tonic::Status::from_header_map(res.headers()).map(|ts| ts.code())
+            }
+        };
+
+        assert_eq!(code("/pkg.Billing/Refund").await, Some(Code::PermissionDenied));
+        assert_eq!(code("/pkg.Billing/Charge").await, None);
+        assert_eq!(code("/pkg.Users/Get").await, None);
+    }
+}
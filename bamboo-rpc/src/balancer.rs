@@ -0,0 +1,350 @@
+//! Client side load balancing over the instances of a service, for `discovery:///name`
+//! endpoints of a [`GrpcClient`](crate::client::GrpcClient).
+//!
+//! tonic's own balancer picks out of two backends by a load that its connections always
+//! report as zero, so backends are picked here and every call counts as load while it waits
+//! for its response.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::Duration,
+};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    runtime::Handle,
+    sync::{oneshot, Notify},
+};
+
+use bamboo_registry::{Discovery, ServiceInstance};
+use bamboo_status::status::AnyResult;
+
+/// How calls are spread over the instances of a service.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Every instance in turn.
+    #[default]
+    RoundRobin,
+    /// In proportion to the `weight` metadata of the instances, 1 when missing.
+    Weighted,
+    /// The instance with fewer calls in flight out of two picked at random.
+    P2c,
+}
+
+/// An instance of the service with its channel.
+#[derive(Clone)]
+pub(crate) struct Backend {
+    id: String,
+    uri: String,
+    pub(crate) channel: tonic::transport::Channel,
+    weight: i64,
+    current: i64,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Backend {
+    /// Count a call as in flight until the returned guard is dropped.
+    pub(crate) fn start(&self) -> InFlight {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight(self.in_flight.clone())
+    }
+}
+
+pub(crate) struct InFlight(Arc<AtomicUsize>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Default)]
+struct State {
+    backends: Vec<Backend>,
+    next: usize,
+    loaded: bool,
+}
+
+/// The backends of one service, kept up to date by a task watching the [`Discovery`].
+pub(crate) struct Pool {
+    service: String,
+    strategy: Strategy,
+    state: Mutex<State>,
+    loaded: Notify,
+    timeout: Duration,
+    // Dropped with the pool, stopping the watch.
+    _stop: oneshot::Sender<()>,
+}
+
+/// Builds the channel of a `grpc://` or `grpcs://` instance endpoint.
+pub(crate) type Connect = Box<dyn Fn(&str) -> AnyResult<tonic::transport::Channel> + Send + Sync>;
+
+impl Pool {
+    /// Follow the instances of `service` on `runtime`. Calls wait at most `timeout` for the
+    /// first ones, a failed watch is retried after it.
+    pub(crate) fn watch(
+        runtime: &Handle,
+        discovery: Arc<dyn Discovery>,
+        service: &str,
+        strategy: Strategy,
+        connect: Connect,
+        timeout: Duration,
+    ) -> Arc<Self> {
+        let (stop, mut stopped) = oneshot::channel();
+        let pool = Arc::new(Self::new(service, strategy, timeout, stop));
+        let weak = Arc::downgrade(&pool);
+        let service = service.to_string();
+        runtime.spawn(async move {
+            tokio::select! {
+                _ = &mut stopped => {}
+                _ = Self::follow(weak, discovery, service, connect, timeout) => {}
+            }
+        });
+        pool
+    }
+
+    /// Update the pool with the instances of `service` until the pool is gone.
+    async fn follow(
+        pool: Weak<Self>,
+        discovery: Arc<dyn Discovery>,
+        service: String,
+        connect: Connect,
+        retry: Duration,
+    ) -> Option<()> {
+        loop {
+            let mut watcher = match discovery.watch(&service).await {
+                Ok(watcher) => watcher,
+                Err(e) => {
+                    log::error!("grpc client: watching {} failed: {:?}", service, e);
+                    pool.upgrade()?.set_loaded();
+                    tokio::time::sleep(retry).await;
+                    continue;
+                }
+            };
+            while let Some(instances) = watcher.next().await {
+                pool.upgrade()?.update(&instances, &connect);
+            }
+            log::warn!("grpc client: watch of {} ended, watching again", service);
+            tokio::time::sleep(retry).await;
+        }
+    }
+
+    fn new(service: &str, strategy: Strategy, timeout: Duration, stop: oneshot::Sender<()>) -> Self {
+        Self {
+            service: service.to_string(),
+            strategy,
+            state: Mutex::new(State::default()),
+            loaded: Notify::new(),
+            timeout,
+            _stop: stop,
+        }
+    }
+
+    pub(crate) fn service(&self) -> &str {
+        &self.service
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.state.lock().unwrap().backends.len()
+    }
+
+    fn set_loaded(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.loaded {
+            state.loaded = true;
+            self.loaded.notify_waiters();
+        }
+    }
+
+    /// Wait for the first instances, at most the timeout of the pool.
+    pub(crate) async fn loaded(&self) {
+        let notified = self.loaded.notified();
+        if self.state.lock().unwrap().loaded {
+            return;
+        }
+        let _ = tokio::time::timeout(self.timeout, notified).await;
+    }
+
+    /// Replace the backends with `instances`, keeping the channels of instances still there.
+    pub(crate) fn update(&self, instances: &[ServiceInstance], connect: &Connect) {
+        let mut state = self.state.lock().unwrap();
+        let mut old: HashMap<String, Backend> = state.backends.drain(..).map(|b| (b.id.clone(), b)).collect();
+        for instance in instances {
+            let weight = instance.weight().unwrap_or(1) as i64;
+            if weight == 0 {
+                continue;
+            }
+            let Some(uri) = instance.endpoint(&["grpc", "grpcs"]) else {
+                log::warn!("grpc client: instance {} of {} has no grpc endpoint", instance.id, self.service);
+                continue;
+            };
+            let backend = match old.remove(&instance.id) {
+                Some(backend) if backend.uri == uri => Backend { weight, ..backend },
+                _ => {
+                    let channel = match connect(uri) {
+                        Ok(channel) => channel,
+                        Err(e) => {
+                            log::warn!("grpc client: instance {} of {}: {:?}", instance.id, self.service, e);
+                            continue;
+                        }
+                    };
+                    Backend {
+                        id: instance.id.clone(),
+                        uri: uri.to_string(),
+                        channel,
+                        weight,
+                        current: 0,
+                        in_flight: Arc::new(AtomicUsize::new(0)),
+                    }
+                }
+            };
+            state.backends.push(backend);
+        }
+        log::info!("grpc client: {} has {} instances", self.service, state.backends.len());
+        drop(state);
+        self.set_loaded();
+    }
+
+    /// The backend for the next call, `None` without instances.
+    pub(crate) fn pick(&self) -> Option<Backend> {
+        let mut state = self.state.lock().unwrap();
+        let len = state.backends.len();
+        if len == 0 {
+            return None;
+        }
+        let index = match self.strategy {
+            Strategy::RoundRobin => {
+                let index = state.next % len;
+                state.next = state.next.wrapping_add(1);
+                index
+            }
+            Strategy::Weighted => {
+                // Smooth weighted round robin: every backend gains its weight, the one ahead
+                // is picked and falls back by the total.
+                let mut total = 0;
+                let mut best = 0;
+                for i in 0..len {
+                    let current = {
+                        let backend = &mut state.backends[i];
+                        backend.current += backend.weight;
+                        total += backend.weight;
+                        backend.current
+                    };
+                    if current > state.backends[best].current {
+                        best = i;
+                    }
+                }
+                state.backends[best].current -= total;
+                best
+            }
+            Strategy::P2c => {
+                let mut rng = rand::thread_rng();
+                let a = rng.gen_range(0..len);
+                let b = (a + rng.gen_range(1..len.max(2))) % len;
+                let load = |i: usize| state.backends[i].in_flight.load(Ordering::Relaxed);
+                if load(b) < load(a) { b } else { a }
+            }
+        };
+        Some(state.backends[index].clone())
+    }
+}
+
+/// The service name of a `discovery:///name` endpoint, `None` for other endpoints.
+pub(crate) fn service_name(endpoint: &str) -> Option<&str> {
+    endpoint.strip_prefix("discovery:///")
+}
+
+/// The `http` or `https` URI of a `grpc://` or `grpcs://` endpoint.
+pub(crate) fn endpoint_uri(endpoint: &str, tls: bool) -> AnyResult<String> {
+    let (scheme, rest) = endpoint
+        .split_once("://")
+        .ok_or_else(|| anyhow::anyhow!("grpc client: invalid instance endpoint {:?}", endpoint))?;
+    let scheme = if tls || scheme.eq_ignore_ascii_case("grpcs") { "https" } else { "http" };
+    Ok(format!("{}://{}", scheme, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::transport::Endpoint;
+
+    use super::*;
+
+    fn pool(strategy: Strategy) -> Pool {
+        let (stop, _) = oneshot::channel();
+        Pool::new("bamboo.Probe", strategy, Duration::from_secs(1), stop)
+    }
+
+    fn connect() -> Connect {
+        Box::new(|_| Ok(Endpoint::from_static("http://127.0.0.1:1").connect_lazy()))
+    }
+
+    fn instance(id: &str, weight: Option<u32>) -> ServiceInstance {
+        ServiceInstance {
+            id: id.to_string(),
+            name: "bamboo.Probe".to_string(),
+            endpoints: vec![format!("grpc://{}:9000", id)],
+            metadata: weight.map(|w| HashMap::from([("weight".to_string(), w.to_string())])).unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    fn picks(pool: &Pool, n: usize) -> String {
+        (0..n).map(|_| pool.pick().unwrap().id).collect()
+    }
+
+    #[tokio::test]
+    async fn valid_pick() {
+        let rr = pool(Strategy::RoundRobin);
+        assert!(rr.pick().is_none());
+        rr.update(&[instance("a", None), instance("b", None), instance("c", None)], &connect());
+        assert_eq!(picks(&rr, 6), "abcabc");
+
+        let weighted = pool(Strategy::Weighted);
+        weighted.update(&[instance("a", Some(5)), instance("b", Some(1)), instance("c", Some(1)), instance("d", Some(0))], &connect());
+        assert_eq!(picks(&weighted, 7), "aabacaa");
+
+        let p2c = pool(Strategy::P2c);
+        p2c.update(&[instance("a", None), instance("b", None)], &connect());
+        let a = p2c.state.lock().unwrap().backends[0].clone();
+        let _busy = (a.start(), a.start());
+        assert_eq!(picks(&p2c, 4), "bbbb");
+    }
+
+    #[tokio::test]
+    async fn valid_update() {
+        let pool = pool(Strategy::RoundRobin);
+        pool.update(&[instance("a", None), instance("b", None)], &connect());
+        let a = pool.pick().unwrap();
+        let _call = a.start();
+
+        let mut moved = instance("b", None);
+        moved.endpoints = vec!["http://b:8000".to_string(), "grpcs://b:9443".to_string()];
+        let no_grpc = ServiceInstance {
+            endpoints: vec!["http://c:8000".to_string()],
+            ..instance("c", None)
+        };
+        pool.update(&[instance("a", None), moved, no_grpc], &connect());
+        let state = pool.state.lock().unwrap();
+        let backends: Vec<_> = state.backends.iter().map(|b| (b.id.as_str(), b.uri.as_str())).collect();
+        assert_eq!(backends, vec![("a", "grpc://a:9000"), ("b", "grpcs://b:9443")]);
+        // The channel and load of an instance still there are kept.
+        assert_eq!(state.backends[0].in_flight.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn valid_endpoint() {
+        assert_eq!(service_name("discovery:///bamboo.Probe"), Some("bamboo.Probe"));
+        assert_eq!(service_name("http://localhost:9000"), None);
+        assert_eq!(endpoint_uri("grpc://10.0.0.7:9000", false).unwrap(), "http://10.0.0.7:9000");
+        assert_eq!(endpoint_uri("grpc://10.0.0.7:9000", true).unwrap(), "https://10.0.0.7:9000");
+        assert_eq!(endpoint_uri("grpcs://10.0.0.7:9000", false).unwrap(), "https://10.0.0.7:9000");
+        assert!(endpoint_uri("10.0.0.7:9000", false).is_err());
+    }
+}
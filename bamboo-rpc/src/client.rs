@@ -0,0 +1,668 @@
+//! Clients of other gRPC services, configured from [`GrpcClient`].
+//!
+//! Calls go through a [`Channel`] that runs the client interceptors, retries idempotent
+//! methods on unavailable servers and reconnects lazily. Generated clients are built with [`Client::connect`]
+//! once they are registered with [`grpc_client!`](crate::grpc_client).
+//!
+//! An endpoint `discovery:///name` spreads the calls over the instances of the service
+//! `name` found by the [`Discovery`] of [`Client::with_discovery`], following them as they
+//! register and deregister.
+
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use anyhow::Context as _;
+use http_body_util::{BodyExt, Full};
+use hyper_util::rt::TokioIo;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+use tonic::{
+    body::BoxBody,
+    transport::{Endpoint, Uri},
+    Code, Request, Status,
+};
+use tower::{service_fn, BoxError, Service, ServiceExt};
+
+use bamboo_auth::path_matches;
+use bamboo_registry::Discovery;
+use bamboo_status::status::AnyResult;
+use bamboo_tls::{ClientTls, Connector};
+
+use crate::{
+    balancer::{endpoint_uri, service_name, Pool, Strategy},
+    compression::Compression,
+    context::{propagate_deadline, propagate_request_id, propagate_trace},
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GrpcClient {
+    /// `http://host:port`, or `https://host:port` together with `tls`, or
+    /// `discovery:///name` for the instances of a service.
+    pub endpoint: String,
+    /// How calls are spread over the instances of a `discovery:///name` endpoint.
+    #[serde(default)]
+    pub balancer: Strategy,
+    #[serde(default = "default_connect_timeout", with = "humantime_serde")]
+    pub connect_timeout: Duration,
+    /// Timeout of every call, missing leaves calls bounded by the propagated deadline only.
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+    /// Send HTTP/2 pings at this interval, disabled when missing.
+    #[serde(default, with = "humantime_serde")]
+    pub keepalive_interval: Option<Duration>,
+    /// Close the connection when a ping isn't answered in time.
+    #[serde(default = "default_keepalive_timeout", with = "humantime_serde")]
+    pub keepalive_timeout: Duration,
+    /// Connect over TLS, plaintext HTTP/2 when missing.
+    #[serde(default)]
+    pub tls: Option<ClientTls>,
+    /// Maximum size of sent and received messages in bytes, `0` keeps tonic's default of 4MB.
+    #[serde(default)]
+    pub max_message_size: usize,
+    /// Retry calls of `idempotent_methods` answered with `Unavailable` or failing to connect.
+    #[serde(default)]
+    pub retries: u32,
+    /// Methods like `/pkg.Service/Method`, or all methods of `/pkg.Service`, that are safe to
+    /// retry. Their requests are buffered to be sent again, so only list unary and
+    /// server-streaming methods.
+    #[serde(default)]
+    pub idempotent_methods: Vec<String>,
+    /// Wait before the first retry, doubled for every following one.
+    #[serde(default = "default_retry_backoff", with = "humantime_serde")]
+    pub retry_backoff: Duration,
+    /// `send` compresses requests, `accept` is offered for responses.
+    #[serde(default)]
+    pub compression: Compression,
+}
+
+fn default_connect_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_keepalive_timeout() -> Duration {
+    Duration::from_secs(20)
+}
+
+fn default_retry_backoff() -> Duration {
+    Duration::from_millis(100)
+}
+
+impl GrpcClient {
+    pub fn new(endpoint: &str) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            balancer: Strategy::default(),
+            connect_timeout: default_connect_timeout(),
+            timeout: None,
+            keepalive_interval: None,
+            keepalive_timeout: default_keepalive_timeout(),
+            tls: None,
+            max_message_size: 0,
+            retries: 0,
+            idempotent_methods: Vec::new(),
+            retry_backoff: default_retry_backoff(),
+            compression: Compression::default(),
+        }
+    }
+}
+
+/// Adds metadata to outgoing calls, or fails them with a [`Status`].
+pub type Interceptor = Arc<dyn Fn(Request<()>) -> Result<Request<()>, Status> + Send + Sync>;
+
+/// Builds channels and generated clients for one [`GrpcClient`] configuration.
+///
+/// ```ignore
+/// bamboo_rpc::grpc_client!(GreeterClient);
+///
+/// let greeter = Client::new(conf.greeter.clone()).connect::<GreeterClient<Channel>>()?;
+/// ```
+#[derive(Clone)]
+pub struct Client {
+    conf: GrpcClient,
+    interceptors: Vec<Interceptor>,
+    discovery: Option<Arc<dyn Discovery>>,
+}
+
+impl Client {
+    /// A client propagating the request id, trace context and deadline of the current call.
+    pub fn new(conf: GrpcClient) -> Self {
+        Self {
+            conf,
+            interceptors: vec![
+                Arc::new(propagate_request_id),
+                Arc::new(propagate_trace),
+                Arc::new(propagate_deadline),
+            ],
+            discovery: None,
+        }
+    }
+
+    /// Find the instances of `discovery:///name` endpoints with `discovery`.
+    pub fn with_discovery(mut self, discovery: Arc<dyn Discovery>) -> Self {
+        self.discovery = Some(discovery);
+        self
+    }
+
+    /// Run `interceptor` on every call, after the ones added before.
+    pub fn with_interceptor<F>(mut self, interceptor: F) -> Self
+        where F: Fn(Request<()>) -> Result<Request<()>, Status> + Send + Sync + 'static,
+    {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    pub fn conf(&self) -> &GrpcClient {
+        &self.conf
+    }
+
+    /// The endpoint settings of the configuration, for `uri`.
+    pub(crate) fn endpoint(&self, uri: Uri) -> Endpoint {
+        let mut endpoint = Endpoint::from(uri)
+            .connect_timeout(self.conf.connect_timeout)
+            .keep_alive_timeout(self.conf.keepalive_timeout)
+            .tcp_nodelay(true);
+        if let Some(timeout) = self.conf.timeout {
+            endpoint = endpoint.timeout(timeout);
+        }
+        if let Some(interval) = self.conf.keepalive_interval {
+            endpoint = endpoint.http2_keep_alive_interval(interval).keep_alive_while_idle(true);
+        }
+        endpoint
+    }
+
+    /// A channel connecting on its first call and reconnecting after failures. Endpoints
+    /// `discovery:///name` start watching the instances of `name`.
+    ///
+    /// Fails outside a Tokio runtime, which runs the connections and the watch.
+    pub fn channel(&self) -> AnyResult<Channel> {
+        let runtime = tokio::runtime::Handle::try_current().context("grpc client: channels need a Tokio runtime")?;
+        if let Some(service) = service_name(&self.conf.endpoint) {
+            let discovery = self
+                .discovery
+                .clone()
+                .with_context(|| format!("grpc client: no discovery for endpoint {:?}", self.conf.endpoint))?;
+            let client = self.clone();
+            let connect = Box::new(move |endpoint: &str| {
+                let uri = endpoint_uri(endpoint, client.conf.tls.is_some())?.parse()?;
+                client.connect_lazy(uri)
+            });
+            let pool = Pool::watch(&runtime, discovery, service, self.conf.balancer, connect, self.conf.connect_timeout);
+            return Ok(self.with_target(Target::Pool(pool)));
+        }
+        let uri: Uri = self
+            .conf
+            .endpoint
+            .parse()
+            .with_context(|| format!("grpc client: invalid endpoint {:?}", self.conf.endpoint))?;
+        Ok(self.wrap(self.connect_lazy(uri)?))
+    }
+
+    /// A tonic channel to `uri`, over TLS when configured.
+    fn connect_lazy(&self, uri: Uri) -> AnyResult<tonic::transport::Channel> {
+        let endpoint = self.endpoint(uri.clone());
+        let Some(tls) = &self.conf.tls else {
+            return Ok(endpoint.connect_lazy());
+        };
+        let host = uri.host().unwrap_or_default();
+        let connector = Connector::new(tls, host, &[b"h2"])?;
+        let addr = format!("{}:{}", host, uri.port_u16().unwrap_or(443));
+        let timeout = self.conf.connect_timeout;
+        let inner = endpoint.connect_with_connector_lazy(service_fn(move |_: Uri| {
+            let (connector, addr) = (connector.clone(), addr.clone());
+            async move {
+                let connect = async {
+                    let tcp = TcpStream::connect(&addr).await?;
+                    tcp.set_nodelay(true)?;
+                    connector.connect(tcp).await
+                };
+                let stream = tokio::time::timeout(timeout, connect)
+                    .await
+                    .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "grpc client: connect timed out"))??;
+                Ok::<_, io::Error>(TokioIo::new(stream))
+            }
+        }));
+        Ok(inner)
+    }
+
+    /// Run the interceptors and retries of this client on a tonic channel, e.g. one made by
+    /// `Channel::balance_channel`.
+    pub fn wrap(&self, inner: tonic::transport::Channel) -> Channel {
+        self.with_target(Target::Fixed(inner))
+    }
+
+    fn with_target(&self, target: Target) -> Channel {
+        Channel {
+            target,
+            interceptors: self.interceptors.clone().into(),
+            retries: self.conf.retries,
+            idempotent: self.conf.idempotent_methods.clone().into(),
+            backoff: self.conf.retry_backoff,
+        }
+    }
+
+    /// A generated client on a new [`channel`](Self::channel).
+    pub fn connect<T: FromChannel>(&self) -> AnyResult<T> {
+        Ok(T::from_channel(self.channel()?, &self.conf))
+    }
+}
+
+/// Generated clients [`Client::connect`] can build, implemented by [`grpc_client!`](crate::grpc_client).
+pub trait FromChannel {
+    fn from_channel(channel: Channel, conf: &GrpcClient) -> Self;
+}
+
+/// Let [`Client::connect`] build the generated clients, with the message size limits and
+/// compression of the configuration.
+///
+/// ```ignore
+/// use pb::greeter_client::GreeterClient;
+///
+/// bamboo_rpc::grpc_client!(GreeterClient);
+/// ```
+#[macro_export]
+macro_rules! grpc_client {
+    ($($client:ident),+ $(,)?) => {
+        $(
+            impl $crate::client::FromChannel for $client<$crate::client::Channel> {
+                fn from_channel(channel: $crate::client::Channel, conf: &$crate::client::GrpcClient) -> Self {
+                    let mut client = $client::new(channel);
+                    if conf.max_message_size > 0 {
+                        client = client
+                            .max_decoding_message_size(conf.max_message_size)
+                            .max_encoding_message_size(conf.max_message_size);
+                    }
+                    conf.compression.apply(client, $client::accept_compressed, $client::send_compressed)
+                }
+            }
+        )+
+    };
+}
+
+/// Where the calls of a [`Channel`] go.
+#[derive(Clone)]
+enum Target {
+    Fixed(tonic::transport::Channel),
+    Pool(Arc<Pool>),
+}
+
+impl Target {
+    /// Send `req`, to a backend picked for it with a pool. A fixed channel is polled ready
+    /// unless `polled` already.
+    async fn call(
+        &mut self,
+        req: http::Request<BoxBody>,
+        polled: bool,
+    ) -> Result<http::Response<BoxBody>, tonic::transport::Error> {
+        match self {
+            Target::Fixed(inner) => {
+                if !polled {
+                    inner.ready().await?;
+                }
+                inner.call(req).await
+            }
+            Target::Pool(pool) => {
+                pool.loaded().await;
+                let Some(mut backend) = pool.pick() else {
+                    return Ok(Status::unavailable(format!("no instances of {}", pool.service())).into_http());
+                };
+                let _in_flight = backend.start();
+                backend.channel.ready().await?;
+                backend.channel.call(req).await
+            }
+        }
+    }
+}
+
+/// A tonic channel with client interceptors and retries, cheap to clone.
+#[derive(Clone)]
+pub struct Channel {
+    target: Target,
+    interceptors: Arc<[Interceptor]>,
+    retries: u32,
+    idempotent: Arc<[String]>,
+    backoff: Duration,
+}
+
+impl Channel {
+    /// Run the interceptors on the metadata of `req`, or answer it with their [`Status`].
+    #[allow(clippy::result_large_err)]
+    fn intercept(&self, req: http::Request<BoxBody>) -> Result<http::Request<BoxBody>, Status> {
+        let (parts, body) = req.into_parts();
+        let mut req = Request::from_parts(
+            tonic::metadata::MetadataMap::from_headers(parts.headers.clone()),
+            parts.extensions.clone(),
+            (),
+        );
+        for interceptor in self.interceptors.iter() {
+            req = interceptor(req)?;
+        }
+        let (metadata, extensions, ()) = req.into_parts();
+        let mut parts = parts;
+        parts.headers = metadata.into_headers();
+        parts.extensions = extensions;
+        Ok(http::Request::from_parts(parts, body))
+    }
+}
+
+/// Whether the server can't be reached or answered with `Unavailable`.
+fn unavailable(res: &Result<http::Response<BoxBody>, tonic::transport::Error>) -> bool {
+    match res {
+        Ok(res) => Status::from_header_map(res.headers()).is_some_and(|s| s.code() == Code::Unavailable),
+        Err(_) => true,
+    }
+}
+
+impl Service<http::Request<BoxBody>> for Channel {
+    type Response = http::Response<BoxBody>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output=Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match &mut self.target {
+            Target::Fixed(inner) => inner.poll_ready(cx).map_err(Into::into),
+            // Backends are picked and polled ready per call.
+            Target::Pool(_) => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn call(&mut self, req: http::Request<BoxBody>) -> Self::Future {
+        // Call the channel that was polled ready, leave a fresh clone for the next call.
+        let clone = self.target.clone();
+        let mut target = std::mem::replace(&mut self.target, clone);
+        let req = match self.intercept(req) {
+            Ok(req) => req,
+            Err(status) => return Box::pin(async move { Ok(status.into_http()) }),
+        };
+        let (retries, mut backoff) = (self.retries, self.backoff);
+        let idempotent = self.idempotent.iter().any(|m| path_matches(m, req.uri().path(), None));
+        // Other methods may be client-streaming, their bodies can't be buffered.
+        if retries == 0 || !idempotent {
+            return Box::pin(async move { target.call(req, true).await.map_err(Into::into) });
+        }
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            let body = body.collect().await?.to_bytes();
+            let mut attempt = 0;
+            loop {
+                let req = http::Request::from_parts(parts.clone(), tonic::body::boxed(Full::new(body.clone())));
+                let res = target.call(req, attempt == 0).await;
+                if attempt >= retries || !unavailable(&res) {
+                    return res.map_err(Into::into);
+                }
+                attempt += 1;
+                log::debug!("grpc client: retrying {} after {:?}, attempt {}", parts.uri.path(), backoff, attempt);
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use bamboo_boot::plugin::Plugin;
+    use bamboo_registry::{ServiceInstance, Watcher};
+    use bamboo_tls::{testing::Pki, ClientAuth};
+    use futures_util::{stream, StreamExt};
+    use tokio::{net::TcpSocket, sync::watch};
+    use tokio_graceful::Shutdown;
+    use tonic::{codec::ProstCodec, server::NamedService, transport::server::TcpIncoming};
+    use tonic_health::pb::{health_client::HealthClient, HealthCheckRequest};
+
+    use super::*;
+    use crate::{context::CallContext, testing::TestServer, Config, Grpc, Server};
+
+    crate::grpc_client!(HealthClient);
+
+    struct Conf(Grpc);
+
+    impl Config for Conf {
+        fn grpc(&self) -> &Grpc {
+            &self.0
+        }
+    }
+
+    /// Answers `Unavailable` to the first `failures` calls, then `NotFound` describing the
+    /// context the call was served in.
+    #[derive(Clone, Default)]
+    struct Probe {
+        calls: Arc<AtomicUsize>,
+        failures: usize,
+    }
+
+    impl NamedService for Probe {
+        const NAME: &'static str = "bamboo.Probe";
+    }
+
+    impl Service<http::Request<BoxBody>> for Probe {
+        type Response = http::Response<BoxBody>;
+        type Error = Infallible;
+        type Future = Pin<Box<dyn Future<Output=Result<Self::Response, Infallible>> + Send>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: http::Request<BoxBody>) -> Self::Future {
+            let this = self.clone();
+            Box::pin(async move {
+                if this.calls.fetch_add(1, Ordering::SeqCst) < this.failures {
+                    return Ok(Status::unavailable("try again").into_http());
+                }
+                let ctx = CallContext::current().unwrap_or_default();
+                let remaining = ctx.remaining().map(|r| r.as_secs()).unwrap_or_default();
+                let message = format!("{} {} {}", ctx.request_id.unwrap_or_default(), ctx.traceparent.unwrap_or_default(), remaining);
+                Ok(Status::not_found(message).into_http())
+            })
+        }
+    }
+
+    async fn probe(channel: Channel) -> Status {
+        let mut grpc = tonic::client::Grpc::new(channel);
+        grpc.ready().await.unwrap();
+        let path = http::uri::PathAndQuery::from_static("/bamboo.Probe/Call");
+        grpc.unary::<(), (), _>(Request::new(()), path, ProstCodec::default()).await.unwrap_err()
+    }
+
+    fn server(probe: Probe, grpc: Grpc) -> Server<Conf, Probe> {
+        Server::new(Arc::new(Conf(grpc)), probe)
+    }
+
+    #[tokio::test]
+    async fn valid_propagate_context() {
+        let test = TestServer::new(&server(Probe::default(), Grpc::default())).await.unwrap();
+        let client = Client::new(GrpcClient::new("http://in-process"));
+        let channel = client.wrap(test.channel().await.unwrap());
+        let ctx = CallContext {
+            request_id: Some("abc".to_string()),
+            traceparent: Some("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01".to_string()),
+            tracestate: None,
+            deadline: Some(tokio::time::Instant::now() + Duration::from_secs(30)),
+        };
+
+        let status = ctx.scope(probe(channel.clone())).await;
+        assert_eq!(status.code(), Code::NotFound);
+        assert_eq!(status.message(), "abc 00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01 29");
+        assert_eq!(probe(channel).await.message(), "  0");
+        test.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn valid_retry() {
+        let probe_server = Probe { failures: 2, ..Default::default() };
+        let test = TestServer::new(&server(probe_server.clone(), Grpc::default())).await.unwrap();
+        let mut conf = GrpcClient::new("http://in-process");
+        conf.retry_backoff = Duration::from_millis(1);
+        conf.retries = 2;
+
+        // Methods not marked idempotent are never retried.
+        let status = probe(Client::new(conf.clone()).wrap(test.channel().await.unwrap())).await;
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(probe_server.calls.load(Ordering::SeqCst), 1);
+
+        conf.idempotent_methods = vec!["/bamboo.Probe".to_string()];
+        conf.retries = 1;
+        let status = probe(Client::new(conf.clone()).wrap(test.channel().await.unwrap())).await;
+        assert_eq!(status.code(), Code::NotFound);
+        assert_eq!(probe_server.calls.load(Ordering::SeqCst), 3);
+        test.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn valid_retry_client_streaming() {
+        let probe_server = Probe { failures: 1, ..Default::default() };
+        let test = TestServer::new(&server(probe_server.clone(), Grpc::default())).await.unwrap();
+        let mut conf = GrpcClient::new("http://in-process");
+        conf.retries = 2;
+        conf.idempotent_methods = vec!["/bamboo.Probe/Call".to_string()];
+        let channel = Client::new(conf).wrap(test.channel().await.unwrap());
+
+        // The request stream never ends, buffering it would hang the call.
+        let mut grpc = tonic::client::Grpc::new(channel);
+        grpc.ready().await.unwrap();
+        let path = http::uri::PathAndQuery::from_static("/bamboo.Probe/Stream");
+        let messages = stream::iter([()]).chain(stream::pending());
+        let call = grpc.client_streaming::<_, (), (), _>(Request::new(messages), path, ProstCodec::default());
+        let status = tokio::time::timeout(Duration::from_secs(5), call).await.unwrap().unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(probe_server.calls.load(Ordering::SeqCst), 1);
+        test.shutdown().await;
+    }
+
+    /// Serve on a free port until the test ends.
+    async fn serve(probe: Probe, grpc: Grpc) -> (Shutdown, SocketAddr) {
+        let grpc = Grpc {
+            address: "127.0.0.1:0".to_string(),
+            ..grpc
+        };
+        let shutdown = Shutdown::new(std::future::pending());
+        let server = Arc::new(server(probe, grpc));
+        let serving = server.clone();
+        shutdown.spawn_task_fn(move |guard| async move { serving.serve(guard).await.unwrap() });
+        let addr = server.listening.addr().await;
+        (shutdown, addr)
+    }
+
+    #[test]
+    fn invalid_channel_without_runtime() {
+        let Err(err) = Client::new(GrpcClient::new("http://127.0.0.1:1")).channel() else {
+            panic!("channel built without a runtime");
+        };
+        assert_eq!(err.to_string(), "grpc client: channels need a Tokio runtime");
+    }
+
+    #[tokio::test]
+    async fn valid_connect_lazily() {
+        // Bound but not listening yet: the port is ours and connections are refused.
+        let socket = TcpSocket::new_v4().unwrap();
+        socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = socket.local_addr().unwrap();
+        let mut health = Client::new(GrpcClient::new(&format!("http://{}", addr)))
+            .connect::<HealthClient<Channel>>()
+            .unwrap();
+        let req = || HealthCheckRequest { service: "bamboo.Probe".to_string() };
+        assert_eq!(health.check(req()).await.unwrap_err().code(), Code::Unavailable);
+
+        let listener = socket.listen(16).unwrap();
+        let shutdown = Shutdown::new(std::future::pending());
+        let server = server(Probe::default(), Grpc::default());
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        shutdown.spawn_task_fn(move |guard| async move { server.serve_incoming(incoming, &guard).await.unwrap() });
+        assert!(health.check(req()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn valid_connect_tls() {
+        let pki = Pki::new("rpc-client");
+        let grpc = Grpc {
+            tls: Some(pki.tls(ClientAuth::None)),
+            ..Default::default()
+        };
+        let (_shutdown, addr) = serve(Probe::default(), grpc).await;
+
+        let mut conf = GrpcClient::new(&format!("https://localhost:{}", addr.port()));
+        conf.tls = Some(pki.client_tls(None));
+        let mut health = Client::new(conf).connect::<HealthClient<Channel>>().unwrap();
+        assert!(health.check(HealthCheckRequest { service: String::new() }).await.is_ok());
+    }
+
+    /// Publishes the instances sent on its channel.
+    struct Registry(watch::Sender<Vec<ServiceInstance>>);
+
+    #[tonic::async_trait]
+    impl Discovery for Registry {
+        async fn get_service(&self, _name: &str) -> AnyResult<Vec<ServiceInstance>> {
+            Ok(self.0.borrow().clone())
+        }
+
+        async fn watch(&self, _name: &str) -> AnyResult<Watcher> {
+            Ok(Watcher::new(self.0.subscribe()))
+        }
+    }
+
+    /// Wait until the pool of `channel` follows the last published instances.
+    async fn pool_len(channel: &Channel, len: usize) {
+        let Target::Pool(pool) = &channel.target else {
+            panic!("not a discovery channel");
+        };
+        let updated = async {
+            while pool.len() != len {
+                tokio::task::yield_now().await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), updated).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn valid_discovery() {
+        let (a, b) = (Probe::default(), Probe::default());
+        let mut instances = Vec::new();
+        let mut shutdowns = Vec::new();
+        for (id, probe) in [("a", &a), ("b", &b)] {
+            let (shutdown, addr) = serve(probe.clone(), Grpc::default()).await;
+            shutdowns.push(shutdown);
+            instances.push(ServiceInstance {
+                id: id.to_string(),
+                name: "bamboo.Probe".to_string(),
+                endpoints: vec![format!("grpc://{}", addr)],
+                ..Default::default()
+            });
+        }
+        let (tx, _) = watch::channel(instances.clone());
+        let registry = Arc::new(Registry(tx));
+
+        let conf = GrpcClient::new("discovery:///bamboo.Probe");
+        assert!(Client::new(conf.clone()).channel().is_err());
+        let channel = Client::new(conf).with_discovery(registry.clone()).channel().unwrap();
+        for _ in 0..4 {
+            assert_eq!(probe(channel.clone()).await.code(), Code::NotFound);
+        }
+        assert_eq!((a.calls.load(Ordering::SeqCst), b.calls.load(Ordering::SeqCst)), (2, 2));
+
+        // Deregistered instances get no more calls.
+        registry.0.send_replace(instances[1..].to_vec());
+        pool_len(&channel, 1).await;
+        for _ in 0..2 {
+            assert_eq!(probe(channel.clone()).await.code(), Code::NotFound);
+        }
+        assert_eq!((a.calls.load(Ordering::SeqCst), b.calls.load(Ordering::SeqCst)), (2, 4));
+
+        registry.0.send_replace(Vec::new());
+        pool_len(&channel, 0).await;
+        let status = probe(channel).await;
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(status.message(), "no instances of bamboo.Probe");
+    }
+}
@@ -0,0 +1,141 @@
+//! gRPC message compression, configured by [`Grpc::compression`](crate::Grpc::compression).
+
+use serde::{Deserialize, Serialize};
+use tonic::codec::CompressionEncoding;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Gzip,
+    Zstd,
+}
+
+impl From<Encoding> for CompressionEncoding {
+    fn from(value: Encoding) -> Self {
+        match value {
+            Encoding::Gzip => CompressionEncoding::Gzip,
+            Encoding::Zstd => CompressionEncoding::Zstd,
+        }
+    }
+}
+
+/// Compression of request and response messages, disabled when both lists are empty.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Compression {
+    /// Encodings accepted in requests, others are answered with `Unimplemented`.
+    #[serde(default)]
+    pub accept: Vec<Encoding>,
+    /// Encodings responses are compressed with, if the client accepts one of them.
+    #[serde(default)]
+    pub send: Vec<Encoding>,
+}
+
+impl Compression {
+    /// Configure a generated server through its `accept_compressed` and `send_compressed`.
+    pub fn apply<S, A, Se>(&self, mut service: S, accept: A, send: Se) -> S
+        where
+            A: Fn(S, CompressionEncoding) -> S,
+            Se: Fn(S, CompressionEncoding) -> S,
+    {
+        for encoding in &self.accept {
+            service = accept(service, (*encoding).into());
+        }
+        for encoding in &self.send {
+            service = send(service, (*encoding).into());
+        }
+        service
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        sync::Arc,
+        task::{Context, Poll},
+    };
+
+    use tonic::{body::BoxBody, server::NamedService, Code};
+    use tonic_health::pb::{health_client::HealthClient, HealthCheckRequest};
+
+    use super::*;
+    use crate::{testing::TestServer, Config, Grpc, Server};
+
+    struct Conf(Grpc);
+
+    impl Config for Conf {
+        fn grpc(&self) -> &Grpc {
+            &self.0
+        }
+    }
+
+    /// Records the encodings it is configured with.
+    #[derive(Clone, Default)]
+    struct Echo {
+        accept: Vec<CompressionEncoding>,
+        send: Vec<CompressionEncoding>,
+    }
+
+    impl Echo {
+        fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept.push(encoding);
+            self
+        }
+
+        fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.send.push(encoding);
+            self
+        }
+    }
+
+    impl NamedService for Echo {
+        const NAME: &'static str = "bamboo.Echo";
+    }
+
+    impl tower::Service<http::Request<BoxBody>> for Echo {
+        type Response = http::Response<BoxBody>;
+        type Error = Infallible;
+        type Future = std::future::Ready<Result<Self::Response, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: http::Request<BoxBody>) -> Self::Future {
+            std::future::ready(Ok(tonic::Status::unimplemented("echo").into_http()))
+        }
+    }
+
+    fn conf(compression: Compression) -> Arc<Conf> {
+        Arc::new(Conf(Grpc {
+            compression,
+            ..Default::default()
+        }))
+    }
+
+    async fn check(compression: Compression) -> Result<(), tonic::Status> {
+        let test = TestServer::new(&Server::new(conf(compression), Echo::default())).await.unwrap();
+        let client = HealthClient::new(test.channel().await.unwrap())
+            .send_compressed(CompressionEncoding::Gzip)
+            .accept_compressed(CompressionEncoding::Gzip);
+        let req = HealthCheckRequest { service: String::new() };
+        let res = client.clone().check(req).await.map(|_| ());
+        test.shutdown().await;
+        res
+    }
+
+    #[tokio::test]
+    async fn valid_compression() {
+        let gzip = Compression {
+            accept: vec![Encoding::Gzip, Encoding::Zstd],
+            send: vec![Encoding::Gzip],
+        };
+        let server = Server::new(conf(gzip.clone()), Echo::default())
+            .compressed(Echo::accept_compressed, Echo::send_compressed);
+        assert_eq!(server.s.accept, vec![CompressionEncoding::Gzip, CompressionEncoding::Zstd]);
+        assert_eq!(server.s.send, vec![CompressionEncoding::Gzip]);
+
+        assert!(check(gzip).await.is_ok());
+        assert_eq!(check(Compression::default()).await.unwrap_err().code(), Code::Unimplemented);
+    }
+}
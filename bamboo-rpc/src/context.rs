@@ -0,0 +1,213 @@
+//! Request id, trace context and deadline of the call being served, carried over to the
+//! calls it makes through a [`Client`](crate::client::Client).
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use http::HeaderMap;
+use tokio::time::Instant;
+use tonic::{metadata::MetadataMap, Request, Status};
+use tower::{Layer, Service};
+
+pub const REQUEST_ID: &str = "x-request-id";
+pub const TRACEPARENT: &str = "traceparent";
+pub const TRACESTATE: &str = "tracestate";
+pub const GRPC_TIMEOUT: &str = "grpc-timeout";
+
+tokio::task_local! {
+    static CURRENT: CallContext;
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallContext {
+    pub request_id: Option<String>,
+    /// W3C `traceparent` header.
+    pub traceparent: Option<String>,
+    /// W3C `tracestate` header.
+    pub tracestate: Option<String>,
+    pub deadline: Option<Instant>,
+}
+
+impl CallContext {
+    /// The context of an incoming request, its deadline taken from `grpc-timeout`.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+        Self {
+            request_id: get(REQUEST_ID),
+            traceparent: get(TRACEPARENT),
+            tracestate: get(TRACESTATE),
+            deadline: get(GRPC_TIMEOUT)
+                .and_then(|v| parse_timeout(&v))
+                .map(|timeout| Instant::now() + timeout),
+        }
+    }
+
+    /// The context of the call served by the current task.
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(Clone::clone).ok()
+    }
+
+    /// Run `fut` in this context, e.g. a REST handler calling gRPC services.
+    pub async fn scope<F: Future>(self, fut: F) -> F::Output {
+        CURRENT.scope(self, fut).await
+    }
+
+    /// Time left until the deadline, `None` without one.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|d| d.saturating_duration_since(Instant::now()))
+    }
+}
+
+/// Parse a `grpc-timeout` value: at most 8 digits and a unit out of `HMSmun`.
+pub(crate) fn parse_timeout(value: &str) -> Option<Duration> {
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (digits, unit) = value.split_at(value.len() - 1);
+    let n: u64 = digits.parse().ok()?;
+    Some(match unit {
+        "H" => Duration::from_secs(n * 3600),
+        "M" => Duration::from_secs(n * 60),
+        "S" => Duration::from_secs(n),
+        "m" => Duration::from_millis(n),
+        "u" => Duration::from_micros(n),
+        "n" => Duration::from_nanos(n),
+        _ => return None,
+    })
+}
+
+fn insert(metadata: &mut MetadataMap, name: &'static str, value: Option<&str>) {
+    if metadata.contains_key(name) {
+        return;
+    }
+    if let Some(value) = value.and_then(|v| v.parse().ok()) {
+        metadata.insert(name, value);
+    }
+}
+
+/// Client interceptor sending the request id of the current call.
+#[allow(clippy::result_large_err)]
+pub fn propagate_request_id(mut req: Request<()>) -> Result<Request<()>, Status> {
+    if let Some(ctx) = CallContext::current() {
+        insert(req.metadata_mut(), REQUEST_ID, ctx.request_id.as_deref());
+    }
+    Ok(req)
+}
+
+/// Client interceptor sending the trace context of the current call.
+#[allow(clippy::result_large_err)]
+pub fn propagate_trace(mut req: Request<()>) -> Result<Request<()>, Status> {
+    if let Some(ctx) = CallContext::current() {
+        insert(req.metadata_mut(), TRACEPARENT, ctx.traceparent.as_deref());
+        insert(req.metadata_mut(), TRACESTATE, ctx.tracestate.as_deref());
+    }
+    Ok(req)
+}
+
+/// Client interceptor sending what is left of the current call's deadline, failing calls
+/// made after it passed.
+#[allow(clippy::result_large_err)]
+pub fn propagate_deadline(mut req: Request<()>) -> Result<Request<()>, Status> {
+    let Some(remaining) = CallContext::current().and_then(|ctx| ctx.remaining()) else {
+        return Ok(req);
+    };
+    if remaining.is_zero() {
+        return Err(Status::deadline_exceeded("deadline exceeded before the call was made"));
+    }
+    let shorter = req
+        .metadata()
+        .get(GRPC_TIMEOUT)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_timeout)
+        .is_none_or(|timeout| remaining < timeout);
+    if shorter {
+        req.set_timeout(remaining);
+    }
+    Ok(req)
+}
+
+/// Serves every call in the [`CallContext`] of its request.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ContextLayer;
+
+impl<S> Layer<S> for ContextLayer {
+    type Service = Scoped<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Scoped { inner }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Scoped<S> {
+    inner: S,
+}
+
+impl<S, ReqBody> Service<http::Request<ReqBody>> for Scoped<S>
+    where
+        S: Service<http::Request<ReqBody>>,
+        S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output=Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<ReqBody>) -> Self::Future {
+        let ctx = CallContext::from_headers(req.headers());
+        Box::pin(CURRENT.scope(ctx, self.inner.call(req)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_parse_timeout() {
+        assert_eq!(parse_timeout("100m"), Some(Duration::from_millis(100)));
+        assert_eq!(parse_timeout("2S"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_timeout("1H"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_timeout("123456789m"), None);
+        assert_eq!(parse_timeout("5x"), None);
+        assert_eq!(parse_timeout("m"), None);
+    }
+
+    #[tokio::test]
+    async fn valid_propagate() {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID, "abc".parse().unwrap());
+        headers.insert(TRACEPARENT, "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01".parse().unwrap());
+        headers.insert(GRPC_TIMEOUT, "5S".parse().unwrap());
+        let ctx = CallContext::from_headers(&headers);
+        assert!(ctx.remaining().unwrap() <= Duration::from_secs(5));
+
+        let req = ctx
+            .scope(async {
+                let req = propagate_request_id(Request::new(())).unwrap();
+                let req = propagate_trace(req).unwrap();
+                propagate_deadline(req).unwrap()
+            })
+            .await;
+        assert_eq!(req.metadata().get(REQUEST_ID).unwrap(), "abc");
+        assert!(req.metadata().contains_key(TRACEPARENT));
+        assert!(!req.metadata().contains_key(TRACESTATE));
+        let timeout = parse_timeout(req.metadata().get(GRPC_TIMEOUT).unwrap().to_str().unwrap()).unwrap();
+        assert!(timeout <= Duration::from_secs(5) && timeout > Duration::from_secs(4));
+
+        let expired = CallContext {
+            deadline: Some(Instant::now()),
+            ..Default::default()
+        };
+        let res = expired.scope(async { propagate_deadline(Request::new(())) }).await;
+        assert_eq!(res.unwrap_err().code(), tonic::Code::DeadlineExceeded);
+        assert!(propagate_request_id(Request::new(())).unwrap().metadata().is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+
+use bytes::Bytes;
+use http::{header, HeaderMap, Request, Response};
+use hyper::body::{Body, Frame, SizeHint};
+use tonic::body::BoxBody;
+use tower::BoxError;
+use tower_layer::Layer;
+use tower_service::Service;
+
+pub use bamboo_status::i18n::{Catalog, parse_accept_language};
+use bamboo_status::errors::Status;
+
+type BoxFuture<'a, T> = Pin<Box<dyn std::future::Future<Output=T> + Send + 'a>>;
+
+/// Localizes the `grpc-message` of error responses using the `accept-language` metadata.
+///
+/// Only errors carrying a [`Status`] in their details are rewritten, whether they are returned
+/// in the response headers (trailers-only), which is how tonic sends handler errors, or in the
+/// trailers after a streamed response.
+#[derive(Debug, Clone)]
+pub struct LocalizeLayer {
+    catalog: Arc<Catalog>,
+}
+
+impl LocalizeLayer {
+    pub fn new(catalog: Arc<Catalog>) -> Self {
+        Self { catalog }
+    }
+}
+
+impl<S> Layer<S> for LocalizeLayer {
+    type Service = Localize<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Localize {
+            inner,
+            catalog: self.catalog.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Localize<S> {
+    inner: S,
+    catalog: Arc<Catalog>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Localize<S>
+    where
+        S: Service<Request<ReqBody>, Response=Response<ResBody>> + Clone + Send + 'static,
+        S::Future: Send + 'static,
+        ReqBody: Send + 'static,
+        ResBody: Body<Data=Bytes> + Send + 'static,
+        ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let langs = req
+            .headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok())
+            .map(parse_accept_language)
+            .unwrap_or_default();
+        let catalog = self.catalog.clone();
+
+        // See bamboo-tower's `MyMiddleware` for why the ready service is swapped out.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            let mut res = inner.call(req).await?;
+            localize(&catalog, &langs, res.headers_mut());
+            Ok(res.map(|body| {
+                tonic::body::boxed(LocalizeBody {
+                    inner: tonic::body::boxed(body),
+                    catalog,
+                    langs,
+                })
+            }))
+        })
+    }
+}
+
+/// Localizes the status sent in the trailers of `inner`.
+struct LocalizeBody {
+    inner: BoxBody,
+    catalog: Arc<Catalog>,
+    langs: Vec<String>,
+}
+
+impl Body for LocalizeBody {
+    type Data = Bytes;
+    type Error = tonic::Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+        let mut frame = ready!(Pin::new(&mut this.inner).poll_frame(cx));
+        if let Some(trailers) = frame.as_mut().and_then(|f| f.as_mut().ok()).and_then(Frame::trailers_mut) {
+            localize(&this.catalog, &this.langs, trailers);
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+fn localize(catalog: &Catalog, langs: &[String], headers: &mut HeaderMap) {
+    let Some(ts) = tonic::Status::from_header_map(headers) else {
+        return;
+    };
+    let Ok(status) = serde_json::from_slice::<Status>(ts.details()) else {
+        return;
+    };
+    let status = catalog.localize(status, langs);
+    let Ok(details) = serde_json::to_vec(&status) else {
+        return;
+    };
+    let ts = tonic::Status::with_details_and_metadata(
+        ts.code(),
+        status.message,
+        details.into(),
+        ts.metadata().clone(),
+    );
+    if let Err(err) = ts.add_header(headers) {
+        log::warn!("localize grpc status: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn valid_localize() {
+        let mut catalog = Catalog::new("en-US");
+        catalog.insert("zh-CN", "UserNotFound", "用户 {id} 不存在");
+        let svc = LocalizeLayer::new(Arc::new(catalog)).layer(service_fn(|_req: Request<()>| async {
+            let mut s = Status::new("UserNotFound", "user not found");
+            s.metadata.insert("id".to_string(), "7".to_string());
+            let ts: tonic::Status = s.into();
+            Ok::<_, std::convert::Infallible>(ts.into_http())
+        }));
+
+        let req = Request::builder()
+            .header(header::ACCEPT_LANGUAGE, "zh-CN")
+            .body(())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        let ts = tonic::Status::from_header_map(res.headers()).unwrap();
+        assert_eq!(ts.message(), "用户 7 不存在");
+        let s: Status = ts.into();
+        assert_eq!(s.reason, "UserNotFound");
+    }
+
+    #[tokio::test]
+    async fn valid_localize_trailers() {
+        use http_body_util::{BodyExt, StreamBody};
+
+        let mut catalog = Catalog::new("en-US");
+        catalog.insert("zh-CN", "UserNotFound", "用户 {id} 不存在");
+        let svc = LocalizeLayer::new(Arc::new(catalog)).layer(service_fn(|_req: Request<()>| async {
+            let mut s = Status::new("UserNotFound", "user not found");
+            s.metadata.insert("id".to_string(), "7".to_string());
+            let mut trailers = HeaderMap::new();
+            tonic::Status::from(s).add_header(&mut trailers).unwrap();
+            let frames = vec![
+                Ok::<_, tonic::Status>(Frame::data(Bytes::from_static(b"message"))),
+                Ok(Frame::trailers(trailers)),
+            ];
+            let body = StreamBody::new(futures_util::stream::iter(frames));
+            Ok::<_, std::convert::Infallible>(Response::new(body))
+        }));
+
+        let req = Request::builder()
+            .header(header::ACCEPT_LANGUAGE, "zh-CN")
+            .body(())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert!(tonic::Status::from_header_map(res.headers()).is_none());
+        let trailers = res.into_body().collect().await.unwrap().trailers().cloned().unwrap();
+        let ts = tonic::Status::from_header_map(&trailers).unwrap();
+        assert_eq!(ts.message(), "用户 7 不存在");
+    }
+}
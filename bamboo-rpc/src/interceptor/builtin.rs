@@ -0,0 +1,252 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use tonic::Code;
+
+use crate::{
+    async_trait,
+    auth::{self, Authorization, Verifier, HEALTH_PREFIX},
+    context::REQUEST_ID,
+    Status,
+};
+
+use super::{Call, Interceptor};
+
+/// Answers calls whose service panicked with `Internal`, logging the panic.
+///
+/// Only panics until the service returns the response head are recovered. A panic while the
+/// response body streams, in the stream of a server streaming method, carries on and resets
+/// the call.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Recovery;
+
+impl Interceptor for Recovery {
+    fn recover(&self, call: &Call, panic: &str) -> Option<Status> {
+        log::error!("Grpc {} panicked: {}", call.method(), panic);
+        Some(Status::internal("internal error"))
+    }
+}
+
+/// Logs every call with its request id, status code and duration.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AccessLog;
+
+#[async_trait]
+impl Interceptor for AccessLog {
+    async fn after(&self, call: &Call, status: &Status) {
+        let request_id = call.metadata().get(REQUEST_ID).and_then(|v| v.to_str().ok()).unwrap_or("-");
+        log::info!("Grpc {} {} {:?} {:?}", request_id, call.method(), status.code(), call.elapsed());
+    }
+}
+
+/// Counts calls per method and status code, share it to render the counts:
+///
+/// ```ignore
+/// let metrics = Arc::new(Metrics::default());
+/// let server = Server::new(conf, svc).intercept(metrics.clone());
+/// ```
+#[derive(Debug, Default)]
+pub struct Metrics {
+    in_flight: AtomicI64,
+    handled: Mutex<BTreeMap<(String, i32), Handled>>,
+}
+
+#[derive(Debug, Default)]
+struct Handled {
+    count: u64,
+    seconds: f64,
+}
+
+impl Metrics {
+    /// Number of finished calls of `method` that ended with `code`.
+    pub fn handled(&self, method: &str, code: Code) -> u64 {
+        let handled = self.handled.lock().unwrap();
+        handled.get(&(method.to_string(), code as i32)).map_or(0, |h| h.count)
+    }
+
+    /// The counters in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let in_flight = self.in_flight.load(Ordering::Relaxed);
+        let _ = writeln!(out, "# TYPE grpc_server_in_flight gauge\ngrpc_server_in_flight {}", in_flight);
+        let handled = self.handled.lock().unwrap();
+        let _ = writeln!(out, "# TYPE grpc_server_handled_total counter");
+        for ((method, code), h) in handled.iter() {
+            let _ = writeln!(out, "grpc_server_handled_total{} {}", labels(method, *code), h.count);
+        }
+        let _ = writeln!(out, "# TYPE grpc_server_handling_seconds_sum counter");
+        for ((method, code), h) in handled.iter() {
+            let _ = writeln!(out, "grpc_server_handling_seconds_sum{} {}", labels(method, *code), h.seconds);
+        }
+        out
+    }
+}
+
+fn labels(method: &str, code: i32) -> String {
+    format!("{{grpc_method=\"{}\",grpc_code=\"{:?}\"}}", method, Code::from_i32(code))
+}
+
+#[async_trait]
+impl Interceptor for Metrics {
+    async fn before(&self, _call: &mut Call) -> Result<(), Status> {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn after(&self, call: &Call, status: &Status) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+        let mut handled = self.handled.lock().unwrap();
+        let h = handled.entry((call.method().to_string(), status.code() as i32)).or_default();
+        h.count += 1;
+        h.seconds += call.elapsed().as_secs_f64();
+    }
+}
+
+/// Verifies the `authorization` metadata like [`Grpc::auth`](crate::Grpc::auth), but at its
+/// place in the interceptor chain. Health checks are let through. The server fails to start
+/// when `Grpc::auth` is configured too, callers would be authenticated twice.
+///
+/// The caller is put in the request extensions as a [`Principal`](crate::Principal), read it
+/// with [`claims`](crate::claims).
+#[derive(Clone)]
+pub struct Authenticate {
+    verifier: Arc<Verifier>,
+    authorization: Option<Arc<Authorization>>,
+}
+
+impl Authenticate {
+    pub fn new(verifier: Arc<Verifier>) -> Self {
+        Self {
+            verifier,
+            authorization: None,
+        }
+    }
+
+    /// Check the role and scope rules of the called method after authenticating.
+    pub fn with_authorization(mut self, authorization: Arc<Authorization>) -> Self {
+        self.authorization = Some(authorization);
+        self
+    }
+}
+
+#[async_trait]
+impl Interceptor for Authenticate {
+    async fn before(&self, call: &mut Call) -> Result<(), Status> {
+        if call.method().starts_with(HEALTH_PREFIX) {
+            return Ok(());
+        }
+        let principal = auth::authenticate(
+            &self.verifier,
+            self.authorization.as_deref(),
+            call.metadata().get("authorization").and_then(|v| v.to_str().ok()),
+            call.head.0.as_str(),
+            call.method(),
+        )?;
+        if let Some(principal) = principal {
+            call.extensions_mut().insert(principal);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use bamboo_boot::plugin::Plugin;
+    use tonic_health::pb::{health_client::HealthClient, HealthCheckRequest};
+
+    use crate::{
+        testing::{assert_status, TestServer},
+        tests::{call, Conf, Hello, Slow},
+        Grpc, Jwt, Server,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn valid_metrics() {
+        let metrics = Arc::new(Metrics::default());
+        let server = Server::new(Arc::new(Conf(Grpc::default())), Hello).intercept(metrics.clone());
+        let test = TestServer::new(&server).await.unwrap();
+        let channel = test.channel().await.unwrap();
+
+        let req = HealthCheckRequest { service: "bamboo.Hello".to_string() };
+        HealthClient::new(channel.clone()).check(req).await.unwrap();
+        assert_eq!(call(&channel, "/bamboo.Hello/Call").await.unwrap_err().code(), Code::Unimplemented);
+        assert_eq!(call(&channel, "/bamboo.Hello/Call").await.unwrap_err().code(), Code::Unimplemented);
+
+        assert_eq!(metrics.handled("/grpc.health.v1.Health/Check", Code::Ok), 1);
+        assert_eq!(metrics.handled("/bamboo.Hello/Call", Code::Unimplemented), 2);
+        let text = metrics.render();
+        assert!(text.contains("grpc_server_in_flight 0\n"));
+        assert!(text.contains("grpc_server_handled_total{grpc_method=\"/bamboo.Hello/Call\",grpc_code=\"Unimplemented\"} 2\n"));
+        test.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn valid_metrics_timeout() {
+        let grpc = Grpc {
+            timeout: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let metrics = Arc::new(Metrics::default());
+        let server = Server::new(Arc::new(Conf(grpc)), Slow).intercept(metrics.clone());
+        let test = TestServer::new(&server).await.unwrap();
+        let channel = test.channel().await.unwrap();
+
+        assert!(call(&channel, "/bamboo.Slow/Call").await.is_err());
+        // The hooks of dropped calls run in a task of their own.
+        while metrics.handled("/bamboo.Slow/Call", Code::DeadlineExceeded) == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(metrics.render().contains("grpc_server_in_flight 0\n"));
+        test.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn valid_authenticate() {
+        let verifier = Arc::new(Verifier::new(&Jwt::with_secret("secret")).unwrap());
+        let server = Server::new(Arc::new(Conf(Grpc::default())), Hello).intercept(Authenticate::new(verifier));
+        let test = TestServer::new(&server).await.unwrap();
+        let channel = test.channel().await.unwrap();
+
+        let req = HealthCheckRequest { service: "bamboo.Hello".to_string() };
+        assert!(HealthClient::new(channel.clone()).check(req).await.is_ok());
+        assert_status(call(&channel, "/bamboo.Hello/Call").await, Code::Unauthenticated, "Unauthenticated");
+        test.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn invalid_authenticate_with_auth() {
+        let grpc = Grpc {
+            address: "127.0.0.1:0".to_string(),
+            auth: Some(Jwt::with_secret("secret")),
+            ..Default::default()
+        };
+        let verifier = Arc::new(Verifier::new(&Jwt::with_secret("secret")).unwrap());
+        let server = Server::new(Arc::new(Conf(grpc)), Hello).intercept(Arc::new(Authenticate::new(verifier)));
+        let shutdown = tokio_graceful::Shutdown::no_signal();
+        let err = server.serve(shutdown.guard()).await.unwrap_err();
+        assert_eq!(err.to_string(), "grpc server: the Authenticate interceptor can't be combined with auth");
+    }
+
+    #[tokio::test]
+    async fn invalid_authorization_without_auth() {
+        let grpc = Grpc {
+            address: "127.0.0.1:0".to_string(),
+            authorization: Some(Authorization::default()),
+            ..Default::default()
+        };
+        let server = Server::new(Arc::new(Conf(grpc)), Hello);
+        let shutdown = tokio_graceful::Shutdown::no_signal();
+        let err = server.serve(shutdown.guard()).await.unwrap_err();
+        assert_eq!(err.to_string(), "authorization needs auth to be configured");
+    }
+}
@@ -0,0 +1,574 @@
+//! Async interceptors of the calls being served, added with [`Server::intercept`]:
+//!
+//! ```ignore
+//! let metrics = Arc::new(Metrics::default());
+//! Server::new(conf, GreeterServer::new(greeter))
+//!     .intercept(Recovery)
+//!     .intercept(AccessLog)
+//!     .intercept(metrics.clone())
+//!     .intercept(Validation::default().method::<HelloRequest>("/helloworld.Greeter/SayHello"))
+//! ```
+//!
+//! Unlike tonic's `Interceptor` they see the method, are awaited, and are told the final status
+//! of the call. `before` hooks run in the order the interceptors were added, `after` hooks in the
+//! reverse order.
+//!
+//! [`Server::intercept`]: crate::Server::intercept
+
+use std::{
+    any::Any,
+    future::Future,
+    io::Read,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures_util::FutureExt;
+use http::Extensions;
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use hyper::body::{Body, Frame, SizeHint};
+use tokio::time::Instant;
+use tonic::{body::BoxBody, metadata::MetadataMap};
+use tower::{BoxError, Layer, Service};
+
+use crate::{async_trait, context::CallContext, HttpRequest, HttpResponse, Status};
+
+pub use builtin::{AccessLog, Authenticate, Metrics, Recovery};
+pub use validation::Validation;
+
+mod builtin;
+mod validation;
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output=T> + Send + 'a>>;
+
+/// Tonic's default limit of decoded request messages.
+pub(crate) const MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+#[async_trait]
+pub trait Interceptor: Send + Sync + 'static {
+    /// Runs before the service is called, an error answers the call with that status instead.
+    #[allow(clippy::result_large_err)]
+    async fn before(&self, call: &mut Call) -> Result<(), Status> {
+        let _ = call;
+        Ok(())
+    }
+
+    /// Runs with the final status of every call whose `before` ran, once the response is done.
+    /// Calls that time out get `DeadlineExceeded`, calls the client gives up on `Cancelled`.
+    async fn after(&self, call: &Call, status: &Status) {
+        let _ = (call, status);
+    }
+
+    /// Answers a call whose service panicked with `panic` before returning the response head.
+    /// The panic carries on when no interceptor answers it, and panics while the response
+    /// body streams always do.
+    fn recover(&self, call: &Call, panic: &str) -> Option<Status> {
+        let _ = (call, panic);
+        None
+    }
+}
+
+#[async_trait]
+impl<T: Interceptor + ?Sized> Interceptor for Arc<T> {
+    async fn before(&self, call: &mut Call) -> Result<(), Status> {
+        (**self).before(call).await
+    }
+
+    async fn after(&self, call: &Call, status: &Status) {
+        (**self).after(call, status).await
+    }
+
+    fn recover(&self, call: &Call, panic: &str) -> Option<Status> {
+        (**self).recover(call, panic)
+    }
+}
+
+/// A call being served.
+pub struct Call {
+    method: String,
+    metadata: MetadataMap,
+    extensions: Extensions,
+    deadline: Option<Instant>,
+    started: Instant,
+    head: (http::Method, http::Uri, http::Version),
+    /// The `max_decoding_message_size` of the server.
+    limit: usize,
+    // Only touched through `&mut self` without locking, the mutex makes `&Call` `Send`.
+    body: Mutex<RequestBody>,
+}
+
+enum RequestBody {
+    Streaming(BoxBody),
+    Buffered(Bytes),
+}
+
+impl Call {
+    fn new(req: HttpRequest<BoxBody>, limit: usize) -> Self {
+        let (parts, body) = req.into_parts();
+        let deadline = match CallContext::current() {
+            Some(ctx) => ctx.deadline,
+            None => CallContext::from_headers(&parts.headers).deadline,
+        };
+        Self {
+            method: parts.uri.path().to_string(),
+            metadata: MetadataMap::from_headers(parts.headers),
+            extensions: parts.extensions,
+            deadline,
+            started: Instant::now(),
+            head: (parts.method, parts.uri, parts.version),
+            limit,
+            body: Mutex::new(RequestBody::Streaming(body)),
+        }
+    }
+
+    /// The called method, `/pkg.Service/Method`.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn metadata(&self) -> &MetadataMap {
+        &self.metadata
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut MetadataMap {
+        &mut self.metadata
+    }
+
+    /// Handed on to the service with the request, empty once the service is called.
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    /// The deadline the client set with `grpc-timeout`.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Time since the interceptors got the call.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// The first, decompressed, request message, which buffers the request body. Meant for
+    /// unary and server streaming methods, whose requests are a single message. Fails with
+    /// `OutOfRange` when the message is larger than the `max_decoding_message_size` of the
+    /// server, compressed or not.
+    pub async fn message(&mut self) -> Result<Bytes, Status> {
+        let limit = self.limit;
+        let body = self.body.get_mut().unwrap_or_else(|err| err.into_inner());
+        if let RequestBody::Streaming(streaming) = body {
+            let streaming = std::mem::take(streaming);
+            let buffered = Limited::new(streaming, limit + 5).collect().await.map_err(|err| {
+                match err.downcast::<Status>() {
+                    Ok(status) => *status,
+                    Err(err) if err.is::<LengthLimitError>() => Status::out_of_range("request message too large"),
+                    Err(err) => Status::from_error(err),
+                }
+            })?;
+            *body = RequestBody::Buffered(buffered.to_bytes());
+        }
+        let RequestBody::Buffered(buf) = body else {
+            unreachable!("the request body was just buffered");
+        };
+        let encoding = self.metadata.get("grpc-encoding").and_then(|v| v.to_str().ok());
+        decode_frame(buf, encoding, limit)
+    }
+
+    /// The request to call the service with, the body as buffered by [`message`](Self::message).
+    fn request(&mut self) -> HttpRequest<BoxBody> {
+        let body = self.body.get_mut().unwrap_or_else(|err| err.into_inner());
+        let body = match std::mem::replace(body, RequestBody::Buffered(Bytes::new())) {
+            RequestBody::Streaming(body) => body,
+            RequestBody::Buffered(buf) => tonic::body::boxed(Full::new(buf)),
+        };
+        let mut req = HttpRequest::new(body);
+        *req.method_mut() = self.head.0.clone();
+        *req.uri_mut() = self.head.1.clone();
+        *req.version_mut() = self.head.2;
+        *req.headers_mut() = self.metadata.clone().into_headers();
+        *req.extensions_mut() = std::mem::take(&mut self.extensions);
+        req
+    }
+}
+
+/// The message of a length-prefixed gRPC frame, decompressed with `encoding` if flagged, of at
+/// most `limit` bytes.
+#[allow(clippy::result_large_err)]
+fn decode_frame(buf: &Bytes, encoding: Option<&str>, limit: usize) -> Result<Bytes, Status> {
+    if buf.len() < 5 {
+        return Err(Status::invalid_argument("missing request message"));
+    }
+    let len = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
+    let message = buf
+        .get(5..5 + len)
+        .ok_or_else(|| Status::invalid_argument("truncated request message"))?;
+    if buf[0] == 0 {
+        return Ok(buf.slice(5..5 + len));
+    }
+    // Read one byte past the limit to tell a message of exactly `limit` bytes from a larger one,
+    // without inflating the rest of it.
+    let mut decompressed = Vec::new();
+    let res = match encoding {
+        Some("gzip") => flate2::read::GzDecoder::new(message).take(limit as u64 + 1).read_to_end(&mut decompressed),
+        Some("zstd") => zstd::stream::read::Decoder::new(message)
+            .and_then(|decoder| decoder.take(limit as u64 + 1).read_to_end(&mut decompressed)),
+        other => return Err(Status::unimplemented(format!("request message compressed with {:?}", other))),
+    };
+    res.map_err(|err| Status::invalid_argument(format!("invalid compressed request message: {}", err)))?;
+    if decompressed.len() > limit {
+        return Err(Status::out_of_range("request message too large"));
+    }
+    Ok(decompressed.into())
+}
+
+/// Whether `interceptor` is an [`Authenticate`], shared or not.
+pub(crate) fn authenticates<I: Interceptor>(interceptor: &I) -> bool {
+    let any = interceptor as &dyn Any;
+    any.is::<Authenticate>() || any.is::<Arc<Authenticate>>()
+}
+
+type Chain = Arc<[Arc<dyn Interceptor>]>;
+
+/// Runs the interceptors added with [`Server::intercept`](crate::Server::intercept).
+#[derive(Clone)]
+pub(crate) struct InterceptorLayer {
+    chain: Chain,
+    limit: usize,
+    timeout: Duration,
+}
+
+impl InterceptorLayer {
+    /// Runs `chain`, buffering request messages of at most `limit` bytes, for calls timing out
+    /// after `timeout`.
+    pub(crate) fn new(chain: Vec<Arc<dyn Interceptor>>, limit: usize, timeout: Duration) -> Self {
+        Self { chain: chain.into(), limit, timeout }
+    }
+}
+
+impl<S> Layer<S> for InterceptorLayer {
+    type Service = Intercepted<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Intercepted {
+            inner,
+            chain: self.chain.clone(),
+            limit: self.limit,
+            timeout: self.timeout,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Intercepted<S> {
+    inner: S,
+    chain: Chain,
+    limit: usize,
+    timeout: Duration,
+}
+
+impl<S> Service<HttpRequest<BoxBody>> for Intercepted<S>
+    where
+        S: Service<HttpRequest<BoxBody>, Response=HttpResponse<BoxBody>> + Clone + Send + 'static,
+        S::Future: Send + 'static,
+        S::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: HttpRequest<BoxBody>) -> Self::Future {
+        let chain = self.chain.clone();
+        let (limit, timeout) = (self.limit, self.timeout);
+        // See bamboo-tower's `MyMiddleware` for why the ready service is swapped out.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let mut pending = Pending {
+                chain: chain.clone(),
+                ran: 0,
+                timeout,
+                call: Some(Call::new(req, limit)),
+            };
+            for interceptor in chain.iter() {
+                if let Err(status) = interceptor.before(pending.call()).await {
+                    let call = pending.take();
+                    finish(&chain[..=pending.ran], &call, &status).await;
+                    return Ok(status.into_http());
+                }
+                pending.ran += 1;
+            }
+            let req = pending.call().request();
+            let res = match AssertUnwindSafe(inner.call(req)).catch_unwind().await {
+                Ok(Ok(res)) => res,
+                // Like tonic's server answers errors of the middleware.
+                Ok(Err(err)) => Status::from_error(err.into()).into_http(),
+                Err(panic) => {
+                    let message = panic_message(&*panic);
+                    let call = pending.call();
+                    match chain.iter().find_map(|interceptor| interceptor.recover(call, message)) {
+                        Some(status) => status.into_http(),
+                        None => std::panic::resume_unwind(panic),
+                    }
+                }
+            };
+            let call = pending.take();
+            // Trailers-only responses carry the status in their headers.
+            if let Some(status) = Status::from_header_map(res.headers()) {
+                finish(&chain, &call, &status).await;
+                return Ok(res);
+            }
+            Ok(res.map(|body| {
+                tonic::body::boxed(Finish {
+                    body,
+                    state: State::Streaming { chain, call },
+                })
+            }))
+        })
+    }
+}
+
+async fn finish(chain: &[Arc<dyn Interceptor>], call: &Call, status: &Status) {
+    for interceptor in chain.iter().rev() {
+        interceptor.after(call, status).await;
+    }
+}
+
+/// A call until the service returned the response head. Dropped before, because the call
+/// timed out or the client went away, it runs the `after` hooks of the interceptors whose
+/// `before` ran.
+struct Pending {
+    chain: Chain,
+    /// Number of interceptors whose `before` ran.
+    ran: usize,
+    timeout: Duration,
+    call: Option<Call>,
+}
+
+impl Pending {
+    fn call(&mut self) -> &mut Call {
+        self.call.as_mut().expect("the call was taken")
+    }
+
+    /// The call, whose hooks are then up to the caller.
+    fn take(&mut self) -> Call {
+        self.call.take().expect("the call was taken")
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        let Some(call) = self.call.take() else {
+            return;
+        };
+        if self.ran == 0 {
+            return;
+        }
+        let timed_out = call.elapsed() >= self.timeout || call.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        let status = match timed_out {
+            true => Status::deadline_exceeded("the call timed out"),
+            false => Status::cancelled("the call was cancelled"),
+        };
+        let chain = self.chain.clone();
+        let ran = self.ran;
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(async move { finish(&chain[..ran], &call, &status).await });
+        }
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => panic.downcast_ref::<String>().map(String::as_str).unwrap_or("Box<dyn Any>"),
+    }
+}
+
+/// A response body running the `after` hooks with the status of its trailers, before
+/// handing the trailers on.
+struct Finish {
+    body: BoxBody,
+    state: State,
+}
+
+enum State {
+    Streaming { chain: Chain, call: Call },
+    Finishing { after: BoxFuture<'static, ()>, last: Option<Result<Frame<Bytes>, Status>> },
+    Done,
+}
+
+impl Body for Finish {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        loop {
+            match &mut this.state {
+                State::Streaming { .. } => {
+                    let frame = ready!(Pin::new(&mut this.body).poll_frame(cx));
+                    let status = match &frame {
+                        Some(Ok(frame)) if frame.is_data() => None,
+                        Some(Ok(frame)) => Some(
+                            frame
+                                .trailers_ref()
+                                .and_then(Status::from_header_map)
+                                .unwrap_or_else(|| Status::unknown("missing grpc-status")),
+                        ),
+                        Some(Err(status)) => Some(status.clone()),
+                        None => Some(Status::unknown("missing grpc-status")),
+                    };
+                    let Some(status) = status else {
+                        return Poll::Ready(frame);
+                    };
+                    let State::Streaming { chain, call } = std::mem::replace(&mut this.state, State::Done) else {
+                        unreachable!();
+                    };
+                    let after = Box::pin(async move { finish(&chain, &call, &status).await });
+                    this.state = State::Finishing { after, last: frame };
+                }
+                State::Finishing { after, last } => {
+                    ready!(after.as_mut().poll(cx));
+                    let last = last.take();
+                    this.state = State::Done;
+                    return Poll::Ready(last);
+                }
+                State::Done => return Pin::new(&mut this.body).poll_frame(cx),
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        matches!(self.state, State::Done) && self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+impl Drop for Finish {
+    fn drop(&mut self) {
+        // The client went away before the response was done.
+        if let State::Streaming { chain, call } = std::mem::replace(&mut self.state, State::Done) {
+            if let Ok(handle) = tokio::runtime::Handle::try_current() {
+                handle.spawn(async move { finish(&chain, &call, &Status::cancelled("the call was cancelled")).await });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        sync::Mutex,
+    };
+
+    use tonic::{server::NamedService, Code};
+    use tonic_health::pb::{health_client::HealthClient, HealthCheckRequest};
+
+    use crate::{
+        testing::TestServer,
+        tests::{call, Conf, Hello},
+        Grpc, Server,
+    };
+
+    use super::*;
+
+    /// Records its hooks, rejecting calls to `reject`.
+    struct Record {
+        name: &'static str,
+        reject: Option<&'static str>,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Interceptor for Record {
+        async fn before(&self, call: &mut Call) -> Result<(), Status> {
+            self.log.lock().unwrap().push(format!("{} before {}", self.name, call.method()));
+            match self.reject {
+                Some(method) if method == call.method() => Err(Status::permission_denied("rejected")),
+                _ => Ok(()),
+            }
+        }
+
+        async fn after(&self, call: &Call, status: &Status) {
+            self.log.lock().unwrap().push(format!("{} after {} {:?}", self.name, call.method(), status.code()));
+        }
+    }
+
+    #[tokio::test]
+    async fn valid_chain() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let record = |name, reject| Record { name, reject, log: log.clone() };
+        let server = Server::new(Arc::new(Conf(Grpc::default())), Hello)
+            .intercept(record("a", None))
+            .intercept(record("b", Some("/bamboo.Hello/Call")))
+            .intercept(record("c", None));
+        let test = TestServer::new(&server).await.unwrap();
+        let channel = test.channel().await.unwrap();
+
+        let req = HealthCheckRequest { service: "bamboo.Hello".to_string() };
+        HealthClient::new(channel.clone()).check(req).await.unwrap();
+        assert_eq!(call(&channel, "/bamboo.Hello/Call").await.unwrap_err().code(), Code::PermissionDenied);
+        assert_eq!(*log.lock().unwrap(), [
+            "a before /grpc.health.v1.Health/Check",
+            "b before /grpc.health.v1.Health/Check",
+            "c before /grpc.health.v1.Health/Check",
+            "c after /grpc.health.v1.Health/Check Ok",
+            "b after /grpc.health.v1.Health/Check Ok",
+            "a after /grpc.health.v1.Health/Check Ok",
+            "a before /bamboo.Hello/Call",
+            "b before /bamboo.Hello/Call",
+            "b after /bamboo.Hello/Call PermissionDenied",
+            "a after /bamboo.Hello/Call PermissionDenied",
+        ]);
+        test.shutdown().await;
+    }
+
+    /// Panics on every call.
+    #[derive(Clone)]
+    struct Panic;
+
+    impl NamedService for Panic {
+        const NAME: &'static str = "bamboo.Panic";
+    }
+
+    impl Service<HttpRequest<BoxBody>> for Panic {
+        type Response = HttpResponse<BoxBody>;
+        type Error = Infallible;
+        type Future = BoxFuture<'static, Result<Self::Response, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: HttpRequest<BoxBody>) -> Self::Future {
+            Box::pin(async { panic!("boom") })
+        }
+    }
+
+    #[tokio::test]
+    async fn valid_recovery() {
+        let metrics = Arc::new(Metrics::default());
+        let server = Server::new(Arc::new(Conf(Grpc::default())), Panic)
+            .intercept(metrics.clone())
+            .intercept(Recovery);
+        let test = TestServer::new(&server).await.unwrap();
+        let channel = test.channel().await.unwrap();
+
+        let status = call(&channel, "/bamboo.Panic/Call").await.unwrap_err();
+        assert_eq!(status.code(), Code::Internal);
+        assert_eq!(status.message(), "internal error");
+        assert_eq!(metrics.handled("/bamboo.Panic/Call", Code::Internal), 1);
+        test.shutdown().await;
+    }
+}
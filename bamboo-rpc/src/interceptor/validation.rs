@@ -0,0 +1,118 @@
+use std::{collections::HashMap, sync::Arc};
+
+use bytes::Bytes;
+use prost::Message;
+use validator::Validate;
+
+use crate::{async_trait, Status};
+
+use super::{Call, Interceptor};
+
+type Check = Arc<dyn Fn(Bytes) -> Result<(), Status> + Send + Sync>;
+
+/// Validates the request message of the registered methods, answering invalid ones with
+/// `InvalidArgument` and their field violations, like REST's `ValidatedJson`.
+///
+/// Messages derive [`Validate`] next to [`Message`], e.g. with prost-build's
+/// `type_attribute(".helloworld.HelloRequest", "#[derive(validator::Validate)]")`.
+#[derive(Clone, Default)]
+pub struct Validation {
+    methods: HashMap<String, Check>,
+}
+
+impl Validation {
+    /// Validate the requests of `method`, a `/pkg.Service/Method` path, as `M`.
+    #[allow(clippy::result_large_err)]
+    pub fn method<M>(mut self, method: impl Into<String>) -> Self
+        where M: Message + Default + Validate + 'static,
+    {
+        let check = |message: Bytes| {
+            let message = M::decode(message)
+                .map_err(|err| Status::invalid_argument(format!("invalid request message: {}", err)))?;
+            message.validate().map_err(|errors| bamboo_status::errors::Status::from(errors).into())
+        };
+        self.methods.insert(method.into(), Arc::new(check));
+        self
+    }
+}
+
+#[async_trait]
+impl Interceptor for Validation {
+    async fn before(&self, call: &mut Call) -> Result<(), Status> {
+        match self.methods.get(call.method()) {
+            Some(check) => check(call.message().await?),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tonic::{
+        codec::{CompressionEncoding, ProstCodec},
+        Code,
+    };
+
+    use crate::{
+        testing::{assert_status, TestServer},
+        tests::{Conf, Hello},
+        Grpc, Request, Server,
+    };
+
+    use super::*;
+
+    #[derive(Clone, PartialEq, Message, Validate)]
+    struct Greet {
+        #[prost(string, tag = "1")]
+        #[validate(length(min = 1))]
+        name: String,
+    }
+
+    #[allow(clippy::result_large_err)]
+    async fn greet(channel: &tonic::transport::Channel, name: &str, gzip: bool) -> Result<tonic::Response<()>, Status> {
+        let mut grpc = tonic::client::Grpc::new(channel.clone());
+        if gzip {
+            grpc = grpc.send_compressed(CompressionEncoding::Gzip);
+        }
+        grpc.ready().await.unwrap();
+        let path = http::uri::PathAndQuery::from_static("/bamboo.Hello/Call");
+        let req = Request::new(Greet { name: name.to_string() });
+        grpc.unary::<Greet, (), _>(req, path, ProstCodec::default()).await
+    }
+
+    #[tokio::test]
+    async fn valid_validation() {
+        let validation = Validation::default().method::<Greet>("/bamboo.Hello/Call");
+        let server = Server::new(Arc::new(Conf(Grpc::default())), Hello).intercept(validation);
+        let test = TestServer::new(&server).await.unwrap();
+        let channel = test.channel().await.unwrap();
+
+        let status = assert_status(greet(&channel, "", false).await, Code::InvalidArgument, "ValidationErrors");
+        assert_eq!(status.bad_request.unwrap().field_violations[0].field, "name");
+        assert_status(greet(&channel, "", true).await, Code::InvalidArgument, "ValidationErrors");
+        // Valid messages reach the service.
+        assert_eq!(greet(&channel, "bamboo", false).await.unwrap_err().code(), Code::Unimplemented);
+        assert_eq!(greet(&channel, "bamboo", true).await.unwrap_err().code(), Code::Unimplemented);
+        test.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn invalid_validation_too_large() {
+        let grpc = Grpc {
+            max_decoding_message_size: Some(64),
+            ..Default::default()
+        };
+        let validation = Validation::default().method::<Greet>("/bamboo.Hello/Call");
+        let server = Server::new(Arc::new(Conf(grpc)), Hello).limited(|s, _| s, |s, _| s).intercept(validation);
+        let test = TestServer::new(&server).await.unwrap();
+        let channel = test.channel().await.unwrap();
+
+        // Compressed the message fits the limit, inflated it doesn't.
+        let name = "b".repeat(4096);
+        let status = greet(&channel, &name, true).await.unwrap_err();
+        assert_eq!((status.code(), status.message()), (Code::OutOfRange, "request message too large"));
+        test.shutdown().await;
+    }
+}
@@ -1,31 +1,191 @@
+use anyhow::Context as _;
 use serde::{Deserialize, Serialize};
 use std::{
-    iter::once, net::SocketAddr, time::Duration,
+    net::SocketAddr, time::Duration,
     convert::Infallible,
+    future::Future,
+    path::{Path, PathBuf},
     sync::Arc,
 };
-use tokio::net::TcpListener;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+};
 use tokio_graceful::ShutdownGuard;
-use tokio_stream::wrappers::TcpListenerStream;
+use tokio_stream::Stream;
+use tonic::{
+    service::{Routes, RoutesBuilder},
+    transport::server::{Router, TcpIncoming},
+};
+use tonic_health::{pb::health_server::HealthServer, server::HealthReporter, ServingStatus};
+use tonic_reflection::server::v1::{ServerReflection, ServerReflectionServer};
 use tower::{
-    Service, ServiceBuilder,
+    BoxError, Layer, Service, ServiceBuilder,
 };
 pub use http::{Request as HttpRequest, Response as HttpResponse};
-use hyper::header::{self};
 pub use tonic::{
     Code,
     Status, async_trait,
     body::BoxBody,
+    codec::CompressionEncoding,
     server::NamedService,
-    transport::Body,
+    transport::{server::Connected, Body},
     Request, Response,
 };
-use bamboo_boot::plugin::Plugin;
-use bamboo_status::status::{Result, AnyResult};
+use bamboo_boot::plugin::{Listening, Plugin};
+use bamboo_status::status::AnyResult;
+use bamboo_status::i18n::Catalog;
+
+pub use bamboo_tls::{ClientAuth, ClientTls, PeerIdentity, Tls};
+pub use tls::{peer_identity, TlsConnectInfo};
+pub use auth::{claims, Authorization, Jwt, JwtInterceptor, Policy, Principal};
+pub use compression::{Compression, Encoding};
+pub use context::CallContext;
+pub use client::{Channel, Client, GrpcClient};
+pub use balancer::Strategy;
+pub use web::Web;
+#[cfg(feature = "transcoding")]
+pub use transcoding::Transcoder;
+#[cfg(feature = "mux")]
+pub use mux::{Mux, MuxServer};
+pub use interceptor::{Call, Interceptor};
 
-#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub mod i18n;
+pub mod auth;
+pub mod compression;
+pub mod context;
+pub mod client;
+pub mod balancer;
+pub mod web;
+#[cfg(feature = "transcoding")]
+pub mod transcoding;
+#[cfg(feature = "mux")]
+pub mod mux;
+pub mod interceptor;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+mod tls;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Grpc {
     pub address: String,
+    /// Also serve plaintext on this Unix domain socket, removed on shutdown. A stale socket is
+    /// replaced, any other file there fails the server.
+    #[serde(default)]
+    pub uds: Option<PathBuf>,
+    /// Per-call timeout, e.g. `10s`.
+    #[serde(default = "default_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+    /// Maximum number of concurrent calls per connection, unlimited when missing.
+    #[serde(default)]
+    pub concurrency_limit_per_connection: Option<usize>,
+    /// Maximum number of concurrent HTTP/2 streams per connection, hyper's default when missing.
+    #[serde(default)]
+    pub max_concurrent_streams: Option<u32>,
+    /// Interval of HTTP/2 keepalive pings, e.g. `30s`, disabled when missing.
+    #[serde(default, with = "humantime_serde")]
+    pub http2_keepalive_interval: Option<Duration>,
+    /// How long a keepalive ping may go unanswered before the connection is closed, 20s when missing.
+    #[serde(default, with = "humantime_serde")]
+    pub http2_keepalive_timeout: Option<Duration>,
+    /// Initial HTTP/2 stream-level flow control window in bytes, hyper's default when missing.
+    #[serde(default)]
+    pub initial_stream_window_size: Option<u32>,
+    /// Initial HTTP/2 connection-level flow control window in bytes, hyper's default when missing.
+    #[serde(default)]
+    pub initial_connection_window_size: Option<u32>,
+    /// TCP keepalive of accepted connections, e.g. `60s`, disabled when missing.
+    #[serde(default, with = "humantime_serde")]
+    pub tcp_keepalive: Option<Duration>,
+    /// Set `TCP_NODELAY` on accepted connections.
+    #[serde(default = "default_tcp_nodelay")]
+    pub tcp_nodelay: bool,
+    /// Maximum size of a decoded request message in bytes, tonic's 4MB when missing. Applies to
+    /// the health and reflection services and to services set up with [`Server::limited`], the
+    /// server fails to start when the main service isn't. Services added with
+    /// [`Server::add_service`] are limited with [`Grpc::limit`].
+    #[serde(default)]
+    pub max_decoding_message_size: Option<usize>,
+    /// Maximum size of an encoded response message in bytes, unlimited when missing. Applies
+    /// like `max_decoding_message_size`.
+    #[serde(default)]
+    pub max_encoding_message_size: Option<usize>,
+    /// Serve over TLS, plaintext HTTP/2 when missing.
+    #[serde(default)]
+    pub tls: Option<Tls>,
+    /// Verify JWT bearer tokens of every call except health checks, disabled when missing.
+    #[serde(default)]
+    pub auth: Option<Jwt>,
+    /// Role and scope rules per `/pkg.Service/Method`, checked against the claims of `auth`.
+    #[serde(default)]
+    pub authorization: Option<Authorization>,
+    /// Message compression of the health service and of services set up with [`Server::compressed`].
+    #[serde(default)]
+    pub compression: Compression,
+    /// Serve gRPC server reflection for the file descriptor sets registered with
+    /// [`bamboo_prost::register_file_descriptor_set`].
+    #[serde(default)]
+    pub reflection: bool,
+    /// Serve gRPC-Web calls of browsers, also over HTTP/1.1, disabled when missing.
+    #[serde(default)]
+    pub web: Option<Web>,
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_tcp_nodelay() -> bool {
+    true
+}
+
+impl Default for Grpc {
+    fn default() -> Self {
+        Self {
+            address: String::new(),
+            uds: None,
+            timeout: default_timeout(),
+            concurrency_limit_per_connection: None,
+            max_concurrent_streams: None,
+            http2_keepalive_interval: None,
+            http2_keepalive_timeout: None,
+            initial_stream_window_size: None,
+            initial_connection_window_size: None,
+            tcp_keepalive: None,
+            tcp_nodelay: default_tcp_nodelay(),
+            max_decoding_message_size: None,
+            max_encoding_message_size: None,
+            tls: None,
+            auth: None,
+            authorization: None,
+            compression: Compression::default(),
+            reflection: false,
+            web: None,
+        }
+    }
+}
+
+impl Grpc {
+    /// Configure a generated server through its `max_decoding_message_size` and
+    /// `max_encoding_message_size`.
+    pub fn limit<S, D, E>(&self, mut service: S, decoding: D, encoding: E) -> S
+        where
+            D: Fn(S, usize) -> S,
+            E: Fn(S, usize) -> S,
+    {
+        if let Some(limit) = self.max_decoding_message_size {
+            service = decoding(service, limit);
+        }
+        if let Some(limit) = self.max_encoding_message_size {
+            service = encoding(service, limit);
+        }
+        service
+    }
+
+    /// The `max_decoding_message_size`, tonic's default when missing.
+    pub(crate) fn decoding_limit(&self) -> usize {
+        self.max_decoding_message_size.unwrap_or(interceptor::MAX_MESSAGE_SIZE)
+    }
 }
 
 pub trait Config {
@@ -35,19 +195,127 @@ pub trait Config {
 pub struct Server<C, S> {
     conf: Arc<C>,
     s: S,
+    /// Services added besides `s`, with the health service.
+    routes: RoutesBuilder,
+    names: Vec<&'static str>,
+    health: HealthReporter,
+    interceptors: Vec<Arc<dyn Interceptor>>,
+    catalog: Option<Arc<Catalog>>,
+    /// Whether `s` enforces the message size limits, see [`Server::limited`].
+    limited: bool,
+    /// Whether an [`interceptor::Authenticate`] is among the interceptors.
+    authenticates: bool,
+    listening: Listening,
 }
 
 impl<C, S> Server<C, S> {
+    /// The reporter of the health statuses, services are serving from the start of
+    /// [`serve`](Plugin::serve) until shutdown.
+    pub fn health(&self) -> HealthReporter {
+        self.health.clone()
+    }
+
+    /// Serve `svc` besides the other services, through the same middleware.
+    ///
+    /// ```ignore
+    /// Server::new(conf, GreeterServer::new(greeter))
+    ///     .add_service(EchoServer::new(echo))
+    /// ```
+    pub fn add_service<T>(mut self, svc: T) -> Self
+        where
+            T: Service<HttpRequest<BoxBody>, Response=HttpResponse<BoxBody>, Error=Infallible>
+            + NamedService
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+            T::Future: Send + 'static,
+    {
+        self.routes.add_service(svc);
+        self.names.push(T::NAME);
+        self
+    }
+
+    /// Run `interceptor` around every call, inside the interceptors added before it.
+    pub fn intercept<I: Interceptor>(mut self, interceptor: I) -> Self {
+        self.authenticates |= interceptor::authenticates(&interceptor);
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Localize error messages with `catalog` based on the `accept-language` metadata.
+    pub fn with_catalog(mut self, catalog: Arc<Catalog>) -> Self {
+        self.catalog = Some(catalog);
+        self
+    }
+}
+
+impl<C, S> Server<C, S>
+    where C: Config,
+{
     pub fn new(conf: Arc<C>, s: S) -> Self {
+        let (health, health_service) = tonic_health::server::health_reporter();
+        let health_service = conf.grpc().compression.apply(
+            health_service,
+            HealthServer::accept_compressed,
+            HealthServer::send_compressed,
+        );
+        let health_service = conf.grpc().limit(
+            health_service,
+            HealthServer::max_decoding_message_size,
+            HealthServer::max_encoding_message_size,
+        );
+        let mut routes = RoutesBuilder::default();
+        routes.add_service(health_service);
         Self {
             conf,
             s,
+            routes,
+            names: Vec::new(),
+            health,
+            interceptors: Vec::new(),
+            catalog: None,
+            limited: false,
+            authenticates: false,
+            listening: Listening::default(),
         }
     }
+
+    /// Enable the message compression of [`Grpc::compression`] on the service, given the
+    /// `accept_compressed` and `send_compressed` of its generated server.
+    ///
+    /// ```ignore
+    /// Server::new(conf, GreeterServer::new(greeter))
+    ///     .compressed(GreeterServer::accept_compressed, GreeterServer::send_compressed)
+    /// ```
+    pub fn compressed<A, Se>(mut self, accept: A, send: Se) -> Self
+        where
+            A: Fn(S, CompressionEncoding) -> S,
+            Se: Fn(S, CompressionEncoding) -> S,
+    {
+        self.s = self.conf.grpc().compression.apply(self.s, accept, send);
+        self
+    }
+
+    /// Enforce the message size limits of [`Grpc`] on the service, given the
+    /// `max_decoding_message_size` and `max_encoding_message_size` of its generated server.
+    ///
+    /// ```ignore
+    /// Server::new(conf, GreeterServer::new(greeter))
+    ///     .limited(GreeterServer::max_decoding_message_size, GreeterServer::max_encoding_message_size)
+    /// ```
+    pub fn limited<D, E>(mut self, decoding: D, encoding: E) -> Self
+        where
+            D: Fn(S, usize) -> S,
+            E: Fn(S, usize) -> S,
+    {
+        self.s = self.conf.grpc().limit(self.s, decoding, encoding);
+        self.limited = true;
+        self
+    }
 }
 
-#[async_trait]
-impl<C, S> Plugin for Server<C, S> where
+impl<C, S> Server<C, S> where
     C: Config + Send + Sync + 'static,
     S: Service<HttpRequest<BoxBody>, Response=HttpResponse<BoxBody>, Error=Infallible>
     + NamedService
@@ -57,13 +325,99 @@ impl<C, S> Plugin for Server<C, S> where
     + 'static,
     S::Future: Send + 'static,
 {
-    async fn serve(&self, guard: ShutdownGuard) -> AnyResult<()> {
+    /// Serve the connections of `incoming` with the configured middleware until shutdown.
+    pub(crate) async fn serve_incoming<I, IO, IE>(&self, incoming: I, guard: &ShutdownGuard) -> AnyResult<()>
+        where
+            I: Stream<Item=Result<IO, IE>> + Send,
+            IO: AsyncRead + AsyncWrite + Connected + Unpin + Send + 'static,
+            IO::ConnectInfo: Clone + Send + Sync + 'static,
+            IE: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let (router, signal) = self.router(guard).await?;
+        router.serve_with_incoming_shutdown(incoming, signal).await?;
+        Ok(())
+    }
+
+    /// Fail on settings the server can't honour: message size limits the service doesn't
+    /// enforce, or callers authenticated twice.
+    fn check(&self) -> AnyResult<()> {
+        let conf = self.conf.grpc();
+        if !self.limited && (conf.max_decoding_message_size.is_some() || conf.max_encoding_message_size.is_some()) {
+            anyhow::bail!("grpc server: message size limits need Server::limited on {}", S::NAME);
+        }
+        if self.authenticates && conf.auth.is_some() {
+            anyhow::bail!("grpc server: the Authenticate interceptor can't be combined with auth");
+        }
+        Ok(())
+    }
+
+    /// Serve the connections of `listener`, bound at `path`, until shutdown, then remove the
+    /// socket.
+    #[cfg(unix)]
+    async fn serve_uds(&self, listener: UdsListener, path: &Path, guard: &ShutdownGuard) -> AnyResult<()> {
+        log::info!("Grpc Listening on {}", path.display());
+        let res = self.serve_incoming(tokio_stream::wrappers::UnixListenerStream::new(listener), guard).await;
+        if let Err(err) = std::fs::remove_file(path) {
+            log::warn!("grpc server: remove socket {}: {}", path.display(), err);
+        }
+        res
+    }
+
+    #[cfg(not(unix))]
+    async fn serve_uds(&self, listener: UdsListener, _path: &Path, _guard: &ShutdownGuard) -> AnyResult<()> {
+        match listener {}
+    }
+
+    /// The services behind the configured middleware, marked serving. The returned signal
+    /// resolves once `guard` is cancelled and the services are marked not serving.
+    pub(crate) async fn router(
+        &self,
+        guard: &ShutdownGuard,
+    ) -> AnyResult<(
+        Router<
+            impl Layer<
+                Routes,
+                Service: Service<HttpRequest<BoxBody>, Response=HttpResponse<BoxBody>, Error=BoxError, Future: Send>
+                + Clone
+                + Send
+                + 'static,
+            >,
+        >,
+        impl Future<Output=()> + Send + 'static,
+    )> {
+        self.check()?;
+        let auth = match &self.conf.grpc().auth {
+            Some(conf) => {
+                let layer = auth::AuthLayer::new(Arc::new(auth::Verifier::new(conf)?));
+                Some(match &self.conf.grpc().authorization {
+                    Some(policies) => layer.with_authorization(Arc::new(policies.clone())),
+                    None => layer,
+                })
+            }
+            None => {
+                if self.conf.grpc().authorization.is_some() {
+                    anyhow::bail!("authorization needs auth to be configured");
+                }
+                None
+            }
+        };
         // Build our middleware stack
         let layer = ServiceBuilder::new()
+            // Serve gRPC-Web calls as gRPC
+            .option_layer(self.conf.grpc().web.as_ref().map(|web| web.layer(self.conf.grpc().decoding_limit())))
             // Set a timeout
-            .timeout(Duration::from_secs(10))
-            // Compress responses
-            // .layer(CompressionLayer::new())
+            .timeout(self.conf.grpc().timeout)
+            // Serve calls in their request id, trace and deadline context
+            .layer(context::ContextLayer)
+            // Localize error messages
+            .option_layer(self.catalog.clone().map(i18n::LocalizeLayer::new))
+            // Authenticate callers
+            .option_layer(auth)
+            // Run the interceptors
+            .option_layer((!self.interceptors.is_empty()).then(|| {
+                let grpc = self.conf.grpc();
+                interceptor::InterceptorLayer::new(self.interceptors.clone(), grpc.decoding_limit(), grpc.timeout)
+            }))
             // Mark the `Authorization` header as sensitive so it doesn't show in logs
             // .layer(SetSensitiveHeadersLayer::new(once(header::AUTHORIZATION)))
             // Log all requests and responses
@@ -73,38 +427,422 @@ impl<C, S> Plugin for Server<C, S> where
             // )
             .into_inner();
 
-        let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
-        health_reporter.set_serving::<S>().await;
+        let reflection = match self.conf.grpc().reflection {
+            true => Some(self.conf.grpc().limit(
+                self.reflection()?,
+                ServerReflectionServer::max_decoding_message_size,
+                ServerReflectionServer::max_encoding_message_size,
+            )),
+            false => None,
+        };
+        let names: Vec<_> = std::iter::once(S::NAME).chain(self.names.iter().copied()).collect();
+        let mut health = self.health.clone();
+        for name in &names {
+            health.set_service_status(name, ServingStatus::Serving).await;
+        }
 
-        // Build and run the server
-        let addr = self.conf.grpc().address.parse::<SocketAddr>().unwrap();
-        log::info!("Grpc Listening on {}", addr);
-        let listener = TcpListener::bind(addr).await?;
-        let listener_stream = TcpListenerStream::new(listener);
-        let _result = tonic::transport::Server::builder()
+        let conf = self.conf.grpc();
+        let mut builder = tonic::transport::Server::builder()
+            .accept_http1(conf.web.is_some())
+            .max_concurrent_streams(conf.max_concurrent_streams)
+            .http2_keepalive_interval(conf.http2_keepalive_interval)
+            .http2_keepalive_timeout(conf.http2_keepalive_timeout)
+            .initial_stream_window_size(conf.initial_stream_window_size)
+            .initial_connection_window_size(conf.initial_connection_window_size);
+        if let Some(limit) = conf.concurrency_limit_per_connection {
+            builder = builder.concurrency_limit_per_connection(limit);
+        }
+        let router = builder
             .layer(layer)
-            .add_service(health_service)
+            .add_routes(self.routes.clone().routes())
             .add_service(self.s.clone())
-            .serve_with_incoming_shutdown(listener_stream, async move {
+            .add_optional_service(reflection);
+        let signal = {
+            let guard = guard.clone();
+            async move {
                 guard.cancelled().await;
-            })
-            .await;
+                // Report not serving before the server stops accepting, so balancers that
+                // check health stop picking it while the calls in flight finish.
+                for name in names {
+                    health.set_service_status(name, ServingStatus::NotServing).await;
+                }
+            }
+        };
+        Ok((router, signal))
+    }
+
+    /// The reflection service of the health service and of the registered descriptor sets.
+    fn reflection(&self) -> AnyResult<ServerReflectionServer<impl ServerReflection>> {
+        let builder = bamboo_prost::file_descriptor_sets().into_iter().fold(
+            tonic_reflection::server::Builder::configure()
+                .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET),
+            |builder, set| builder.register_encoded_file_descriptor_set(set),
+        );
+        Ok(builder.build_v1()?)
+    }
+}
+
+#[async_trait]
+impl<C, S> Plugin for Server<C, S> where
+    C: Config + Send + Sync + 'static,
+    S: Service<HttpRequest<BoxBody>, Response=HttpResponse<BoxBody>, Error=Infallible>
+    + NamedService
+    + Clone
+    + Send
+    + Sync
+    + 'static,
+    S::Future: Send + 'static,
+{
+    async fn serve(&self, guard: ShutdownGuard) -> AnyResult<()> {
+        let conf = self.conf.grpc();
+        // Fail before binding anything.
+        self.check()?;
+        let addr = conf
+            .address
+            .parse::<SocketAddr>()
+            .with_context(|| format!("grpc server: invalid address {:?}", conf.address))?;
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("grpc server: bind {}", addr))?;
+        let uds = conf.uds.as_deref().map(bind_uds).transpose()?;
+        // Listening once both sockets accept connections.
+        self.listening.set(listener.local_addr()?);
+        let incoming = TcpIncoming::from_listener(listener, conf.tcp_nodelay, conf.tcp_keepalive)
+            .map_err(|err| anyhow::anyhow!("grpc server: {}", err))?;
+        let tcp = async {
+            if let Some(tls) = &conf.tls {
+                let acceptor = bamboo_tls::Acceptor::new(tls, &tls::ALPN)?;
+                log::info!("Grpc Listening on {} with tls", addr);
+                self.serve_incoming(tls::incoming(incoming, acceptor, &guard), &guard).await
+            } else {
+                log::info!("Grpc Listening on {}", addr);
+                self.serve_incoming(incoming, &guard).await
+            }
+        };
+        let uds = async {
+            match (uds, &conf.uds) {
+                (Some(listener), Some(path)) => self.serve_uds(listener, path, &guard).await,
+                _ => Ok(()),
+            }
+        };
+        tokio::try_join!(tcp, uds)?;
         log::info!("Grpc stopping");
         Ok(())
     }
+
+    async fn listening(&self) -> Option<String> {
+        let scheme = if self.conf.grpc().tls.is_some() { "grpcs" } else { "grpc" };
+        Some(format!("{}://{}", scheme, self.listening.addr().await))
+    }
+}
+
+#[cfg(unix)]
+type UdsListener = tokio::net::UnixListener;
+
+#[cfg(not(unix))]
+type UdsListener = std::convert::Infallible;
+
+/// Bind the Unix domain socket at `path`, replacing a stale socket but no other file.
+#[cfg(unix)]
+fn bind_uds(path: &Path) -> AnyResult<UdsListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)
+            .with_context(|| format!("grpc server: remove stale socket {}", path.display()))?,
+        Ok(_) => anyhow::bail!("grpc server: {} exists and is not a socket", path.display()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err).with_context(|| format!("grpc server: stat {}", path.display())),
+    }
+    tokio::net::UnixListener::bind(path).with_context(|| format!("grpc server: bind {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn bind_uds(path: &Path) -> AnyResult<UdsListener> {
+    anyhow::bail!("grpc server: unix domain socket {} not supported on this platform", path.display())
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use tonic::codec::ProstCodec;
+    use tonic_health::pb::{health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest};
+
+    use crate::testing::{assert_status, TestServer};
+
     use super::*;
 
-    #[test]
-    fn it_works() {
-        assert_eq!(4, 4);
+    pub(crate) struct Conf(pub(crate) Grpc);
+
+    impl Config for Conf {
+        fn grpc(&self) -> &Grpc {
+            &self.0
+        }
+    }
+
+    /// Answers every call with `unimplemented`.
+    #[derive(Clone)]
+    pub(crate) struct Hello;
+
+    impl NamedService for Hello {
+        const NAME: &'static str = "bamboo.Hello";
+    }
+
+    impl Service<HttpRequest<BoxBody>> for Hello {
+        type Response = HttpResponse<BoxBody>;
+        type Error = Infallible;
+        type Future = std::future::Ready<Result<Self::Response, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: HttpRequest<BoxBody>) -> Self::Future {
+            std::future::ready(Ok(Status::unimplemented("hello").into_http()))
+        }
+    }
+
+    /// Answers every call with `unimplemented`, a second later.
+    #[derive(Clone)]
+    pub(crate) struct Slow;
+
+    impl NamedService for Slow {
+        const NAME: &'static str = "bamboo.Slow";
+    }
+
+    impl Service<HttpRequest<BoxBody>> for Slow {
+        type Response = HttpResponse<BoxBody>;
+        type Error = Infallible;
+        type Future = Pin<Box<dyn Future<Output=Result<Self::Response, Infallible>> + Send>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: HttpRequest<BoxBody>) -> Self::Future {
+            Box::pin(async {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                Ok(Status::unimplemented("slow").into_http())
+            })
+        }
+    }
+
+    /// Answers every call with `not_found`.
+    #[derive(Clone)]
+    struct Bye;
+
+    impl NamedService for Bye {
+        const NAME: &'static str = "bamboo.Bye";
+    }
+
+    impl Service<HttpRequest<BoxBody>> for Bye {
+        type Response = HttpResponse<BoxBody>;
+        type Error = Infallible;
+        type Future = std::future::Ready<Result<Self::Response, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: HttpRequest<BoxBody>) -> Self::Future {
+            std::future::ready(Ok(Status::not_found("bye").into_http()))
+        }
+    }
+
+    async fn health(channel: &tonic::transport::Channel, service: &str) -> ServingStatus {
+        let req = HealthCheckRequest { service: service.to_string() };
+        let res = HealthClient::new(channel.clone()).check(req).await.unwrap();
+        res.into_inner().status()
+    }
+
+    pub(crate) async fn call(channel: &tonic::transport::Channel, path: &'static str) -> Result<Response<()>, Status> {
+        let mut grpc = tonic::client::Grpc::new(channel.clone());
+        grpc.ready().await.unwrap();
+        let path = http::uri::PathAndQuery::from_static(path);
+        grpc.unary::<(), (), _>(Request::new(()), path, ProstCodec::default()).await
     }
 
     #[tokio::test]
     async fn valid_serve() {
-        assert_eq!(4, 4);
+        let grpc = Grpc {
+            auth: Some(Jwt::with_secret("secret")),
+            ..Default::default()
+        };
+        let test = TestServer::new(&Server::new(Arc::new(Conf(grpc)), Hello)).await.unwrap();
+        let channel = test.channel().await.unwrap();
+
+        assert_eq!(health(&channel, "bamboo.Hello").await, ServingStatus::Serving);
+        let res = call(&channel, "/bamboo.Hello/Call").await;
+        assert_status(res, Code::Unauthenticated, "Unauthenticated");
+        test.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn valid_services() {
+        let server = Server::new(Arc::new(Conf(Grpc::default())), Hello).add_service(Bye);
+        let mut reporter = server.health();
+        let test = TestServer::new(&server).await.unwrap();
+        let channel = test.channel().await.unwrap();
+
+        assert_eq!(health(&channel, "bamboo.Hello").await, ServingStatus::Serving);
+        assert_eq!(health(&channel, "bamboo.Bye").await, ServingStatus::Serving);
+        assert_eq!(call(&channel, "/bamboo.Hello/Call").await.unwrap_err().code(), Code::Unimplemented);
+        assert_eq!(call(&channel, "/bamboo.Bye/Call").await.unwrap_err().code(), Code::NotFound);
+
+        reporter.set_service_status("bamboo.Bye", tonic_health::ServingStatus::NotServing).await;
+        assert_eq!(health(&channel, "bamboo.Bye").await, ServingStatus::NotServing);
+        assert_eq!(health(&channel, "bamboo.Hello").await, ServingStatus::Serving);
+        test.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn valid_reflection() {
+        use tokio_stream::StreamExt;
+        use tonic_reflection::pb::v1::{
+            server_reflection_client::ServerReflectionClient, server_reflection_request::MessageRequest,
+            server_reflection_response::MessageResponse, ServerReflectionRequest,
+        };
+
+        bamboo_prost::register_file_descriptor_set(tonic_reflection::pb::v1alpha::FILE_DESCRIPTOR_SET);
+        let grpc = Grpc {
+            reflection: true,
+            ..Default::default()
+        };
+        let test = TestServer::new(&Server::new(Arc::new(Conf(grpc)), Hello)).await.unwrap();
+        let channel = test.channel().await.unwrap();
+
+        let req = ServerReflectionRequest {
+            host: String::new(),
+            message_request: Some(MessageRequest::ListServices(String::new())),
+        };
+        let mut res = ServerReflectionClient::new(channel)
+            .server_reflection_info(tokio_stream::once(req))
+            .await
+            .unwrap()
+            .into_inner();
+        let services = match res.next().await.unwrap().unwrap().message_response {
+            Some(MessageResponse::ListServicesResponse(list)) => list.service,
+            other => panic!("unexpected response {:?}", other),
+        };
+        let mut names: Vec<_> = services.into_iter().map(|s| s.name).collect();
+        names.sort();
+        assert_eq!(names, [
+            "grpc.health.v1.Health",
+            "grpc.reflection.v1.ServerReflection",
+            "grpc.reflection.v1alpha.ServerReflection",
+        ]);
+        test.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn valid_limits() {
+        let grpc = Grpc {
+            max_decoding_message_size: Some(8),
+            ..Default::default()
+        };
+        let server = Server::new(Arc::new(Conf(grpc)), Hello).limited(|s, _| s, |s, _| s);
+        let test = TestServer::new(&server).await.unwrap();
+        let channel = test.channel().await.unwrap();
+
+        let req = HealthCheckRequest { service: "bamboo.Hello".to_string() };
+        let res = HealthClient::new(channel).check(req).await;
+        assert_eq!(res.unwrap_err().code(), Code::OutOfRange);
+        test.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn invalid_limits_unlimited() {
+        let grpc = Grpc {
+            max_encoding_message_size: Some(8),
+            ..Default::default()
+        };
+        let server = Server::new(Arc::new(Conf(grpc)), Hello);
+        let shutdown = tokio_graceful::Shutdown::no_signal();
+        let err = server.serve(shutdown.guard()).await.unwrap_err();
+        assert_eq!(err.to_string(), "grpc server: message size limits need Server::limited on bamboo.Hello");
+    }
+
+    #[tokio::test]
+    async fn invalid_address() {
+        let grpc = Grpc {
+            address: "localhost".to_string(),
+            ..Default::default()
+        };
+        let server = Server::new(Arc::new(Conf(grpc)), Hello);
+        let shutdown = tokio_graceful::Shutdown::no_signal();
+        let err = server.serve(shutdown.guard()).await.unwrap_err();
+        assert_eq!(err.to_string(), "grpc server: invalid address \"localhost\"");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn valid_serve_uds() {
+        use tonic::transport::{Endpoint, Uri};
+
+        let dir = std::env::temp_dir().join(format!("bamboo-rpc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("grpc.sock");
+        // A stale socket of a previous run is replaced.
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let grpc = Grpc {
+            address: "127.0.0.1:0".to_string(),
+            uds: Some(path.clone()),
+            ..Default::default()
+        };
+        let server = Arc::new(Server::new(Arc::new(Conf(grpc)), Hello));
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let shutdown = tokio_graceful::Shutdown::new(async move {
+            let _ = rx.await;
+        });
+        shutdown.spawn_task_fn({
+            let server = server.clone();
+            move |guard| async move { server.serve(guard).await.unwrap() }
+        });
+        server.listening.addr().await;
+
+        let connect = path.clone();
+        let channel = Endpoint::from_static("http://uds")
+            .connect_with_connector(tower::service_fn(move |_: Uri| {
+                let path = connect.clone();
+                async move {
+                    let stream = tokio::net::UnixStream::connect(path).await?;
+                    Ok::<_, std::io::Error>(hyper_util::rt::TokioIo::new(stream))
+                }
+            }))
+            .await
+            .unwrap();
+        assert_eq!(health(&channel, "bamboo.Hello").await, ServingStatus::Serving);
+        drop(channel);
+
+        tx.send(()).unwrap();
+        shutdown.shutdown().await;
+        assert!(!path.exists());
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn invalid_serve_uds_not_socket() {
+        let dir = std::env::temp_dir().join(format!("bamboo-rpc-{}-file", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("grpc.sock");
+        std::fs::write(&path, b"").unwrap();
+        let grpc = Grpc {
+            address: "127.0.0.1:0".to_string(),
+            uds: Some(path.clone()),
+            ..Default::default()
+        };
+        let server = Server::new(Arc::new(Conf(grpc)), Hello);
+        let shutdown = tokio_graceful::Shutdown::no_signal();
+        let err = server.serve(shutdown.guard()).await.unwrap_err();
+        assert_eq!(err.to_string(), format!("grpc server: {} exists and is not a socket", path.display()));
+        // The file is left alone.
+        assert!(path.is_file());
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,495 @@
+//! HTTP and gRPC on a single port.
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::{body::Body, extract::ConnectInfo, Router};
+use http::{header, Method};
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::conn::auto,
+    service::TowerToHyperService,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+};
+use tokio_graceful::ShutdownGuard;
+use tonic::{transport::server::TcpConnectInfo, TimeoutExpired};
+use tower::{limit::ConcurrencyLimitLayer, BoxError, Service, ServiceBuilder, ServiceExt};
+
+use bamboo_boot::plugin::{Listening, Plugin};
+use bamboo_status::status::AnyResult;
+use bamboo_tls::{Acceptor, PeerIdentity, Tls};
+
+use crate::{async_trait, context, BoxBody, Grpc, HttpRequest, HttpResponse, NamedService, Server, Status, TlsConnectInfo};
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output=T> + Send + 'a>>;
+
+/// ALPN protocols announced by the combined server.
+const ALPN: [&[u8]; 2] = [b"h2", b"http/1.1"];
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Mux {
+    pub address: String,
+    /// Serve over TLS, plaintext HTTP/1.1 and HTTP/2 when missing.
+    #[serde(default)]
+    pub tls: Option<Tls>,
+}
+
+pub trait Config: bamboo_rest::Config + crate::Config {
+    fn mux(&self) -> &Mux;
+}
+
+/// Serves a REST and a gRPC server on the address of [`Mux`].
+///
+/// Requests with an `application/grpc` content type, gRPC-Web included, go through the gRPC
+/// middleware to the gRPC services, and so do the CORS preflights of gRPC-Web calls when
+/// [`Grpc::web`] is enabled. Everything else goes to the REST router. The call and HTTP/2
+/// settings of [`Grpc`] apply to every connection, its address, socket and TLS settings and
+/// those of [`Http`](bamboo_rest::Http) are not used.
+pub struct MuxServer<C, S> {
+    rest: bamboo_rest::Server<C>,
+    grpc: Server<C, S>,
+    listening: Listening,
+}
+
+impl<C, S> MuxServer<C, S> {
+    pub fn new(rest: bamboo_rest::Server<C>, grpc: Server<C, S>) -> Self {
+        Self {
+            rest,
+            grpc,
+            listening: Listening::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl<C, S> Plugin for MuxServer<C, S> where
+    C: Config + Send + Sync + 'static,
+    S: Service<HttpRequest<BoxBody>, Response=HttpResponse<BoxBody>, Error=Infallible>
+    + NamedService
+    + Clone
+    + Send
+    + Sync
+    + 'static,
+    S::Future: Send + 'static,
+{
+    async fn serve(&self, guard: ShutdownGuard) -> AnyResult<()> {
+        let conf = self.grpc.conf.mux();
+        let rest = self.rest.app(&guard)?;
+        let (router, signal) = self.grpc.router(&guard).await?;
+        guard.spawn_task(signal);
+        let grpc = router.into_service::<BoxBody>();
+        let grpc_conf = self.grpc.conf.grpc();
+        let builder = builder(grpc_conf);
+        let web_services = grpc_conf.web.is_some().then(|| self.services());
+
+        let addr = conf.address.parse::<SocketAddr>()?;
+        let listener = TcpListener::bind(addr).await?;
+        self.listening.set(listener.local_addr()?);
+        let acceptor = match &conf.tls {
+            Some(tls) => {
+                let acceptor = Acceptor::new(tls, &ALPN)?;
+                acceptor.watch(&guard);
+                log::info!("Http and Grpc Listening on {} with tls", addr);
+                Some(acceptor)
+            }
+            None => {
+                log::info!("Http and Grpc Listening on {}", addr);
+                None
+            }
+        };
+        loop {
+            let (tcp, remote) = tokio::select! {
+                res = listener.accept() => match res {
+                    Ok(conn) => conn,
+                    Err(err) => {
+                        log::error!("Mux accept error: {}", err);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                },
+                _ = guard.cancelled() => break,
+            };
+            // Like tonic's own server, the concurrency limit is per connection.
+            let grpc = ServiceBuilder::new()
+                .option_layer(grpc_conf.concurrency_limit_per_connection.map(ConcurrencyLimitLayer::new))
+                .service(grpc.clone());
+            let svc = Dispatch {
+                rest: rest.clone(),
+                grpc,
+                web_services: web_services.clone(),
+                remote,
+                local: tcp.local_addr().ok(),
+                tls: None,
+            };
+            let acceptor = acceptor.clone();
+            let builder = builder.clone();
+            guard.spawn_task_fn(move |guard| async move {
+                match acceptor {
+                    Some(acceptor) => match acceptor.accept(tcp).await {
+                        Ok(stream) => {
+                            let info = TlsConnectInfo {
+                                remote_addr: Some(remote),
+                                peer: PeerIdentity::from_connection(stream.get_ref().1),
+                            };
+                            let svc = Dispatch { tls: Some(info), ..svc };
+                            serve_connection(stream, svc, builder, guard).await
+                        }
+                        Err(err) => log::debug!("Mux handshake with {} failed: {:#}", remote, err),
+                    },
+                    None => serve_connection(tcp, svc, builder, guard).await,
+                }
+            });
+        }
+        log::info!("Http and Grpc stopping");
+        Ok(())
+    }
+
+    async fn listening(&self) -> Option<String> {
+        let scheme = if self.grpc.conf.mux().tls.is_some() { "https" } else { "http" };
+        Some(format!("{}://{}", scheme, self.listening.addr().await))
+    }
+}
+
+impl<C, S> MuxServer<C, S>
+    where
+        C: crate::Config,
+        S: NamedService,
+{
+    /// The names of the gRPC services, the health and reflection ones included.
+    fn services(&self) -> Arc<[&'static str]> {
+        let reflection = self
+            .grpc
+            .conf
+            .grpc()
+            .reflection
+            .then_some(tonic_reflection::pb::v1::server_reflection_server::SERVICE_NAME);
+        std::iter::once(S::NAME)
+            .chain(self.grpc.names.iter().copied())
+            .chain([tonic_health::pb::health_server::SERVICE_NAME])
+            .chain(reflection)
+            .collect()
+    }
+}
+
+/// The connection builder, with the HTTP/2 settings of `conf`.
+fn builder(conf: &Grpc) -> auto::Builder<TokioExecutor> {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    let mut http2 = builder.http2();
+    http2
+        .timer(TokioTimer::new())
+        .max_concurrent_streams(conf.max_concurrent_streams)
+        .keep_alive_interval(conf.http2_keepalive_interval)
+        .initial_stream_window_size(conf.initial_stream_window_size)
+        .initial_connection_window_size(conf.initial_connection_window_size);
+    if let Some(timeout) = conf.http2_keepalive_timeout {
+        http2.keep_alive_timeout(timeout);
+    }
+    builder
+}
+
+/// Serve HTTP/1.1 and HTTP/2 requests of `io` until it closes, or gracefully until shutdown.
+async fn serve_connection<IO, G>(io: IO, svc: Dispatch<G>, builder: auto::Builder<TokioExecutor>, guard: ShutdownGuard)
+    where
+        IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        G: Service<HttpRequest<BoxBody>, Response=HttpResponse<BoxBody>, Error=BoxError> + Clone + Send + 'static,
+        G::Future: Send,
+{
+    let remote = svc.remote;
+    let conn = builder.serve_connection_with_upgrades(TokioIo::new(io), TowerToHyperService::new(svc));
+    tokio::pin!(conn);
+    tokio::select! {
+        res = conn.as_mut() => {
+            if let Err(err) = res {
+                log::debug!("Mux connection with {} failed: {}", remote, err);
+            }
+        }
+        _ = guard.cancelled() => {
+            conn.as_mut().graceful_shutdown();
+            let _ = conn.await;
+        }
+    }
+}
+
+/// Dispatches the requests of a connection by content type.
+#[derive(Clone)]
+struct Dispatch<G> {
+    rest: Router,
+    grpc: G,
+    /// The gRPC services whose CORS preflights go to `grpc`, set when gRPC-Web is enabled.
+    web_services: Option<Arc<[&'static str]>>,
+    remote: SocketAddr,
+    local: Option<SocketAddr>,
+    /// Set on TLS connections.
+    tls: Option<TlsConnectInfo>,
+}
+
+impl<G> Service<HttpRequest<Incoming>> for Dispatch<G>
+    where
+        G: Service<HttpRequest<BoxBody>, Response=HttpResponse<BoxBody>, Error=BoxError> + Clone + Send + 'static,
+        G::Future: Send,
+{
+    type Response = HttpResponse<Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Both sides are readied per request.
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, mut req: HttpRequest<Incoming>) -> Self::Future {
+        // The connection info the REST handlers and the gRPC services read.
+        req.extensions_mut().insert(ConnectInfo(self.remote));
+        match &self.tls {
+            Some(info) => {
+                if let Some(peer) = &info.peer {
+                    req.extensions_mut().insert(peer.clone());
+                }
+                req.extensions_mut().insert(info.clone());
+            }
+            None => {
+                req.extensions_mut().insert(TcpConnectInfo {
+                    local_addr: self.local,
+                    remote_addr: Some(self.remote),
+                });
+            }
+        }
+        if self.is_grpc(&req) {
+            let grpc = self.grpc.clone();
+            // Like tonic's own server, calls end with their `grpc-timeout`.
+            let timeout = req
+                .headers()
+                .get(context::GRPC_TIMEOUT)
+                .and_then(|v| v.to_str().ok())
+                .and_then(context::parse_timeout);
+            Box::pin(async move {
+                let call = grpc.oneshot(req.map(tonic::body::boxed));
+                let res = match timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, call)
+                        .await
+                        .unwrap_or_else(|_| Err(TimeoutExpired(()).into())),
+                    None => call.await,
+                };
+                let res = match res {
+                    Ok(res) => res,
+                    // Timeouts and other middleware errors, like tonic's own server answers them.
+                    Err(err) => Status::from_error(err).into_http(),
+                };
+                Ok(res.map(Body::new))
+            })
+        } else {
+            let rest = self.rest.clone();
+            Box::pin(async move {
+                let res = rest.oneshot(req).await?;
+                Ok(res)
+            })
+        }
+    }
+}
+
+impl<G> Dispatch<G> {
+    /// Whether `req` is a gRPC call, or the CORS preflight of a gRPC-Web one.
+    fn is_grpc<B>(&self, req: &HttpRequest<B>) -> bool {
+        let headers = req.headers();
+        if headers.get(header::CONTENT_TYPE).is_some_and(|v| v.as_bytes().starts_with(b"application/grpc")) {
+            return true;
+        }
+        let Some(services) = &self.web_services else {
+            return false;
+        };
+        if req.method() != Method::OPTIONS {
+            return false;
+        }
+        let grpc_web = headers
+            .get(header::ACCESS_CONTROL_REQUEST_HEADERS)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.split(',').any(|h| h.trim().eq_ignore_ascii_case("x-grpc-web")));
+        // Calls are `/{service}/{method}`
+        let service = req.uri().path().strip_prefix('/').and_then(|p| p.split_once('/')).map(|(s, _)| s);
+        grpc_web || service.is_some_and(|s| services.contains(&s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::routing::get;
+    use bamboo_rest::Http;
+    use tokio_graceful::Shutdown;
+    use tonic::{transport::Channel, Code};
+    use tonic_health::pb::{health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest};
+
+    use crate::{testing::assert_status, tests::{Hello, Slow}, Jwt, Request, Web};
+
+    use super::*;
+
+    struct Conf {
+        http: Http,
+        grpc: Grpc,
+        mux: Mux,
+    }
+
+    impl bamboo_rest::Config for Conf {
+        fn http(&self) -> &Http {
+            &self.http
+        }
+    }
+
+    impl crate::Config for Conf {
+        fn grpc(&self) -> &Grpc {
+            &self.grpc
+        }
+    }
+
+    impl Config for Conf {
+        fn mux(&self) -> &Mux {
+            &self.mux
+        }
+    }
+
+    /// Serve `grpc` and a `/hello` REST route on a free port, until the sender is dropped.
+    async fn serve<S>(grpc: Grpc, s: S) -> (Shutdown, tokio::sync::oneshot::Sender<()>, SocketAddr)
+        where
+            S: Service<HttpRequest<BoxBody>, Response=HttpResponse<BoxBody>, Error=Infallible>
+            + NamedService
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+            S::Future: Send + 'static,
+    {
+        let conf = Arc::new(Conf {
+            http: Http::default(),
+            grpc,
+            mux: Mux {
+                address: "127.0.0.1:0".to_string(),
+                tls: None,
+            },
+        });
+        let rest = bamboo_rest::Server::new(conf.clone(), Router::new().route("/hello", get(|| async { "hello" })));
+        let server = Arc::new(MuxServer::new(rest, Server::new(conf, s)));
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let shutdown = Shutdown::new(async move {
+            let _ = rx.await;
+        });
+        let serving = server.clone();
+        shutdown.spawn_task_fn(move |guard| async move { serving.serve(guard).await.unwrap() });
+        let addr = server.listening.addr().await;
+        (shutdown, tx, addr)
+    }
+
+    async fn channel(addr: SocketAddr) -> Channel {
+        tonic::transport::Endpoint::from_shared(format!("http://{}", addr))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap()
+    }
+
+    #[allow(clippy::result_large_err)]
+    async fn call(channel: Channel, path: &'static str, timeout: Option<Duration>) -> Result<tonic::Response<()>, tonic::Status> {
+        let mut grpc = tonic::client::Grpc::new(channel);
+        grpc.ready().await.unwrap();
+        let mut req = Request::new(());
+        if let Some(timeout) = timeout {
+            req.set_timeout(timeout);
+        }
+        let path = http::uri::PathAndQuery::from_static(path);
+        grpc.unary::<(), (), _>(req, path, tonic::codec::ProstCodec::default()).await
+    }
+
+    async fn send(addr: SocketAddr, req: HttpRequest<Body>) -> AnyResult<(http::StatusCode, http::HeaderMap, String)> {
+        let tcp = tokio::net::TcpStream::connect(addr).await?;
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(tcp)).await?;
+        tokio::spawn(conn);
+        let res = sender.send_request(req).await?;
+        let (parts, body) = res.into_parts();
+        let body = axum::body::to_bytes(Body::new(body), usize::MAX).await?;
+        Ok((parts.status, parts.headers, String::from_utf8(body.to_vec())?))
+    }
+
+    #[tokio::test]
+    async fn valid_serve() {
+        let grpc = Grpc {
+            auth: Some(Jwt::with_secret("secret")),
+            ..Default::default()
+        };
+        let (shutdown, tx, addr) = serve(grpc, Hello).await;
+
+        let req = HttpRequest::get("/hello").header("host", "localhost").body(Body::empty()).unwrap();
+        let (status, _, body) = send(addr, req).await.unwrap();
+        assert_eq!(status, http::StatusCode::OK);
+        assert_eq!(body, "hello");
+
+        let channel = channel(addr).await;
+        let req = HealthCheckRequest { service: "bamboo.Hello".to_string() };
+        let res = HealthClient::new(channel.clone()).check(req).await.unwrap();
+        assert_eq!(res.into_inner().status(), ServingStatus::Serving);
+        assert_status(call(channel, "/bamboo.Hello/Call", None).await, Code::Unauthenticated, "Unauthenticated");
+
+        tx.send(()).unwrap();
+        shutdown.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn invalid_slow_call() {
+        let grpc = Grpc {
+            timeout: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let (shutdown, tx, addr) = serve(grpc, Slow).await;
+        let channel = channel(addr).await;
+
+        // Both the server timeout and the shorter `grpc-timeout` of the caller end calls.
+        let err = call(channel.clone(), "/bamboo.Slow/Call", None).await.unwrap_err();
+        assert_eq!(err.message(), "request timed out");
+        let err = call(channel, "/bamboo.Slow/Call", Some(Duration::from_millis(20))).await.unwrap_err();
+        assert_eq!(err.code(), Code::Cancelled);
+
+        tx.send(()).unwrap();
+        shutdown.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn valid_grpc_web_preflight() {
+        let grpc = Grpc {
+            web: Some(Web { allow_origins: vec!["https://app.example.com".to_string()] }),
+            ..Default::default()
+        };
+        let (shutdown, tx, addr) = serve(grpc, Hello).await;
+
+        let preflight = |path: &str, headers: &str| {
+            HttpRequest::options(path)
+                .header("host", "localhost")
+                .header(header::ORIGIN, "https://app.example.com")
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                .header(header::ACCESS_CONTROL_REQUEST_HEADERS, headers)
+                .body(Body::empty())
+                .unwrap()
+        };
+        for req in [preflight("/bamboo.Hello/Call", "content-type"), preflight("/any/path", "content-type,x-grpc-web")] {
+            let (_, headers, _) = send(addr, req).await.unwrap();
+            assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://app.example.com");
+        }
+        // Other preflights are the REST router's.
+        let (_, headers, _) = send(addr, preflight("/hello", "content-type")).await.unwrap();
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        tx.send(()).unwrap();
+        shutdown.shutdown().await;
+    }
+}
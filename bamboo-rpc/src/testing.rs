@@ -0,0 +1,91 @@
+//! In-process servers for tests, enabled by the `test-util` feature.
+
+use std::{convert::Infallible, fmt::Debug, io, time::Duration};
+
+use hyper_util::rt::TokioIo;
+use tokio::{io::DuplexStream, sync::{mpsc, oneshot}};
+use tokio_graceful::Shutdown;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::{Channel, Endpoint, Uri};
+use tower::{service_fn, Service};
+
+use bamboo_status::{errors, status::AnyResult};
+
+use crate::{BoxBody, Code, Config, HttpRequest, HttpResponse, NamedService, Server, Status};
+
+const BUFFER: usize = 64 * 1024;
+
+/// Runs a [`Server`] with its middleware stack over in-memory connections.
+///
+/// Needs a tokio runtime, e.g. `#[tokio::test]`. Like REST's `TestServer::new`, it fails with
+/// the configuration errors of the server. Generated clients take the
+/// [`channel`](Self::channel): `GreeterClient::new(test.channel().await?)`.
+pub struct TestServer {
+    conns: mpsc::Sender<io::Result<DuplexStream>>,
+    shutdown: Shutdown,
+    trigger: oneshot::Sender<()>,
+}
+
+impl TestServer {
+    pub async fn new<C, S>(server: &Server<C, S>) -> AnyResult<Self>
+        where
+            C: Config + Send + Sync + 'static,
+            S: Service<HttpRequest<BoxBody>, Response=HttpResponse<BoxBody>, Error=Infallible>
+            + NamedService
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+            S::Future: Send + 'static,
+    {
+        let (trigger, rx) = oneshot::channel::<()>();
+        let shutdown = Shutdown::new(async move {
+            let _ = rx.await;
+        });
+        let (router, signal) = server.router(&shutdown.guard()).await?;
+        let (conns, incoming) = mpsc::channel(16);
+        shutdown.spawn_task(async move {
+            if let Err(err) = router.serve_with_incoming_shutdown(ReceiverStream::new(incoming), signal).await {
+                log::error!("test server failed: {}", err);
+            }
+        });
+        Ok(Self { conns, shutdown, trigger })
+    }
+
+    /// A channel whose connections are served by the test server.
+    pub async fn channel(&self) -> AnyResult<Channel> {
+        let conns = self.conns.clone();
+        let channel = Endpoint::from_static("http://in-process")
+            .connect_with_connector(service_fn(move |_: Uri| {
+                let conns = conns.clone();
+                async move {
+                    let (client, server) = tokio::io::duplex(BUFFER);
+                    conns
+                        .send(Ok(server))
+                        .await
+                        .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "test server stopped"))?;
+                    Ok::<_, io::Error>(TokioIo::new(client))
+                }
+            }))
+            .await?;
+        Ok(channel)
+    }
+
+    /// Trigger a graceful shutdown and wait for the server to stop.
+    pub async fn shutdown(self) {
+        let _ = self.trigger.send(());
+        drop(self.conns);
+        if let Err(err) = self.shutdown.shutdown_with_limit(Duration::from_secs(5)).await {
+            panic!("test server did not shut down: {}", err);
+        }
+    }
+}
+
+/// Assert that a call failed with `code` and the `reason` of the error envelope, return the envelope.
+pub fn assert_status<T: Debug>(res: Result<T, Status>, code: Code, reason: &str) -> errors::Status {
+    let status = res.expect_err("expected a status");
+    assert_eq!(status.code(), code, "unexpected status {:?}", status);
+    let status = errors::Status::from(status);
+    assert_eq!(status.reason, reason, "unexpected status {}", status);
+    status
+}
@@ -0,0 +1,230 @@
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+    sync::mpsc,
+};
+use tokio_graceful::ShutdownGuard;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tonic::transport::server::{Connected, TcpIncoming};
+
+use bamboo_tls::{Acceptor, PeerIdentity, TlsStream};
+
+/// ALPN protocols announced by the gRPC server.
+pub(crate) const ALPN: [&[u8]; 1] = [b"h2"];
+
+/// Connection info of TLS connections, tonic puts it in the request extensions.
+#[derive(Debug, Clone)]
+pub struct TlsConnectInfo {
+    pub remote_addr: Option<SocketAddr>,
+    /// The verified client certificate, `None` for anonymous clients.
+    pub peer: Option<PeerIdentity>,
+}
+
+/// The client certificate of the connection `req` came in on.
+pub fn peer_identity<T>(req: &tonic::Request<T>) -> Option<&PeerIdentity> {
+    req.extensions().get::<TlsConnectInfo>()?.peer.as_ref()
+}
+
+/// A TLS connection handed to tonic.
+pub struct TlsIo {
+    stream: TlsStream<TcpStream>,
+    info: TlsConnectInfo,
+}
+
+impl Connected for TlsIo {
+    type ConnectInfo = TlsConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        self.info.clone()
+    }
+}
+
+impl AsyncRead for TlsIo {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsIo {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+}
+
+/// Accept TLS connections until shutdown, handshaking concurrently so a slow client
+/// doesn't hold up the others.
+pub(crate) fn incoming(mut listener: TcpIncoming, acceptor: Acceptor, guard: &ShutdownGuard) -> ReceiverStream<io::Result<TlsIo>> {
+    acceptor.watch(guard);
+    let (tx, rx) = mpsc::channel(64);
+    let guard = guard.clone_weak();
+    tokio::spawn(async move {
+        loop {
+            let tcp = tokio::select! {
+                res = listener.next() => match res {
+                    Some(Ok(tcp)) => tcp,
+                    Some(Err(err)) => {
+                        log::error!("Grpc accept error: {}", err);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                    None => return,
+                },
+                _ = guard.cancelled() => return,
+                _ = tx.closed() => return,
+            };
+            let Ok(remote) = tcp.peer_addr() else {
+                continue;
+            };
+            let acceptor = acceptor.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                match acceptor.accept(tcp).await {
+                    Ok(stream) => {
+                        let info = TlsConnectInfo {
+                            remote_addr: Some(remote),
+                            peer: PeerIdentity::from_connection(stream.get_ref().1),
+                        };
+                        let _ = tx.send(Ok(TlsIo { stream, info })).await;
+                    }
+                    Err(err) => log::debug!("Grpc handshake with {} failed: {:#}", remote, err),
+                }
+            });
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, sync::Arc};
+
+    use bamboo_boot::plugin::Plugin;
+    use bamboo_tls::{testing::Pki, ClientAuth};
+    use hyper_util::rt::TokioIo;
+    use tokio_graceful::Shutdown;
+    use tokio_rustls::{rustls::pki_types::ServerName, TlsConnector};
+    use tonic::{
+        body::BoxBody,
+        codec::ProstCodec,
+        server::NamedService,
+        transport::{Endpoint, Uri},
+        Code,
+    };
+    use tower::service_fn;
+
+    use super::*;
+    use crate::{Config, Grpc, Server};
+
+    struct Conf(Grpc);
+
+    impl Config for Conf {
+        fn grpc(&self) -> &Grpc {
+            &self.0
+        }
+    }
+
+    /// Answers every call with an error carrying the client's common name.
+    #[derive(Clone)]
+    struct WhoAmI;
+
+    impl NamedService for WhoAmI {
+        const NAME: &'static str = "bamboo.WhoAmI";
+    }
+
+    impl tower::Service<http::Request<BoxBody>> for WhoAmI {
+        type Response = http::Response<BoxBody>;
+        type Error = Infallible;
+        type Future = std::future::Ready<Result<Self::Response, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: http::Request<BoxBody>) -> Self::Future {
+            let req = tonic::Request::from_http(req);
+            let name = peer_identity(&req).map(|p| p.common_name.clone()).unwrap_or_default();
+            std::future::ready(Ok(tonic::Status::new(Code::Unauthenticated, name).into_http()))
+        }
+    }
+
+    async fn whoami(addr: SocketAddr, pki: &Pki, client: Option<&str>) -> Result<String, tonic::Status> {
+        let config = Arc::new(pki.client_config(client, &[b"h2"]));
+        let channel = Endpoint::from_static("http://localhost")
+            .connect_with_connector(service_fn(move |_: Uri| {
+                let connector = TlsConnector::from(config.clone());
+                async move {
+                    let tcp = TcpStream::connect(addr).await?;
+                    let domain = ServerName::try_from("localhost").unwrap();
+                    let stream = connector.connect(domain, tcp).await?;
+                    Ok::<_, io::Error>(TokioIo::new(stream))
+                }
+            }))
+            .await
+            .map_err(|err| tonic::Status::unavailable(err.to_string()))?;
+        let mut grpc = tonic::client::Grpc::new(channel);
+        grpc.ready().await.map_err(|err| tonic::Status::unavailable(err.to_string()))?;
+        let path = http::uri::PathAndQuery::from_static("/bamboo.WhoAmI/Call");
+        let res = grpc
+            .unary::<(), (), _>(tonic::Request::new(()), path, ProstCodec::default())
+            .await;
+        match res {
+            Err(status) if status.code() == Code::Unauthenticated => Ok(status.message().to_string()),
+            Err(status) => Err(status),
+            Ok(_) => Ok(String::new()),
+        }
+    }
+
+    #[tokio::test]
+    async fn valid_serve_mtls() {
+        let pki = Pki::new("rpc");
+        pki.issue("client", "alice");
+        let grpc = Grpc {
+            address: "127.0.0.1:0".to_string(),
+            tls: Some(pki.tls(ClientAuth::Required)),
+            ..Default::default()
+        };
+        let server = Arc::new(Server::new(Arc::new(Conf(grpc)), WhoAmI));
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let shutdown = Shutdown::new(async move {
+            let _ = rx.await;
+        });
+        let serving = server.clone();
+        shutdown.spawn_task_fn(move |guard| async move { serving.serve(guard).await.unwrap() });
+        let addr = server.listening.addr().await;
+
+        assert_eq!(whoami(addr, &pki, Some("client")).await.unwrap(), "alice");
+        assert!(whoami(addr, &pki, None).await.is_err());
+
+        tx.send(()).unwrap();
+        shutdown.shutdown().await;
+    }
+}
@@ -0,0 +1,139 @@
+//! The parts of file descriptors transcoding needs, including the `google.api.http` option
+//! of methods that [`prost_types::MethodOptions`] drops.
+
+use std::collections::HashMap;
+
+use prost_types::{DescriptorProto, EnumDescriptorProto};
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct FileDescriptorSet {
+    #[prost(message, repeated, tag = "1")]
+    pub file: Vec<FileDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct FileDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub package: Option<String>,
+    #[prost(message, repeated, tag = "4")]
+    pub message_type: Vec<DescriptorProto>,
+    #[prost(message, repeated, tag = "5")]
+    pub enum_type: Vec<EnumDescriptorProto>,
+    #[prost(message, repeated, tag = "6")]
+    pub service: Vec<ServiceDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct ServiceDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+    #[prost(message, repeated, tag = "2")]
+    pub method: Vec<MethodDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct MethodDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub input_type: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub output_type: Option<String>,
+    #[prost(message, optional, tag = "4")]
+    pub options: Option<MethodOptions>,
+    #[prost(bool, optional, tag = "5")]
+    pub client_streaming: Option<bool>,
+    #[prost(bool, optional, tag = "6")]
+    pub server_streaming: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct MethodOptions {
+    /// The `google.api.http` extension.
+    #[prost(message, optional, tag = "72295728")]
+    pub http: Option<HttpRule>,
+}
+
+/// Mirrors `google.api.HttpRule`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct HttpRule {
+    #[prost(string, tag = "1")]
+    pub selector: String,
+    #[prost(oneof = "Pattern", tags = "2, 3, 4, 5, 6, 8")]
+    pub pattern: Option<Pattern>,
+    /// The request field the body maps to, `*` for the whole request.
+    #[prost(string, tag = "7")]
+    pub body: String,
+    /// The response field the body is taken from, the whole response when empty.
+    #[prost(string, tag = "12")]
+    pub response_body: String,
+    #[prost(message, repeated, tag = "11")]
+    pub additional_bindings: Vec<HttpRule>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub(crate) enum Pattern {
+    #[prost(string, tag = "2")]
+    Get(String),
+    #[prost(string, tag = "3")]
+    Put(String),
+    #[prost(string, tag = "4")]
+    Post(String),
+    #[prost(string, tag = "5")]
+    Delete(String),
+    #[prost(string, tag = "6")]
+    Patch(String),
+    #[prost(message, tag = "8")]
+    Custom(CustomHttpPattern),
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct CustomHttpPattern {
+    #[prost(string, tag = "1")]
+    pub kind: String,
+    #[prost(string, tag = "2")]
+    pub path: String,
+}
+
+/// Messages and enums by fully qualified name, like `.pkg.Outer.Inner`.
+#[derive(Debug, Default)]
+pub(crate) struct Types {
+    messages: HashMap<String, DescriptorProto>,
+    enums: HashMap<String, EnumDescriptorProto>,
+}
+
+impl Types {
+    pub fn add_file(&mut self, file: &FileDescriptorProto) {
+        let scope = match file.package.as_deref() {
+            Some(package) if !package.is_empty() => format!(".{}", package),
+            _ => String::new(),
+        };
+        for message in &file.message_type {
+            self.add_message(&scope, message);
+        }
+        for enumeration in &file.enum_type {
+            self.enums.insert(format!("{}.{}", scope, enumeration.name()), enumeration.clone());
+        }
+    }
+
+    fn add_message(&mut self, scope: &str, message: &DescriptorProto) {
+        let name = format!("{}.{}", scope, message.name());
+        for nested in &message.nested_type {
+            self.add_message(&name, nested);
+        }
+        for enumeration in &message.enum_type {
+            self.enums.insert(format!("{}.{}", name, enumeration.name()), enumeration.clone());
+        }
+        self.messages.insert(name, message.clone());
+    }
+
+    pub fn message(&self, name: &str) -> Result<&DescriptorProto, String> {
+        self.messages.get(name).ok_or_else(|| format!("unknown message {}", name))
+    }
+
+    pub fn enumeration(&self, name: &str) -> Result<&EnumDescriptorProto, String> {
+        self.enums.get(name).ok_or_else(|| format!("unknown enum {}", name))
+    }
+}
@@ -0,0 +1,665 @@
+//! Protobuf messages to and from their proto3 JSON mapping, driven by descriptors.
+//!
+//! Well-known types follow their own mapping, e.g. a `Timestamp` is an RFC 3339 string and
+//! an `Int32Value` a plain number. `Any` is still mapped like any other message.
+
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE},
+    Engine,
+};
+use bytes::Buf;
+use prost::{
+    encoding::{decode_key, decode_varint, encode_key, encode_varint, skip_field, DecodeContext, WireType},
+    Message,
+};
+use prost_types::{
+    field_descriptor_proto::{Label, Type},
+    value::Kind,
+    DescriptorProto, FieldDescriptorProto, FieldMask, ListValue, Struct,
+};
+use serde_json::{Map, Number, Value};
+
+use super::descriptor::Types;
+
+/// Encode the JSON `value` as the message `name`.
+pub(crate) fn encode(types: &Types, name: &str, value: &Value) -> Result<Vec<u8>, String> {
+    let mut buf = Vec::new();
+    Codec { types }.encode_named(name, value, &mut buf)?;
+    Ok(buf)
+}
+
+/// Decode the message `name` from `buf` to JSON.
+pub(crate) fn decode(types: &Types, name: &str, buf: &[u8]) -> Result<Value, String> {
+    Codec { types }.decode_named(name, buf)
+}
+
+/// The JSON key of `field`.
+pub(crate) fn json_name(field: &FieldDescriptorProto) -> String {
+    match &field.json_name {
+        Some(name) => name.clone(),
+        None => lower_camel(field.name()),
+    }
+}
+
+/// `snake_case` as `lowerCamelCase`.
+fn lower_camel(snake: &str) -> String {
+    let mut name = String::with_capacity(snake.len());
+    let mut upper = false;
+    for c in snake.chars() {
+        match c {
+            '_' => upper = true,
+            c if upper => {
+                name.extend(c.to_uppercase());
+                upper = false;
+            }
+            c => name.push(c),
+        }
+    }
+    name
+}
+
+/// A field of `message` by JSON key or by its name in the proto file.
+pub(crate) fn field<'a>(message: &'a DescriptorProto, key: &str) -> Option<&'a FieldDescriptorProto> {
+    message.field.iter().find(|f| f.name() == key || json_name(f) == key)
+}
+
+fn wire_type(field: &FieldDescriptorProto) -> WireType {
+    match field.r#type() {
+        Type::Double | Type::Fixed64 | Type::Sfixed64 => WireType::SixtyFourBit,
+        Type::Float | Type::Fixed32 | Type::Sfixed32 => WireType::ThirtyTwoBit,
+        Type::String | Type::Bytes | Type::Message | Type::Group => WireType::LengthDelimited,
+        _ => WireType::Varint,
+    }
+}
+
+fn float(value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Number(n) => n.as_f64().ok_or_else(|| format!("invalid number {}", n)),
+        Value::String(s) => s.parse().map_err(|_| format!("invalid number {:?}", s)),
+        other => Err(format!("expected a number, got {}", other)),
+    }
+}
+
+fn int(value: &Value) -> Result<i64, String> {
+    match value {
+        Value::Number(n) => n.as_i64().ok_or_else(|| format!("invalid integer {}", n)),
+        Value::String(s) => s.parse().map_err(|_| format!("invalid integer {:?}", s)),
+        other => Err(format!("expected an integer, got {}", other)),
+    }
+}
+
+fn uint(value: &Value) -> Result<u64, String> {
+    match value {
+        Value::Number(n) => n.as_u64().ok_or_else(|| format!("invalid unsigned integer {}", n)),
+        Value::String(s) => s.parse().map_err(|_| format!("invalid unsigned integer {:?}", s)),
+        other => Err(format!("expected an unsigned integer, got {}", other)),
+    }
+}
+
+fn int32(value: &Value) -> Result<i32, String> {
+    let n = int(value)?;
+    i32::try_from(n).map_err(|_| format!("{} out of range", n))
+}
+
+fn uint32(value: &Value) -> Result<u32, String> {
+    let n = uint(value)?;
+    u32::try_from(n).map_err(|_| format!("{} out of range", n))
+}
+
+fn boolean(value: &Value) -> Result<bool, String> {
+    match value {
+        Value::Bool(b) => Ok(*b),
+        Value::String(s) if s == "true" => Ok(true),
+        Value::String(s) if s == "false" => Ok(false),
+        other => Err(format!("expected a boolean, got {}", other)),
+    }
+}
+
+fn string(value: &Value) -> Result<&str, String> {
+    match value {
+        Value::String(s) => Ok(s),
+        other => Err(format!("expected a string, got {}", other)),
+    }
+}
+
+/// A float as JSON, non-finite values as strings.
+fn float_value(f: f64) -> Value {
+    match Number::from_f64(f) {
+        Some(n) => Value::Number(n),
+        None if f.is_nan() => Value::String("NaN".to_string()),
+        None if f > 0.0 => Value::String("Infinity".to_string()),
+        None => Value::String("-Infinity".to_string()),
+    }
+}
+
+/// `lowerCamelCase` as `snake_case`.
+fn snake(camel: &str) -> String {
+    let mut name = String::with_capacity(camel.len() + 4);
+    for c in camel.chars() {
+        if c.is_ascii_uppercase() {
+            name.push('_');
+        }
+        name.push(c.to_ascii_lowercase());
+    }
+    name
+}
+
+/// Well-known types with a JSON mapping of their own.
+#[derive(Clone, Copy)]
+enum WellKnown {
+    Timestamp,
+    Duration,
+    /// A wrapper of a scalar, mapped as the scalar.
+    Wrapper(Type),
+    Empty,
+    Struct,
+    Value,
+    ListValue,
+    FieldMask,
+}
+
+impl WellKnown {
+    fn of(name: &str) -> Option<Self> {
+        Some(match name.strip_prefix(".google.protobuf.")? {
+            "Timestamp" => WellKnown::Timestamp,
+            "Duration" => WellKnown::Duration,
+            "DoubleValue" => WellKnown::Wrapper(Type::Double),
+            "FloatValue" => WellKnown::Wrapper(Type::Float),
+            "Int64Value" => WellKnown::Wrapper(Type::Int64),
+            "UInt64Value" => WellKnown::Wrapper(Type::Uint64),
+            "Int32Value" => WellKnown::Wrapper(Type::Int32),
+            "UInt32Value" => WellKnown::Wrapper(Type::Uint32),
+            "BoolValue" => WellKnown::Wrapper(Type::Bool),
+            "StringValue" => WellKnown::Wrapper(Type::String),
+            "BytesValue" => WellKnown::Wrapper(Type::Bytes),
+            "Empty" => WellKnown::Empty,
+            "Struct" => WellKnown::Struct,
+            "Value" => WellKnown::Value,
+            "ListValue" => WellKnown::ListValue,
+            "FieldMask" => WellKnown::FieldMask,
+            _ => return None,
+        })
+    }
+}
+
+/// The message of a wrapper of `scalar`, its `value` field.
+fn wrapper(scalar: Type) -> DescriptorProto {
+    DescriptorProto {
+        field: vec![FieldDescriptorProto {
+            name: Some("value".to_string()),
+            number: Some(1),
+            r#type: Some(scalar as i32),
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
+
+/// A JSON value as a `google.protobuf.Value`.
+fn proto_value(value: &Value) -> prost_types::Value {
+    let kind = match value {
+        Value::Null => Kind::NullValue(0),
+        Value::Bool(b) => Kind::BoolValue(*b),
+        Value::Number(n) => Kind::NumberValue(n.as_f64().unwrap_or_default()),
+        Value::String(s) => Kind::StringValue(s.clone()),
+        Value::Array(items) => Kind::ListValue(ListValue { values: items.iter().map(proto_value).collect() }),
+        Value::Object(object) => Kind::StructValue(Struct {
+            fields: object.iter().map(|(k, v)| (k.clone(), proto_value(v))).collect(),
+        }),
+    };
+    prost_types::Value { kind: Some(kind) }
+}
+
+/// A `google.protobuf.Value` as JSON.
+fn json_value(value: prost_types::Value) -> Value {
+    match value.kind {
+        None | Some(Kind::NullValue(_)) => Value::Null,
+        Some(Kind::BoolValue(b)) => Value::Bool(b),
+        Some(Kind::NumberValue(n)) => float_value(n),
+        Some(Kind::StringValue(s)) => Value::String(s),
+        Some(Kind::ListValue(list)) => Value::Array(list.values.into_iter().map(json_value).collect()),
+        Some(Kind::StructValue(object)) => {
+            Value::Object(object.fields.into_iter().map(|(k, v)| (k, json_value(v))).collect())
+        }
+    }
+}
+
+/// A field value as read from the wire.
+enum Raw<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Fixed32(u32),
+    Bytes(&'a [u8]),
+}
+
+struct Codec<'a> {
+    types: &'a Types,
+}
+
+impl Codec<'_> {
+    /// Encode `value` as the message `name`, well-known types with their own mapping.
+    fn encode_named(&self, name: &str, value: &Value, buf: &mut Vec<u8>) -> Result<(), String> {
+        let Some(well_known) = WellKnown::of(name) else {
+            return self.encode_message(self.types.message(name)?, value, buf);
+        };
+        match well_known {
+            WellKnown::Timestamp => {
+                let s = string(value)?;
+                let timestamp: prost_types::Timestamp = s.parse().map_err(|_| format!("invalid timestamp {:?}", s))?;
+                timestamp.encode_raw(buf);
+            }
+            WellKnown::Duration => {
+                let s = string(value)?;
+                let duration: prost_types::Duration = s.parse().map_err(|_| format!("invalid duration {:?}", s))?;
+                duration.encode_raw(buf);
+            }
+            WellKnown::Wrapper(scalar) => self.encode_value(&wrapper(scalar).field[0], value, buf)?,
+            WellKnown::Empty => match value {
+                Value::Object(object) if object.is_empty() => {}
+                other => return Err(format!("expected an empty object, got {}", other)),
+            },
+            WellKnown::Struct => match proto_value(value).kind {
+                Some(Kind::StructValue(object)) => object.encode_raw(buf),
+                _ => return Err(format!("expected an object, got {}", value)),
+            },
+            WellKnown::Value => proto_value(value).encode_raw(buf),
+            WellKnown::ListValue => match proto_value(value).kind {
+                Some(Kind::ListValue(list)) => list.encode_raw(buf),
+                _ => return Err(format!("expected an array, got {}", value)),
+            },
+            WellKnown::FieldMask => {
+                let paths = string(value)?
+                    .split(',')
+                    .filter(|path| !path.is_empty())
+                    .map(|path| path.split('.').map(snake).collect::<Vec<_>>().join("."))
+                    .collect();
+                FieldMask { paths }.encode_raw(buf);
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode the message `name` from `buf`, well-known types with their own mapping.
+    fn decode_named(&self, name: &str, buf: &[u8]) -> Result<Value, String> {
+        let Some(well_known) = WellKnown::of(name) else {
+            return self.decode_message(self.types.message(name)?, buf).map(Value::Object);
+        };
+        let invalid = |e: prost::DecodeError| e.to_string();
+        Ok(match well_known {
+            WellKnown::Timestamp => Value::String(prost_types::Timestamp::decode(buf).map_err(invalid)?.to_string()),
+            WellKnown::Duration => Value::String(prost_types::Duration::decode(buf).map_err(invalid)?.to_string()),
+            WellKnown::Wrapper(scalar) => {
+                let wrapper = wrapper(scalar);
+                match self.decode_message(&wrapper, buf)?.remove("value") {
+                    Some(value) => value,
+                    None => self.default_value(&wrapper.field[0])?,
+                }
+            }
+            WellKnown::Empty => Value::Object(Map::new()),
+            WellKnown::Struct => json_value(prost_types::Value {
+                kind: Some(Kind::StructValue(Struct::decode(buf).map_err(invalid)?)),
+            }),
+            WellKnown::Value => json_value(prost_types::Value::decode(buf).map_err(invalid)?),
+            WellKnown::ListValue => json_value(prost_types::Value {
+                kind: Some(Kind::ListValue(ListValue::decode(buf).map_err(invalid)?)),
+            }),
+            WellKnown::FieldMask => {
+                let mask = FieldMask::decode(buf).map_err(invalid)?;
+                let paths: Vec<String> = mask
+                    .paths
+                    .iter()
+                    .map(|path| path.split('.').map(lower_camel).collect::<Vec<_>>().join("."))
+                    .collect();
+                Value::String(paths.join(","))
+            }
+        })
+    }
+
+    /// The key and value fields of `field`, if it is a map.
+    fn map_entry(&self, field: &FieldDescriptorProto) -> Result<Option<(&FieldDescriptorProto, &FieldDescriptorProto)>, String> {
+        if field.r#type() != Type::Message || field.label() != Label::Repeated {
+            return Ok(None);
+        }
+        let entry = self.types.message(field.type_name())?;
+        if !entry.options.as_ref().is_some_and(|o| o.map_entry()) {
+            return Ok(None);
+        }
+        match (entry.field.iter().find(|f| f.number() == 1), entry.field.iter().find(|f| f.number() == 2)) {
+            (Some(key), Some(value)) => Ok(Some((key, value))),
+            _ => Err(format!("invalid map entry {}", field.type_name())),
+        }
+    }
+
+    fn encode_message(&self, message: &DescriptorProto, value: &Value, buf: &mut Vec<u8>) -> Result<(), String> {
+        let object = match value {
+            Value::Object(object) => object,
+            Value::Null => return Ok(()),
+            other => return Err(format!("expected an object for {}, got {}", message.name(), other)),
+        };
+        for (key, value) in object {
+            let field = field(message, key).ok_or_else(|| format!("unknown field {} of {}", key, message.name()))?;
+            self.encode_field(field, value, buf).map_err(|e| format!("{}: {}", key, e))?;
+        }
+        Ok(())
+    }
+
+    fn encode_field(&self, field: &FieldDescriptorProto, value: &Value, buf: &mut Vec<u8>) -> Result<(), String> {
+        // Null is a value of its own for `google.protobuf.Value` only.
+        if value.is_null() && !matches!(WellKnown::of(field.type_name()), Some(WellKnown::Value)) {
+            return Ok(());
+        }
+        if let Some((key, val)) = self.map_entry(field)? {
+            let Value::Object(entries) = value else {
+                return Err(format!("expected an object, got {}", value));
+            };
+            for (k, v) in entries {
+                let mut entry = Vec::new();
+                self.encode_value(key, &Value::String(k.clone()), &mut entry)?;
+                self.encode_value(val, v, &mut entry)?;
+                encode_key(field.number() as u32, WireType::LengthDelimited, buf);
+                encode_varint(entry.len() as u64, buf);
+                buf.extend(entry);
+            }
+            return Ok(());
+        }
+        match (field.label(), value) {
+            (Label::Repeated, Value::Array(items)) => {
+                for item in items.iter().filter(|item| !item.is_null()) {
+                    self.encode_value(field, item, buf)?;
+                }
+                Ok(())
+            }
+            (_, Value::Array(_)) => Err("expected a single value, got an array".to_string()),
+            (_, value) => self.encode_value(field, value, buf),
+        }
+    }
+
+    fn encode_value(&self, field: &FieldDescriptorProto, value: &Value, buf: &mut Vec<u8>) -> Result<(), String> {
+        encode_key(field.number() as u32, wire_type(field), buf);
+        match field.r#type() {
+            Type::Double => buf.extend(float(value)?.to_le_bytes()),
+            Type::Float => buf.extend((float(value)? as f32).to_le_bytes()),
+            Type::Int64 => encode_varint(int(value)? as u64, buf),
+            Type::Uint64 => encode_varint(uint(value)?, buf),
+            // Negative 32-bit integers are sign extended to 64 bits.
+            Type::Int32 => encode_varint(int32(value)? as i64 as u64, buf),
+            Type::Uint32 => encode_varint(uint32(value)? as u64, buf),
+            Type::Fixed64 => buf.extend(uint(value)?.to_le_bytes()),
+            Type::Fixed32 => buf.extend(uint32(value)?.to_le_bytes()),
+            Type::Sfixed64 => buf.extend(int(value)?.to_le_bytes()),
+            Type::Sfixed32 => buf.extend(int32(value)?.to_le_bytes()),
+            Type::Sint64 => {
+                let n = int(value)?;
+                encode_varint(((n << 1) ^ (n >> 63)) as u64, buf)
+            }
+            Type::Sint32 => {
+                let n = int32(value)?;
+                encode_varint(((n << 1) ^ (n >> 31)) as u32 as u64, buf)
+            }
+            Type::Bool => encode_varint(boolean(value)? as u64, buf),
+            Type::Enum => {
+                let number = match value {
+                    Value::String(name) => self
+                        .types
+                        .enumeration(field.type_name())?
+                        .value
+                        .iter()
+                        .find(|v| v.name() == name)
+                        .map(|v| v.number())
+                        .ok_or_else(|| format!("unknown value {} of {}", name, field.type_name()))?,
+                    other => int32(other)?,
+                };
+                encode_varint(number as i64 as u64, buf)
+            }
+            Type::String => {
+                let s = string(value)?;
+                encode_varint(s.len() as u64, buf);
+                buf.extend(s.as_bytes());
+            }
+            Type::Bytes => {
+                let s = string(value)?;
+                let bytes = STANDARD
+                    .decode(s)
+                    .or_else(|_| URL_SAFE.decode(s))
+                    .map_err(|_| format!("invalid base64 {:?}", s))?;
+                encode_varint(bytes.len() as u64, buf);
+                buf.extend(bytes);
+            }
+            Type::Message => {
+                let mut nested = Vec::new();
+                self.encode_named(field.type_name(), value, &mut nested)?;
+                encode_varint(nested.len() as u64, buf);
+                buf.extend(nested);
+            }
+            Type::Group => return Err("groups are not supported".to_string()),
+        }
+        Ok(())
+    }
+
+    fn decode_message(&self, message: &DescriptorProto, mut buf: &[u8]) -> Result<Map<String, Value>, String> {
+        let mut object = Map::new();
+        while buf.has_remaining() {
+            let (tag, wire_type) = decode_key(&mut buf).map_err(|e| e.to_string())?;
+            let Some(field) = message.field.iter().find(|f| f.number() as u32 == tag) else {
+                skip_field(wire_type, tag, &mut buf, DecodeContext::default()).map_err(|e| e.to_string())?;
+                continue;
+            };
+            let raw = match wire_type {
+                WireType::Varint => Raw::Varint(decode_varint(&mut buf).map_err(|e| e.to_string())?),
+                WireType::SixtyFourBit if buf.remaining() >= 8 => Raw::Fixed64(buf.get_u64_le()),
+                WireType::ThirtyTwoBit if buf.remaining() >= 4 => Raw::Fixed32(buf.get_u32_le()),
+                WireType::LengthDelimited => {
+                    let len = decode_varint(&mut buf).map_err(|e| e.to_string())? as usize;
+                    if buf.remaining() < len {
+                        return Err("buffer underflow".to_string());
+                    }
+                    let (bytes, rest) = buf.split_at(len);
+                    buf = rest;
+                    Raw::Bytes(bytes)
+                }
+                _ => return Err(format!("unexpected wire type of field {}", field.name())),
+            };
+            self.decode_field(field, raw, &mut object)?;
+        }
+        Ok(object)
+    }
+
+    fn decode_field(&self, field: &FieldDescriptorProto, raw: Raw, object: &mut Map<String, Value>) -> Result<(), String> {
+        let key = json_name(field);
+        if let Some((key_field, value_field)) = self.map_entry(field)? {
+            let Raw::Bytes(bytes) = raw else {
+                return Err(format!("unexpected wire type of map {}", field.name()));
+            };
+            let entry = self.decode_message(self.types.message(field.type_name())?, bytes)?;
+            let k = match entry.get(&json_name(key_field)).cloned().unwrap_or(self.default_value(key_field)?) {
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+            let v = match entry.get(&json_name(value_field)) {
+                Some(v) => v.clone(),
+                None => self.default_value(value_field)?,
+            };
+            let map = object.entry(key).or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(map) = map {
+                map.insert(k, v);
+            }
+            return Ok(());
+        }
+        if field.label() != Label::Repeated {
+            object.insert(key, self.decode_value(field, raw)?);
+            return Ok(());
+        }
+        let Value::Array(items) = object.entry(key).or_insert_with(|| Value::Array(Vec::new())) else {
+            unreachable!("repeated fields are arrays");
+        };
+        match raw {
+            // Packed scalars
+            Raw::Bytes(mut bytes) if wire_type(field) != WireType::LengthDelimited => {
+                while bytes.has_remaining() {
+                    let raw = match wire_type(field) {
+                        WireType::SixtyFourBit if bytes.remaining() >= 8 => Raw::Fixed64(bytes.get_u64_le()),
+                        WireType::ThirtyTwoBit if bytes.remaining() >= 4 => Raw::Fixed32(bytes.get_u32_le()),
+                        WireType::Varint => Raw::Varint(decode_varint(&mut bytes).map_err(|e| e.to_string())?),
+                        _ => return Err(format!("invalid packed field {}", field.name())),
+                    };
+                    items.push(self.decode_value(field, raw)?);
+                }
+            }
+            raw => items.push(self.decode_value(field, raw)?),
+        }
+        Ok(())
+    }
+
+    fn decode_value(&self, field: &FieldDescriptorProto, raw: Raw) -> Result<Value, String> {
+        Ok(match (field.r#type(), raw) {
+            (Type::Double, Raw::Fixed64(n)) => float_value(f64::from_bits(n)),
+            (Type::Float, Raw::Fixed32(n)) => float_value(f32::from_bits(n) as f64),
+            // 64-bit integers are strings, they don't fit in JavaScript numbers.
+            (Type::Int64, Raw::Varint(n)) => Value::String((n as i64).to_string()),
+            (Type::Uint64, Raw::Varint(n)) => Value::String(n.to_string()),
+            (Type::Sint64, Raw::Varint(n)) => Value::String((((n >> 1) as i64) ^ -((n & 1) as i64)).to_string()),
+            (Type::Fixed64, Raw::Fixed64(n)) => Value::String(n.to_string()),
+            (Type::Sfixed64, Raw::Fixed64(n)) => Value::String((n as i64).to_string()),
+            (Type::Int32, Raw::Varint(n)) => Value::from(n as i32),
+            (Type::Uint32, Raw::Varint(n)) => Value::from(n as u32),
+            (Type::Sint32, Raw::Varint(n)) => Value::from(((n as u32 >> 1) as i32) ^ -((n & 1) as i32)),
+            (Type::Fixed32, Raw::Fixed32(n)) => Value::from(n),
+            (Type::Sfixed32, Raw::Fixed32(n)) => Value::from(n as i32),
+            (Type::Bool, Raw::Varint(n)) => Value::Bool(n != 0),
+            (Type::Enum, Raw::Varint(n)) => {
+                let number = n as i32;
+                let enumeration = self.types.enumeration(field.type_name())?;
+                match enumeration.value.iter().find(|v| v.number() == number) {
+                    Some(value) => Value::String(value.name().to_string()),
+                    None => Value::from(number),
+                }
+            }
+            (Type::String, Raw::Bytes(bytes)) => {
+                Value::String(String::from_utf8(bytes.to_vec()).map_err(|_| format!("invalid utf-8 in {}", field.name()))?)
+            }
+            (Type::Bytes, Raw::Bytes(bytes)) => Value::String(STANDARD.encode(bytes)),
+            (Type::Message, Raw::Bytes(bytes)) => self.decode_named(field.type_name(), bytes)?,
+            _ => return Err(format!("unexpected wire type of field {}", field.name())),
+        })
+    }
+
+    /// The value of `field` when missing from the wire.
+    fn default_value(&self, field: &FieldDescriptorProto) -> Result<Value, String> {
+        Ok(match field.r#type() {
+            Type::Int64 | Type::Uint64 | Type::Sint64 | Type::Fixed64 | Type::Sfixed64 => Value::String("0".to_string()),
+            Type::Bool => Value::Bool(false),
+            Type::String | Type::Bytes => Value::String(String::new()),
+            Type::Enum => {
+                let enumeration = self.types.enumeration(field.type_name())?;
+                match enumeration.value.iter().find(|v| v.number() == 0) {
+                    Some(value) => Value::String(value.name().to_string()),
+                    None => Value::from(0),
+                }
+            }
+            Type::Message | Type::Group => Value::Object(Map::new()),
+            _ => Value::from(0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use prost::Message;
+    use serde_json::json;
+
+    use super::*;
+    use crate::transcoding::tests::{types, Genre, Shelf};
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct Event {
+        #[prost(message, optional, tag = "1")]
+        at: Option<prost_types::Timestamp>,
+        #[prost(message, optional, tag = "2")]
+        ttl: Option<prost_types::Duration>,
+        #[prost(message, optional, tag = "3")]
+        limit: Option<i32>,
+        #[prost(message, optional, tag = "4")]
+        note: Option<String>,
+        #[prost(message, optional, tag = "5")]
+        attrs: Option<Struct>,
+        #[prost(message, optional, tag = "6")]
+        extra: Option<prost_types::Value>,
+        #[prost(message, optional, tag = "7")]
+        update_mask: Option<FieldMask>,
+    }
+
+    #[test]
+    fn valid_json() {
+        let types = types();
+        let value = json!({
+            "name": "shelves/1",
+            "theme": "Poetry",
+            "books": "12",
+            "tags": ["old", "rare"],
+            "genre": "FICTION",
+            "labels": {"floor": 2, "aisle": -3},
+            "cover": "aGk=",
+            "rating": -1.5,
+        });
+        let bytes = encode(&types, ".bamboo.library.Shelf", &value).unwrap();
+        let shelf = Shelf::decode(bytes.as_slice()).unwrap();
+        assert_eq!(shelf, Shelf {
+            name: "shelves/1".to_string(),
+            theme: "Poetry".to_string(),
+            books: 12,
+            tags: vec!["old".to_string(), "rare".to_string()],
+            genre: Genre::Fiction as i32,
+            labels: HashMap::from([("floor".to_string(), 2), ("aisle".to_string(), -3)]),
+            cover: b"hi".to_vec(),
+            rating: -1.5,
+        });
+        assert_eq!(decode(&types, ".bamboo.library.Shelf", &shelf.encode_to_vec()).unwrap(), value);
+
+        // Field names of the proto file, numbers as strings and enums as numbers are accepted too.
+        let value = json!({"name": "shelves/2", "books": 3, "genre": 2, "labels": {"floor": "0"}});
+        let shelf = Shelf::decode(encode(&types, ".bamboo.library.Shelf", &value).unwrap().as_slice()).unwrap();
+        assert_eq!(shelf.genre, Genre::Poetry as i32);
+        let value = decode(&types, ".bamboo.library.Shelf", &shelf.encode_to_vec()).unwrap();
+        assert_eq!(value, json!({"name": "shelves/2", "books": "3", "genre": "POETRY", "labels": {"floor": 0}}));
+
+        assert!(encode(&types, ".bamboo.library.Shelf", &json!({"unknown": 1})).is_err());
+        assert!(encode(&types, ".bamboo.library.Shelf", &json!({"books": "many"})).is_err());
+        assert!(encode(&types, ".bamboo.library.Shelf", &json!({"genre": "DRAMA"})).is_err());
+        assert!(encode(&types, ".bamboo.library.Shelf", &json!({"name": ["a", "b"]})).is_err());
+        assert!(encode(&types, ".bamboo.library.Missing", &json!({})).is_err());
+    }
+
+    #[test]
+    fn valid_json_well_known() {
+        let types = types();
+        let value = json!({
+            "at": "2024-05-01T12:30:00Z",
+            "ttl": "1.500s",
+            "limit": 0,
+            "note": "hi",
+            "attrs": {"a": [1.0, true, null, "x"]},
+            "extra": null,
+            "updateMask": "name,shelf.theme",
+        });
+        let bytes = encode(&types, ".bamboo.library.Event", &value).unwrap();
+        let event = Event::decode(bytes.as_slice()).unwrap();
+        assert_eq!(event.at, Some(prost_types::Timestamp { seconds: 1714566600, nanos: 0 }));
+        assert_eq!(event.ttl, Some(prost_types::Duration { seconds: 1, nanos: 500_000_000 }));
+        assert_eq!(event.limit, Some(0));
+        assert_eq!(event.note.as_deref(), Some("hi"));
+        assert_eq!(event.extra, Some(prost_types::Value { kind: Some(Kind::NullValue(0)) }));
+        assert_eq!(event.update_mask.unwrap().paths, vec!["name", "shelf.theme"]);
+        assert_eq!(decode(&types, ".bamboo.library.Event", &bytes).unwrap(), value);
+
+        // Well-known messages are mapped at the top level too, without their descriptors.
+        assert_eq!(decode(&types, ".google.protobuf.Empty", &[]).unwrap(), json!({}));
+        assert_eq!(encode(&types, ".google.protobuf.StringValue", &json!("a")).unwrap(), "a".to_string().encode_to_vec());
+
+        assert!(encode(&types, ".bamboo.library.Event", &json!({"at": "yesterday"})).is_err());
+        assert!(encode(&types, ".bamboo.library.Event", &json!({"ttl": 1})).is_err());
+        assert!(encode(&types, ".bamboo.library.Event", &json!({"attrs": [1]})).is_err());
+        assert!(encode(&types, ".google.protobuf.Empty", &json!({"a": 1})).is_err());
+    }
+}
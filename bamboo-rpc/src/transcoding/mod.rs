@@ -0,0 +1,790 @@
+//! HTTP/JSON transcoding of gRPC methods annotated with `google.api.http`, so REST clients
+//! call them through the REST server:
+//!
+//! ```ignore
+//! bamboo_prost::register_file_descriptor_set(tonic::include_file_descriptor_set!("library"));
+//! let transcoder = Transcoder::new()?.with_limits(conf.grpc());
+//! let rest = bamboo_rest::Server::new(conf, router).layer(transcoder.layer(client.channel()?));
+//! ```
+//!
+//! Only unary methods are transcoded. Results render in the [`Success`] envelope and errors of
+//! the methods in the [`Status`] one, like REST handlers. Requests that can't be transcoded
+//! are answered with a [`Rejection`], like the REST extractors: 400 when invalid, 413 when
+//! over the body limit of the REST server or the message limit of the gRPC one.
+
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::{
+    extract::{FromRequest, Request},
+    http::{HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use bytes::{Buf, BufMut, Bytes};
+use http::uri::PathAndQuery;
+use hyper::body::Body;
+use prost::Message;
+use serde_json::{Map, Value};
+use tonic::{
+    body::BoxBody,
+    client::GrpcService,
+    codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder},
+    metadata::MetadataMap,
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use bamboo_rest::Rejection;
+use bamboo_status::{errors::Status, spring::Success, status::AnyResult};
+
+use crate::{
+    context::{REQUEST_ID, TRACEPARENT, TRACESTATE},
+    interceptor::MAX_MESSAGE_SIZE,
+    Grpc,
+};
+use descriptor::{FileDescriptorSet, HttpRule, Pattern, Types};
+use template::Template;
+
+mod descriptor;
+mod json;
+mod template;
+
+type BoxFuture<'a, T> = Pin<Box<dyn std::future::Future<Output=T> + Send + 'a>>;
+
+type StdError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Headers passed on to the gRPC call as metadata.
+const FORWARDED: [&str; 5] = ["authorization", "accept-language", REQUEST_ID, TRACEPARENT, TRACESTATE];
+
+const REASON: &str = "TranscodingError";
+
+/// A request that can't be transcoded.
+fn invalid(message: impl std::fmt::Display) -> Rejection {
+    Rejection(Status::new(REASON, &message.to_string()).with_code(StatusCode::BAD_REQUEST))
+}
+
+fn too_large(message: impl std::fmt::Display) -> Rejection {
+    Rejection(Status::new("PayloadTooLarge", &message.to_string()).with_code(StatusCode::PAYLOAD_TOO_LARGE))
+}
+
+/// A gRPC method and its request and response messages.
+#[derive(Debug)]
+struct Rpc {
+    path: PathAndQuery,
+    input: String,
+    output: String,
+}
+
+/// An HTTP method and path template bound to a gRPC method.
+#[derive(Debug)]
+struct Binding {
+    method: Method,
+    template: Template,
+    body: String,
+    response_body: String,
+    rpc: Arc<Rpc>,
+}
+
+impl Binding {
+    fn new(rule: &HttpRule, rpc: Arc<Rpc>) -> AnyResult<Self> {
+        let (method, path) = match &rule.pattern {
+            Some(Pattern::Get(path)) => (Method::GET, path),
+            Some(Pattern::Put(path)) => (Method::PUT, path),
+            Some(Pattern::Post(path)) => (Method::POST, path),
+            Some(Pattern::Delete(path)) => (Method::DELETE, path),
+            Some(Pattern::Patch(path)) => (Method::PATCH, path),
+            Some(Pattern::Custom(custom)) => (Method::from_bytes(custom.kind.as_bytes())?, &custom.path),
+            None => anyhow::bail!("transcoding: http rule of {} has no pattern", rpc.path),
+        };
+        Ok(Self {
+            method,
+            template: Template::parse(path).map_err(anyhow::Error::msg)?,
+            body: rule.body.clone(),
+            response_body: rule.response_body.clone(),
+            rpc,
+        })
+    }
+}
+
+/// The methods annotated with `google.api.http`, cheap to clone.
+#[derive(Debug, Clone)]
+pub struct Transcoder {
+    types: Arc<Types>,
+    bindings: Arc<[Binding]>,
+    /// Largest request message sent, in bytes.
+    message_limit: usize,
+}
+
+impl Transcoder {
+    /// Transcode the methods of the sets registered with [`bamboo_prost::register_file_descriptor_set`].
+    pub fn new() -> AnyResult<Self> {
+        Self::from_file_descriptor_sets(&bamboo_prost::file_descriptor_sets())
+    }
+
+    /// Transcode the methods of the encoded `FileDescriptorSet`s `sets`.
+    pub fn from_file_descriptor_sets(sets: &[&[u8]]) -> AnyResult<Self> {
+        let mut names = HashSet::new();
+        let mut files = Vec::new();
+        for set in sets {
+            let set = FileDescriptorSet::decode(*set)?;
+            files.extend(set.file.into_iter().filter(|f| names.insert(f.name.clone())));
+        }
+        let mut types = Types::default();
+        for file in &files {
+            types.add_file(file);
+        }
+        let mut bindings = Vec::new();
+        for file in &files {
+            let package = match file.package.as_deref() {
+                Some(package) if !package.is_empty() => format!("{}.", package),
+                _ => String::new(),
+            };
+            for service in &file.service {
+                for method in &service.method {
+                    let Some(rule) = method.options.as_ref().and_then(|o| o.http.as_ref()) else {
+                        continue;
+                    };
+                    let path = format!("/{}{}/{}", package, service.name(), method.name());
+                    if method.client_streaming() || method.server_streaming() {
+                        log::warn!("transcoding: skipping streaming method {}", path);
+                        continue;
+                    }
+                    let rpc = Arc::new(Rpc {
+                        path: PathAndQuery::try_from(path)?,
+                        input: method.input_type().to_string(),
+                        output: method.output_type().to_string(),
+                    });
+                    for rule in std::iter::once(rule).chain(&rule.additional_bindings) {
+                        bindings.push(Binding::new(rule, rpc.clone())?);
+                    }
+                }
+            }
+        }
+        Ok(Self {
+            types: Arc::new(types),
+            bindings: bindings.into(),
+            message_limit: MAX_MESSAGE_SIZE,
+        })
+    }
+
+    /// Reject request messages over the `max_decoding_message_size` of the gRPC server `grpc`,
+    /// tonic's default of 4MB otherwise. Request bodies are limited by the `body_limit` of the
+    /// REST server.
+    pub fn with_limits(mut self, grpc: &Grpc) -> Self {
+        self.message_limit = grpc.decoding_limit();
+        self
+    }
+
+    /// A layer of the REST routes calling the methods through `channel`, other requests
+    /// pass through.
+    pub fn layer<T>(&self, channel: T) -> TranscodingLayer<T> {
+        TranscodingLayer {
+            transcoder: self.clone(),
+            channel,
+        }
+    }
+
+    /// The binding of the request and the values of its path variables.
+    fn route(&self, method: &Method, path: &str) -> Option<(usize, Vec<(String, String)>)> {
+        self.bindings
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.method == method)
+            .find_map(|(i, b)| b.template.matches(path).map(|vars| (i, vars)))
+    }
+
+    /// The JSON keys of the field `path` of `message`, like `shelf.theme`.
+    fn json_path(&self, message: &str, path: &str) -> Option<Vec<String>> {
+        let mut message = self.types.message(message).ok()?;
+        let mut keys = Vec::new();
+        let mut fields = path.split('.').peekable();
+        while let Some(name) = fields.next() {
+            let field = json::field(message, name)?;
+            keys.push(json::json_name(field));
+            if fields.peek().is_some() {
+                message = self.types.message(field.type_name()).ok()?;
+            }
+        }
+        Some(keys)
+    }
+
+    /// The encoded request message of `binding`, from the body, path variables and query
+    /// parameters.
+    async fn input(&self, binding: &Binding, vars: Vec<(String, String)>, req: Request) -> Result<Vec<u8>, Rejection> {
+        let message = self.message(binding, vars, req).await?;
+        let input = json::encode(&self.types, &binding.rpc.input, &message).map_err(invalid)?;
+        if input.len() > self.message_limit {
+            return Err(too_large(format!("request message exceeds the limit of {} bytes", self.message_limit)));
+        }
+        Ok(input)
+    }
+
+    /// The request message of `binding` in JSON.
+    async fn message(&self, binding: &Binding, vars: Vec<(String, String)>, req: Request) -> Result<Value, Rejection> {
+        let input = &binding.rpc.input;
+        let uri = req.uri().clone();
+        let mut message = Value::Object(Map::new());
+        if !binding.body.is_empty() {
+            // Read like REST handlers do, within the body limit of the server.
+            let bytes = Bytes::from_request(req, &()).await.map_err(|err| match err.status() {
+                StatusCode::PAYLOAD_TOO_LARGE => too_large(err.body_text()),
+                _ => invalid(err.body_text()),
+            })?;
+            if !bytes.is_empty() {
+                let value: Value = serde_json::from_slice(&bytes).map_err(invalid)?;
+                match binding.body.as_str() {
+                    "*" => message = value,
+                    field => {
+                        let keys = self.json_path(input, field).ok_or_else(|| invalid(format!("unknown body field {}", field)))?;
+                        set(&mut message, &keys, value, false);
+                    }
+                }
+            }
+        }
+        // Fields not bound to the body or the path can be set in the query, unknown ones are ignored.
+        if binding.body != "*" {
+            let query = uri.query().unwrap_or_default();
+            for (name, value) in form_urlencoded::parse(query.as_bytes()) {
+                if let Some(keys) = self.json_path(input, &name) {
+                    set(&mut message, &keys, Value::String(value.into_owned()), true);
+                }
+            }
+        }
+        for (field, value) in vars {
+            let keys = self.json_path(input, &field).ok_or_else(|| invalid(format!("unknown path field {}", field)))?;
+            set(&mut message, &keys, Value::String(value), false);
+        }
+        Ok(message)
+    }
+
+    async fn transcode<T>(&self, index: usize, vars: Vec<(String, String)>, req: Request, channel: T) -> Result<Response, Status>
+        where
+            T: GrpcService<BoxBody> + Send,
+            T::Error: Into<StdError>,
+            T::ResponseBody: Body<Data=Bytes> + Send + 'static,
+            <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+    {
+        let binding = &self.bindings[index];
+        let mut headers = HeaderMap::new();
+        for name in FORWARDED {
+            if let Some(value) = req.headers().get(name) {
+                headers.insert(name, value.clone());
+            }
+        }
+        let input = match self.input(binding, vars, req).await {
+            Ok(input) => input,
+            Err(rejection) => return Ok(rejection.into_response()),
+        };
+
+        let mut grpc = tonic::client::Grpc::new(channel);
+        grpc.ready().await.map_err(|e| tonic::Status::unavailable(e.into().to_string()))?;
+        let req = tonic::Request::from_parts(MetadataMap::from_headers(headers), Default::default(), Bytes::from(input));
+        let res = grpc.unary(req, binding.rpc.path.clone(), RawCodec).await?;
+
+        let mut output = json::decode(&self.types, &binding.rpc.output, res.get_ref())
+            .map_err(|e| Status::new(REASON, &e))?;
+        if !binding.response_body.is_empty() {
+            let keys = self
+                .json_path(&binding.rpc.output, &binding.response_body)
+                .ok_or_else(|| Status::new(REASON, &format!("unknown response field {}", binding.response_body)))?;
+            output = keys.iter().try_fold(output, |mut value, key| value.get_mut(key).map(Value::take)).unwrap_or_default();
+        }
+        Ok(Success(output).into_response())
+    }
+}
+
+/// Set `keys` of `target` to `value`, making it an array of both when `append`ing to a value.
+fn set(target: &mut Value, keys: &[String], value: Value, append: bool) {
+    let Some((last, parents)) = keys.split_last() else {
+        return;
+    };
+    let mut target = target;
+    for key in parents {
+        if !target.is_object() {
+            *target = Value::Object(Map::new());
+        }
+        target = target
+            .as_object_mut()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let object = target.as_object_mut().unwrap();
+    match (object.get_mut(last), append) {
+        (Some(Value::Array(items)), true) => items.push(value),
+        (Some(existing), true) => {
+            let first = existing.take();
+            *existing = Value::Array(vec![first, value]);
+        }
+        _ => {
+            object.insert(last.clone(), value);
+        }
+    }
+}
+
+/// Passes encoded messages through as they are.
+#[derive(Debug, Clone, Copy, Default)]
+struct RawCodec;
+
+impl Codec for RawCodec {
+    type Encode = Bytes;
+    type Decode = Bytes;
+    type Encoder = RawCodec;
+    type Decoder = RawCodec;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        RawCodec
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        RawCodec
+    }
+}
+
+impl Encoder for RawCodec {
+    type Item = Bytes;
+    type Error = tonic::Status;
+
+    fn encode(&mut self, item: Bytes, dst: &mut EncodeBuf<'_>) -> Result<(), tonic::Status> {
+        dst.put(item);
+        Ok(())
+    }
+}
+
+impl Decoder for RawCodec {
+    type Item = Bytes;
+    type Error = tonic::Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Bytes>, tonic::Status> {
+        Ok(Some(src.copy_to_bytes(src.remaining())))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TranscodingLayer<T> {
+    transcoder: Transcoder,
+    channel: T,
+}
+
+impl<S, T: Clone> Layer<S> for TranscodingLayer<T> {
+    type Service = Transcoding<S, T>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Transcoding {
+            inner,
+            transcoder: self.transcoder.clone(),
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Transcoding<S, T> {
+    inner: S,
+    transcoder: Transcoder,
+    channel: T,
+}
+
+impl<S, T> Service<Request> for Transcoding<S, T>
+    where
+        S: Service<Request, Response=Response, Error=Infallible> + Clone + Send + 'static,
+        S::Future: Send + 'static,
+        T: GrpcService<BoxBody> + Clone + Send + 'static,
+        T::Error: Into<StdError>,
+        T::Future: Send,
+        T::ResponseBody: Body<Data=Bytes> + Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Response, Infallible>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let Some((index, vars)) = self.transcoder.route(req.method(), req.uri().path()) else {
+            // See bamboo-tower's `MyMiddleware` for why the ready service is swapped out.
+            let clone = self.inner.clone();
+            let mut inner = std::mem::replace(&mut self.inner, clone);
+            return Box::pin(inner.call(req));
+        };
+        let (transcoder, channel) = (self.transcoder.clone(), self.channel.clone());
+        Box::pin(async move {
+            match transcoder.transcode(index, vars, req, channel).await {
+                Ok(res) => Ok(res),
+                Err(status) => Ok(status.into_response()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashMap;
+
+    use axum::{body::Body as AxumBody, routing::get, Router};
+    use http_body_util::BodyExt;
+    use prost_types::{
+        field_descriptor_proto::{Label, Type},
+        DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FieldDescriptorProto, MessageOptions,
+    };
+    use serde_json::json;
+    use tonic::{codec::ProstCodec, server::UnaryService};
+    use tower::ServiceExt;
+
+    use super::*;
+    use descriptor::{FileDescriptorProto, MethodDescriptorProto, MethodOptions, ServiceDescriptorProto};
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+    pub(crate) enum Genre {
+        Unspecified = 0,
+        Fiction = 1,
+        Poetry = 2,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(crate) struct Shelf {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub theme: String,
+        #[prost(int64, tag = "3")]
+        pub books: i64,
+        #[prost(string, repeated, tag = "4")]
+        pub tags: Vec<String>,
+        #[prost(enumeration = "Genre", tag = "5")]
+        pub genre: i32,
+        #[prost(map = "string, int32", tag = "6")]
+        pub labels: HashMap<String, i32>,
+        #[prost(bytes = "vec", tag = "7")]
+        pub cover: Vec<u8>,
+        #[prost(double, tag = "8")]
+        pub rating: f64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct GetShelfRequest {
+        #[prost(string, tag = "1")]
+        name: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct CreateShelfRequest {
+        #[prost(string, tag = "1")]
+        parent: String,
+        #[prost(message, optional, tag = "2")]
+        shelf: Option<Shelf>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct ListShelvesRequest {
+        #[prost(int32, tag = "1")]
+        page_size: i32,
+        #[prost(enumeration = "Genre", repeated, tag = "2")]
+        genres: Vec<i32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct ListShelvesResponse {
+        #[prost(message, repeated, tag = "1")]
+        shelves: Vec<Shelf>,
+    }
+
+    fn field(name: &str, number: i32, r#type: Type, label: Label, type_name: &str) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(label as i32),
+            r#type: Some(r#type as i32),
+            type_name: (!type_name.is_empty()).then(|| type_name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn message(name: &str, field: Vec<FieldDescriptorProto>) -> DescriptorProto {
+        DescriptorProto {
+            name: Some(name.to_string()),
+            field,
+            ..Default::default()
+        }
+    }
+
+    fn method(name: &str, input: &str, output: &str, http: HttpRule) -> MethodDescriptorProto {
+        MethodDescriptorProto {
+            name: Some(name.to_string()),
+            input_type: Some(format!(".bamboo.library.{}", input)),
+            output_type: Some(format!(".bamboo.library.{}", output)),
+            options: Some(MethodOptions { http: Some(http) }),
+            client_streaming: None,
+            server_streaming: None,
+        }
+    }
+
+    fn rule(pattern: Pattern, body: &str) -> HttpRule {
+        HttpRule {
+            pattern: Some(pattern),
+            body: body.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// The encoded descriptors of the messages above, and of a `Library` service annotated
+    /// like `bamboo/library.proto` would be.
+    pub(crate) fn file_descriptor_set() -> Vec<u8> {
+        use Label::{Optional, Repeated};
+
+        let mut shelf = message("Shelf", vec![
+            field("name", 1, Type::String, Optional, ""),
+            field("theme", 2, Type::String, Optional, ""),
+            field("books", 3, Type::Int64, Optional, ""),
+            field("tags", 4, Type::String, Repeated, ""),
+            field("genre", 5, Type::Enum, Optional, ".bamboo.library.Genre"),
+            field("labels", 6, Type::Message, Repeated, ".bamboo.library.Shelf.LabelsEntry"),
+            field("cover", 7, Type::Bytes, Optional, ""),
+            field("rating", 8, Type::Double, Optional, ""),
+        ]);
+        let mut labels = message("LabelsEntry", vec![
+            field("key", 1, Type::String, Optional, ""),
+            field("value", 2, Type::Int32, Optional, ""),
+        ]);
+        labels.options = Some(MessageOptions { map_entry: Some(true), ..Default::default() });
+        shelf.nested_type.push(labels);
+        let genre = EnumDescriptorProto {
+            name: Some("Genre".to_string()),
+            value: ["GENRE_UNSPECIFIED", "FICTION", "POETRY"]
+                .iter()
+                .zip(0..)
+                .map(|(name, number)| EnumValueDescriptorProto {
+                    name: Some(name.to_string()),
+                    number: Some(number),
+                    options: None,
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        let mut create = rule(Pattern::Post("/v1/{parent=libraries/*}/shelves".to_string()), "shelf");
+        create.additional_bindings.push(rule(Pattern::Post("/v1/shelves:create".to_string()), "*"));
+        let mut list = rule(Pattern::Get("/v1/shelves".to_string()), "");
+        list.response_body = "shelves".to_string();
+        let file = FileDescriptorProto {
+            name: Some("bamboo/library.proto".to_string()),
+            package: Some("bamboo.library".to_string()),
+            message_type: vec![
+                shelf,
+                message("GetShelfRequest", vec![field("name", 1, Type::String, Optional, "")]),
+                message("CreateShelfRequest", vec![
+                    field("parent", 1, Type::String, Optional, ""),
+                    field("shelf", 2, Type::Message, Optional, ".bamboo.library.Shelf"),
+                ]),
+                message("ListShelvesRequest", vec![
+                    field("page_size", 1, Type::Int32, Optional, ""),
+                    field("genres", 2, Type::Enum, Repeated, ".bamboo.library.Genre"),
+                ]),
+                message("ListShelvesResponse", vec![
+                    field("shelves", 1, Type::Message, Repeated, ".bamboo.library.Shelf"),
+                ]),
+                message("Event", vec![
+                    field("at", 1, Type::Message, Optional, ".google.protobuf.Timestamp"),
+                    field("ttl", 2, Type::Message, Optional, ".google.protobuf.Duration"),
+                    field("limit", 3, Type::Message, Optional, ".google.protobuf.Int32Value"),
+                    field("note", 4, Type::Message, Optional, ".google.protobuf.StringValue"),
+                    field("attrs", 5, Type::Message, Optional, ".google.protobuf.Struct"),
+                    field("extra", 6, Type::Message, Optional, ".google.protobuf.Value"),
+                    field("update_mask", 7, Type::Message, Optional, ".google.protobuf.FieldMask"),
+                ]),
+            ],
+            enum_type: vec![genre],
+            service: vec![ServiceDescriptorProto {
+                name: Some("Library".to_string()),
+                method: vec![
+                    method("GetShelf", "GetShelfRequest", "Shelf", rule(Pattern::Get("/v1/{name=shelves/*}".to_string()), "")),
+                    method("CreateShelf", "CreateShelfRequest", "Shelf", create),
+                    method("ListShelves", "ListShelvesRequest", "ListShelvesResponse", list),
+                ],
+            }],
+        };
+        FileDescriptorSet { file: vec![file] }.encode_to_vec()
+    }
+
+    pub(crate) fn types() -> Types {
+        let mut types = Types::default();
+        for file in FileDescriptorSet::decode(file_descriptor_set().as_slice()).unwrap().file {
+            types.add_file(&file);
+        }
+        types
+    }
+
+    struct Unary<F>(F);
+
+    impl<Req, Res, F> UnaryService<Req> for Unary<F>
+        where F: FnMut(tonic::Request<Req>) -> Result<Res, tonic::Status>
+    {
+        type Response = Res;
+        type Future = std::future::Ready<Result<tonic::Response<Res>, tonic::Status>>;
+
+        fn call(&mut self, request: tonic::Request<Req>) -> Self::Future {
+            std::future::ready((self.0)(request).map(tonic::Response::new))
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn get_shelf(req: tonic::Request<GetShelfRequest>) -> Result<Shelf, tonic::Status> {
+        let request_id = req.metadata().get(REQUEST_ID).map(|v| v.to_str().unwrap().to_string());
+        match req.into_inner().name.as_str() {
+            "shelves/missing" => Err(tonic::Status::not_found("no shelf shelves/missing")),
+            name => Ok(Shelf {
+                name: name.to_string(),
+                theme: request_id.unwrap_or_default(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn create_shelf(req: tonic::Request<CreateShelfRequest>) -> Result<Shelf, tonic::Status> {
+        let req = req.into_inner();
+        let shelf = req.shelf.unwrap_or_default();
+        Ok(Shelf {
+            name: format!("{}/shelves/{}", req.parent, shelf.theme.to_lowercase()),
+            ..shelf
+        })
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn list_shelves(req: tonic::Request<ListShelvesRequest>) -> Result<ListShelvesResponse, tonic::Status> {
+        let req = req.into_inner();
+        let shelves = req
+            .genres
+            .iter()
+            .take(req.page_size as usize)
+            .map(|genre| Shelf { genre: *genre, ..Default::default() })
+            .collect();
+        Ok(ListShelvesResponse { shelves })
+    }
+
+    /// The `Library` service, as tonic would generate it.
+    #[derive(Clone)]
+    struct Library;
+
+    impl Service<http::Request<BoxBody>> for Library {
+        type Response = http::Response<BoxBody>;
+        type Error = Infallible;
+        type Future = BoxFuture<'static, Result<Self::Response, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: http::Request<BoxBody>) -> Self::Future {
+            Box::pin(async move {
+                Ok(match req.uri().path() {
+                    "/bamboo.library.Library/GetShelf" => {
+                        tonic::server::Grpc::new(ProstCodec::default()).unary(Unary(get_shelf), req).await
+                    }
+                    "/bamboo.library.Library/CreateShelf" => {
+                        tonic::server::Grpc::new(ProstCodec::default()).unary(Unary(create_shelf), req).await
+                    }
+                    "/bamboo.library.Library/ListShelves" => {
+                        tonic::server::Grpc::new(ProstCodec::default()).unary(Unary(list_shelves), req).await
+                    }
+                    _ => tonic::Status::unimplemented("library").into_http(),
+                })
+            })
+        }
+    }
+
+    async fn send(app: &Router, method: Method, uri: &str, body: &str) -> Value {
+        let req = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(REQUEST_ID, "req-1")
+            .body(AxumBody::from(body.to_string()))
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&body).unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned()))
+    }
+
+    fn success(data: Value) -> Value {
+        json!({"success": true, "code": "OK", "message": "OK", "data": data})
+    }
+
+    async fn rendered(status: Status) -> Value {
+        let body = status.into_response().into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn valid_transcoding() {
+        let transcoder = Transcoder::from_file_descriptor_sets(&[&file_descriptor_set()]).unwrap();
+        let app = Router::new()
+            .route("/hello", get(|| async { "hello" }))
+            .layer(transcoder.layer(Library));
+
+        let res = send(&app, Method::GET, "/v1/shelves/1", "").await;
+        assert_eq!(res, success(json!({"name": "shelves/1", "theme": "req-1"})));
+
+        let body = r#"{"theme": "Poetry", "books": 3, "labels": {"floor": 2}}"#;
+        let res = send(&app, Method::POST, "/v1/libraries/main/shelves", body).await;
+        let shelf = json!({"name": "libraries/main/shelves/poetry", "theme": "Poetry", "books": "3", "labels": {"floor": 2}});
+        assert_eq!(res, success(shelf.clone()));
+        let body = r#"{"parent": "libraries/main", "shelf": {"theme": "Poetry", "books": "3", "labels": {"floor": 2}}}"#;
+        assert_eq!(send(&app, Method::POST, "/v1/shelves:create", body).await, success(shelf));
+
+        let res = send(&app, Method::GET, "/v1/shelves?pageSize=2&genres=FICTION&genres=POETRY&genres=FICTION&other=1", "").await;
+        assert_eq!(res, success(json!([{"genre": "FICTION"}, {"genre": "POETRY"}])));
+
+        let res = send(&app, Method::GET, "/v1/shelves/missing", "").await;
+        assert_eq!(res, rendered(tonic::Status::not_found("no shelf shelves/missing").into()).await);
+        let res = send(&app, Method::POST, "/v1/libraries/main/shelves", "{").await;
+        assert_eq!(res["success"], false);
+        assert_eq!(res["code"], REASON);
+        let res = send(&app, Method::POST, "/v1/libraries/main/shelves", r#"{"pages": 1}"#).await;
+        assert_eq!(res["code"], REASON);
+
+        assert_eq!(send(&app, Method::GET, "/hello", "").await, "hello");
+        assert_eq!(send(&app, Method::DELETE, "/v1/shelves/1", "").await, "");
+    }
+
+    #[tokio::test]
+    async fn invalid_transcoding() {
+        let grpc = Grpc {
+            max_decoding_message_size: Some(32),
+            ..Default::default()
+        };
+        let transcoder = Transcoder::from_file_descriptor_sets(&[&file_descriptor_set()]).unwrap().with_limits(&grpc);
+        let app = Router::new()
+            .layer(transcoder.layer(Library))
+            .layer(axum::extract::DefaultBodyLimit::max(64));
+        let post = |body: String| {
+            let req = http::Request::post("/v1/libraries/main/shelves").body(AxumBody::from(body)).unwrap();
+            let app = app.clone();
+            async move {
+                let res = app.oneshot(req).await.unwrap();
+                let status = res.extensions().get::<Status>().map(|s| s.reason.clone());
+                (res.status(), status)
+            }
+        };
+
+        let rejected = |code, reason: &str| (code, Some(reason.to_string()));
+        assert_eq!(post("{".to_string()).await, rejected(StatusCode::BAD_REQUEST, REASON));
+        assert_eq!(post(r#"{"pages": 1}"#.to_string()).await, rejected(StatusCode::BAD_REQUEST, REASON));
+        // Over the message limit of the gRPC server, then over the body limit of the REST one.
+        let body = format!(r#"{{"theme": "{}"}}"#, "a".repeat(16));
+        assert_eq!(post(body).await, rejected(StatusCode::PAYLOAD_TOO_LARGE, "PayloadTooLarge"));
+        let body = format!(r#"{{"theme": "{}"}}"#, "a".repeat(64));
+        assert_eq!(post(body).await, rejected(StatusCode::PAYLOAD_TOO_LARGE, "PayloadTooLarge"));
+        assert_eq!(post(r#"{"theme": "a"}"#.to_string()).await.0, StatusCode::OK);
+    }
+}
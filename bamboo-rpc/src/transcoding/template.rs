@@ -0,0 +1,162 @@
+//! Path templates of `google.api.http` rules, like `/v1/{name=shelves/*}/books:batchGet`.
+
+use percent_encoding::percent_decode_str;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    /// `*`, one segment.
+    Single,
+    /// `**`, the remaining segments.
+    Multi,
+}
+
+/// A variable bound to the segments `start..end` of the path, to the end when `end` is `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Variable {
+    field: String,
+    start: usize,
+    end: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Template {
+    segments: Vec<Segment>,
+    variables: Vec<Variable>,
+    verb: Option<String>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let invalid = |why: &str| format!("invalid path template {}: {}", template, why);
+        let path = template.strip_prefix('/').ok_or_else(|| invalid("missing leading /"))?;
+        // The verb follows the last `:` outside of variables.
+        let (path, verb) = match path.rfind(':') {
+            Some(i) if !path[i..].contains('}') => (&path[..i], Some(path[i + 1..].to_string())),
+            _ => (path, None),
+        };
+        let mut parsed = Template {
+            segments: Vec::new(),
+            variables: Vec::new(),
+            verb,
+        };
+        let mut rest = path;
+        while !rest.is_empty() {
+            if let Some(variable) = rest.strip_prefix('{') {
+                let close = variable.find('}').ok_or_else(|| invalid("unclosed variable"))?;
+                let (field, segments) = match variable[..close].split_once('=') {
+                    Some((field, segments)) => (field, segments),
+                    None => (&variable[..close], "*"),
+                };
+                let start = parsed.segments.len();
+                for segment in segments.split('/') {
+                    parsed.push(segment).map_err(|e| invalid(&e))?;
+                }
+                let end = match parsed.segments.last() {
+                    Some(Segment::Multi) => None,
+                    _ => Some(parsed.segments.len()),
+                };
+                parsed.variables.push(Variable { field: field.to_string(), start, end });
+                rest = &variable[close + 1..];
+            } else {
+                let end = rest.find('/').unwrap_or(rest.len());
+                parsed.push(&rest[..end]).map_err(|e| invalid(&e))?;
+                rest = &rest[end..];
+            }
+            rest = match rest.strip_prefix('/') {
+                Some(next) if !next.is_empty() => next,
+                Some(_) => return Err(invalid("trailing /")),
+                None if rest.is_empty() => rest,
+                None => return Err(invalid("variables must span whole segments")),
+            };
+        }
+        Ok(parsed)
+    }
+
+    fn push(&mut self, segment: &str) -> Result<(), String> {
+        if self.segments.last() == Some(&Segment::Multi) {
+            return Err("** must be the last segment".to_string());
+        }
+        self.segments.push(match segment {
+            "" => return Err("empty segment".to_string()),
+            "*" => Segment::Single,
+            "**" => Segment::Multi,
+            literal if literal.contains(['{', '}', '*']) => return Err(format!("invalid segment {}", literal)),
+            literal => Segment::Literal(literal.to_string()),
+        });
+        Ok(())
+    }
+
+    /// The field paths and percent-decoded values of the variables, if `path` matches.
+    pub fn matches(&self, path: &str) -> Option<Vec<(String, String)>> {
+        let path = path.strip_prefix('/')?;
+        let path = match &self.verb {
+            Some(verb) => path.strip_suffix(verb.as_str())?.strip_suffix(':')?,
+            None => path,
+        };
+        let parts: Vec<&str> = if path.is_empty() { Vec::new() } else { path.split('/').collect() };
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Multi => break,
+                Segment::Single => {
+                    if parts.get(i).is_none_or(|part| part.is_empty()) {
+                        return None;
+                    }
+                }
+                Segment::Literal(literal) => {
+                    if parts.get(i) != Some(&literal.as_str()) {
+                        return None;
+                    }
+                }
+            }
+        }
+        if self.segments.last() != Some(&Segment::Multi) && parts.len() != self.segments.len() {
+            return None;
+        }
+        let bindings = self
+            .variables
+            .iter()
+            .map(|variable| {
+                let end = variable.end.unwrap_or(parts.len()).min(parts.len());
+                let value = parts[variable.start.min(end)..end].join("/");
+                (variable.field.clone(), percent_decode_str(&value).decode_utf8_lossy().into_owned())
+            })
+            .collect();
+        Some(bindings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bindings(pairs: &[(&str, &str)]) -> Option<Vec<(String, String)>> {
+        Some(pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+    }
+
+    #[test]
+    fn valid_template() {
+        let get = Template::parse("/v1/{name=shelves/*}").unwrap();
+        assert_eq!(get.matches("/v1/shelves/1"), bindings(&[("name", "shelves/1")]));
+        assert_eq!(get.matches("/v1/shelves/a%20b"), bindings(&[("name", "shelves/a b")]));
+        assert_eq!(get.matches("/v1/shelves"), None);
+        assert_eq!(get.matches("/v1/shelves/1/books"), None);
+        assert_eq!(get.matches("/v1/books/1"), None);
+
+        let nested = Template::parse("/v1/shelves/{shelf.id}/books/{book}").unwrap();
+        assert_eq!(nested.matches("/v1/shelves/7/books/9"), bindings(&[("shelf.id", "7"), ("book", "9")]));
+
+        let verb = Template::parse("/v1/{name=operations/**}:cancel").unwrap();
+        assert_eq!(verb.matches("/v1/operations/a/b:cancel"), bindings(&[("name", "operations/a/b")]));
+        assert_eq!(verb.matches("/v1/operations/a/b"), None);
+
+        let literal = Template::parse("/v1/shelves:batchGet").unwrap();
+        assert_eq!(literal.matches("/v1/shelves:batchGet"), bindings(&[]));
+        assert_eq!(literal.matches("/v1/shelves"), None);
+
+        assert!(Template::parse("v1/shelves").is_err());
+        assert!(Template::parse("/v1/{name").is_err());
+        assert!(Template::parse("/v1/**/shelves").is_err());
+        assert!(Template::parse("/v1/x{name}").is_err());
+    }
+}
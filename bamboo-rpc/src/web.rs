@@ -0,0 +1,386 @@
+//! gRPC-Web for browser clients: `application/grpc-web` calls are served as gRPC, with their
+//! trailers sent as the last frame of the body, base64 encoded for `application/grpc-web-text`.
+
+use std::{
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::{BufMut, Bytes, BytesMut};
+use http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request, Response};
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use hyper::body::{Body, Frame};
+use serde::{Deserialize, Serialize};
+use tonic::{body::BoxBody, Status};
+use tower::layer::util::Stack;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tower_layer::Layer;
+use tower_service::Service;
+
+type BoxFuture<'a, T> = Pin<Box<dyn std::future::Future<Output=T> + Send + 'a>>;
+
+const GRPC_WEB: &str = "application/grpc-web";
+const GRPC_WEB_TEXT: &str = "application/grpc-web-text";
+
+/// Flag of the frame carrying the trailers, after the length-prefixed messages.
+const TRAILERS_FLAG: u8 = 0x80;
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Web {
+    /// Origins browsers may call from, `*` allows any. Same-origin calls only when empty.
+    #[serde(default)]
+    pub allow_origins: Vec<String>,
+}
+
+impl Web {
+    /// Answer CORS requests and translate gRPC-Web calls, plain gRPC calls pass through.
+    /// `application/grpc-web-text` bodies are buffered up to the base64 length of a message
+    /// of `max_message_size`.
+    pub(crate) fn layer(&self, max_message_size: usize) -> Stack<GrpcWebLayer, CorsLayer> {
+        let max_text_size = (max_message_size + 5).div_ceil(3) * 4;
+        Stack::new(GrpcWebLayer { max_text_size }, self.cors())
+    }
+
+    fn cors(&self) -> CorsLayer {
+        let layer = CorsLayer::new()
+            .allow_methods([Method::POST])
+            .allow_headers(Any)
+            .expose_headers([
+                HeaderName::from_static("grpc-status"),
+                HeaderName::from_static("grpc-message"),
+                HeaderName::from_static("grpc-status-details-bin"),
+            ]);
+        if self.allow_origins.iter().any(|o| o == "*") {
+            return layer.allow_origin(Any);
+        }
+        let origins: Vec<HeaderValue> = self
+            .allow_origins
+            .iter()
+            .filter_map(|o| match o.parse() {
+                Ok(o) => Some(o),
+                Err(_) => {
+                    log::warn!("grpc-web: ignoring invalid origin {:?}", o);
+                    None
+                }
+            })
+            .collect();
+        layer.allow_origin(AllowOrigin::list(origins))
+    }
+}
+
+/// How the messages of a gRPC-Web call are encoded in the body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Binary,
+    Text,
+}
+
+impl Mode {
+    fn of(headers: &HeaderMap) -> Option<Self> {
+        let content_type = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+        if content_type.starts_with(GRPC_WEB_TEXT) {
+            Some(Mode::Text)
+        } else if content_type.starts_with(GRPC_WEB) {
+            Some(Mode::Binary)
+        } else {
+            None
+        }
+    }
+
+    fn content_type(self) -> HeaderValue {
+        match self {
+            Mode::Binary => HeaderValue::from_static("application/grpc-web+proto"),
+            Mode::Text => HeaderValue::from_static("application/grpc-web-text+proto"),
+        }
+    }
+
+    /// The gRPC request of a gRPC-Web one.
+    async fn request(self, req: Request<BoxBody>, max_text_size: usize) -> Result<Request<BoxBody>, Status> {
+        let (mut parts, body) = req.into_parts();
+        parts.headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+        parts.headers.remove(header::CONTENT_LENGTH);
+        let body = match self {
+            Mode::Binary => body,
+            Mode::Text => {
+                let text = Limited::new(body, max_text_size).collect().await.map_err(|err| {
+                    match err.downcast::<Status>() {
+                        Ok(status) => *status,
+                        Err(err) if err.is::<LengthLimitError>() => Status::out_of_range("grpc-web-text: request message too large"),
+                        Err(err) => Status::from_error(err),
+                    }
+                })?.to_bytes();
+                let bytes = decode_text(&text).map_err(|e| Status::invalid_argument(format!("grpc-web-text: {}", e)))?;
+                tonic::body::boxed(Full::new(Bytes::from(bytes)))
+            }
+        };
+        Ok(Request::from_parts(parts, body))
+    }
+
+    /// The gRPC-Web response of a gRPC one.
+    fn response(self, res: Response<BoxBody>) -> Response<BoxBody> {
+        let (mut parts, body) = res.into_parts();
+        parts.headers.insert(header::CONTENT_TYPE, self.content_type());
+        let body = WebBody { inner: body, mode: self, done: false };
+        Response::from_parts(parts, tonic::body::boxed(body))
+    }
+
+    fn encode(self, bytes: Bytes) -> Bytes {
+        match self {
+            Mode::Binary => bytes,
+            Mode::Text => Bytes::from(STANDARD.encode(bytes)),
+        }
+    }
+}
+
+/// Decode base64 `text`, which clients may send as several padded chunks.
+fn decode_text(mut text: &[u8]) -> Result<Vec<u8>, base64::DecodeError> {
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    while !text.is_empty() {
+        let end = match text.iter().position(|b| *b == b'=') {
+            Some(pad) => pad + text[pad..].iter().take_while(|b| **b == b'=').count(),
+            None => text.len(),
+        };
+        STANDARD.decode_vec(&text[..end], &mut bytes)?;
+        text = &text[end..];
+    }
+    Ok(bytes)
+}
+
+/// The trailers frame of `trailers`, one `name:value` line each.
+fn trailers_frame(trailers: &HeaderMap) -> Bytes {
+    let mut block = BytesMut::new();
+    for (name, value) in trailers {
+        block.put_slice(name.as_str().as_bytes());
+        block.put_u8(b':');
+        block.put_slice(value.as_bytes());
+        block.put_slice(b"\r\n");
+    }
+    let mut frame = BytesMut::with_capacity(5 + block.len());
+    frame.put_u8(TRAILERS_FLAG);
+    frame.put_u32(block.len() as u32);
+    frame.put(block);
+    frame.freeze()
+}
+
+/// Body of a gRPC-Web response, the trailers of the gRPC one moved into a last data frame.
+struct WebBody {
+    inner: BoxBody,
+    mode: Mode,
+    done: bool,
+}
+
+impl Body for WebBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Status>>> {
+        let this = self.get_mut();
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+            let frame = match ready!(Pin::new(&mut this.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => frame,
+                other => return Poll::Ready(other),
+            };
+            let data = match frame.into_data() {
+                Ok(data) => data,
+                Err(frame) => match frame.into_trailers() {
+                    Ok(trailers) => {
+                        this.done = true;
+                        trailers_frame(&trailers)
+                    }
+                    Err(_) => continue,
+                },
+            };
+            return Poll::Ready(Some(Ok(Frame::data(this.mode.encode(data)))));
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.done || self.inner.is_end_stream()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GrpcWebLayer {
+    max_text_size: usize,
+}
+
+impl<S> Layer<S> for GrpcWebLayer {
+    type Service = GrpcWeb<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcWeb { inner, max_text_size: self.max_text_size }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GrpcWeb<S> {
+    inner: S,
+    max_text_size: usize,
+}
+
+impl<S> Service<Request<BoxBody>> for GrpcWeb<S>
+    where
+        S: Service<Request<BoxBody>, Response=Response<BoxBody>> + Clone + Send + 'static,
+        S::Future: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<BoxBody>) -> Self::Future {
+        // See bamboo-tower's `MyMiddleware` for why the ready service is swapped out.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let Some(mode) = Mode::of(req.headers()) else {
+            return Box::pin(inner.call(req));
+        };
+        let max_text_size = self.max_text_size;
+        Box::pin(async move {
+            let req = match mode.request(req, max_text_size).await {
+                Ok(req) => req,
+                Err(status) => return Ok(mode.response(status.into_http())),
+            };
+            let res = inner.call(req).await?;
+            Ok(mode.response(res))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use prost::Message;
+    use tower::ServiceExt;
+    use tonic_health::pb::{health_check_response::ServingStatus, HealthCheckRequest, HealthCheckResponse};
+
+    use super::*;
+    use crate::{testing::TestServer, Config, Grpc, NamedService, Server};
+
+    struct Conf(Grpc);
+
+    impl Config for Conf {
+        fn grpc(&self) -> &Grpc {
+            &self.0
+        }
+    }
+
+    #[derive(Clone)]
+    struct Hello;
+
+    impl NamedService for Hello {
+        const NAME: &'static str = "bamboo.Hello";
+    }
+
+    impl Service<Request<BoxBody>> for Hello {
+        type Response = Response<BoxBody>;
+        type Error = std::convert::Infallible;
+        type Future = std::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: Request<BoxBody>) -> Self::Future {
+            std::future::ready(Ok(Status::unimplemented("hello").into_http()))
+        }
+    }
+
+    /// The length-prefixed frames of `body`, by flag.
+    fn frames(mut body: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        let mut frames = Vec::new();
+        while !body.is_empty() {
+            let len = u32::from_be_bytes(body[1..5].try_into().unwrap()) as usize;
+            frames.push((body[0], body[5..5 + len].to_vec()));
+            body = &body[5 + len..];
+        }
+        frames
+    }
+
+    #[test]
+    fn valid_decode_text() {
+        let text = format!("{}{}", STANDARD.encode("hello"), STANDARD.encode(" web"));
+        assert_eq!(decode_text(text.as_bytes()).unwrap(), b"hello web");
+        assert!(decode_text(b"not base64!").is_err());
+    }
+
+    #[tokio::test]
+    async fn valid_grpc_web() {
+        let grpc = Grpc {
+            web: Some(Web { allow_origins: vec!["https://app.example.com".to_string()] }),
+            ..Default::default()
+        };
+        let test = TestServer::new(&Server::new(Arc::new(Conf(grpc)), Hello)).await.unwrap();
+        let mut channel = test.channel().await.unwrap();
+
+        let message = HealthCheckRequest { service: "bamboo.Hello".to_string() }.encode_to_vec();
+        let mut body = vec![0];
+        body.extend((message.len() as u32).to_be_bytes());
+        body.extend(message);
+
+        for (mode, content_type) in [(Mode::Binary, GRPC_WEB), (Mode::Text, GRPC_WEB_TEXT)] {
+            let payload = match mode {
+                Mode::Binary => Bytes::from(body.clone()),
+                Mode::Text => Bytes::from(STANDARD.encode(&body)),
+            };
+            let req = Request::post("http://in-process/grpc.health.v1.Health/Check")
+                .header(header::CONTENT_TYPE, content_type)
+                .header(header::ORIGIN, "https://app.example.com")
+                .body(tonic::body::boxed(Full::new(payload)))
+                .unwrap();
+            let res = channel.ready().await.unwrap().call(req).await.unwrap();
+            assert_eq!(res.headers()[header::CONTENT_TYPE], mode.content_type());
+            assert_eq!(res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://app.example.com");
+            let bytes = res.into_body().collect().await.unwrap().to_bytes();
+            let bytes = match mode {
+                Mode::Binary => bytes.to_vec(),
+                Mode::Text => decode_text(&bytes).unwrap(),
+            };
+            let frames = frames(&bytes);
+            assert_eq!(frames.len(), 2);
+            let res = HealthCheckResponse::decode(frames[0].1.as_slice()).unwrap();
+            assert_eq!(res.status(), ServingStatus::Serving);
+            assert_eq!(frames[1].0, TRAILERS_FLAG);
+            assert!(String::from_utf8_lossy(&frames[1].1).contains("grpc-status:0\r\n"));
+        }
+
+        let req = Request::options("http://in-process/grpc.health.v1.Health/Check")
+            .header(header::ORIGIN, "https://app.example.com")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type,x-grpc-web")
+            .body(BoxBody::default())
+            .unwrap();
+        let res = channel.ready().await.unwrap().call(req).await.unwrap();
+        assert_eq!(res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://app.example.com");
+        assert_eq!(res.headers()[header::ACCESS_CONTROL_ALLOW_METHODS], "POST");
+        test.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn invalid_grpc_web_text_too_large() {
+        let grpc = Grpc {
+            web: Some(Web::default()),
+            max_decoding_message_size: Some(8),
+            ..Default::default()
+        };
+        let server = Server::new(Arc::new(Conf(grpc)), Hello).limited(|s, _| s, |s, _| s);
+        let test = TestServer::new(&server).await.unwrap();
+        let mut channel = test.channel().await.unwrap();
+
+        let req = Request::post("http://in-process/bamboo.Hello/Call")
+            .header(header::CONTENT_TYPE, GRPC_WEB_TEXT)
+            .body(tonic::body::boxed(Full::new(Bytes::from(STANDARD.encode([0; 64])))))
+            .unwrap();
+        let res = channel.ready().await.unwrap().call(req).await.unwrap();
+        assert_eq!(res.headers()["grpc-status"], (tonic::Code::OutOfRange as i32).to_string());
+        test.shutdown().await;
+    }
+}
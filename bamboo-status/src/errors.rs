@@ -1,17 +1,112 @@
-// This file is @generated by prost-build.
-#[derive(::thiserror::Error, ::serde::Serialize, ::serde::Deserialize)]
-#[allow(clippy::derive_partial_eq_without_eq)]
-#[derive(Clone, PartialEq, ::prost::Message)]
+use std::collections::HashMap;
+
+use bytes::{Buf, BufMut};
+use prost::{
+    encoding::{self, hash_map, int32, message, string, DecodeContext, WireType},
+    DecodeError,
+};
+use serde::{Deserialize, Serialize};
+
+pub use pb::{BadRequest, FieldViolation};
+
+mod pb;
+
+/// The error of bamboo services, encoded as the `bamboo.Status` protobuf message.
+///
+/// Written by hand instead of generated so that [`Internals`] stay out of the message.
+#[derive(::thiserror::Error, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Default)]
 pub struct Status {
-    #[prost(int32, tag = "1")]
     pub code: i32,
-    #[prost(string, tag = "2")]
-    pub reason: ::prost::alloc::string::String,
-    #[prost(string, tag = "3")]
-    pub message: ::prost::alloc::string::String,
-    #[prost(map = "string, string", tag = "4")]
-    pub metadata: ::std::collections::HashMap<
-        ::prost::alloc::string::String,
-        ::prost::alloc::string::String,
-    >,
+    pub reason: String,
+    pub message: String,
+    pub metadata: HashMap<String, String>,
+    /// Field violations of a rejected request, see [`BadRequest`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bad_request: Option<BadRequest>,
+    /// Never encoded nor serialized, so it never reaches clients.
+    #[serde(skip)]
+    pub internals: Internals,
+}
+
+/// Internal details of a [`Status`], for server logs and debug responses only.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Internals {
+    /// Cause chain, outermost first.
+    pub causes: Vec<String>,
+    /// Backtrace captured where the error was converted.
+    pub backtrace: String,
+}
+
+// Tags 5 and 6 are reserved.
+impl prost::Message for Status {
+    fn encode_raw(&self, buf: &mut impl BufMut) {
+        if self.code != 0 {
+            int32::encode(1, &self.code, buf);
+        }
+        if !self.reason.is_empty() {
+            string::encode(2, &self.reason, buf);
+        }
+        if !self.message.is_empty() {
+            string::encode(3, &self.message, buf);
+        }
+        hash_map::encode(string::encode, string::encoded_len, string::encode, string::encoded_len, 4, &self.metadata, buf);
+        if let Some(bad_request) = &self.bad_request {
+            message::encode(7, bad_request, buf);
+        }
+    }
+
+    fn merge_field(&mut self, tag: u32, wire_type: WireType, buf: &mut impl Buf, ctx: DecodeContext) -> Result<(), DecodeError> {
+        match tag {
+            1 => int32::merge(wire_type, &mut self.code, buf, ctx),
+            2 => string::merge(wire_type, &mut self.reason, buf, ctx),
+            3 => string::merge(wire_type, &mut self.message, buf, ctx),
+            4 => hash_map::merge(string::merge, string::merge, &mut self.metadata, buf, ctx),
+            7 => message::merge(wire_type, self.bad_request.get_or_insert_with(Default::default), buf, ctx),
+            _ => encoding::skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut len = hash_map::encoded_len(string::encoded_len, string::encoded_len, 4, &self.metadata);
+        if self.code != 0 {
+            len += int32::encoded_len(1, &self.code);
+        }
+        if !self.reason.is_empty() {
+            len += string::encoded_len(2, &self.reason);
+        }
+        if !self.message.is_empty() {
+            len += string::encoded_len(3, &self.message);
+        }
+        if let Some(bad_request) = &self.bad_request {
+            len += message::encoded_len(7, bad_request);
+        }
+        len
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+
+    use super::*;
+
+    #[test]
+    fn test_internals_not_encoded() {
+        let mut s = Status::new("UserNotFound", "no such user");
+        s.metadata.insert("id".to_string(), "7".to_string());
+        s.bad_request = Some(BadRequest::default());
+        s.internals.causes = vec!["disk full".to_string()];
+        s.internals.backtrace = "0: main".to_string();
+
+        let buf = s.encode_to_vec();
+        assert_eq!(buf.len(), s.encoded_len());
+        let back = Status::decode(buf.as_slice()).unwrap();
+        assert_eq!(back.internals, Internals::default());
+        assert_eq!(back, Status { internals: Internals::default(), ..s });
+    }
 }
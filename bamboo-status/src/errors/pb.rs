@@ -0,0 +1,33 @@
+//! Detail messages of [`Status`](super::Status), written by hand with prost's derives after
+//! `google/rpc/error_details.proto`.
+
+/// Mirrors `google.rpc.BadRequest`.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BadRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub field_violations: ::prost::alloc::vec::Vec<FieldViolation>,
+}
+/// Mirrors `google.rpc.BadRequest.FieldViolation`, `params` is a bamboo extension.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FieldViolation {
+    /// Path to the field, e.g. `items[0].name`.
+    #[prost(string, tag = "1")]
+    pub field: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub description: ::prost::alloc::string::String,
+    /// The violated rule, e.g. `length` or `email`.
+    #[prost(string, tag = "3")]
+    pub reason: ::prost::alloc::string::String,
+    #[prost(string, tag = "4")]
+    pub localized_message: ::prost::alloc::string::String,
+    /// Parameters of the rule, e.g. `min` and `max` for `length`.
+    #[prost(map = "string, string", tag = "5")]
+    pub params: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ::prost::alloc::string::String,
+    >,
+}
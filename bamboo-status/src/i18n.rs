@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::Status;
+
+/// Message catalog used to localize [`Status`] by its `reason`.
+///
+/// A catalog file looks like:
+///
+/// ```yaml
+/// default: en-US
+/// messages:
+///   en-US:
+///     UserNotFound: "user {id} not found"
+///   zh-CN:
+///     UserNotFound: "用户 {id} 不存在"
+/// ```
+///
+/// `{name}` placeholders are filled from `Status.metadata`. Load it with bamboo-config like any
+/// other configuration, e.g. `bamboo_config::clap::load::<Catalog>("i18n.yaml")`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Catalog {
+    /// Language used when none of the requested ones has a translation.
+    #[serde(default = "default_language")]
+    pub default: String,
+    /// Templates keyed by language tag, then by `Status.reason`.
+    #[serde(default)]
+    pub messages: HashMap<String, HashMap<String, String>>,
+}
+
+fn default_language() -> String {
+    "en-US".to_string()
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Self::new(&default_language())
+    }
+}
+
+impl Catalog {
+    pub fn new(default: &str) -> Self {
+        Self {
+            default: default.to_string(),
+            messages: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, lang: &str, reason: &str, template: &str) -> &mut Self {
+        self.messages
+            .entry(lang.to_string())
+            .or_default()
+            .insert(reason.to_string(), template.to_string());
+        self
+    }
+
+    /// Find the template for `reason`, trying `langs` in order and then the default language.
+    ///
+    /// A language matches exactly first, then by its primary subtag, so `zh` finds `zh-CN`
+    /// and `en-GB` falls back to `en-US`. Tags and reasons compare case-insensitively because
+    /// bamboo-config lowercases keys when loading files.
+    pub fn lookup(&self, langs: &[String], reason: &str) -> Option<&str> {
+        langs
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once(self.default.as_str()))
+            .find_map(|lang| self.lookup_lang(lang, reason))
+    }
+
+    fn lookup_lang(&self, lang: &str, reason: &str) -> Option<&str> {
+        let exact = self
+            .messages
+            .iter()
+            .find(|(tag, _)| tag.eq_ignore_ascii_case(lang))
+            .and_then(|(_, m)| get_ignore_case(m, reason));
+        if exact.is_some() {
+            return exact;
+        }
+        let primary = primary_subtag(lang);
+        self.messages
+            .iter()
+            .filter(|(tag, _)| primary_subtag(tag).eq_ignore_ascii_case(primary))
+            .find_map(|(_, m)| get_ignore_case(m, reason))
+    }
+
+    /// Replace the message of `status` with its translation; keeps it untouched if none exists.
+    pub fn localize(&self, mut status: Status, langs: &[String]) -> Status {
+        if let Some(template) = self.lookup(langs, &status.reason) {
+            status.message = render(template, &status.metadata);
+        }
+        status
+    }
+}
+
+fn get_ignore_case<'a>(m: &'a HashMap<String, String>, key: &str) -> Option<&'a str> {
+    m.get(key)
+        .or_else(|| m.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v))
+        .map(String::as_str)
+}
+
+fn primary_subtag(tag: &str) -> &str {
+    tag.split(['-', '_']).next().unwrap_or(tag)
+}
+
+/// Fill `{name}` placeholders from `params`, unknown placeholders are kept as-is.
+pub fn render(template: &str, params: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        match tail.find('}') {
+            Some(end) => {
+                let key = &tail[1..end];
+                match params.get(key) {
+                    Some(v) => out.push_str(v),
+                    None => out.push_str(&tail[..=end]),
+                }
+                rest = &tail[end + 1..];
+            }
+            None => {
+                out.push_str(tail);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Parse an `Accept-Language` value into tags ordered by preference.
+///
+/// Wildcards and tags with `q=0` are dropped.
+pub fn parse_accept_language(value: &str) -> Vec<String> {
+    let mut langs: Vec<(String, f32)> = value
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let tag = parts.next()?.trim();
+            if tag.is_empty() || tag == "*" {
+                return None;
+            }
+            let q = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (q > 0.0).then(|| (tag.to_string(), q))
+        })
+        .collect();
+    // `sort_by` is stable, so equal weights keep the client's order.
+    langs.sort_by(|a, b| b.1.total_cmp(&a.1));
+    langs.into_iter().map(|(tag, _)| tag).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> Catalog {
+        let mut c = Catalog::new("en-US");
+        c.insert("en-US", "UserNotFound", "user {id} not found")
+            .insert("zh-CN", "UserNotFound", "用户 {id} 不存在")
+            .insert("en-US", "Forbidden", "forbidden");
+        c
+    }
+
+    fn user_not_found() -> Status {
+        let mut s = Status::new("UserNotFound", "");
+        s.metadata.insert("id".to_string(), "42".to_string());
+        s
+    }
+
+    #[test]
+    fn test_parse_accept_language() {
+        let langs = parse_accept_language("en;q=0.8, zh-CN, *;q=0.1, fr;q=0");
+        assert_eq!(langs, vec!["zh-CN".to_string(), "en".to_string()]);
+    }
+
+    #[test]
+    fn test_localize() {
+        let s = catalog().localize(user_not_found(), &parse_accept_language("zh-CN"));
+        assert_eq!(s.message, "用户 42 不存在");
+    }
+
+    #[test]
+    fn test_localize_primary_subtag() {
+        let s = catalog().localize(user_not_found(), &parse_accept_language("zh"));
+        assert_eq!(s.message, "用户 42 不存在");
+    }
+
+    #[test]
+    fn test_localize_fallback() {
+        let c = catalog();
+        let s = c.localize(user_not_found(), &parse_accept_language("fr-FR"));
+        assert_eq!(s.message, "user 42 not found");
+        let s = c.localize(Status::new("Forbidden", ""), &parse_accept_language("zh-CN"));
+        assert_eq!(s.message, "forbidden");
+        let s = c.localize(Status::new("Unknown", "keep"), &[]);
+        assert_eq!(s.message, "keep");
+    }
+
+    #[test]
+    fn test_render_missing_param() {
+        assert_eq!(render("{a} and {b", &HashMap::new()), "{a} and {b");
+    }
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join(format!("bamboo-i18n-{}.yaml", std::process::id()));
+        std::fs::write(
+            &path,
+            "default: zh-CN\nmessages:\n  zh-CN:\n    UserNotFound: \"用户 {id} 不存在\"\n",
+        )
+        .unwrap();
+        let c = bamboo_config::clap::load::<Catalog>(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(c.default, "zh-CN");
+        let s = c.localize(user_not_found(), &[]);
+        assert_eq!(s.message, "用户 42 不存在");
+    }
+}
@@ -1,6 +1,7 @@
 pub mod errors;
 pub mod status;
 pub mod spring;
+pub mod i18n;
 pub fn add(left: usize, right: usize) -> usize {
     left + right
 }
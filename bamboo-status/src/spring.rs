@@ -1,6 +1,8 @@
-use std::fmt;
+use axum::{
+    Json,
+    response::{IntoResponse, Response},
+};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use serde::de::{SeqAccess, Visitor};
 use serde::ser::SerializeStruct;
 use crate::errors::Status;
 
@@ -9,6 +11,10 @@ pub(crate) struct SpringResponse<T> {
     pub code: String,
     pub message: String,
     pub data: T,
+    /// Internal cause chain, only filled in debug mode.
+    pub causes: Vec<String>,
+    /// Internal backtrace, only filled in debug mode.
+    pub backtrace: String,
 }
 
 impl<T> SpringResponse<T> {
@@ -18,8 +24,30 @@ impl<T> SpringResponse<T> {
             code,
             message,
             data,
+            causes: Vec::new(),
+            backtrace: String::new(),
         }
     }
+
+    /// Include the internal cause chain and backtrace of `status`.
+    pub fn with_internals(mut self, status: &Status) -> Self {
+        self.causes = status.internals.causes.clone();
+        self.backtrace = status.internals.backtrace.clone();
+        self
+    }
+}
+
+/// A successful response, rendered in the same envelope as [`Status`] errors:
+/// `{"success": true, "code": "OK", "message": "OK", "data": ...}`.
+#[derive(Debug, Clone, Default)]
+pub struct Success<T>(pub T);
+
+impl<T> IntoResponse for Success<T>
+    where T: Serialize
+{
+    fn into_response(self) -> Response {
+        Json(SpringResponse::new(true, "OK".to_string(), "OK".to_string(), self.0)).into_response()
+    }
 }
 
 impl<T> From<Status> for SpringResponse<T>
@@ -31,6 +59,8 @@ impl<T> From<Status> for SpringResponse<T>
             code: value.reason,
             message: value.message,
             data: T::default(),
+            causes: Vec::new(),
+            backtrace: String::new(),
         }
     }
 }
@@ -39,11 +69,24 @@ impl<T> Serialize for SpringResponse<T>
     where T: Serialize
 {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
-        let mut state = serializer.serialize_struct("SpringResponse", 4)?;
+        let mut len = 4;
+        if !self.causes.is_empty() {
+            len += 1;
+        }
+        if !self.backtrace.is_empty() {
+            len += 1;
+        }
+        let mut state = serializer.serialize_struct("SpringResponse", len)?;
         state.serialize_field("success", &self.success)?;
         state.serialize_field("code", &self.code)?;
         state.serialize_field("message", &self.message)?;
         state.serialize_field("data", &self.data)?;
+        if !self.causes.is_empty() {
+            state.serialize_field("causes", &self.causes)?;
+        }
+        if !self.backtrace.is_empty() {
+            state.serialize_field("backtrace", &self.backtrace)?;
+        }
         state.end()
     }
 }
@@ -51,7 +94,7 @@ impl<T> Serialize for SpringResponse<T>
 impl<'de, T> Deserialize<'de> for SpringResponse<T>
     where T: Deserialize<'de>
 {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+    fn deserialize<D>(_deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
         todo!()
     }
 }
@@ -118,4 +161,24 @@ mod tests {
         let b = serde_json::to_string(&a).unwrap();
         println!(" {}", b);
     }
+
+    #[tokio::test]
+    async fn test_success() {
+        let res = Success(vec![1, 2]).into_response();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, serde_json::json!({"success": true, "code": "OK", "message": "OK", "data": [1, 2]}));
+    }
+
+    #[test]
+    fn test_seri_internals() {
+        let mut s = Status::new("Internal", "oops");
+        s.internals.causes = vec!["oops".to_string(), "disk full".to_string()];
+        let a = SpringResponse::new(false, "Internal".to_string(), "oops".to_string(), "");
+        let b = serde_json::to_value(&a).unwrap();
+        assert!(b.get("causes").is_none());
+        let b = serde_json::to_value(a.with_internals(&s)).unwrap();
+        assert_eq!(b["causes"][1], "disk full");
+        assert!(b.get("backtrace").is_none());
+    }
 }
\ No newline at end of file
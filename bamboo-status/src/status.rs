@@ -1,4 +1,6 @@
+use std::backtrace::{Backtrace, BacktraceStatus};
 use std::fmt::{Display, Formatter};
+use std::sync::Arc;
 
 use axum::{
     http::StatusCode,
@@ -13,10 +15,11 @@ use tonic::{
     transport::Error,
 };
 use tokio::sync::TryLockError;
-use validator::{ValidationError, ValidationErrors};
-use axum::extract::rejection::{FormRejection, JsonRejection};
+use validator::{ValidationError, ValidationErrors, ValidationErrorsKind};
+use axum::extract::multipart::{MultipartError, MultipartRejection};
+use axum::extract::rejection::{FormRejection, JsonRejection, PathRejection, QueryRejection};
 
-use crate::errors::Status;
+use crate::errors::{BadRequest, FieldViolation, Status};
 use crate::spring::SpringResponse;
 
 pub type Result<T, E = Status> = std::result::Result<T, E>;
@@ -30,9 +33,36 @@ impl Status {
             code: StatusCode::INTERNAL_SERVER_ERROR.as_u16() as i32,
             reason: reason.to_string(),
             message: message.to_string(),
-            metadata: Default::default(),
+            ..Default::default()
         }
     }
+
+    pub fn with_code(mut self, code: StatusCode) -> Self {
+        self.code = code.as_u16() as i32;
+        self
+    }
+
+    /// Record `err` and its `source()` chain as the internal cause of this status.
+    pub fn with_cause(mut self, err: &(dyn std::error::Error + 'static)) -> Self {
+        let mut cause = Some(err);
+        while let Some(err) = cause {
+            self.internals.causes.push(err.to_string());
+            cause = err.source();
+        }
+        self
+    }
+
+    /// Capture a backtrace here if enabled through `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE`.
+    pub fn with_backtrace(self) -> Self {
+        self.set_backtrace(&Backtrace::capture())
+    }
+
+    fn set_backtrace(mut self, backtrace: &Backtrace) -> Self {
+        if backtrace.status() == BacktraceStatus::Captured {
+            self.internals.backtrace = backtrace.to_string();
+        }
+        self
+    }
 }
 
 impl Display for Status {
@@ -66,10 +96,11 @@ impl From<tonic::Status> for Status {
             code: code.as_u16() as i32,
             reason: code.to_string(),
             message: value.message().to_string(),
-            metadata: Default::default(),
+            ..Default::default()
         };
         if let Ok(ss) = serde_json::from_slice::<Status>(value.details()) {
-            s.reason = ss.reason
+            s.reason = ss.reason;
+            s.bad_request = ss.bad_request;
         }
         s
     }
@@ -81,8 +112,10 @@ impl From<TryLockError> for Status {
             code: StatusCode::INTERNAL_SERVER_ERROR.as_u16() as i32,
             reason: "TryLockError".to_string(),
             message: value.to_string(),
-            metadata: Default::default(),
+            ..Default::default()
         }
+            .with_cause(&value)
+            .with_backtrace()
     }
 }
 
@@ -92,71 +125,242 @@ impl From<tonic::transport::Error> for Status {
             code: StatusCode::INTERNAL_SERVER_ERROR.as_u16() as i32,
             reason: "TonicTransportErr".to_string(),
             message: value.to_string(),
-            metadata: Default::default(),
+            ..Default::default()
         }
+            .with_cause(&value)
+            .with_backtrace()
     }
 }
 
 impl From<ValidationError> for Status {
     fn from(value: ValidationError) -> Self {
         Status {
-            code: StatusCode::INTERNAL_SERVER_ERROR.as_u16() as i32,
+            code: StatusCode::BAD_REQUEST.as_u16() as i32,
             reason: "ValidationError".to_string(),
             message: value.to_string(),
-            metadata: Default::default(),
+            bad_request: Some(BadRequest {
+                field_violations: vec![field_violation(String::new(), &value)],
+            }),
+            ..Default::default()
         }
+            .with_cause(&value)
     }
 }
 
 impl From<ValidationErrors> for Status {
     fn from(value: ValidationErrors) -> Self {
+        let mut field_violations = Vec::new();
+        collect_field_violations("", &value, &mut field_violations);
+        field_violations.sort_by(|a, b| a.field.cmp(&b.field));
         Status {
-            code: StatusCode::INTERNAL_SERVER_ERROR.as_u16() as i32,
+            code: StatusCode::BAD_REQUEST.as_u16() as i32,
             reason: "ValidationErrors".to_string(),
             message: value.to_string(),
-            metadata: Default::default(),
+            bad_request: Some(BadRequest { field_violations }),
+            ..Default::default()
+        }
+            .with_cause(&value)
+    }
+}
+
+fn collect_field_violations(prefix: &str, errors: &ValidationErrors, out: &mut Vec<FieldViolation>) {
+    for (field, kind) in errors.errors() {
+        let path = if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", prefix, field)
+        };
+        match kind {
+            ValidationErrorsKind::Field(errs) => {
+                out.extend(errs.iter().map(|err| field_violation(path.clone(), err)));
+            }
+            ValidationErrorsKind::Struct(errs) => collect_field_violations(&path, errs, out),
+            ValidationErrorsKind::List(items) => {
+                for (i, errs) in items {
+                    collect_field_violations(&format!("{}[{}]", path, i), errs, out);
+                }
+            }
         }
     }
 }
 
+fn field_violation(field: String, err: &ValidationError) -> FieldViolation {
+    // Validator adds the rejected `value`, which may be a password or a token: don't echo it
+    // back to clients and into logs.
+    let params = err
+        .params
+        .iter()
+        .filter(|(k, _)| *k != "value")
+        .map(|(k, v)| {
+            let v = match v {
+                serde_json::Value::String(s) => s.clone(),
+                v => v.to_string(),
+            };
+            (k.to_string(), v)
+        })
+        .collect();
+    let description = match &err.message {
+        Some(message) => message.to_string(),
+        None => format!("failed validation: {}", err.code),
+    };
+    FieldViolation {
+        field,
+        description,
+        reason: err.code.to_string(),
+        params,
+        ..Default::default()
+    }
+}
+
 impl From<FormRejection> for Status {
     fn from(value: FormRejection) -> Self {
         Status {
-            code: StatusCode::INTERNAL_SERVER_ERROR.as_u16() as i32,
+            code: value.status().as_u16() as i32,
             reason: "FormRejection".to_string(),
-            message: value.to_string(),
-            metadata: Default::default(),
+            message: value.body_text(),
+            ..Default::default()
         }
+            .with_cause(&value)
     }
 }
 
 impl From<JsonRejection> for Status {
     fn from(value: JsonRejection) -> Self {
         Status {
-            code: StatusCode::INTERNAL_SERVER_ERROR.as_u16() as i32,
+            code: value.status().as_u16() as i32,
             reason: "JsonRejection".to_string(),
-            message: value.to_string(),
-            metadata: Default::default(),
+            message: value.body_text(),
+            ..Default::default()
+        }
+            .with_cause(&value)
+    }
+}
+
+impl From<QueryRejection> for Status {
+    fn from(value: QueryRejection) -> Self {
+        Status {
+            code: value.status().as_u16() as i32,
+            reason: "QueryRejection".to_string(),
+            message: value.body_text(),
+            ..Default::default()
+        }
+            .with_cause(&value)
+    }
+}
+
+impl From<PathRejection> for Status {
+    fn from(value: PathRejection) -> Self {
+        Status {
+            code: value.status().as_u16() as i32,
+            reason: "PathRejection".to_string(),
+            message: value.body_text(),
+            ..Default::default()
+        }
+            .with_cause(&value)
+    }
+}
+
+impl From<MultipartRejection> for Status {
+    fn from(value: MultipartRejection) -> Self {
+        Status {
+            code: value.status().as_u16() as i32,
+            reason: "MultipartRejection".to_string(),
+            message: value.body_text(),
+            ..Default::default()
+        }
+            .with_cause(&value)
+    }
+}
+
+impl From<MultipartError> for Status {
+    fn from(value: MultipartError) -> Self {
+        Status {
+            code: value.status().as_u16() as i32,
+            reason: "MultipartError".to_string(),
+            message: value.body_text(),
+            ..Default::default()
         }
+            .with_cause(&value)
+    }
+}
+
+
+impl From<anyhow::Error> for Status {
+    fn from(value: anyhow::Error) -> Self {
+        let mut s = Status {
+            code: StatusCode::INTERNAL_SERVER_ERROR.as_u16() as i32,
+            reason: "AnyhowError".to_string(),
+            message: value.to_string(),
+            ..Default::default()
+        };
+        s.internals.causes = value.chain().map(|err| err.to_string()).collect();
+        s.set_backtrace(value.backtrace())
+    }
+}
+
+/// Map the HTTP code of a [`Status`] to the closest gRPC code.
+fn grpc_code(code: i32) -> Code {
+    match u16::try_from(code).ok().and_then(|c| StatusCode::from_u16(c).ok()) {
+        Some(StatusCode::BAD_REQUEST) => Code::InvalidArgument,
+        Some(StatusCode::UNAUTHORIZED) => Code::Unauthenticated,
+        Some(StatusCode::FORBIDDEN) => Code::PermissionDenied,
+        Some(StatusCode::NOT_FOUND) => Code::NotFound,
+        Some(StatusCode::CONFLICT) => Code::AlreadyExists,
+        Some(StatusCode::PAYLOAD_TOO_LARGE) => Code::OutOfRange,
+        Some(StatusCode::TOO_MANY_REQUESTS) => Code::ResourceExhausted,
+        Some(StatusCode::NOT_IMPLEMENTED) => Code::Unimplemented,
+        Some(StatusCode::SERVICE_UNAVAILABLE) => Code::Unavailable,
+        Some(StatusCode::GATEWAY_TIMEOUT) => Code::DeadlineExceeded,
+        _ => Code::Internal,
     }
 }
 
-impl Into<tonic::Status> for Status {
-    fn into(self) -> tonic::Status {
-        let body = serde_json::to_vec(&self).unwrap();
-        let mut mm = MetadataMap::new();
+impl From<Status> for tonic::Status {
+    fn from(value: Status) -> Self {
+        let body = serde_json::to_vec(&value).unwrap();
+        let mm = MetadataMap::new();
         // for (k, v) in self.metadata {
         //     mm.insert(k.parse().unwrap(), v.parse().unwrap());
         // }
-        tonic::Status::with_details_and_metadata(Code::Internal, self.message, Bytes::from(body), mm)
+        let code = grpc_code(value.code);
+        let mut status = tonic::Status::with_details_and_metadata(code, value.message.clone(), Bytes::from(body), mm);
+        // Keep causes and backtrace reachable for server-side logging, the source is not sent on the wire.
+        status.set_source(Arc::new(value));
+        status
+    }
+}
+
+impl Status {
+    /// Render like [`IntoResponse`], but also expose the cause chain and backtrace.
+    ///
+    /// Meant for debug builds only, never enable it for production traffic.
+    pub fn into_debug_response(self) -> Response {
+        let res = SpringResponse::new(false, self.reason.clone(), self.message.clone(), self.data())
+            .with_internals(&self);
+        self.render(res)
+    }
+
+    /// Field violations go to `data`, so clients read them where the payload would be.
+    fn data(&self) -> serde_json::Value {
+        match &self.bad_request {
+            Some(bad_request) => json!(bad_request),
+            None => json!(""),
+        }
+    }
+
+    fn render(self, res: SpringResponse<serde_json::Value>) -> Response {
+        let body = Json(json!(res));
+        let mut response = (StatusCode::OK, body).into_response();
+        // Keep the status around so outer layers (e.g. localization) can re-render it.
+        response.extensions_mut().insert(self);
+        response
     }
 }
 
 impl IntoResponse for Status {
     fn into_response(self) -> Response {
-        let res = SpringResponse::new(false, self.reason, self.message, "");
-        let body = Json(json!(res));
-        (StatusCode::OK, body).into_response()
+        let res = SpringResponse::new(false, self.reason.clone(), self.message.clone(), self.data());
+        self.render(res)
     }
 }
 
@@ -171,6 +375,58 @@ mod tests {
         assert_eq!(a, b);
     }
 
+    #[test]
+    fn test_status_cause_chain() {
+        let err = anyhow::anyhow!("disk full").context("save user");
+        let s: Status = err.into();
+        assert_eq!(s.message, "save user");
+        assert_eq!(s.internals.causes, vec!["save user".to_string(), "disk full".to_string()]);
+
+        let ts: tonic::Status = s.clone().into();
+        let back: Status = ts.into();
+        assert!(back.internals.causes.is_empty());
+        assert!(back.internals.backtrace.is_empty());
+    }
+
+    use validator::Validate;
+
+    #[derive(Validate)]
+    struct Item {
+        #[validate(length(min = 1, max = 8))]
+        name: String,
+    }
+
+    #[derive(Validate)]
+    struct Order {
+        #[validate(email)]
+        email: String,
+        #[validate]
+        items: Vec<Item>,
+    }
+
+    #[test]
+    fn test_status_field_violations() {
+        let order = Order {
+            email: "nope".to_string(),
+            items: vec![Item { name: "ok".to_string() }, Item { name: String::new() }],
+        };
+        let s: Status = order.validate().unwrap_err().into();
+        assert_eq!(s.code, 400);
+        let violations = s.bad_request.clone().unwrap().field_violations;
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[0].field, "email");
+        assert_eq!(violations[0].reason, "email");
+        assert_eq!(violations[1].field, "items[1].name");
+        assert_eq!(violations[1].reason, "length");
+        assert_eq!(violations[1].params["min"], "1");
+        assert!(!violations[1].params.contains_key("value"));
+
+        let ts: tonic::Status = s.clone().into();
+        assert_eq!(ts.code(), Code::InvalidArgument);
+        let back: Status = ts.into();
+        assert_eq!(back.bad_request, s.bad_request);
+    }
+
     #[test]
     fn test_status_eq1() {
         let a = Status::new("UserNotFound", "a");
@@ -0,0 +1,468 @@
+//! TLS for the bamboo servers: certificates loaded from PEM files, optional client
+//! certificate verification (mTLS), and hot reload when the files change. Clients get
+//! [`ClientTls`].
+
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Context};
+use arc_swap::ArcSwap;
+use rustls::{
+    crypto::ring,
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName},
+    server::WebPkiClientVerifier,
+    ClientConfig, RootCertStore, ServerConfig, ServerConnection,
+};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_graceful::ShutdownGuard;
+use x509_parser::prelude::{FromDer, GeneralName, X509Certificate};
+
+use bamboo_status::status::AnyResult;
+
+pub use tokio_rustls::{client::TlsStream as ClientTlsStream, server::TlsStream};
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+
+/// Whether clients must present a certificate signed by [`Tls::client_ca`].
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientAuth {
+    /// Don't ask for client certificates.
+    #[default]
+    None,
+    /// Verify a client certificate when one is sent, accept anonymous clients too.
+    Optional,
+    /// Reject clients without a valid certificate.
+    Required,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Tls {
+    /// PEM certificate chain, leaf first.
+    pub cert: PathBuf,
+    /// PEM private key (PKCS#8, PKCS#1 or SEC1).
+    pub key: PathBuf,
+    /// PEM bundle of CAs trusted to sign client certificates, required unless
+    /// `client_auth` is `none`.
+    #[serde(default)]
+    pub client_ca: Option<PathBuf>,
+    #[serde(default)]
+    pub client_auth: ClientAuth,
+    /// How often to check the files for changes, 30s when missing. Set it to `null` (`~` in
+    /// YAML) to disable reloading.
+    #[serde(default = "default_reload_interval", with = "humantime_serde")]
+    pub reload_interval: Option<Duration>,
+    /// Maximum time a client gets to finish the handshake.
+    #[serde(default = "default_handshake_timeout", with = "humantime_serde")]
+    pub handshake_timeout: Duration,
+}
+
+fn default_reload_interval() -> Option<Duration> {
+    Some(Duration::from_secs(30))
+}
+
+fn default_handshake_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+impl Tls {
+    pub fn new(cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        Self {
+            cert: cert.into(),
+            key: key.into(),
+            client_ca: None,
+            client_auth: ClientAuth::None,
+            reload_interval: default_reload_interval(),
+            handshake_timeout: default_handshake_timeout(),
+        }
+    }
+
+    /// Verify client certificates against the CAs in `client_ca`.
+    pub fn with_client_auth(mut self, client_ca: impl Into<PathBuf>, client_auth: ClientAuth) -> Self {
+        self.client_ca = Some(client_ca.into());
+        self.client_auth = client_auth;
+        self
+    }
+
+    fn files(&self) -> impl Iterator<Item = &Path> {
+        [Some(self.cert.as_path()), Some(self.key.as_path()), self.client_ca.as_deref()]
+            .into_iter()
+            .flatten()
+    }
+
+    /// Build a rustls config announcing `alpn` protocols, e.g. `h2`.
+    pub fn server_config(&self, alpn: &[&[u8]]) -> AnyResult<ServerConfig> {
+        let provider = Arc::new(ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+        let builder = match self.client_auth {
+            ClientAuth::None => builder.with_no_client_auth(),
+            auth => {
+                let path = self
+                    .client_ca
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("tls: client_auth {:?} needs a client_ca", auth))?;
+                let mut roots = RootCertStore::empty();
+                for cert in load_certs(path)? {
+                    roots.add(cert)?;
+                }
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+                let verifier = match auth {
+                    ClientAuth::Optional => verifier.allow_unauthenticated().build()?,
+                    _ => verifier.build()?,
+                };
+                builder.with_client_cert_verifier(verifier)
+            }
+        };
+        let mut config = builder.with_single_cert(load_certs(&self.cert)?, load_key(&self.key)?)?;
+        config.alpn_protocols = alpn.iter().map(|p| p.to_vec()).collect();
+        Ok(config)
+    }
+}
+
+/// TLS of outgoing connections.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct ClientTls {
+    /// PEM bundle of CAs trusted to sign server certificates, the system roots when missing.
+    #[serde(default)]
+    pub ca: Option<PathBuf>,
+    /// PEM certificate chain presented to servers that verify clients (mTLS).
+    #[serde(default)]
+    pub cert: Option<PathBuf>,
+    /// PEM private key of `cert`.
+    #[serde(default)]
+    pub key: Option<PathBuf>,
+    /// Name the server certificate must be valid for, the host of the endpoint when missing.
+    #[serde(default)]
+    pub server_name: Option<String>,
+}
+
+impl ClientTls {
+    /// Build a rustls config announcing `alpn` protocols, e.g. `h2`.
+    pub fn client_config(&self, alpn: &[&[u8]]) -> AnyResult<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        match &self.ca {
+            Some(path) => {
+                for cert in load_certs(path)? {
+                    roots.add(cert)?;
+                }
+            }
+            None => {
+                let native = rustls_native_certs::load_native_certs();
+                for err in &native.errors {
+                    log::warn!("tls: loading system roots: {}", err);
+                }
+                let (added, ignored) = roots.add_parsable_certificates(native.certs);
+                log::debug!("tls: {} system roots, {} ignored", added, ignored);
+            }
+        }
+        let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots);
+        let mut config = match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => builder.with_client_auth_cert(load_certs(cert)?, load_key(key)?)?,
+            (None, None) => builder.with_no_client_auth(),
+            _ => return Err(anyhow!("tls: a client cert needs a key and the other way around")),
+        };
+        config.alpn_protocols = alpn.iter().map(|p| p.to_vec()).collect();
+        Ok(config)
+    }
+}
+
+fn load_certs(path: &Path) -> AnyResult<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).with_context(|| format!("tls: open {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("tls: read certificates from {}", path.display()))?;
+    if certs.is_empty() {
+        return Err(anyhow!("tls: no certificate in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> AnyResult<PrivateKeyDer<'static>> {
+    let file = File::open(path).with_context(|| format!("tls: open {}", path.display()))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("tls: read private key from {}", path.display()))?
+        .ok_or_else(|| anyhow!("tls: no private key in {}", path.display()))
+}
+
+/// The verified client certificate of a mTLS connection.
+///
+/// bamboo-rest puts it in the request extensions, bamboo-rpc in its connect info.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerIdentity {
+    /// Common name of the subject, empty if it has none.
+    pub common_name: String,
+    /// The full subject, e.g. `CN=client, O=bamboo`.
+    pub subject: String,
+    /// DNS and URI subject alternative names, e.g. SPIFFE ids.
+    pub sans: Vec<String>,
+    /// The chain sent by the client, leaf first.
+    pub certificates: Vec<CertificateDer<'static>>,
+}
+
+impl PeerIdentity {
+    /// Identity of the client certificate of `conn`, `None` for anonymous clients.
+    pub fn from_connection(conn: &ServerConnection) -> Option<Self> {
+        let certificates: Vec<_> = conn
+            .peer_certificates()?
+            .iter()
+            .map(|c| c.clone().into_owned())
+            .collect();
+        let (_, leaf) = X509Certificate::from_der(certificates.first()?).ok()?;
+        let common_name = leaf
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let sans = leaf
+            .subject_alternative_name()
+            .ok()
+            .flatten()
+            .map(|ext| {
+                ext.value
+                    .general_names
+                    .iter()
+                    .filter_map(|name| match name {
+                        GeneralName::DNSName(s) | GeneralName::URI(s) => Some(s.to_string()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Some(Self {
+            common_name,
+            subject: leaf.subject().to_string(),
+            sans,
+            certificates,
+        })
+    }
+}
+
+/// Opens TLS connections to one server.
+#[derive(Clone)]
+pub struct Connector {
+    connector: tokio_rustls::TlsConnector,
+    server_name: ServerName<'static>,
+}
+
+impl Connector {
+    /// Connect to `host`, unless [`ClientTls::server_name`] names another certificate.
+    pub fn new(conf: &ClientTls, host: &str, alpn: &[&[u8]]) -> AnyResult<Self> {
+        let name = conf.server_name.as_deref().unwrap_or(host).trim_matches(['[', ']']);
+        let server_name = ServerName::try_from(name.to_string())
+            .with_context(|| format!("tls: invalid server name {:?}", name))?;
+        Ok(Self {
+            connector: tokio_rustls::TlsConnector::from(Arc::new(conf.client_config(alpn)?)),
+            server_name,
+        })
+    }
+
+    /// Handshake with the server over `io`.
+    pub async fn connect<IO>(&self, io: IO) -> std::io::Result<ClientTlsStream<IO>>
+        where IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        self.connector.connect(self.server_name.clone(), io).await
+    }
+}
+
+/// Accepts TLS connections with the latest certificates.
+#[derive(Clone)]
+pub struct Acceptor {
+    conf: Arc<Tls>,
+    alpn: Arc<Vec<Vec<u8>>>,
+    config: Arc<ArcSwap<ServerConfig>>,
+}
+
+impl Acceptor {
+    pub fn new(conf: &Tls, alpn: &[&[u8]]) -> AnyResult<Self> {
+        let config = conf.server_config(alpn)?;
+        Ok(Self {
+            conf: Arc::new(conf.clone()),
+            alpn: Arc::new(alpn.iter().map(|p| p.to_vec()).collect()),
+            config: Arc::new(ArcSwap::from_pointee(config)),
+        })
+    }
+
+    /// Handshake with a client, failing after [`Tls::handshake_timeout`].
+    pub async fn accept<IO>(&self, io: IO) -> AnyResult<TlsStream<IO>>
+        where IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let acceptor = tokio_rustls::TlsAcceptor::from(self.config.load_full());
+        let stream = tokio::time::timeout(self.conf.handshake_timeout, acceptor.accept(io))
+            .await
+            .context("tls: handshake timed out")??;
+        Ok(stream)
+    }
+
+    /// Rebuild the config from the files, existing connections keep their certificates.
+    pub fn reload(&self) -> AnyResult<()> {
+        let alpn: Vec<&[u8]> = self.alpn.iter().map(Vec::as_slice).collect();
+        let config = self.conf.server_config(&alpn)?;
+        self.config.store(Arc::new(config));
+        Ok(())
+    }
+
+    /// Reload the certificates whenever their files change, until shutdown.
+    ///
+    /// Invalid files are logged and the previous certificates kept.
+    pub fn watch(&self, guard: &ShutdownGuard) {
+        let Some(interval) = self.conf.reload_interval else {
+            return;
+        };
+        let acceptor = self.clone();
+        let guard = guard.clone_weak();
+        tokio::spawn(async move {
+            let mut last = acceptor.modified();
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = guard.cancelled() => return,
+                }
+                let modified = acceptor.modified();
+                if modified == last {
+                    continue;
+                }
+                last = modified;
+                match acceptor.reload() {
+                    Ok(()) => log::info!("tls: reloaded certificates"),
+                    Err(err) => log::error!("tls: keep the previous certificates, {:#}", err),
+                }
+            }
+        });
+    }
+
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        self.conf
+            .files()
+            .map(|p| std::fs::metadata(p).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustls::{pki_types::ServerName, ClientConfig};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_graceful::Shutdown;
+
+    use super::*;
+    use crate::testing::Pki;
+
+    /// Connect to an in-memory acceptor, returning the identity it saw for the client.
+    async fn handshake(acceptor: &Acceptor, client: ClientConfig) -> AnyResult<Option<PeerIdentity>> {
+        let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(client));
+        let server = acceptor.accept(server_io);
+        let client = connector.connect(ServerName::try_from("localhost").unwrap(), client_io);
+        let (server, client) = tokio::join!(server, client);
+        let mut server = server?;
+        let mut client = client?;
+        // Round trip a byte so a rejected client certificate surfaces on the client too.
+        server.write_all(b"x").await?;
+        let mut buf = [0u8; 1];
+        client.read_exact(&mut buf).await?;
+        Ok(PeerIdentity::from_connection(server.get_ref().1))
+    }
+
+    #[tokio::test]
+    async fn valid_tls() {
+        let pki = Pki::new("plain");
+        let acceptor = Acceptor::new(&pki.tls(ClientAuth::None), &[b"h2"]).unwrap();
+        let peer = handshake(&acceptor, pki.client_config(None, &[b"h2"])).await.unwrap();
+        assert!(peer.is_none());
+    }
+
+    #[tokio::test]
+    async fn valid_client_tls() {
+        let pki = Pki::new("client");
+        pki.issue("client", "bob");
+        let acceptor = Acceptor::new(&pki.tls(ClientAuth::Required), &[]).unwrap();
+
+        let conf = pki.client_tls(Some("client"));
+        let peer = handshake(&acceptor, conf.client_config(&[]).unwrap()).await.unwrap().unwrap();
+        assert_eq!(peer.common_name, "bob");
+        let anonymous = pki.client_tls(None).client_config(&[]).unwrap();
+        assert!(handshake(&acceptor, anonymous).await.is_err());
+
+        let conf = ClientTls {
+            key: None,
+            ..pki.client_tls(Some("client"))
+        };
+        assert!(conf.client_config(&[]).is_err());
+    }
+
+    #[tokio::test]
+    async fn valid_mtls() {
+        let pki = Pki::new("mtls");
+        pki.issue("client", "alice");
+        let acceptor = Acceptor::new(&pki.tls(ClientAuth::Required), &[]).unwrap();
+
+        let peer = handshake(&acceptor, pki.client_config(Some("client"), &[])).await.unwrap().unwrap();
+        assert_eq!(peer.common_name, "alice");
+        assert!(peer.subject.contains("CN=alice"));
+        assert_eq!(peer.sans, vec!["alice".to_string(), "spiffe://bamboo/alice".to_string()]);
+
+        assert!(handshake(&acceptor, pki.client_config(None, &[])).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn valid_optional_client_auth() {
+        let pki = Pki::new("optional");
+        let acceptor = Acceptor::new(&pki.tls(ClientAuth::Optional), &[]).unwrap();
+        let peer = handshake(&acceptor, pki.client_config(None, &[])).await.unwrap();
+        assert!(peer.is_none());
+    }
+
+    #[test]
+    fn valid_reload_interval() {
+        let tls: Tls = serde_json::from_str(r#"{"cert": "cert.pem", "key": "key.pem"}"#).unwrap();
+        assert_eq!(tls.reload_interval, Some(Duration::from_secs(30)));
+        let tls: Tls = serde_json::from_str(r#"{"cert": "cert.pem", "key": "key.pem", "reload_interval": null}"#).unwrap();
+        assert_eq!(tls.reload_interval, None);
+    }
+
+    #[test]
+    fn invalid_client_auth_without_ca() {
+        let pki = Pki::new("noca");
+        let mut tls = pki.tls(ClientAuth::None);
+        tls.client_auth = ClientAuth::Required;
+        assert!(tls.server_config(&[]).is_err());
+    }
+
+    #[tokio::test]
+    async fn valid_reload() {
+        let pki = Pki::new("reload");
+        let mut tls = pki.tls(ClientAuth::None);
+        tls.reload_interval = Some(Duration::from_millis(20));
+        let acceptor = Acceptor::new(&tls, &[]).unwrap();
+        let shutdown = Shutdown::new(std::future::pending());
+        acceptor.watch(&shutdown.guard());
+
+        // A broken file keeps the previous certificates.
+        std::fs::write(&tls.cert, "garbage").unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        handshake(&acceptor, pki.client_config(None, &[])).await.unwrap();
+
+        // Certificates from another CA are picked up.
+        let other = Pki::new("reload-other");
+        other.issue("server", "localhost");
+        std::fs::copy(other.dir.join("server.pem"), &tls.cert).unwrap();
+        std::fs::copy(other.dir.join("server.key"), &tls.key).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(handshake(&acceptor, pki.client_config(None, &[])).await.is_err());
+        handshake(&acceptor, other.client_config(None, &[])).await.unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+//! Self-signed certificates for tests, enabled by the `test-util` feature.
+
+use std::{path::PathBuf, sync::Arc};
+
+use rcgen::{BasicConstraints, CertificateParams, CertifiedKey, IsCa, KeyPair, SanType};
+use rustls::{crypto::ring, ClientConfig, RootCertStore};
+
+use crate::{load_certs, load_key, ClientAuth, ClientTls, Tls};
+
+/// A CA plus server and client certificates it signed, written to a temp directory.
+pub struct Pki {
+    pub dir: PathBuf,
+    ca: CertifiedKey,
+}
+
+impl Pki {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("bamboo-tls-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let key_pair = KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key_pair).unwrap();
+        std::fs::write(dir.join("ca.pem"), cert.pem()).unwrap();
+        Self {
+            dir,
+            ca: CertifiedKey { cert, key_pair },
+        }
+    }
+
+    /// Issue `name.pem`/`name.key` for `cn`, which is also its DNS name.
+    pub fn issue(&self, name: &str, cn: &str) {
+        let mut params = CertificateParams::new(vec![cn.to_string()]).unwrap();
+        params.distinguished_name.push(rcgen::DnType::CommonName, cn);
+        params
+            .subject_alt_names
+            .push(SanType::URI(format!("spiffe://bamboo/{}", cn).try_into().unwrap()));
+        let key_pair = KeyPair::generate().unwrap();
+        let cert = params.signed_by(&key_pair, &self.ca.cert, &self.ca.key_pair).unwrap();
+        std::fs::write(self.dir.join(format!("{}.pem", name)), cert.pem()).unwrap();
+        std::fs::write(self.dir.join(format!("{}.key", name)), key_pair.serialize_pem()).unwrap();
+    }
+
+    pub fn tls(&self, client_auth: ClientAuth) -> Tls {
+        self.issue("server", "localhost");
+        let tls = Tls::new(self.dir.join("server.pem"), self.dir.join("server.key"));
+        match client_auth {
+            ClientAuth::None => tls,
+            auth => tls.with_client_auth(self.dir.join("ca.pem"), auth),
+        }
+    }
+
+    /// Client settings trusting the CA, presenting the `client` certificate if given.
+    pub fn client_tls(&self, client: Option<&str>) -> ClientTls {
+        ClientTls {
+            ca: Some(self.dir.join("ca.pem")),
+            cert: client.map(|name| self.dir.join(format!("{}.pem", name))),
+            key: client.map(|name| self.dir.join(format!("{}.key", name))),
+            server_name: None,
+        }
+    }
+
+    /// A client config trusting the CA, presenting the `client` certificate if given.
+    pub fn client_config(&self, client: Option<&str>, alpn: &[&[u8]]) -> ClientConfig {
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(&self.dir.join("ca.pem")).unwrap() {
+            roots.add(cert).unwrap();
+        }
+        let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        let mut config = match client {
+            Some(name) => builder
+                .with_client_auth_cert(
+                    load_certs(&self.dir.join(format!("{}.pem", name))).unwrap(),
+                    load_key(&self.dir.join(format!("{}.key", name))).unwrap(),
+                )
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        };
+        config.alpn_protocols = alpn.iter().map(|p| p.to_vec()).collect();
+        config
+    }
+}
+
+impl Drop for Pki {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
@@ -4,6 +4,8 @@ use log::Level;
 use tower_http::{trace::OnFailure, LatencyUnit};
 use tracing::Span;
 
+use bamboo_status::errors::Status;
+
 /// The default [`OnFailure`] implementation used by [`Trace`].
 ///
 /// [`Trace`]: super::Trace
@@ -58,3 +60,33 @@ impl<FailureClass> OnFailure<FailureClass> for DefaultOnFailure
         );
     }
 }
+
+/// Log the internal cause chain and backtrace carried by a failed [`Status`].
+///
+/// Used by [`DefaultOnResponse`](super::on_response::DefaultOnResponse) for responses
+/// rendered from a `Status`; nothing is logged when the status carries no internals.
+pub fn log_status(status: &Status, latency: Duration) {
+    if status.internals.causes.is_empty() && status.internals.backtrace.is_empty() {
+        return;
+    }
+    let mut causes = String::new();
+    for (i, cause) in status.internals.causes.iter().enumerate() {
+        causes.push_str(&format!("\n  {}: {}", i, cause));
+    }
+    if status.internals.backtrace.is_empty() {
+        log::error!(
+            "reason: {}, latency: {:?}, response failed, caused by:{}",
+            status.reason,
+            latency,
+            causes,
+        );
+    } else {
+        log::error!(
+            "reason: {}, latency: {:?}, response failed, caused by:{}\nbacktrace:\n{}",
+            status.reason,
+            latency,
+            causes,
+            status.internals.backtrace,
+        );
+    }
+}
@@ -44,10 +44,18 @@ impl DefaultOnRequest {
 
 impl<B> OnRequest<B> for DefaultOnRequest {
     fn on_request(&mut self, request: &http::request::Request<B>, _span: &Span) {
-            log::info!(
+        match request.headers().get("x-request-id").and_then(|v| v.to_str().ok()) {
+            Some(request_id) => log::info!(
+                " {} {} started processing request, request_id: {}",
+                request.method(),
+                request.uri().path(),
+                request_id
+            ),
+            None => log::info!(
                 " {} {} started processing request",
                 request.method(),
                 request.uri().path()
-            );
+            ),
+        }
     }
 }
@@ -5,6 +5,10 @@ use log::Level;
 use tower_http::{trace::OnResponse, LatencyUnit};
 use tracing::Span;
 
+use bamboo_status::errors::Status;
+
+use super::on_failure::log_status;
+
 /// The default [`OnResponse`] implementation used by [`Trace`].
 ///
 /// [`Trace`]: super::Trace
@@ -77,6 +81,9 @@ impl<B> OnResponse<B> for DefaultOnResponse {
             latency,
             response_headers
         );
+        if let Some(status) = response.extensions().get::<Status>() {
+            log_status(status, latency);
+        }
     }
 }
 
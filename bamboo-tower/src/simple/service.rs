@@ -13,7 +13,9 @@ pub struct MyMiddleware<S> {
 
 impl<S, Request> Service<Request> for MyMiddleware<S>
 where
-    S: Service<Request> + Clone,
+    S: Service<Request> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    Request: Send + 'static,
 {
     type Response = S::Response;
     type Error = S::Error;